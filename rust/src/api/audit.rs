@@ -0,0 +1,318 @@
+use nostr::hashes::hmac::{Hmac, HmacEngine};
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::{Hash, HashEngine};
+use nostr::types::time::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+use super::storage;
+
+static AUDIT_ENABLED: AtomicBool = AtomicBool::new(true);
+static AUDIT_SURFACE: Mutex<Option<String>> = Mutex::new(None);
+// Key of the entry MACs, kept by the platform keystore (see `audit_set_device_key`)
+static AUDIT_KEY: Mutex<Option<Zeroizing<[u8; 32]>>> = Mutex::new(None);
+// Entries recorded before the aux store was opened (or the device key was set)
+static PENDING_ENTRIES: Mutex<Vec<PendingEntry>> = Mutex::new(Vec::new());
+
+/// Sequence number (big-endian u64) -> JSON encoded AuditEntry
+const AUDIT_TREE: &str = "audit_log";
+/// Entries kept in memory at most while the aux store is not open
+const MAX_PENDING_ENTRIES: usize = 1000;
+/// Public key of the entries of a wiped account (see `redact_pubkey`)
+const REDACTED_PUBKEY: &str = "redacted";
+
+/// Operation performed with a private key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyOperation {
+    SignEvent,
+    /// Signature of a message outside of an event (`sign_message`)
+    SignMessage,
+    /// Delegation token signed for another key (`create_delegation_tag`)
+    SignDelegation,
+    Nip04Decrypt,
+    Nip44Decrypt,
+    /// Raw ECDH shared secret handed out (`derive_shared_secret`)
+    DeriveSharedSecret,
+    /// Private key handed out in another encoding (`export_secret_as_wif`, `hex_to_nsec`,
+    /// `nsec_to_hex`)
+    ExportSecret,
+}
+
+/// Audit log entry of a key operation
+///
+/// Entries are hash-chained: `hash` covers the entry and the hash of the previous entry,
+/// so removing or editing entries is detected by `audit_verify_log`. Once a device key is
+/// set (`audit_set_device_key`) `hash` is an HMAC under it, which can't be recomputed by
+/// someone who edits the store without the key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub operation: KeyOperation,
+    /// Hex public key of the key that was used, "redacted" once its account was wiped
+    pub pubkey: String,
+    /// Kind of the signed event
+    pub kind: Option<u16>,
+    /// Part of the app that requested the operation (see `audit_set_surface`)
+    pub surface: Option<String>,
+    pub hash: String,
+    /// Whether `hash` is an HMAC under the device key
+    #[serde(default)]
+    pub keyed: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PendingEntry {
+    timestamp: u64,
+    operation: KeyOperation,
+    pubkey: String,
+    kind: Option<u16>,
+    surface: Option<String>,
+}
+
+/// Hash of an entry chained to the previous one, an HMAC under `key` for keyed entries
+fn entry_hash(prev_hash: &str, entry: &AuditEntry, key: Option<&[u8; 32]>) -> String {
+    let data = format!(
+        "{}|{}|{}|{:?}|{}|{}|{}",
+        prev_hash,
+        entry.seq,
+        entry.timestamp,
+        entry.operation,
+        entry.pubkey,
+        entry.kind.map(|k| k.to_string()).unwrap_or_default(),
+        entry.surface.clone().unwrap_or_default(),
+    );
+    match key {
+        Some(key) => {
+            let mut engine = HmacEngine::<Sha256Hash>::new(key);
+            engine.input(data.as_bytes());
+            hex::encode(Hmac::<Sha256Hash>::from_engine(engine).to_byte_array())
+        }
+        None => Sha256Hash::hash(data.as_bytes()).to_string(),
+    }
+}
+
+fn device_key() -> Option<Zeroizing<[u8; 32]>> {
+    AUDIT_KEY.lock().ok().and_then(|key| key.clone())
+}
+
+fn last_entry(tree: &sled::Tree) -> Result<Option<AuditEntry>, String> {
+    tree.last()
+        .map_err(|e| format!("Failed to read audit log: {}", e))?
+        .map(|(_, value)| serde_json::from_slice::<AuditEntry>(&value))
+        .transpose()
+        .map_err(|e| format!("Invalid audit entry: {}", e))
+}
+
+fn append(tree: &sled::Tree, pending: PendingEntry, key: Option<&[u8; 32]>) -> Result<(), String> {
+    let last = last_entry(tree)?;
+    let (seq, prev_hash) = match last {
+        Some(last) => (last.seq + 1, last.hash),
+        None => (0, String::new()),
+    };
+    
+    let mut entry = AuditEntry {
+        seq,
+        timestamp: pending.timestamp,
+        operation: pending.operation,
+        pubkey: pending.pubkey,
+        kind: pending.kind,
+        surface: pending.surface,
+        hash: String::new(),
+        keyed: key.is_some(),
+    };
+    entry.hash = entry_hash(&prev_hash, &entry, key);
+    
+    let value = serde_json::to_vec(&entry)
+        .map_err(|e| format!("Failed to serialize audit entry: {}", e))?;
+    tree.insert(seq.to_be_bytes(), value)
+        .map_err(|e| format!("Failed to write audit entry: {}", e))?;
+    Ok(())
+}
+
+/// Record a key operation in the audit log (no-op when auditing is disabled)
+pub(crate) fn record(operation: KeyOperation, pubkey: &str, kind: Option<u16>) {
+    if !AUDIT_ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    
+    let entry = PendingEntry {
+        timestamp: Timestamp::now().as_u64(),
+        operation,
+        pubkey: pubkey.to_string(),
+        kind,
+        surface: AUDIT_SURFACE.lock().ok().and_then(|surface| surface.clone()),
+    };
+    
+    let mut pending = match PENDING_ENTRIES.lock() {
+        Ok(pending) => pending,
+        Err(_) => return,
+    };
+    pending.push(entry);
+    flush_pending(&mut pending);
+}
+
+/// Write pending entries to the aux store if it's open, otherwise keep a bounded backlog in
+/// memory. A keyed log also waits for the device key, so it doesn't get unkeyed entries.
+fn flush_pending(pending: &mut Vec<PendingEntry>) {
+    let key = device_key();
+    let tree = storage::open_tree(AUDIT_TREE).ok()
+        .filter(|tree| key.is_some() || !last_entry(tree).ok().flatten().is_some_and(|last| last.keyed));
+    match tree {
+        Some(tree) => {
+            for entry in pending.drain(..) {
+                if let Err(e) = append(&tree, entry, key.as_deref()) {
+                    tracing::warn!("Failed to record key usage: {}", e);
+                }
+            }
+        }
+        None => {
+            let overflow = pending.len().saturating_sub(MAX_PENDING_ENTRIES);
+            pending.drain(..overflow);
+        }
+    }
+}
+
+/// Set the key the audit log entries are authenticated with (HMAC-SHA256)
+///
+/// Keep it in the platform keystore (Android Keystore, iOS Keychain) and set it at every
+/// start, before keys are used: entries of a keyed log wait in memory until it is set, and
+/// `audit_verify_log` needs it. Entries recorded before a key was ever set stay plain hashes.
+///
+/// # Arguments
+/// * `key` - 32 random bytes, the same at every start
+#[flutter_rust_bridge::frb(sync)]
+pub fn audit_set_device_key(key: Vec<u8>) -> Result<(), String> {
+    let key: [u8; 32] = key.try_into()
+        .map_err(|_| "Device key must be 32 bytes".to_string())?;
+    *AUDIT_KEY.lock()
+        .map_err(|e| format!("Failed to lock audit key: {}", e))? = Some(Zeroizing::new(key));
+    
+    let mut pending = PENDING_ENTRIES.lock()
+        .map_err(|e| format!("Failed to lock audit entries: {}", e))?;
+    flush_pending(&mut pending);
+    Ok(())
+}
+
+/// Replace `pubkey` (hex) in the audit log with "redacted", returns how many entries changed
+///
+/// The chain is checked first and re-chained from the first redacted entry, keyed entries
+/// with the device key, so `audit_verify_log` still passes. A chain that is already broken
+/// is left as it is (and an error returned), re-chaining it would hide the break.
+pub(crate) fn redact_pubkey(pubkey: &str) -> Result<u32, String> {
+    // Held until the end, so no entry is appended while the log is re-chained
+    let mut pending = PENDING_ENTRIES.lock()
+        .map_err(|e| format!("Failed to lock audit entries: {}", e))?;
+    let mut redacted = 0;
+    for entry in pending.iter_mut().filter(|entry| entry.pubkey == pubkey) {
+        entry.pubkey = REDACTED_PUBKEY.to_string();
+        redacted += 1;
+    }
+    
+    if let Some(seq) = audit_verify_log()? {
+        return Err(format!("Audit log is broken at entry {}, not redacting it", seq));
+    }
+    let tree = storage::open_tree(AUDIT_TREE)?;
+    let key = device_key();
+    let mut batch = sled::Batch::default();
+    let mut prev_hash = String::new();
+    let mut rewriting = false;
+    for entry in tree.iter() {
+        let (seq, value) = entry.map_err(|e| format!("Failed to read audit log: {}", e))?;
+        let mut entry: AuditEntry = serde_json::from_slice(&value)
+            .map_err(|e| format!("Invalid audit entry: {}", e))?;
+        if entry.pubkey == pubkey {
+            entry.pubkey = REDACTED_PUBKEY.to_string();
+            redacted += 1;
+            rewriting = true;
+        }
+        if rewriting {
+            // The log verified, so keyed entries mean the key is set
+            entry.hash = entry_hash(&prev_hash, &entry, key.as_deref().filter(|_| entry.keyed));
+            let value = serde_json::to_vec(&entry)
+                .map_err(|e| format!("Failed to serialize audit entry: {}", e))?;
+            batch.insert(seq, value);
+        }
+        prev_hash = entry.hash;
+    }
+    tree.apply_batch(batch)
+        .map_err(|e| format!("Failed to redact audit log: {}", e))?;
+    
+    Ok(redacted)
+}
+
+/// Enable or disable the key usage audit log (enabled by default)
+#[flutter_rust_bridge::frb(sync)]
+pub fn audit_set_enabled(enabled: bool) {
+    AUDIT_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn audit_is_enabled() -> bool {
+    AUDIT_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Set the app surface recorded with following key operations (e.g. "composer", "notifications")
+#[flutter_rust_bridge::frb(sync)]
+pub fn audit_set_surface(surface: Option<String>) -> Result<(), String> {
+    let mut surface_guard = AUDIT_SURFACE.lock()
+        .map_err(|e| format!("Failed to lock audit surface: {}", e))?;
+    *surface_guard = surface;
+    Ok(())
+}
+
+/// Get audit log entries, oldest first
+///
+/// # Arguments
+/// * `from_seq` - First sequence number to return (None for the beginning)
+/// * `limit` - Maximum number of entries
+#[flutter_rust_bridge::frb(sync)]
+pub fn audit_get_entries(from_seq: Option<u64>, limit: Option<u32>) -> Result<Vec<AuditEntry>, String> {
+    let tree = storage::open_tree(AUDIT_TREE)?;
+    let limit = limit.map(|n| n as usize).unwrap_or(usize::MAX);
+    
+    tree.range(from_seq.unwrap_or(0).to_be_bytes()..)
+        .take(limit)
+        .map(|entry| {
+            let (_, value) = entry.map_err(|e| format!("Failed to read audit log: {}", e))?;
+            serde_json::from_slice(&value).map_err(|e| format!("Invalid audit entry: {}", e))
+        })
+        .collect()
+}
+
+/// Verify the hash chain of the audit log, returns the first broken sequence number if any
+///
+/// Keyed entries need the device key (`audit_set_device_key`). An unkeyed entry after a
+/// keyed one counts as broken.
+#[flutter_rust_bridge::frb(sync)]
+pub fn audit_verify_log() -> Result<Option<u64>, String> {
+    let tree = storage::open_tree(AUDIT_TREE)?;
+    let key = device_key();
+    
+    let mut prev_hash = String::new();
+    let mut expected_seq = 0;
+    let mut keyed = false;
+    for entry in tree.iter() {
+        let (_, value) = entry.map_err(|e| format!("Failed to read audit log: {}", e))?;
+        let entry: AuditEntry = serde_json::from_slice(&value)
+            .map_err(|e| format!("Invalid audit entry: {}", e))?;
+        
+        let entry_key = match (entry.keyed, key.as_deref()) {
+            (true, None) => return Err("The audit log is keyed, set the device key to verify it".to_string()),
+            (true, Some(key)) => Some(key),
+            (false, _) => None,
+        };
+        if entry.seq != expected_seq
+            || (keyed && !entry.keyed)
+            || entry.hash != entry_hash(&prev_hash, &entry, entry_key)
+        {
+            return Ok(Some(expected_seq));
+        }
+        keyed = entry.keyed;
+        prev_hash = entry.hash;
+        expected_seq += 1;
+    }
+    
+    Ok(None)
+}
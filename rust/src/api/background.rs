@@ -0,0 +1,187 @@
+use nostr_sdk::prelude::{Filter, JsonUtil};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use super::client::{flush_outbox, sync_cursor};
+use super::schedule::publish_due;
+use super::relay::{close_headless_database, delete_events_older_than, open_headless_database, run_blocking_with_timeout};
+
+/// Time kept from the budget to close connections and the database
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+/// Subscription to catch up on, see `client_sync_since`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatchUpSync {
+    pub cursor: String,
+    pub filter_json: String,
+}
+
+/// Configuration of a background sync (passed as JSON)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackgroundSyncConfig {
+    /// Database path, as passed to `relay_start`
+    pub db_path: String,
+    /// Remote relays for the outbox and the catch-up
+    pub relays: Vec<String>,
+    pub flush_outbox: bool,
+    /// Publish the scheduled events that are due (see `schedule_event`)
+    pub publish_scheduled: bool,
+    pub catch_up: Vec<CatchUpSync>,
+    /// Delete events older than this many days (None uses the relay setting)
+    pub retention_days: Option<u32>,
+    /// Total time the sync may take, including shutdown
+    pub time_budget_ms: u64,
+}
+
+impl Default for BackgroundSyncConfig {
+    fn default() -> Self {
+        Self {
+            db_path: String::new(),
+            relays: Vec::new(),
+            flush_outbox: true,
+            publish_scheduled: true,
+            catch_up: Vec::new(),
+            retention_days: None,
+            // iOS gives BGAppRefreshTask about 30 seconds
+            time_budget_ms: 25_000,
+        }
+    }
+}
+
+/// Summary of a background sync
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackgroundSyncSummary {
+    /// Outbox events accepted by at least one relay
+    pub outbox_published: u32,
+    /// Outbox events left queued for the next run
+    pub outbox_pending: u32,
+    pub scheduled_published: u32,
+    pub events_received: u32,
+    pub events_stored: u32,
+    pub retention_applied: bool,
+    /// False if the time budget ran out before all steps were done
+    pub completed: bool,
+    pub errors: Vec<String>,
+    pub elapsed_ms: u64,
+}
+
+async fn run_steps(config: BackgroundSyncConfig, summary: Arc<Mutex<BackgroundSyncSummary>>) {
+    let record_error = |error: String| {
+        tracing::warn!("Background sync: {}", error);
+        if let Ok(mut summary) = summary.lock() {
+            summary.errors.push(error);
+        }
+    };
+    
+    // 1. Publish what the user wrote while offline
+    if config.flush_outbox && !config.relays.is_empty() {
+        match flush_outbox(&config.relays).await {
+            Ok(outcomes) => {
+                if let Ok(mut summary) = summary.lock() {
+                    for outcome in outcomes {
+                        if outcome.accepted_relays.is_empty() {
+                            summary.outbox_pending += 1;
+                        } else {
+                            summary.outbox_published += 1;
+                        }
+                    }
+                }
+            }
+            Err(e) => record_error(format!("Outbox flush failed: {}", e)),
+        }
+    }
+    
+    if config.publish_scheduled {
+        match publish_due(nostr_sdk::prelude::Timestamp::now().as_u64()).await {
+            Ok(published) => {
+                if let Ok(mut summary) = summary.lock() {
+                    summary.scheduled_published = published;
+                }
+            }
+            Err(e) => record_error(format!("Publishing scheduled events failed: {}", e)),
+        }
+    }
+    
+    // 2. Catch up on subscriptions from their cursors
+    for catch_up in config.catch_up.iter() {
+        let filter = match Filter::from_json(&catch_up.filter_json) {
+            Ok(filter) => filter,
+            Err(e) => {
+                record_error(format!("Invalid filter for '{}': {}", catch_up.cursor, e));
+                continue;
+            }
+        };
+        
+        match sync_cursor(&config.relays, filter, &catch_up.cursor).await {
+            Ok(result) => {
+                if let Ok(mut summary) = summary.lock() {
+                    summary.events_received += result.events_received;
+                    summary.events_stored += result.events_stored;
+                }
+            }
+            Err(e) => record_error(format!("Catch-up '{}' failed: {}", catch_up.cursor, e)),
+        }
+    }
+    
+    // 3. Retention
+    let retention_days = config.retention_days
+        .unwrap_or_else(|| super::policy::current_config().retention_days);
+    let now = nostr_sdk::prelude::Timestamp::now().as_u64();
+    match delete_events_older_than(retention_days, now).await {
+        Ok(()) => {
+            if let Ok(mut summary) = summary.lock() {
+                summary.retention_applied = retention_days > 0;
+            }
+        }
+        Err(e) => record_error(format!("Retention failed: {}", e)),
+    }
+    
+    if let Ok(mut summary) = summary.lock() {
+        summary.completed = true;
+    }
+}
+
+/// One-shot sync for OS background tasks (Android WorkManager, iOS BGTaskScheduler)
+///
+/// Meant to be called from a headless Dart isolate: opens the database if the relay isn't
+/// running, flushes the outbox and due scheduled events, catches up on subscriptions,
+/// applies retention and closes everything again. Steps still running when the time budget
+/// is used up are cancelled and reported as not completed; the outbox and the sync cursors
+/// make the next run resume.
+///
+/// # Arguments
+/// * `config_json` - `BackgroundSyncConfig` as JSON
+#[flutter_rust_bridge::frb(sync)]
+pub fn run_background_sync(config_json: String) -> Result<BackgroundSyncSummary, String> {
+    let config: BackgroundSyncConfig = serde_json::from_str(&config_json)
+        .map_err(|e| format!("Invalid background sync config: {}", e))?;
+    let started = Instant::now();
+    
+    let opened = open_headless_database(&config.db_path)?;
+    
+    let budget = Duration::from_millis(config.time_budget_ms).saturating_sub(SHUTDOWN_GRACE);
+    let summary = Arc::new(Mutex::new(BackgroundSyncSummary::default()));
+    let steps = run_steps(config, summary.clone());
+    
+    // Timing out drops the steps future, which closes its connections
+    let result = run_blocking_with_timeout(budget + SHUTDOWN_GRACE, async move {
+        tokio::time::timeout(budget, steps).await.is_ok()
+    });
+    
+    if opened {
+        close_headless_database();
+    }
+    
+    let mut summary = summary.lock()
+        .map_err(|e| format!("Failed to lock sync summary: {}", e))?
+        .clone();
+    match result {
+        Ok(true) => {}
+        Ok(false) => summary.errors.push("Time budget exceeded".to_string()),
+        Err(e) => summary.errors.push(e),
+    }
+    summary.elapsed_ms = started.elapsed().as_millis() as u64;
+    
+    Ok(summary)
+}
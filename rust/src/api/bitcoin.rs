@@ -0,0 +1,141 @@
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::secp256k1::{Scalar, Secp256k1};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::audit::{self, KeyOperation};
+
+// Exporting the Nostr key for on-chain use must be enabled explicitly by the app
+static EXPORT_ENABLED: AtomicBool = AtomicBool::new(false);
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BitcoinNetwork {
+    Mainnet,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl BitcoinNetwork {
+    fn hrp(&self) -> bech32::Hrp {
+        match self {
+            BitcoinNetwork::Mainnet => bech32::hrp::BC,
+            BitcoinNetwork::Testnet | BitcoinNetwork::Signet => bech32::hrp::TB,
+            BitcoinNetwork::Regtest => bech32::hrp::BCRT,
+        }
+    }
+    
+    fn wif_prefix(&self) -> u8 {
+        match self {
+            BitcoinNetwork::Mainnet => 0x80,
+            _ => 0xef,
+        }
+    }
+}
+
+fn check_enabled() -> Result<(), String> {
+    if EXPORT_ENABLED.load(Ordering::Relaxed) {
+        Ok(())
+    } else {
+        Err("Bitcoin key export is disabled, enable it with set_bitcoin_key_export_enabled".to_string())
+    }
+}
+
+/// Allow using the Nostr key for on-chain funds (disabled by default)
+///
+/// Reusing the key across protocols means a leaked nsec also leaks the funds; only enable
+/// it after the user explicitly opted in.
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_bitcoin_key_export_enabled(enabled: bool) {
+    EXPORT_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn base58_encode(data: &[u8]) -> String {
+    // Base 58 digits, least significant first
+    let mut digits: Vec<u8> = Vec::with_capacity(data.len() * 138 / 100 + 1);
+    for byte in data {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    
+    let zeros = data.iter().take_while(|byte| **byte == 0).count();
+    std::iter::repeat(b'1')
+        .take(zeros)
+        .chain(digits.iter().rev().map(|digit| BASE58_ALPHABET[*digit as usize]))
+        .map(char::from)
+        .collect()
+}
+
+/// Export the Nostr private key in Wallet Import Format (compressed)
+///
+/// Import it as a taproot key, e.g. the descriptor `tr(<wif>)`, to spend from the address
+/// given by `nostr_taproot_address`.
+///
+/// # Arguments
+/// * `private_key` - Private key (hex or nsec)
+/// * `network` - Network the wallet uses
+#[flutter_rust_bridge::frb(sync)]
+pub fn export_secret_as_wif(private_key: String, network: BitcoinNetwork) -> Result<String, String> {
+    check_enabled()?;
+    let secret_key = SecretKey::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    
+    let mut payload = Vec::with_capacity(38);
+    payload.push(network.wif_prefix());
+    payload.extend_from_slice(&secret_key.to_secret_bytes());
+    // Compressed public key marker
+    payload.push(0x01);
+    let checksum = Sha256Hash::hash(Sha256Hash::hash(&payload).as_byte_array());
+    payload.extend_from_slice(&checksum.as_byte_array()[..4]);
+    
+    audit::record(KeyOperation::ExportSecret, &Keys::new(secret_key).public_key().to_hex(), None);
+    Ok(base58_encode(&payload))
+}
+
+/// BIP-340 tagged hash
+pub(crate) fn tagged_hash(tag: &str, message: &[u8]) -> [u8; 32] {
+    let tag_hash = Sha256Hash::hash(tag.as_bytes());
+    let mut data = Vec::with_capacity(64 + message.len());
+    data.extend_from_slice(tag_hash.as_byte_array());
+    data.extend_from_slice(tag_hash.as_byte_array());
+    data.extend_from_slice(message);
+    Sha256Hash::hash(&data).to_byte_array()
+}
+
+/// Taproot (P2TR, BIP-86 key path only) address of a Nostr public key
+///
+/// Nostr public keys are x-only keys like taproot internal keys, so anyone can derive the
+/// address from a npub; spending needs the private key (see `export_secret_as_wif`).
+///
+/// # Arguments
+/// * `pubkey` - Public key (hex or npub)
+/// * `network` - Network of the address
+#[flutter_rust_bridge::frb(sync)]
+pub fn nostr_taproot_address(pubkey: String, network: BitcoinNetwork) -> Result<String, String> {
+    check_enabled()?;
+    let public_key = PublicKey::parse(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let internal_key = public_key.xonly()
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    
+    // Q = P + hash_TapTweak(P)·G, without a script tree
+    let tweak = Scalar::from_be_bytes(tagged_hash("TapTweak", &internal_key.serialize()))
+        .map_err(|e| format!("Invalid tweak: {}", e))?;
+    let secp = Secp256k1::verification_only();
+    let (output_key, _) = internal_key.add_tweak(&secp, &tweak)
+        .map_err(|e| format!("Failed to tweak key: {}", e))?;
+    
+    bech32::segwit::encode_v1(network.hrp(), &output_key.serialize())
+        .map_err(|e| format!("Failed to encode address: {}", e))
+}
@@ -0,0 +1,455 @@
+use nostr_sdk::prelude::*;
+use nostr::nips::{nip04, nip44};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use super::client::connect_client;
+use super::relay::{run_async_with_timeout, run_blocking_with_timeout};
+use super::session_keys;
+
+/// NIP-46 request/response kind
+pub(crate) const NOSTR_CONNECT_KIND: u16 = 24133;
+/// How long a request waits for the remote signer (the user may have to approve it there)
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Session id -> connected session
+static SESSIONS: Mutex<Option<HashMap<String, BunkerSession>>> = Mutex::new(None);
+
+/// Keys of this app, only used to talk to the remote signer
+#[derive(Clone)]
+enum AppKeys {
+    /// Keys given by the caller to reuse an approved connection
+    Provided(Keys),
+    /// Session key (see `remote_signer_connect_ephemeral`), never exposed and destroyed when
+    /// the session closes
+    Session(String),
+}
+
+impl AppKeys {
+    fn keys(&self) -> Result<Keys, String> {
+        match self {
+            AppKeys::Provided(keys) => Ok(keys.clone()),
+            AppKeys::Session(id) => session_keys::session_keys(id),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct BunkerSession {
+    client: Client,
+    app_keys: AppKeys,
+    remote_signer: PublicKey,
+    user_pubkey: PublicKey,
+    relays: Vec<String>,
+}
+
+/// Remote signer session established by `remote_signer_connect`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteSignerSession {
+    pub session_id: String,
+    /// Hex public key of the user, the one events are signed with
+    pub user_pubkey: String,
+    /// Hex public key of the remote signer
+    pub remote_signer_pubkey: String,
+    /// Hex private key of this app's session keys, pass it again to reconnect without a new
+    /// approval. None for sessions from `remote_signer_connect_ephemeral`
+    pub app_private_key: Option<String>,
+    pub relays: Vec<String>,
+}
+
+/// NIP-46 request (JSON-RPC like), encrypted in the content of kind 24133 events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Nip46Request {
+    pub id: String,
+    pub method: String,
+    pub params: Vec<String>,
+}
+
+/// NIP-46 response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Nip46Response {
+    pub id: String,
+    #[serde(default)]
+    pub result: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Parsed `bunker://<remote signer pubkey>?relay=...&secret=...` URI
+struct BunkerUri {
+    remote_signer: PublicKey,
+    relays: Vec<String>,
+    secret: Option<String>,
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(if bytes[i] == b'+' { b' ' } else { bytes[i] });
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Percent-encode a URI query value, keeping only unreserved characters as they are
+pub(crate) fn percent_encode(value: &str) -> String {
+    value.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn parse_bunker_uri(uri: &str) -> Result<BunkerUri, String> {
+    let rest = uri.trim()
+        .strip_prefix("bunker://")
+        .ok_or_else(|| "Not a bunker:// URI".to_string())?;
+    let (pubkey, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let remote_signer = PublicKey::from_hex(pubkey)
+        .map_err(|e| format!("Invalid remote signer public key: {}", e))?;
+    
+    let mut relays = Vec::new();
+    let mut secret = None;
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "relay" => {
+                let relay = percent_decode(value);
+                RelayUrl::parse(&relay).map_err(|e| format!("Invalid relay URL '{}': {}", relay, e))?;
+                relays.push(relay);
+            }
+            "secret" if !value.is_empty() => secret = Some(percent_decode(value)),
+            _ => {}
+        }
+    }
+    if relays.is_empty() {
+        return Err("Bunker URI has no relay".to_string());
+    }
+    Ok(BunkerUri { remote_signer, relays, secret })
+}
+
+/// Random id of a NIP-46 request
+pub(crate) fn request_id() -> String {
+    SecretKey::generate().to_secret_hex()[..16].to_string()
+}
+
+/// Decrypt the content of a kind 24133 event (NIP-44, or NIP-04 from older implementations)
+pub(crate) fn decrypt_nip46_content(keys: &Keys, event: &Event) -> Result<String, String> {
+    nip44::decrypt(keys.secret_key(), &event.pubkey, &event.content)
+        .or_else(|_| nip04::decrypt(keys.secret_key(), &event.pubkey, &event.content))
+        .map_err(|e| format!("Failed to decrypt NIP-46 message: {}", e))
+}
+
+/// Encrypt and sign a NIP-46 message (request or response) for `receiver`
+pub(crate) fn nip46_event(keys: &Keys, receiver: &PublicKey, message_json: String) -> Result<Event, String> {
+    let content = nip44::encrypt(keys.secret_key(), receiver, message_json, nip44::Version::V2)
+        .map_err(|e| format!("NIP-44 encryption failed: {}", e))?;
+    EventBuilder::new(Kind::from(NOSTR_CONNECT_KIND), content)
+        .tag(Tag::public_key(*receiver))
+        .sign_with_keys(keys)
+        .map_err(|e| format!("Failed to sign NIP-46 message: {}", e))
+}
+
+/// Subscribe to the responses of the remote signer, once per session
+async fn subscribe_responses(client: &Client, app_keys: &Keys, remote_signer: &PublicKey) -> Result<(), String> {
+    let filter = Filter::new()
+        .kind(Kind::from(NOSTR_CONNECT_KIND))
+        .author(*remote_signer)
+        .pubkey(app_keys.public_key());
+    client.subscribe(filter, None)
+        .await
+        .map_err(|e| format!("Failed to subscribe to the remote signer: {}", e))?;
+    Ok(())
+}
+
+/// Send a request to the remote signer and wait for its response (see `subscribe_responses`)
+async fn send_request(
+    client: &Client,
+    app_keys: &Keys,
+    remote_signer: &PublicKey,
+    method: &str,
+    params: Vec<String>,
+) -> Result<String, String> {
+    let request = Nip46Request {
+        id: request_id(),
+        method: method.to_string(),
+        params,
+    };
+    let request_json = serde_json::to_string(&request)
+        .map_err(|e| format!("Failed to serialize request: {}", e))?;
+    let event = nip46_event(app_keys, remote_signer, request_json)?;
+    
+    // Listen before sending, the response can be fast
+    let mut notifications = client.notifications();
+    client.send_event(&event)
+        .await
+        .map_err(|e| format!("Failed to send request to the remote signer: {}", e))?;
+    
+    let wait_response = async {
+        loop {
+            let event = match notifications.recv().await {
+                Ok(RelayPoolNotification::Event { event, .. }) => event,
+                Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => {
+                    return Err("Remote signer session closed".to_string());
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            };
+            if event.pubkey != *remote_signer || event.kind != Kind::from(NOSTR_CONNECT_KIND) {
+                continue;
+            }
+            let response: Nip46Response = match decrypt_nip46_content(app_keys, &event)
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok())
+            {
+                Some(response) => response,
+                None => continue,
+            };
+            if response.id != request.id {
+                continue;
+            }
+            return match (response.result, response.error) {
+                // The response to the request follows once the user authenticated
+                (Some(result), Some(url)) if result == "auth_url" => {
+                    Err(format!("Remote signer requires authentication, open {} and retry", url))
+                }
+                (_, Some(error)) => Err(format!("Remote signer error: {}", error)),
+                (Some(result), None) => Ok(result),
+                (None, None) => Err("Empty response from the remote signer".to_string()),
+            };
+        }
+    };
+    tokio::time::timeout(REQUEST_TIMEOUT, wait_response)
+        .await
+        .map_err(|_| "Remote signer did not respond".to_string())?
+}
+
+fn get_session(session_id: &str) -> Result<BunkerSession, String> {
+    SESSIONS.lock()
+        .map_err(|e| format!("Failed to lock sessions: {}", e))?
+        .as_ref()
+        .and_then(|sessions| sessions.get(session_id).cloned())
+        .ok_or_else(|| format!("No remote signer session '{}'", session_id))
+}
+
+/// Call a method of the remote signer of a session
+async fn call(session_id: &str, method: &str, params: Vec<String>) -> Result<String, String> {
+    let session = get_session(session_id)?;
+    let app_keys = session.app_keys.keys()?;
+    let method = method.to_string();
+    let timeout = REQUEST_TIMEOUT + Duration::from_secs(5);
+    run_async_with_timeout(timeout, async move {
+        send_request(&session.client, &app_keys, &session.remote_signer, &method, params).await
+    })
+    .await?
+}
+
+/// Connect to a remote signer (NIP-46) from a `bunker://` URI, e.g. from Amber or nsecbunker
+///
+/// The remote signer may ask the user to approve the connection, the call waits up to a
+/// minute for it (asynchronously, like the signing and encryption calls of the session).
+/// Sessions live until `remote_signer_disconnect` or the app exits; keep `app_private_key`
+/// to reconnect later without a new approval.
+///
+/// # Arguments
+/// * `bunker_uri` - `bunker://<remote signer pubkey>?relay=<url>&secret=<secret>`
+/// * `session_id` - Id chosen by the caller for the session
+/// * `app_private_key` - Session keys of a previous connection, None for new ones
+pub async fn remote_signer_connect(
+    bunker_uri: String,
+    session_id: String,
+    app_private_key: Option<String>,
+) -> Result<RemoteSignerSession, String> {
+    let uri = parse_bunker_uri(&bunker_uri)?;
+    let keys = match &app_private_key {
+        Some(secret) => Keys::parse(secret).map_err(|e| format!("Invalid private key: {}", e))?,
+        None => Keys::generate(),
+    };
+    if get_session(&session_id).is_ok() {
+        return Err(format!("Remote signer session '{}' already exists", session_id));
+    }
+    let app_private_key = keys.secret_key().to_secret_hex();
+    let mut info = connect(uri, session_id, AppKeys::Provided(keys.clone()), keys).await?;
+    info.app_private_key = Some(app_private_key);
+    Ok(info)
+}
+
+/// Connect to a remote signer like `remote_signer_connect`, with app keys that never leave Rust
+///
+/// The app keys are a session key (see `session_key_generate`) held for as long as the
+/// session lives and destroyed by `remote_signer_disconnect`; a new connection needs a new
+/// approval.
+pub async fn remote_signer_connect_ephemeral(
+    bunker_uri: String,
+    session_id: String,
+) -> Result<RemoteSignerSession, String> {
+    let uri = parse_bunker_uri(&bunker_uri)?;
+    if get_session(&session_id).is_ok() {
+        return Err(format!("Remote signer session '{}' already exists", session_id));
+    }
+    let (id, keys) = session_keys::create_pinned()?;
+    let connected = connect(uri, session_id, AppKeys::Session(id.clone()), keys).await;
+    if connected.is_err() {
+        let _ = session_keys::destroy(&id);
+    }
+    connected
+}
+
+/// Connect and register a remote signer session, `app_private_key` of the result is None
+async fn connect(
+    uri: BunkerUri,
+    session_id: String,
+    app_keys: AppKeys,
+    connect_keys: Keys,
+) -> Result<RemoteSignerSession, String> {
+    let timeout = REQUEST_TIMEOUT * 2 + Duration::from_secs(10);
+    let session = run_async_with_timeout(timeout, async move {
+        let client = connect_client(&uri.relays, None).await?;
+        let mut params = vec![uri.remote_signer.to_hex()];
+        params.extend(uri.secret.clone());
+        let connected = async {
+            subscribe_responses(&client, &connect_keys, &uri.remote_signer).await?;
+            let result = send_request(&client, &connect_keys, &uri.remote_signer, "connect", params).await?;
+            // "ack", or the secret from the URI
+            if result != "ack" && Some(&result) != uri.secret.as_ref() {
+                return Err(format!("Unexpected connect response '{}'", result));
+            }
+            let user_pubkey = send_request(&client, &connect_keys, &uri.remote_signer, "get_public_key", Vec::new()).await?;
+            PublicKey::from_hex(&user_pubkey).map_err(|e| format!("Invalid user public key: {}", e))
+        };
+        match connected.await {
+            Ok(user_pubkey) => Ok(BunkerSession {
+                client,
+                app_keys,
+                remote_signer: uri.remote_signer,
+                user_pubkey,
+                relays: uri.relays,
+            }),
+            Err(e) => {
+                client.shutdown().await;
+                Err(e)
+            }
+        }
+    })
+    .await??;
+    
+    let info = RemoteSignerSession {
+        session_id: session_id.clone(),
+        user_pubkey: session.user_pubkey.to_hex(),
+        remote_signer_pubkey: session.remote_signer.to_hex(),
+        app_private_key: None,
+        relays: session.relays.clone(),
+    };
+    SESSIONS.lock()
+        .map_err(|e| format!("Failed to lock sessions: {}", e))?
+        .get_or_insert_with(HashMap::new)
+        .insert(session_id, session);
+    Ok(info)
+}
+
+/// Close a remote signer session, returns false if it didn't exist
+#[flutter_rust_bridge::frb(sync)]
+pub fn remote_signer_disconnect(session_id: String) -> Result<bool, String> {
+    let session = SESSIONS.lock()
+        .map_err(|e| format!("Failed to lock sessions: {}", e))?
+        .as_mut()
+        .and_then(|sessions| sessions.remove(&session_id));
+    match session {
+        Some(session) => {
+            if let AppKeys::Session(id) = &session.app_keys {
+                session_keys::destroy(id)?;
+            }
+            run_blocking_with_timeout(Duration::from_secs(10), async move { session.client.shutdown().await })?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Close the remote signer sessions of a user (hex public key), returns how many were open
+pub(crate) fn disconnect_user(user_pubkey: &str) -> Result<u32, String> {
+    let session_ids: Vec<String> = SESSIONS.lock()
+        .map_err(|e| format!("Failed to lock sessions: {}", e))?
+        .as_ref()
+        .map(|sessions| {
+            sessions.iter()
+                .filter(|(_, session)| session.user_pubkey.to_hex() == user_pubkey)
+                .map(|(id, _)| id.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    let mut disconnected = 0;
+    for session_id in session_ids {
+        if remote_signer_disconnect(session_id)? {
+            disconnected += 1;
+        }
+    }
+    Ok(disconnected)
+}
+
+/// Public key (hex) of the user of a remote signer session
+#[flutter_rust_bridge::frb(sync)]
+pub fn remote_get_public_key(session_id: String) -> Result<String, String> {
+    Ok(get_session(&session_id)?.user_pubkey.to_hex())
+}
+
+/// Sign an event with the remote signer
+///
+/// # Arguments
+/// * `session_id` - Remote signer session
+/// * `unsigned_event_json` - Event with `kind`, `content`, `tags` and `created_at`
+///
+/// Returns the signed event JSON, checked against the user's public key.
+pub async fn remote_sign_event(session_id: String, unsigned_event_json: String) -> Result<String, String> {
+    let session = get_session(&session_id)?;
+    let mut unsigned: serde_json::Value = serde_json::from_str(&unsigned_event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    // The remote signer fills in the pubkey and id
+    if let Some(object) = unsigned.as_object_mut() {
+        object.remove("id");
+        object.remove("sig");
+        object.insert("pubkey".to_string(), serde_json::Value::String(session.user_pubkey.to_hex()));
+    }
+    
+    let signed = call(&session_id, "sign_event", vec![unsigned.to_string()]).await?;
+    let event = Event::from_json(&signed)
+        .map_err(|e| format!("Invalid signed event: {}", e))?;
+    if event.pubkey != session.user_pubkey {
+        return Err("Remote signer signed with another key".to_string());
+    }
+    event.verify()
+        .map_err(|e| format!("Invalid signed event: {}", e))?;
+    Ok(event.as_json())
+}
+
+pub async fn remote_nip04_encrypt(session_id: String, public_key: String, plaintext: String) -> Result<String, String> {
+    call(&session_id, "nip04_encrypt", vec![public_key, plaintext]).await
+}
+
+pub async fn remote_nip04_decrypt(session_id: String, public_key: String, ciphertext: String) -> Result<String, String> {
+    call(&session_id, "nip04_decrypt", vec![public_key, ciphertext]).await
+}
+
+pub async fn remote_nip44_encrypt(session_id: String, public_key: String, plaintext: String) -> Result<String, String> {
+    call(&session_id, "nip44_encrypt", vec![public_key, plaintext]).await
+}
+
+pub async fn remote_nip44_decrypt(session_id: String, public_key: String, ciphertext: String) -> Result<String, String> {
+    call(&session_id, "nip44_decrypt", vec![public_key, ciphertext]).await
+}
@@ -0,0 +1,350 @@
+use nostr::event::{Event, EventBuilder, Kind, Tag, UnsignedEvent};
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::nip04;
+use nostr::types::time::Timestamp;
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+use super::inbox::{gift_wrap_rumor, gift_wrap_rumor_with_signer, unwrap_gift_wrap};
+use super::names::resolve_display_name;
+use super::nostr::with_fixed_created_at;
+use super::signer::Signer;
+
+// No NIP assigns kinds to these yet, keep them in one place
+/// Ephemeral typing indicator (never stored by the relay)
+const TYPING_INDICATOR_KIND: u16 = 20014;
+/// Read receipt rumor, sent gift-wrapped like NIP-17 messages
+const READ_RECEIPT_KIND: u16 = 1015;
+
+/// Chat message rumor (NIP-17)
+const PRIVATE_DM_KIND: u16 = 14;
+/// File message rumor (NIP-17)
+const PRIVATE_FILE_KIND: u16 = 15;
+
+/// Longest notification snippet, in characters
+const NOTIFICATION_SNIPPET_CHARS: usize = 120;
+
+/// Typing indicators expire quickly (NIP-40) in case "stopped" is never sent
+const TYPING_INDICATOR_TTL_SECS: u64 = 30;
+
+fn parse_keys(private_key: &str) -> Result<Keys, String> {
+    let secret_key = SecretKey::from_str(private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    Ok(Keys::new(secret_key))
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<PublicKey, String> {
+    PublicKey::from_str(pubkey).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+/// Build and sign an ephemeral typing indicator for a conversation
+///
+/// Ephemeral events are relayed but never stored. The `p` tag is visible to relays, so
+/// only send these to the peer's DM relays.
+///
+/// # Arguments
+/// * `receiver_pubkey` - Hex public key of the other participant
+/// * `typing` - True while typing, false once stopped
+/// * `private_key` - Hex private key of the sender
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_typing_indicator(receiver_pubkey: String, typing: bool, private_key: String) -> Result<String, String> {
+    let keys = parse_keys(&private_key)?;
+    let event = with_fixed_created_at(typing_indicator_builder(&receiver_pubkey, typing)?)
+        .sign_with_keys(&keys)
+        .map_err(|e| format!("Failed to sign typing indicator: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(TYPING_INDICATOR_KIND));
+    
+    Ok(event.as_json())
+}
+
+/// `build_typing_indicator` with a platform or in-memory signer
+pub async fn build_typing_indicator_with_signer(receiver_pubkey: String, typing: bool, signer: &Signer) -> Result<String, String> {
+    Ok(signer.sign_builder(typing_indicator_builder(&receiver_pubkey, typing)?).await?.as_json())
+}
+
+fn typing_indicator_builder(receiver_pubkey: &str, typing: bool) -> Result<EventBuilder, String> {
+    let receiver = parse_pubkey(receiver_pubkey)?;
+    let expiration = Timestamp::from(Timestamp::now().as_u64() + TYPING_INDICATOR_TTL_SECS);
+    Ok(EventBuilder::new(Kind::from(TYPING_INDICATOR_KIND), if typing { "typing" } else { "stopped" })
+        .tag(Tag::public_key(receiver))
+        .tag(Tag::expiration(expiration)))
+}
+
+/// Whether a typing indicator says the peer is typing (false once stopped or expired)
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_typing_indicator(event_json: String) -> Result<bool, String> {
+    let event = nostr::event::Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if event.kind != Kind::from(TYPING_INDICATOR_KIND) {
+        return Err(format!("Not a typing indicator: kind {}", event.kind));
+    }
+    Ok(event.content == "typing" && !event.is_expired())
+}
+
+/// Build gift-wrapped read receipts for messages of a conversation
+///
+/// Returns two gift wraps (JSON): one for the peer and one for the sender's own other
+/// devices, to publish to the respective DM relays.
+///
+/// # Arguments
+/// * `message_ids` - Ids of the read rumors (kind 14 messages)
+/// * `peer_pubkey` - Hex public key of the other participant
+/// * `private_key` - Hex private key of the reader
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_read_receipt(message_ids: Vec<String>, peer_pubkey: String, private_key: String) -> Result<Vec<String>, String> {
+    let keys = parse_keys(&private_key)?;
+    let peer = parse_pubkey(&peer_pubkey)?;
+    let rumor = read_receipt_rumor(&message_ids, peer, keys.public_key())?;
+    
+    [peer, keys.public_key()].iter()
+        .map(|receiver| gift_wrap_rumor(&keys, receiver, rumor.clone()).map(|wrap| wrap.as_json()))
+        .collect()
+}
+
+/// `build_read_receipt` with a platform or in-memory signer (one that can encrypt)
+pub async fn build_read_receipt_with_signer(message_ids: Vec<String>, peer_pubkey: String, signer: &Signer) -> Result<Vec<String>, String> {
+    let peer = parse_pubkey(&peer_pubkey)?;
+    let rumor = read_receipt_rumor(&message_ids, peer, signer.nostr_public_key())?;
+    
+    let mut wraps = Vec::new();
+    for receiver in [peer, signer.nostr_public_key()] {
+        wraps.push(gift_wrap_rumor_with_signer(signer, &receiver, rumor.clone()).await?.as_json());
+    }
+    Ok(wraps)
+}
+
+fn read_receipt_rumor(message_ids: &[String], peer: PublicKey, reader: PublicKey) -> Result<UnsignedEvent, String> {
+    if message_ids.is_empty() {
+        return Err("No message ids given".to_string());
+    }
+    
+    let mut tags = vec![Tag::public_key(peer)];
+    for id in message_ids.iter() {
+        tags.push(Tag::parse(["e", id.as_str()]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    Ok(EventBuilder::new(Kind::from(READ_RECEIPT_KIND), "")
+        .tags(tags)
+        .build(reader))
+}
+
+/// Get the ids of the messages marked as read by a read receipt rumor
+///
+/// # Arguments
+/// * `rumor_json` - Unwrapped rumor (e.g. from `decrypt_inbox`)
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_read_receipt(rumor_json: String) -> Result<Vec<String>, String> {
+    let rumor = UnsignedEvent::from_json(&rumor_json)
+        .map_err(|e| format!("Invalid rumor JSON: {}", e))?;
+    if rumor.kind != Kind::from(READ_RECEIPT_KIND) {
+        return Err(format!("Not a read receipt: kind {}", rumor.kind));
+    }
+    Ok(rumor.tags.iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, id, ..] if name == "e" => Some(id.clone()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Private direct message (NIP-17), decrypted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivateDm {
+    /// Id of the rumor, the id to reply to or to send read receipts for
+    pub id: String,
+    /// Hex public key of the sender
+    pub sender: String,
+    /// Hex public keys of the receivers (`p` tags)
+    pub receivers: Vec<String>,
+    pub content: String,
+    /// Canonical timestamp of the message (the rumor's, not the randomized wrap's)
+    pub created_at: u64,
+    /// Id of the message replied to
+    pub reply_to: Option<String>,
+    /// Conversation title
+    pub subject: Option<String>,
+}
+
+/// Build a private direct message (NIP-17) to one receiver
+///
+/// The message is a kind 14 rumor, sealed and gift wrapped. Returns two gift wraps (JSON):
+/// one for the receiver and one for the sender's own other devices, to publish to the
+/// respective DM relays.
+///
+/// # Arguments
+/// * `content` - Message text
+/// * `receiver_pubkey` - Hex public key of the receiver
+/// * `private_key` - Hex private key of the sender
+/// * `reply_to` - Id of the message replied to
+#[flutter_rust_bridge::frb(sync)]
+pub fn send_private_dm(
+    content: String,
+    receiver_pubkey: String,
+    private_key: String,
+    reply_to: Option<String>,
+) -> Result<Vec<String>, String> {
+    let keys = parse_keys(&private_key)?;
+    let receiver = parse_pubkey(&receiver_pubkey)?;
+    let rumor = private_dm_rumor(content, receiver, keys.public_key(), reply_to.as_deref())?;
+    
+    [receiver, keys.public_key()].iter()
+        .map(|receiver| gift_wrap_rumor(&keys, receiver, rumor.clone()).map(|wrap| wrap.as_json()))
+        .collect()
+}
+
+/// `send_private_dm` with a platform or in-memory signer (one that can encrypt)
+pub async fn send_private_dm_with_signer(
+    content: String,
+    receiver_pubkey: String,
+    signer: &Signer,
+    reply_to: Option<String>,
+) -> Result<Vec<String>, String> {
+    let receiver = parse_pubkey(&receiver_pubkey)?;
+    let rumor = private_dm_rumor(content, receiver, signer.nostr_public_key(), reply_to.as_deref())?;
+    
+    let mut wraps = Vec::new();
+    for receiver in [receiver, signer.nostr_public_key()] {
+        wraps.push(gift_wrap_rumor_with_signer(signer, &receiver, rumor.clone()).await?.as_json());
+    }
+    Ok(wraps)
+}
+
+fn private_dm_rumor(content: String, receiver: PublicKey, sender: PublicKey, reply_to: Option<&str>) -> Result<UnsignedEvent, String> {
+    let mut tags = vec![Tag::public_key(receiver)];
+    if let Some(reply_to) = reply_to {
+        tags.push(Tag::parse(["e", reply_to]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    Ok(EventBuilder::new(Kind::from(PRIVATE_DM_KIND), content)
+        .tags(tags)
+        .build(sender))
+}
+
+/// Decrypt a private direct message (NIP-17) from its gift wrap
+///
+/// # Arguments
+/// * `event_json` - Gift wrap (kind 1059) addressed to the key
+/// * `private_key` - Hex private key of the receiver (or of the sender, for its own copy)
+#[flutter_rust_bridge::frb(sync)]
+pub fn decrypt_private_dm(event_json: String, private_key: String) -> Result<PrivateDm, String> {
+    let keys = parse_keys(&private_key)?;
+    let gift_wrap = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if gift_wrap.kind != Kind::GiftWrap {
+        return Err(format!("Not a gift wrap: kind {}", gift_wrap.kind));
+    }
+    let (sender, mut rumor) = unwrap_gift_wrap(&keys, &gift_wrap)?;
+    if rumor.kind != Kind::from(PRIVATE_DM_KIND) {
+        return Err(format!("Not a private direct message: kind {}", rumor.kind));
+    }
+    rumor.ensure_id();
+    
+    let mut dm = PrivateDm {
+        id: rumor.id.map(|id| id.to_hex()).unwrap_or_default(),
+        sender: sender.to_hex(),
+        receivers: Vec::new(),
+        content: rumor.content.clone(),
+        created_at: rumor.created_at.as_u64(),
+        reply_to: None,
+        subject: None,
+    };
+    for tag in rumor.tags.iter() {
+        match tag.as_slice() {
+            [name, pubkey, ..] if name == "p" => dm.receivers.push(pubkey.clone()),
+            [name, id, ..] if name == "e" => dm.reply_to = Some(id.clone()),
+            [name, subject, ..] if name == "subject" => dm.subject = Some(subject.clone()),
+            _ => {}
+        }
+    }
+    Ok(dm)
+}
+
+/// What a local notification needs to show for an incoming direct message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DmNotification {
+    /// Id of the received event (the gift wrap for NIP-17)
+    pub event_id: String,
+    /// Kind of the message: 4 (NIP-04), 14 (NIP-17 chat) or 15 (NIP-17 file)
+    pub kind: u16,
+    /// Hex public key of the sender
+    pub sender: String,
+    /// Name of the sender from the local caches (see `resolve_display_name`)
+    pub sender_name: String,
+    /// Start of the message on one line, empty for files
+    pub snippet: String,
+    /// Same for every message between the same participants, to group notifications
+    pub conversation_id: String,
+    pub created_at: u64,
+    /// Our own message (copy sent to our other devices), usually not worth a notification
+    pub from_self: bool,
+}
+
+/// Hex SHA-256 of the sorted, distinct participants of a conversation
+pub(crate) fn conversation_id(participants: &[PublicKey]) -> String {
+    let mut participants: Vec<String> = participants.iter().map(|pubkey| pubkey.to_hex()).collect();
+    participants.sort();
+    participants.dedup();
+    Sha256Hash::hash(participants.join(",").as_bytes()).to_string()
+}
+
+/// Message on one line, cut at `NOTIFICATION_SNIPPET_CHARS`
+fn notification_snippet(content: &str) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    match line.char_indices().nth(NOTIFICATION_SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line,
+    }
+}
+
+/// Build the payload of a local notification for an incoming direct message
+///
+/// Meant for background isolates woken by a push: it decrypts the message (NIP-04, or
+/// NIP-17 gift wrap), looks the sender's name up in the local caches only and never
+/// touches the network.
+///
+/// # Arguments
+/// * `event_json` - Received event (kind 4 or 1059)
+/// * `secret` - Hex private key of the receiver
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_dm_notification(event_json: String, secret: String) -> Result<DmNotification, String> {
+    let keys = parse_keys(&secret)?;
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    
+    let (kind, sender, content, created_at, mut participants) = match event.kind {
+        Kind::EncryptedDirectMessage => {
+            let receiver = event.tags.public_keys().next().copied()
+                .ok_or_else(|| "Direct message has no recipient".to_string())?;
+            let peer = if event.pubkey == keys.public_key() { receiver } else { event.pubkey };
+            let content = nip04::decrypt(keys.secret_key(), &peer, &event.content)
+                .map_err(|e| format!("Decryption failed: {}", e))?;
+            audit::record(KeyOperation::Nip04Decrypt, &keys.public_key().to_hex(), Some(event.kind.as_u16()));
+            (event.kind.as_u16(), event.pubkey, content, event.created_at, vec![event.pubkey, receiver])
+        }
+        Kind::GiftWrap => {
+            let (sender, rumor) = unwrap_gift_wrap(&keys, &event)?;
+            let kind = rumor.kind.as_u16();
+            if kind != PRIVATE_DM_KIND && kind != PRIVATE_FILE_KIND {
+                return Err(format!("Not a direct message: kind {}", kind));
+            }
+            let content = if kind == PRIVATE_FILE_KIND { String::new() } else { rumor.content.clone() };
+            let receivers: Vec<PublicKey> = rumor.tags.public_keys().copied().collect();
+            (kind, sender, content, rumor.created_at, [vec![sender], receivers].concat())
+        }
+        kind => return Err(format!("Not a direct message: kind {}", kind)),
+    };
+    participants.push(keys.public_key());
+    
+    let sender_name = resolve_display_name(sender.to_hex())?.name;
+    Ok(DmNotification {
+        event_id: event.id.to_hex(),
+        kind,
+        sender: sender.to_hex(),
+        sender_name,
+        snippet: notification_snippet(&content),
+        conversation_id: conversation_id(&participants),
+        created_at: created_at.as_u64(),
+        from_self: sender == keys.public_key(),
+    })
+}
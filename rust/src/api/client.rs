@@ -0,0 +1,1288 @@
+use nostr_sdk::prelude::*;
+use nostr_database::prelude::{Filter as DbFilter, JsonUtil as DbJsonUtil, PublicKey as DbPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use crate::frb_generated::StreamSink;
+use super::conflicts::save_synced_event;
+use super::hooks;
+use super::nostr::{dm_relays_from_event, DM_RELAY_LIST_KIND};
+use super::kv;
+use super::relay_scores;
+use super::relay::{get_or_create_runtime, query_local_events_async, run_async_with_timeout, run_blocking, run_blocking_with_timeout};
+use super::system::call_timeout;
+use super::watchdog::{emit, RelayStatusEvent};
+
+// Global client used for long-lived subscriptions
+static CLIENT: Mutex<Option<Client>> = Mutex::new(None);
+static SUBSCRIPTIONS: Mutex<Option<HashMap<String, ManagedSubscription>>> = Mutex::new(None);
+static BUDGET: Mutex<BandwidthBudget> = Mutex::new(BandwidthBudget::new());
+static BUDGET_SINK: Mutex<Option<StreamSink<BudgetEvent>>> = Mutex::new(None);
+static CONNECTION_LIMITS: Mutex<ConnectionLimits> = Mutex::new(ConnectionLimits::DEFAULT);
+static FILTER_CLAMP: Mutex<FilterClampPolicy> = Mutex::new(FilterClampPolicy::DEFAULT);
+// Relay URL -> last time a message was received on the global client
+static RELAY_ACTIVITY: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+// Shared HTTP client (NIP-11 fetches), rebuilt when the limits change
+static HTTP_CLIENT: Mutex<Option<reqwest::Client>> = Mutex::new(None);
+// Host -> (resolved at, addresses)
+static DNS_CACHE: Mutex<Option<HashMap<String, (u64, Vec<SocketAddr>)>>> = Mutex::new(None);
+
+/// Length of the bandwidth accounting window
+const BUDGET_WINDOW_SECS: u64 = 3600;
+
+/// Priority of a subscription, used when the bandwidth budget is exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubscriptionPriority {
+    /// Paused when the budget is exceeded
+    Low,
+    Normal,
+    High,
+}
+
+struct ManagedSubscription {
+    filter: Filter,
+    priority: SubscriptionPriority,
+    bytes_used: u64,
+    paused: bool,
+    sink: StreamSink<String>,
+}
+
+struct BandwidthBudget {
+    bytes_per_hour: Option<u64>,
+    window_started_at: u64,
+    used: u64,
+    exceeded: bool,
+}
+
+impl BandwidthBudget {
+    const fn new() -> Self {
+        Self {
+            bytes_per_hour: None,
+            window_started_at: 0,
+            used: 0,
+            exceeded: false,
+        }
+    }
+    
+    /// Start a new window if the current one is over, returns true if the budget was reset
+    fn roll_window(&mut self, now: u64) -> bool {
+        if now.saturating_sub(self.window_started_at) < BUDGET_WINDOW_SECS {
+            return false;
+        }
+        self.window_started_at = now;
+        self.used = 0;
+        std::mem::replace(&mut self.exceeded, false)
+    }
+}
+
+/// Bandwidth budget notification sent to Dart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetEvent {
+    /// True when the budget was exceeded, false when a new window started
+    pub exceeded: bool,
+    /// Subscriptions that were paused (exceeded) or resumed (reset)
+    pub subscription_ids: Vec<String>,
+    pub used_bytes: u64,
+    pub budget_bytes: Option<u64>,
+}
+
+/// Bandwidth usage of one subscription in the current client session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionUsage {
+    pub subscription_id: String,
+    pub priority: SubscriptionPriority,
+    pub bytes_used: u64,
+    pub paused: bool,
+}
+
+/// Bandwidth usage in the current budget window
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BandwidthUsage {
+    pub budget_bytes: Option<u64>,
+    pub used_bytes: u64,
+    pub window_started_at: u64,
+    pub subscriptions: Vec<SubscriptionUsage>,
+}
+
+/// Outcome of publishing one event to remote relays
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublishOutcome {
+    pub event_id: String,
+    pub kind: u16,
+    pub accepted_relays: Vec<String>,
+    /// Failures formatted as "<relay url>: <reason>"
+    pub failed_relays: Vec<String>,
+}
+
+/// Limits on outbound connections
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ConnectionLimits {
+    /// Maximum number of relays a client connects to at once (0 = unlimited), the relays left
+    /// out are reported as `RelayStatusEvent::RelaysDropped`
+    pub max_relay_connections: u32,
+    /// Idle HTTP connections kept open per host for reuse (0 = close after each request)
+    pub max_idle_per_host: u32,
+    /// How long resolved host addresses are reused (0 = resolve every time)
+    ///
+    /// Only HTTP requests (NIP-05, NIP-11, uploads, ...) go through this cache, relay
+    /// WebSockets resolve host names on every connection.
+    pub dns_cache_ttl_secs: u32,
+}
+
+impl ConnectionLimits {
+    const DEFAULT: Self = Self {
+        max_relay_connections: 0,
+        max_idle_per_host: 2,
+        dns_cache_ttl_secs: 300,
+    };
+}
+
+fn connection_limits() -> ConnectionLimits {
+    CONNECTION_LIMITS.lock().map(|limits| *limits).unwrap_or(ConnectionLimits::DEFAULT)
+}
+
+/// Set the outbound connection limits
+///
+/// Applies to connections opened afterwards; already connected relays are kept.
+pub fn set_connection_limits(limits: ConnectionLimits) -> Result<(), String> {
+    *CONNECTION_LIMITS.lock()
+        .map_err(|e| format!("Failed to lock connection limits: {}", e))? = limits;
+    
+    // Rebuild the HTTP client and drop cached addresses with the next request
+    if let Ok(mut http) = HTTP_CLIENT.lock() {
+        *http = None;
+    }
+    if let Ok(mut cache) = DNS_CACHE.lock() {
+        *cache = None;
+    }
+    Ok(())
+}
+
+pub fn get_connection_limits() -> ConnectionLimits {
+    connection_limits()
+}
+
+/// Deduplicate relay URLs, returns the relays within the connection limit and the ones over it
+fn split_relays(relays: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut seen = HashSet::new();
+    let mut unique: Vec<String> = relays.iter()
+        .filter(|url| seen.insert(url.trim_end_matches('/').to_string()))
+        .cloned()
+        .collect();
+    
+    let max = connection_limits().max_relay_connections as usize;
+    let dropped = if max > 0 && unique.len() > max { unique.split_off(max) } else { Vec::new() };
+    (unique, dropped)
+}
+
+/// Relays to connect to, reporting the ones dropped by the connection limit
+fn limit_relays(relays: &[String]) -> Vec<String> {
+    let (kept, dropped) = split_relays(relays);
+    if !dropped.is_empty() {
+        tracing::warn!("Connecting to {} of {} relays (connection limit)", kept.len(), kept.len() + dropped.len());
+        emit(RelayStatusEvent::RelaysDropped { relays: dropped });
+    }
+    kept
+}
+
+/// What to do with filters that would fetch a relay's whole history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilterClampMode {
+    /// Send filters as given
+    Off,
+    /// Rewrite unbounded filters with a `since` and a `limit`
+    Clamp,
+    /// Refuse unbounded filters
+    Reject,
+}
+
+/// Rewriting of subscription filters before they are sent to relays
+///
+/// A filter is unbounded when it has no `ids`, no `since` and no `limit`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct FilterClampPolicy {
+    pub mode: FilterClampMode,
+    /// `since` given to unbounded filters, as seconds before now
+    pub max_history_secs: u64,
+    /// `limit` given to unbounded filters, and the highest limit allowed (0 = any limit)
+    pub max_limit: u32,
+}
+
+impl FilterClampPolicy {
+    const DEFAULT: Self = Self {
+        mode: FilterClampMode::Clamp,
+        max_history_secs: 7 * 24 * 60 * 60,
+        max_limit: 500,
+    };
+}
+
+/// Set how filters requesting unbounded history are rewritten
+pub fn set_filter_clamp_policy(policy: FilterClampPolicy) -> Result<(), String> {
+    *FILTER_CLAMP.lock()
+        .map_err(|e| format!("Failed to lock filter clamp policy: {}", e))? = policy;
+    Ok(())
+}
+
+pub fn get_filter_clamp_policy() -> FilterClampPolicy {
+    FILTER_CLAMP.lock().map(|policy| *policy).unwrap_or(FilterClampPolicy::DEFAULT)
+}
+
+/// Apply the filter clamp policy to a filter about to be sent
+fn clamp_filter(mut filter: Filter) -> Result<Filter, String> {
+    let policy = get_filter_clamp_policy();
+    if policy.mode == FilterClampMode::Off {
+        return Ok(filter);
+    }
+    
+    // A zero since (e.g. a fresh sync cursor) doesn't bound anything
+    let unbounded = filter.ids.as_ref().map_or(true, |ids| ids.is_empty())
+        && filter.since.map_or(true, |since| since.as_u64() == 0)
+        && filter.limit.is_none();
+    let max_limit = policy.max_limit as usize;
+    
+    match policy.mode {
+        FilterClampMode::Reject if unbounded => {
+            Err("Filter requests unbounded history, add since or limit".to_string())
+        }
+        FilterClampMode::Reject => Ok(filter),
+        _ => {
+            if unbounded {
+                let since = Timestamp::now().as_u64().saturating_sub(policy.max_history_secs);
+                filter = filter.since(Timestamp::from(since));
+                tracing::warn!("Clamped unbounded filter to since {}", since);
+            }
+            if max_limit > 0 && filter.limit.map_or(unbounded, |limit| limit > max_limit) {
+                filter = filter.limit(max_limit);
+            }
+            Ok(filter)
+        }
+    }
+}
+
+/// Apply the filter clamp policy's limit to a sync filter
+///
+/// Sync filters are bounded by their cursor: a fresh cursor pages back through the whole
+/// history `max_limit` events at a time, so `since` isn't clamped and Reject doesn't apply.
+fn clamp_sync_filter(filter: Filter) -> Filter {
+    let policy = get_filter_clamp_policy();
+    let max_limit = policy.max_limit as usize;
+    if policy.mode == FilterClampMode::Off || max_limit == 0 {
+        return filter;
+    }
+    if filter.limit.map_or(true, |limit| limit > max_limit) {
+        filter.limit(max_limit)
+    } else {
+        filter
+    }
+}
+
+/// Resolves host names through a cache honouring `dns_cache_ttl_secs`
+struct CachingResolver;
+
+impl reqwest::dns::Resolve for CachingResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: reqwest::dns::Addrs = Box::new(resolve_host(&host).await?.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+async fn resolve_host(host: &str) -> Result<Vec<SocketAddr>, Box<dyn std::error::Error + Send + Sync>> {
+    let ttl = connection_limits().dns_cache_ttl_secs as u64;
+    let now = Timestamp::now().as_u64();
+    
+    if ttl > 0 {
+        let cached = DNS_CACHE.lock().ok().and_then(|cache| {
+            cache.as_ref()?
+                .get(host)
+                .filter(|(resolved_at, _)| now.saturating_sub(*resolved_at) < ttl)
+                .map(|(_, addrs)| addrs.clone())
+        });
+        if let Some(addrs) = cached {
+            return Ok(addrs);
+        }
+    }
+    
+    // The port is replaced by the one of the request URL
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0)).await?.collect();
+    if ttl > 0 {
+        if let Ok(mut cache) = DNS_CACHE.lock() {
+            cache.get_or_insert_with(HashMap::new).insert(host.to_string(), (now, addrs.clone()));
+        }
+    }
+    Ok(addrs)
+}
+
+/// Shared HTTP client, reusing connections and resolved addresses across requests
+pub(crate) fn http_client() -> Result<reqwest::Client, String> {
+    let mut http_guard = HTTP_CLIENT.lock()
+        .map_err(|e| format!("Failed to lock HTTP client: {}", e))?;
+    if let Some(client) = http_guard.as_ref() {
+        return Ok(client.clone());
+    }
+    
+    let client = reqwest::Client::builder()
+        .pool_max_idle_per_host(connection_limits().max_idle_per_host as usize)
+        .dns_resolver(Arc::new(CachingResolver))
+        .build()
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+    *http_guard = Some(client.clone());
+    
+    Ok(client)
+}
+
+fn record_relay_activity(relay_url: &str) {
+    if let Ok(mut activity) = RELAY_ACTIVITY.lock() {
+        activity.get_or_insert_with(HashMap::new).insert(relay_url.to_string(), Timestamp::now().as_u64());
+    }
+}
+
+/// Create a client connected to the given relays (up to the connection limit)
+pub(crate) async fn connect_client(relays: &[String], keys: Option<Keys>) -> Result<Client, String> {
+    let client = match keys {
+        Some(keys) => Client::new(keys),
+        None => Client::default(),
+    };
+    
+    for url in limit_relays(relays).iter() {
+        client.add_relay(url.as_str())
+            .await
+            .map_err(|e| format!("Invalid relay URL '{}': {}", url, e))?;
+    }
+    client.connect().await;
+    
+    Ok(client)
+}
+
+/// Publish an already signed event and collect per-relay results
+pub(crate) async fn publish_event(client: &Client, event: &Event) -> PublishOutcome {
+    let mut outcome = PublishOutcome {
+        event_id: event.id.to_hex(),
+        kind: event.kind.as_u16(),
+        accepted_relays: Vec::new(),
+        failed_relays: Vec::new(),
+    };
+    
+    match client.send_event(event).await {
+        Ok(output) => {
+            let dm = event.kind == Kind::GiftWrap;
+            for url in output.success.iter() {
+                relay_scores::record_write(url.as_str(), true, dm);
+            }
+            for url in output.failed.keys() {
+                relay_scores::record_write(url.as_str(), false, dm);
+            }
+            
+            outcome.accepted_relays = output.success.iter().map(|url| url.to_string()).collect();
+            outcome.failed_relays = output.failed.iter()
+                .map(|(url, reason)| format!("{}: {}", url, reason))
+                .collect();
+        }
+        Err(e) => outcome.failed_relays.push(e.to_string()),
+    }
+    
+    if !outcome.accepted_relays.is_empty() && hooks::wants_ack(&event.pubkey.to_hex(), outcome.kind, true) {
+        emit(RelayStatusEvent::EventMirrored {
+            event_id: outcome.event_id.clone(),
+            kind: outcome.kind,
+            relays: outcome.accepted_relays.clone(),
+        });
+    }
+    outcome
+}
+
+/// Latest replaceable (kind 0, 3, 10000-19999) and addressable (30000-39999) events of an author
+///
+/// Of two versions with the same created_at, the one with the lowest id is kept (NIP-01).
+pub(crate) fn latest_replaceable_events(events_json: Vec<String>) -> Result<Vec<Event>, String> {
+    let mut latest: HashMap<(u16, String), Event> = HashMap::new();
+    
+    for json in events_json {
+        let event = Event::from_json(&json)
+            .map_err(|e| format!("Invalid stored event: {}", e))?;
+        if !event.kind.is_replaceable() && !event.kind.is_addressable() {
+            continue;
+        }
+        
+        let d_tag = if event.kind.is_addressable() {
+            event.tags.identifier().unwrap_or_default().to_string()
+        } else {
+            String::new()
+        };
+        
+        let key = (event.kind.as_u16(), d_tag);
+        let newer = latest.get(&key).map_or(true, |current| {
+            (event.created_at, std::cmp::Reverse(event.id)) > (current.created_at, std::cmp::Reverse(current.id))
+        });
+        if newer {
+            latest.insert(key, event);
+        }
+    }
+    
+    let mut events: Vec<Event> = latest.into_values().collect();
+    events.sort_by_key(|event| event.kind.as_u16());
+    Ok(events)
+}
+
+/// Republish the user's latest replaceable and addressable events from the local database
+///
+/// Useful after restoring a backup on a new device so remote relays converge on the
+/// restored profile (kind 0), follow list (kind 3), relay list (kind 10002), etc.
+///
+/// # Arguments
+/// * `relays` - Remote relay URLs to publish to
+/// * `secret` - Private key (hex) of the user, also used for NIP-42 auth
+pub fn republish_replaceables(relays: Vec<String>, secret: String) -> Result<Vec<PublishOutcome>, String> {
+    let keys = Keys::from_str(&secret)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let author = keys.public_key();
+    let events = run_blocking(async move { stored_replaceables(&author).await })??;
+    
+    run_blocking_with_timeout(republish_timeout(events.len()), publish_all(relays, keys, events))?
+}
+
+/// Latest replaceable and addressable events of an author in the local database
+async fn stored_replaceables(author: &PublicKey) -> Result<Vec<Event>, String> {
+    let author = DbPublicKey::from_hex(&author.to_hex())
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let events = query_local_events_async(DbFilter::new().author(author)).await?;
+    latest_replaceable_events(events.iter().map(|event| event.as_json()).collect())
+}
+
+/// Publishing waits for each relay, allow more than a single call
+fn republish_timeout(events: usize) -> std::time::Duration {
+    call_timeout() * (events as u32).clamp(1, 10)
+}
+
+async fn publish_all(relays: Vec<String>, keys: Keys, events: Vec<Event>) -> Result<Vec<PublishOutcome>, String> {
+    let client = connect_client(&relays, Some(keys)).await?;
+    
+    let mut outcomes = Vec::with_capacity(events.len());
+    for event in events.iter() {
+        outcomes.push(publish_event(&client, event).await);
+    }
+    
+    client.disconnect().await;
+    Ok(outcomes)
+}
+
+pub async fn client_republish_replaceables(relays: Vec<String>, secret: String) -> Result<Vec<PublishOutcome>, String> {
+    let keys = Keys::from_str(&secret)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let author = keys.public_key();
+    let events = run_async_with_timeout(call_timeout(), async move { stored_replaceables(&author).await }).await??;
+    
+    run_async_with_timeout(republish_timeout(events.len()), publish_all(relays, keys, events)).await?
+}
+
+/// Timeout when fetching events from remote relays
+const FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Find the NIP-17 DM relays of a user, from the local database or the lookup relays
+pub(crate) async fn find_dm_relays(receiver: &PublicKey, lookup_relays: &[String]) -> Result<Vec<String>, String> {
+    let latest = |events: Vec<Event>| events.into_iter().max_by_key(|event| event.created_at);
+    
+    // Local database first (only available while the relay is running)
+    let local_filter = DbFilter::new()
+        .author(DbPublicKey::from_hex(&receiver.to_hex()).map_err(|e| format!("Invalid public key: {}", e))?)
+        .kind(nostr_database::prelude::Kind::from(DM_RELAY_LIST_KIND));
+    let local_events: Vec<Event> = query_local_events_async(local_filter)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|event| Event::from_json(event.as_json()).ok())
+        .collect();
+    if let Some(event) = latest(local_events) {
+        return Ok(dm_relays_from_event(&event));
+    }
+    
+    if lookup_relays.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    let client = connect_client(lookup_relays, None).await?;
+    let filter = Filter::new()
+        .author(*receiver)
+        .kind(Kind::from(DM_RELAY_LIST_KIND))
+        .limit(1);
+    let events = client.fetch_events(filter, FETCH_TIMEOUT).await;
+    client.disconnect().await;
+    
+    let events = events.map_err(|e| format!("Failed to fetch DM relay list: {}", e))?;
+    Ok(latest(events.into_iter().collect())
+        .map(|event| dm_relays_from_event(&event))
+        .unwrap_or_default())
+}
+
+/// Publish a gift-wrapped DM to the DM relays (kind 10050) declared by its receiver
+///
+/// # Arguments
+/// * `event_json` - Signed gift wrap (kind 1059) event
+/// * `receiver_pubkey` - Hex public key of the receiver
+/// * `lookup_relays` - Relays used to fetch the receiver's DM relay list if it's not cached locally
+pub fn publish_to_dm_relays(
+    event_json: String,
+    receiver_pubkey: String,
+    lookup_relays: Vec<String>,
+) -> Result<PublishOutcome, String> {
+    let (event, receiver) = parse_gift_wrap(&event_json, &receiver_pubkey)?;
+    run_blocking(async move { publish_gift_wrap(&event, &receiver, &lookup_relays).await })?
+}
+
+fn parse_gift_wrap(event_json: &str, receiver_pubkey: &str) -> Result<(Event, PublicKey), String> {
+    let event = Event::from_json(event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    let receiver = PublicKey::from_hex(receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    Ok((event, receiver))
+}
+
+async fn publish_gift_wrap(event: &Event, receiver: &PublicKey, lookup_relays: &[String]) -> Result<PublishOutcome, String> {
+    let dm_relays = find_dm_relays(receiver, lookup_relays).await?;
+    if dm_relays.is_empty() {
+        return Err(format!("No DM relay list found for {}", receiver.to_hex()));
+    }
+    
+    let client = connect_client(&dm_relays, None).await?;
+    let outcome = publish_event(&client, event).await;
+    client.disconnect().await;
+    Ok(outcome)
+}
+
+/// Send a private direct message (NIP-17): build it with `send_private_dm` and publish the
+/// receiver's gift wrap to the receiver's DM relays and the sender's copy to the sender's
+///
+/// Returns the outcome of each gift wrap, the receiver's first. A missing DM relay list
+/// fails the whole call for the receiver, only skips the copy for the sender.
+///
+/// # Arguments
+/// * `content` - Message text
+/// * `receiver_pubkey` - Hex public key of the receiver
+/// * `private_key` - Hex private key of the sender
+/// * `reply_to` - Id of the message replied to
+/// * `lookup_relays` - Relays used to fetch DM relay lists that are not cached locally
+pub fn send_private_dm_to_relays(
+    content: String,
+    receiver_pubkey: String,
+    private_key: String,
+    reply_to: Option<String>,
+    lookup_relays: Vec<String>,
+) -> Result<Vec<PublishOutcome>, String> {
+    let (wraps, receiver, sender) = build_private_dm(content, receiver_pubkey, private_key, reply_to)?;
+    run_blocking(publish_private_dm(wraps, receiver, sender, lookup_relays))?
+}
+
+/// Gift wraps of a NIP-17 DM (the receiver's first), with the receiver and the sender
+fn build_private_dm(
+    content: String,
+    receiver_pubkey: String,
+    private_key: String,
+    reply_to: Option<String>,
+) -> Result<(Vec<Event>, PublicKey, PublicKey), String> {
+    let receiver = PublicKey::from_hex(&receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let sender = Keys::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?
+        .public_key();
+    let wraps = super::chat::send_private_dm(content, receiver_pubkey, private_key, reply_to)?
+        .iter()
+        .map(|json| Event::from_json(json).map_err(|e| format!("Invalid gift wrap: {}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((wraps, receiver, sender))
+}
+
+async fn publish_private_dm(
+    wraps: Vec<Event>,
+    receiver: PublicKey,
+    sender: PublicKey,
+    lookup_relays: Vec<String>,
+) -> Result<Vec<PublishOutcome>, String> {
+    let mut outcomes = vec![publish_gift_wrap(&wraps[0], &receiver, &lookup_relays).await?];
+    match publish_gift_wrap(&wraps[1], &sender, &lookup_relays).await {
+        Ok(outcome) => outcomes.push(outcome),
+        Err(e) => tracing::debug!("Sender copy of DM not published: {}", e),
+    }
+    Ok(outcomes)
+}
+
+pub async fn client_publish_to_dm_relays(
+    event_json: String,
+    receiver_pubkey: String,
+    lookup_relays: Vec<String>,
+) -> Result<PublishOutcome, String> {
+    let (event, receiver) = parse_gift_wrap(&event_json, &receiver_pubkey)?;
+    run_async_with_timeout(call_timeout(), async move {
+        publish_gift_wrap(&event, &receiver, &lookup_relays).await
+    })
+    .await?
+}
+
+pub async fn client_send_private_dm(
+    content: String,
+    receiver_pubkey: String,
+    private_key: String,
+    reply_to: Option<String>,
+    lookup_relays: Vec<String>,
+) -> Result<Vec<PublishOutcome>, String> {
+    let (wraps, receiver, sender) = build_private_dm(content, receiver_pubkey, private_key, reply_to)?;
+    run_async_with_timeout(call_timeout(), publish_private_dm(wraps, receiver, sender, lookup_relays)).await?
+}
+
+/// KV namespace holding sync cursors, keyed by "<cursor name>|<relay url>"
+pub(crate) const SYNC_CURSOR_NAMESPACE: &str = "sync_cursors";
+
+/// Cursor position on one relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayCursor {
+    pub relay_url: String,
+    /// Every event up to this created_at has been fetched from this relay
+    pub since: u64,
+    /// True while older events are still being paged through (a fetch was cut short by the
+    /// limit or the timeout); the next sync continues below the oldest event received
+    pub more: bool,
+    /// Error if this relay could not be synced (its cursor was left unchanged)
+    pub error: Option<String>,
+}
+
+/// Result of a differential sync
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncResult {
+    pub events_received: u32,
+    /// Events that were not yet in the local database
+    pub events_stored: u32,
+    /// Oldest cursor across relays (safe resume point for all of them)
+    pub new_cursor: u64,
+    pub relay_cursors: Vec<RelayCursor>,
+}
+
+fn cursor_key(cursor: &str, relay_url: &str) -> String {
+    format!("{}|{}", cursor, relay_url)
+}
+
+/// Stored cursor of one relay
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct StoredCursor {
+    /// Everything up to here was fetched
+    since: u64,
+    /// While paging backwards: (upper bound of the next page, newest created_at seen)
+    paging: Option<(u64, u64)>,
+}
+
+impl StoredCursor {
+    /// "<since>" or "<since>:<until>:<newest>" while paging
+    fn parse(value: &str) -> Self {
+        let mut parts = value.split(':').map(|part| part.parse::<u64>().ok());
+        match (parts.next().flatten(), parts.next().flatten(), parts.next().flatten()) {
+            (Some(since), Some(until), Some(newest)) => StoredCursor { since, paging: Some((until, newest)) },
+            (Some(since), _, _) => StoredCursor { since, paging: None },
+            _ => StoredCursor::default(),
+        }
+    }
+    
+    fn to_value(self) -> String {
+        match self.paging {
+            Some((until, newest)) => format!("{}:{}:{}", self.since, until, newest),
+            None => self.since.to_string(),
+        }
+    }
+    
+    /// Next cursor after fetching a page; `oldest` and `newest` are None when the page was empty
+    fn advance(self, complete: bool, oldest: Option<u64>, newest: Option<u64>) -> Self {
+        let seen = self.paging.map(|(_, seen)| seen).into_iter().chain(newest).max();
+        match oldest {
+            // Timed out before anything arrived, try the same page again
+            None if !complete => self,
+            // A cut-short page that made progress, continue below its oldest event
+            Some(oldest) if !complete && self.paging.map_or(true, |(until, _)| oldest < until) => StoredCursor {
+                since: self.since,
+                paging: Some((oldest, seen.unwrap_or(oldest))),
+            },
+            // Complete (or more events at one timestamp than a page holds): the gap is closed
+            _ => StoredCursor {
+                since: seen.unwrap_or(self.since).max(self.since),
+                paging: None,
+            },
+        }
+    }
+}
+
+/// Fetch only events newer than the stored per-relay cursor and store them locally
+///
+/// Cursors are persisted in the KV store, so repeated calls are cheap. Events at the cursor
+/// timestamp are fetched again (duplicates are ignored by the database), so no event with the
+/// same created_at as the newest seen one is ever missed.
+///
+/// A cursor only moves forward once everything up to it was fetched. When a fetch is cut
+/// short by the filter limit or the timeout, the next calls page backwards from the oldest
+/// event received (`RelayCursor::more` is set until the gap is closed).
+///
+/// Addressable events differing from the local version are resolved with the policy of
+/// their kind (see `set_conflict_policy`) and reported to `client_sync_conflicts`.
+///
+/// # Arguments
+/// * `relays` - Remote relay URLs
+/// * `filter_json` - NIP-01 filter (its `since` is overridden by the cursor)
+/// * `cursor` - Name of the cursor (e.g. "timeline" or "profile:<pubkey>")
+pub fn sync_since(relays: Vec<String>, filter_json: String, cursor: String) -> Result<SyncResult, String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    run_blocking_with_timeout(sync_timeout(relays.len()), async move { sync_cursor(&relays, filter, &cursor).await })?
+}
+
+/// Relays are fetched one after the other
+fn sync_timeout(relays: usize) -> std::time::Duration {
+    call_timeout().max(FETCH_TIMEOUT * relays as u32 + FETCH_TIMEOUT)
+}
+
+/// Fetch events newer than the stored per-relay cursor, see `sync_since`
+pub(crate) async fn sync_cursor(relays: &[String], filter: Filter, cursor: &str) -> Result<SyncResult, String> {
+    let client = connect_client(relays, None).await?;
+    let mut result = SyncResult {
+        events_received: 0,
+        events_stored: 0,
+        new_cursor: u64::MAX,
+        relay_cursors: Vec::with_capacity(relays.len()),
+    };
+    
+    for relay_url in relays.iter() {
+        let key = cursor_key(cursor, relay_url);
+        let stored = kv::kv_get(SYNC_CURSOR_NAMESPACE.to_string(), key.clone())?
+            .map(|value| StoredCursor::parse(&value))
+            .unwrap_or_default();
+        let since = stored.since;
+        
+        let mut relay_filter = filter.clone().since(Timestamp::from(since));
+        if let Some((until, _)) = stored.paging {
+            relay_filter = relay_filter.until(Timestamp::from(until));
+        }
+        let relay_filter = clamp_sync_filter(relay_filter);
+        let limit = relay_filter.limit;
+        let started = std::time::Instant::now();
+        let events: Vec<Event> = match client.fetch_events_from([relay_url.as_str()], relay_filter, FETCH_TIMEOUT).await {
+            Ok(events) => {
+                let events = events.into_iter().collect();
+                relay_scores::record_read(relay_url, Some(&events), started.elapsed());
+                events
+            }
+            Err(e) => {
+                relay_scores::record_read(relay_url, None, started.elapsed());
+                result.new_cursor = result.new_cursor.min(since);
+                result.relay_cursors.push(RelayCursor {
+                    relay_url: relay_url.clone(),
+                    since,
+                    more: stored.paging.is_some(),
+                    error: Some(e.to_string()),
+                });
+                continue;
+            }
+        };
+        
+        // Cut short by the limit or the timeout: older events may be missing
+        let complete = limit.map_or(true, |limit| events.len() < limit) && started.elapsed() < FETCH_TIMEOUT;
+        let mut oldest: Option<u64> = None;
+        let mut newest: Option<u64> = None;
+        for event in events.into_iter() {
+            result.events_received += 1;
+            let created_at = event.created_at.as_u64();
+            oldest = Some(oldest.map_or(created_at, |oldest| oldest.min(created_at)));
+            newest = Some(newest.map_or(created_at, |newest| newest.max(created_at)));
+            if save_synced_event(&event.as_json()).await? {
+                result.events_stored += 1;
+            }
+        }
+        
+        let next = stored.advance(complete, oldest, newest);
+        kv::kv_set(SYNC_CURSOR_NAMESPACE.to_string(), key, next.to_value())?;
+        result.new_cursor = result.new_cursor.min(next.since);
+        result.relay_cursors.push(RelayCursor {
+            relay_url: relay_url.clone(),
+            since: next.since,
+            more: next.paging.is_some(),
+            error: None,
+        });
+    }
+    
+    client.disconnect().await;
+    if result.relay_cursors.is_empty() {
+        result.new_cursor = 0;
+    }
+    Ok(result)
+}
+
+/// Forget the stored cursors of a sync, so the next `sync_since` starts from scratch
+pub fn reset_sync_cursor(cursor: String) -> Result<(), String> {
+    let prefix = format!("{}|", cursor);
+    for entry in kv::kv_list(SYNC_CURSOR_NAMESPACE.to_string(), Some(prefix))? {
+        kv::kv_delete(SYNC_CURSOR_NAMESPACE.to_string(), entry.key)?;
+    }
+    Ok(())
+}
+
+pub async fn client_sync_since(relays: Vec<String>, filter_json: String, cursor: String) -> Result<SyncResult, String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    run_async_with_timeout(sync_timeout(relays.len()), async move { sync_cursor(&relays, filter, &cursor).await }).await?
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_reset_sync_cursor(cursor: String) -> Result<(), String> {
+    reset_sync_cursor(cursor)
+}
+
+/// KV namespace holding signed events waiting to be published, keyed by event id
+pub(crate) const OUTBOX_NAMESPACE: &str = "outbox";
+
+/// Queue a signed event for publishing by the next outbox flush
+///
+/// Events stay queued until at least one relay accepted them, so they survive the app
+/// being killed while offline.
+pub fn queue_outbox(event_json: String) -> Result<String, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    event.verify()
+        .map_err(|e| format!("Invalid event: {}", e))?;
+    
+    let event_id = event.id.to_hex();
+    kv::kv_set(OUTBOX_NAMESPACE.to_string(), event_id.clone(), event_json)?;
+    Ok(event_id)
+}
+
+/// Number of events waiting in the outbox
+pub fn outbox_len() -> Result<u32, String> {
+    Ok(kv::kv_list(OUTBOX_NAMESPACE.to_string(), None)?.len() as u32)
+}
+
+/// Publish the queued events, removing the ones accepted by at least one relay
+pub(crate) async fn flush_outbox(relays: &[String]) -> Result<Vec<PublishOutcome>, String> {
+    let entries = kv::kv_list(OUTBOX_NAMESPACE.to_string(), None)?;
+    if entries.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    let client = connect_client(relays, None).await?;
+    let mut outcomes = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let event = match Event::from_json(&entry.value) {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("Dropping invalid outbox event {}: {}", entry.key, e);
+                kv::kv_delete(OUTBOX_NAMESPACE.to_string(), entry.key)?;
+                continue;
+            }
+        };
+        
+        let outcome = publish_event(&client, &event).await;
+        if !outcome.accepted_relays.is_empty() {
+            kv::kv_delete(OUTBOX_NAMESPACE.to_string(), entry.key)?;
+        }
+        outcomes.push(outcome);
+    }
+    
+    client.disconnect().await;
+    Ok(outcomes)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_queue_outbox(event_json: String) -> Result<String, String> {
+    queue_outbox(event_json)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_outbox_len() -> Result<u32, String> {
+    outbox_len()
+}
+
+pub async fn client_flush_outbox(relays: Vec<String>) -> Result<Vec<PublishOutcome>, String> {
+    let timeout = call_timeout() * 2;
+    run_async_with_timeout(timeout, async move { flush_outbox(&relays).await }).await?
+}
+
+/// Get the global client
+pub(crate) fn get_client() -> Result<Client, String> {
+    let client_guard = CLIENT.lock()
+        .map_err(|e| format!("Failed to lock client: {}", e))?;
+    client_guard
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "Client is not connected".to_string())
+}
+
+/// Connect the global client to remote relays
+///
+/// # Arguments
+/// * `relays` - Remote relay URLs
+/// * `secret` - Optional private key (hex or nsec) used for NIP-42 auth
+pub fn connect(relays: Vec<String>, secret: Option<String>) -> Result<(), String> {
+    let keys = connect_keys(secret)?;
+    let relays_for_activity = relays.clone();
+    let client = run_blocking(async move { connect_client(&relays, keys).await })??;
+    install_client(client, &relays_for_activity)
+}
+
+/// Keys of a new global client, failing early if one is already connected
+fn connect_keys(secret: Option<String>) -> Result<Option<Keys>, String> {
+    if CLIENT.lock().map_err(|e| format!("Failed to lock client: {}", e))?.is_some() {
+        return Err("Client is already connected".to_string());
+    }
+    secret
+        .map(|secret| Keys::from_str(&secret).map_err(|e| format!("Invalid private key: {}", e)))
+        .transpose()
+}
+
+/// Make a newly connected client the global client, shutting it down if another call
+/// connected one in the meantime
+fn install_client(client: Client, relays: &[String]) -> Result<(), String> {
+    let runtime = get_or_create_runtime()?;
+    let mut client_guard = CLIENT.lock()
+        .map_err(|e| format!("Failed to lock client: {}", e))?;
+    if client_guard.is_some() {
+        runtime.spawn(async move { client.shutdown().await });
+        return Err("Client is already connected".to_string());
+    }
+    
+    // Already reported by `connect_client`
+    for url in split_relays(relays).0.iter() {
+        record_relay_activity(url);
+    }
+    
+    // Dispatch subscription events and keep the budget window rolling
+    let mut notifications = client.notifications();
+    let loop_client = client.clone();
+    runtime.spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            tokio::select! {
+                notification = notifications.recv() => match notification {
+                    Ok(RelayPoolNotification::Event { subscription_id, event, .. }) => {
+                        on_subscription_event(&loop_client, subscription_id.to_string(), event.as_json()).await;
+                    }
+                    Ok(RelayPoolNotification::Message { relay_url, .. }) => record_relay_activity(relay_url.as_str()),
+                    Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => break,
+                    Ok(_) | Err(RecvError::Lagged(_)) => {}
+                },
+                _ = ticker.tick() => check_budget_window(&loop_client).await,
+            }
+        }
+    });
+    
+    *client_guard = Some(client);
+    Ok(())
+}
+
+/// Disconnect the global client and drop all subscriptions
+pub fn disconnect() -> Result<(), String> {
+    let client = CLIENT.lock()
+        .map_err(|e| format!("Failed to lock client: {}", e))?
+        .take()
+        .ok_or_else(|| "Client is not connected".to_string())?;
+    
+    if let Ok(mut subs) = SUBSCRIPTIONS.lock() {
+        *subs = None;
+    }
+    if let Ok(mut activity) = RELAY_ACTIVITY.lock() {
+        *activity = None;
+    }
+    
+    run_blocking(async move { client.shutdown().await })?;
+    Ok(())
+}
+
+/// Subscribe on the global client, streaming matching events (JSON) to Dart
+///
+/// # Arguments
+/// * `subscription_id` - Caller-chosen id, used to unsubscribe and in usage reports
+/// * `filter_json` - NIP-01 filter (unbounded filters are rewritten, see `set_filter_clamp_policy`)
+/// * `priority` - Low priority subscriptions are paused when the bandwidth budget is exceeded
+pub fn client_subscribe(
+    subscription_id: String,
+    filter_json: String,
+    priority: SubscriptionPriority,
+    sink: StreamSink<String>,
+) -> Result<(), String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    let filter = clamp_filter(filter)?;
+    let client = get_client()?;
+    
+    // Don't start low priority subscriptions while over budget
+    let paused = priority == SubscriptionPriority::Low && BUDGET.lock()
+        .map_err(|e| format!("Failed to lock budget: {}", e))?
+        .exceeded;
+    
+    if !paused {
+        let id = SubscriptionId::new(subscription_id.clone());
+        let sub_filter = filter.clone();
+        run_blocking(async move {
+            reconnect_idle_relays(&client).await;
+            client.subscribe_with_id(id, sub_filter, None)
+                .await
+                .map_err(|e| format!("Failed to subscribe: {}", e))
+        })??;
+    }
+    
+    let mut subs = SUBSCRIPTIONS.lock()
+        .map_err(|e| format!("Failed to lock subscriptions: {}", e))?;
+    subs.get_or_insert_with(HashMap::new).insert(subscription_id, ManagedSubscription {
+        filter,
+        priority,
+        bytes_used: 0,
+        paused,
+        sink,
+    });
+    
+    Ok(())
+}
+
+/// Reconnect the relays closed by `disconnect_idle_relays`
+async fn reconnect_idle_relays(client: &Client) {
+    for (url, relay) in client.relays().await {
+        if !relay.status().is_connected() {
+            record_relay_activity(url.as_str());
+        }
+    }
+    client.connect().await;
+}
+
+/// Disconnect the relays of the global client that received nothing for `after_secs`
+///
+/// Lets the radio go idle while nothing is happening. Subscriptions on these relays stop
+/// receiving events until the next `client_subscribe`, which reconnects them.
+///
+/// Returns the URLs of the disconnected relays.
+pub fn disconnect_idle_relays(after_secs: u64) -> Result<Vec<String>, String> {
+    let client = get_client()?;
+    let activity = RELAY_ACTIVITY.lock()
+        .map_err(|e| format!("Failed to lock relay activity: {}", e))?
+        .clone()
+        .unwrap_or_default();
+    let now = Timestamp::now().as_u64();
+    
+    run_blocking(async move {
+        let mut disconnected = Vec::new();
+        for (url, relay) in client.relays().await {
+            if !relay.status().is_connected() {
+                continue;
+            }
+            let last_activity = activity.get(url.as_str()).copied().unwrap_or(0);
+            if now.saturating_sub(last_activity) < after_secs {
+                continue;
+            }
+            client.disconnect_relay(url.as_str())
+                .await
+                .map_err(|e| format!("Failed to disconnect {}: {}", url, e))?;
+            disconnected.push(url.to_string());
+        }
+        Ok(disconnected)
+    })?
+}
+
+/// Close a subscription of the global client
+pub fn client_unsubscribe(subscription_id: String) -> Result<(), String> {
+    let removed = SUBSCRIPTIONS.lock()
+        .map_err(|e| format!("Failed to lock subscriptions: {}", e))?
+        .as_mut()
+        .and_then(|subs| subs.remove(&subscription_id));
+    if removed.is_none() {
+        return Err(format!("Unknown subscription: {}", subscription_id));
+    }
+    
+    let client = get_client()?;
+    let id = SubscriptionId::new(subscription_id);
+    run_blocking(async move { client.unsubscribe(&id).await })?;
+    Ok(())
+}
+
+/// Account the event against the budget and forward it to the subscription sink
+async fn on_subscription_event(client: &Client, subscription_id: String, event_json: String) {
+    let bytes = event_json.len() as u64;
+    
+    {
+        let mut subs = match SUBSCRIPTIONS.lock() {
+            Ok(subs) => subs,
+            Err(_) => return,
+        };
+        let sub = match subs.as_mut().and_then(|subs| subs.get_mut(&subscription_id)) {
+            Some(sub) => sub,
+            None => return,
+        };
+        sub.bytes_used += bytes;
+        let _ = sub.sink.add(event_json);
+    }
+    
+    let (reset, newly_exceeded) = match BUDGET.lock() {
+        Ok(mut budget) => {
+            let reset = budget.roll_window(Timestamp::now().as_u64());
+            budget.used += bytes;
+            let over = budget.bytes_per_hour.map_or(false, |limit| budget.used > limit);
+            (reset, over && !std::mem::replace(&mut budget.exceeded, true))
+        }
+        Err(_) => (false, false),
+    };
+    
+    // The window may roll over here before `check_budget_window` sees it
+    if reset {
+        let resumed = set_low_priority_paused(client, false).await;
+        notify_budget(false, resumed);
+    }
+    if newly_exceeded {
+        let paused = set_low_priority_paused(client, true).await;
+        tracing::warn!("Bandwidth budget exceeded, paused {} subscriptions", paused.len());
+        notify_budget(true, paused);
+    }
+}
+
+/// Resume paused subscriptions when a new budget window starts
+async fn check_budget_window(client: &Client) {
+    let reset = match BUDGET.lock() {
+        Ok(mut budget) => budget.roll_window(Timestamp::now().as_u64()),
+        Err(_) => false,
+    };
+    
+    if reset {
+        let resumed = set_low_priority_paused(client, false).await;
+        notify_budget(false, resumed);
+    }
+}
+
+/// Pause or resume all low priority subscriptions, returns the affected ids
+async fn set_low_priority_paused(client: &Client, paused: bool) -> Vec<String> {
+    let affected: Vec<(String, Filter)> = match SUBSCRIPTIONS.lock() {
+        Ok(mut subs) => subs.get_or_insert_with(HashMap::new)
+            .iter_mut()
+            .filter(|(_, sub)| sub.priority == SubscriptionPriority::Low && sub.paused != paused)
+            .map(|(id, sub)| {
+                sub.paused = paused;
+                (id.clone(), sub.filter.clone())
+            })
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    
+    for (id, filter) in affected.iter() {
+        let sub_id = SubscriptionId::new(id.clone());
+        if paused {
+            client.unsubscribe(&sub_id).await;
+        } else if let Err(e) = client.subscribe_with_id(sub_id, filter.clone(), None).await {
+            tracing::warn!("Failed to resume subscription {}: {}", id, e);
+        }
+    }
+    
+    affected.into_iter().map(|(id, _)| id).collect()
+}
+
+fn notify_budget(exceeded: bool, subscription_ids: Vec<String>) {
+    let (used_bytes, budget_bytes) = match BUDGET.lock() {
+        Ok(budget) => (budget.used, budget.bytes_per_hour),
+        Err(_) => return,
+    };
+    
+    if let Ok(sink) = BUDGET_SINK.lock() {
+        if let Some(sink) = sink.as_ref() {
+            let _ = sink.add(BudgetEvent {
+                exceeded,
+                subscription_ids,
+                used_bytes,
+                budget_bytes,
+            });
+        }
+    }
+}
+
+/// Set the bandwidth budget in bytes per hour (None disables the budget)
+pub fn set_bandwidth_budget(bytes_per_hour: Option<u64>) -> Result<(), String> {
+    let mut budget = BUDGET.lock()
+        .map_err(|e| format!("Failed to lock budget: {}", e))?;
+    budget.bytes_per_hour = bytes_per_hour;
+    Ok(())
+}
+
+/// Get bandwidth usage of the current window (sizes are of the received event JSON)
+pub fn get_bandwidth_usage() -> Result<BandwidthUsage, String> {
+    let (budget_bytes, used_bytes, window_started_at) = {
+        let budget = BUDGET.lock()
+            .map_err(|e| format!("Failed to lock budget: {}", e))?;
+        (budget.bytes_per_hour, budget.used, budget.window_started_at)
+    };
+    
+    let subscriptions = SUBSCRIPTIONS.lock()
+        .map_err(|e| format!("Failed to lock subscriptions: {}", e))?
+        .as_ref()
+        .map(|subs| {
+            subs.iter()
+                .map(|(id, sub)| SubscriptionUsage {
+                    subscription_id: id.clone(),
+                    priority: sub.priority,
+                    bytes_used: sub.bytes_used,
+                    paused: sub.paused,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    Ok(BandwidthUsage {
+        budget_bytes,
+        used_bytes,
+        window_started_at,
+        subscriptions,
+    })
+}
+
+/// Stream budget notifications (exceeded / new window) to Dart
+pub fn client_budget_events(sink: StreamSink<BudgetEvent>) -> Result<(), String> {
+    let mut sink_guard = BUDGET_SINK.lock()
+        .map_err(|e| format!("Failed to lock budget sink: {}", e))?;
+    *sink_guard = Some(sink);
+    Ok(())
+}
+
+pub async fn client_connect(relays: Vec<String>, secret: Option<String>) -> Result<(), String> {
+    let keys = connect_keys(secret)?;
+    let relays_for_activity = relays.clone();
+    let client = run_async_with_timeout(call_timeout(), async move { connect_client(&relays, keys).await }).await??;
+    install_client(client, &relays_for_activity)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_disconnect() -> Result<(), String> {
+    disconnect()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_set_bandwidth_budget(bytes_per_hour: Option<u64>) -> Result<(), String> {
+    set_bandwidth_budget(bytes_per_hour)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_get_bandwidth_usage() -> Result<BandwidthUsage, String> {
+    get_bandwidth_usage()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_set_connection_limits(limits: ConnectionLimits) -> Result<(), String> {
+    set_connection_limits(limits)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_get_connection_limits() -> ConnectionLimits {
+    get_connection_limits()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_disconnect_idle_relays(after_secs: u64) -> Result<Vec<String>, String> {
+    disconnect_idle_relays(after_secs)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_set_filter_clamp_policy(policy: FilterClampPolicy) -> Result<(), String> {
+    set_filter_clamp_policy(policy)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn client_get_filter_clamp_policy() -> FilterClampPolicy {
+    get_filter_clamp_policy()
+}
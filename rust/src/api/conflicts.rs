@@ -0,0 +1,158 @@
+use nostr_database::prelude::{Event, Filter, JsonUtil};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::frb_generated::StreamSink;
+use super::ingest;
+use super::relay::{get_database, save_parsed_event};
+
+// Kind -> policy, kinds without one use `ConflictPolicy::LatestWins`
+static POLICIES: Mutex<Option<HashMap<u16, ConflictPolicy>>> = Mutex::new(None);
+static CONFLICT_SINK: Mutex<Option<StreamSink<SyncConflict>>> = Mutex::new(None);
+
+/// How a synced addressable event differing from the local version is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Keep the version with the newest created_at (NIP-01 behavior)
+    LatestWins,
+    /// Keep the local version, even if the remote one is newer
+    LocalWins,
+    /// Keep the remote version, even if it is older
+    RemoteWins,
+    /// Keep the newest version and the other one in the event history
+    KeepBoth,
+}
+
+/// Which version of the event was kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConflictSide {
+    Local,
+    Remote,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KindConflictPolicy {
+    pub kind: u16,
+    pub policy: ConflictPolicy,
+}
+
+/// Addressable event that differed between the local database and a remote relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+    pub kind: u16,
+    /// Hex public key of the author
+    pub pubkey: String,
+    /// `d` tag of the event
+    pub identifier: String,
+    pub local_event_json: String,
+    pub remote_event_json: String,
+    pub policy: ConflictPolicy,
+    pub kept: ConflictSide,
+}
+
+/// Set the conflict policy of an addressable kind, None to go back to `LatestWins`
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_conflict_policy(kind: u16, policy: Option<ConflictPolicy>) -> Result<(), String> {
+    if !nostr_database::prelude::Kind::from(kind).is_addressable() {
+        return Err(format!("Kind {} is not addressable", kind));
+    }
+    let mut policies = POLICIES.lock()
+        .map_err(|e| format!("Failed to lock conflict policies: {}", e))?;
+    let policies = policies.get_or_insert_with(HashMap::new);
+    match policy {
+        Some(policy) => policies.insert(kind, policy),
+        None => policies.remove(&kind),
+    };
+    Ok(())
+}
+
+/// Kinds with a conflict policy other than the default
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_conflict_policies() -> Vec<KindConflictPolicy> {
+    let mut policies: Vec<KindConflictPolicy> = POLICIES.lock()
+        .ok()
+        .and_then(|policies| policies.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(kind, policy)| KindConflictPolicy { kind, policy })
+        .collect();
+    policies.sort_by_key(|policy| policy.kind);
+    policies
+}
+
+/// Stream the conflicts met by `client_sync_since`, to let the user review them
+///
+/// Conflicts are resolved by the kind's policy before being reported; with `KeepBoth` the
+/// other version can be restored from the event history.
+pub fn client_sync_conflicts(sink: StreamSink<SyncConflict>) -> Result<(), String> {
+    let mut sink_guard = CONFLICT_SINK.lock()
+        .map_err(|e| format!("Failed to lock conflict sink: {}", e))?;
+    *sink_guard = Some(sink);
+    Ok(())
+}
+
+fn policy_for(kind: u16) -> ConflictPolicy {
+    POLICIES.lock()
+        .ok()
+        .and_then(|policies| policies.as_ref().and_then(|policies| policies.get(&kind).copied()))
+        .unwrap_or(ConflictPolicy::LatestWins)
+}
+
+fn report(conflict: SyncConflict) {
+    if let Ok(sink) = CONFLICT_SINK.lock() {
+        if let Some(sink) = sink.as_ref() {
+            let _ = sink.add(conflict);
+        }
+    }
+}
+
+/// Store an event fetched by a sync, applying the conflict policy of addressable kinds
+///
+/// Returns whether the event was stored.
+pub(crate) async fn save_synced_event(event_json: &str) -> Result<bool, String> {
+    let remote = Event::from_json(event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if !remote.kind.is_addressable() {
+        return save_parsed_event(&remote).await;
+    }
+    let database = get_database()?;
+    let local = match ingest::current_version(&database, &remote).await {
+        Some(local) if local.id != remote.id => local,
+        _ => return save_parsed_event(&remote).await,
+    };
+    
+    let policy = policy_for(remote.kind.as_u16());
+    let remote_is_newer = remote.created_at > local.created_at;
+    let kept = match policy {
+        ConflictPolicy::LatestWins | ConflictPolicy::KeepBoth if remote_is_newer => ConflictSide::Remote,
+        ConflictPolicy::LatestWins | ConflictPolicy::KeepBoth | ConflictPolicy::LocalWins => ConflictSide::Local,
+        ConflictPolicy::RemoteWins => ConflictSide::Remote,
+    };
+    
+    let stored = match kept {
+        ConflictSide::Local => false,
+        ConflictSide::Remote => {
+            // The database refuses an older version while the local one is there
+            if !remote_is_newer {
+                ingest::delete_events(&database, Filter::new().id(local.id))
+                    .await
+                    .map_err(|e| format!("Failed to replace local event: {}", e))?;
+            }
+            save_parsed_event(&remote).await?
+        }
+    };
+    if policy == ConflictPolicy::KeepBoth {
+        ingest::record_superseded(if kept == ConflictSide::Remote { &local } else { &remote });
+    }
+    
+    report(SyncConflict {
+        kind: remote.kind.as_u16(),
+        pubkey: remote.pubkey.to_hex(),
+        identifier: remote.tags.identifier().unwrap_or_default().to_string(),
+        local_event_json: local.as_json(),
+        remote_event_json: remote.as_json(),
+        policy,
+        kept,
+    });
+    Ok(stored)
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+/// Kind of a content segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentSegmentKind {
+    /// Plain text (the only translatable kind)
+    Text,
+    /// http(s) URL
+    Url,
+    /// NIP-19/NIP-21 reference (`nostr:npub1...`, `note1...`, ...)
+    NostrRef,
+    /// Hashtag including the leading `#`
+    Hashtag,
+    /// Unicode emoji sequence or NIP-30 `:shortcode:`
+    Emoji,
+}
+
+/// A run of note content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentSegment {
+    pub kind: ContentSegmentKind,
+    pub text: String,
+}
+
+const BECH32_PREFIXES: [&str; 7] = ["npub1", "nsec1", "note1", "nprofile1", "nevent1", "naddr1", "nrelay1"];
+
+/// Characters that are stripped from the end of a URL (sentence punctuation)
+const URL_TRAILING_PUNCTUATION: [char; 10] = ['.', ',', ';', ':', '!', '?', '\'', '"', ']', '}'];
+
+fn is_bech32_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_digit()
+}
+
+fn is_hashtag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn is_emoji_char(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF | 0x2300..=0x23FF
+        | 0x3030 | 0x303D | 0x3297 | 0x3299)
+}
+
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c as u32, 0x200D | 0xFE0E | 0xFE0F | 0x20E3 | 0x1F3FB..=0x1F3FF | 0xE0020..=0xE007F)
+}
+
+/// Length in bytes of the URL starting at the beginning of `rest`, if any
+fn match_url(rest: &str) -> Option<usize> {
+    let lower = rest.chars().take(8).collect::<String>().to_ascii_lowercase();
+    if !lower.starts_with("http://") && !lower.starts_with("https://") {
+        return None;
+    }
+
+    let mut end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+
+    // Strip trailing punctuation, keeping closing parentheses that are balanced within the URL
+    loop {
+        let last = rest[..end].chars().next_back()?;
+        if URL_TRAILING_PUNCTUATION.contains(&last) {
+            end -= last.len_utf8();
+        } else if last == ')' && rest[..end].matches(')').count() > rest[..end].matches('(').count() {
+            end -= 1;
+        } else {
+            break;
+        }
+    }
+
+    let scheme_len = if lower.starts_with("https://") { 8 } else { 7 };
+    if end > scheme_len { Some(end) } else { None }
+}
+
+/// Length in bytes of the NIP-19/NIP-21 reference starting at the beginning of `rest`, if any
+fn match_nostr_ref(rest: &str) -> Option<usize> {
+    let has_scheme = rest.get(..6).map_or(false, |scheme| scheme.eq_ignore_ascii_case("nostr:"));
+    let scheme_len = if has_scheme { 6 } else { 0 };
+    let entity = &rest[scheme_len..];
+
+    let prefix = BECH32_PREFIXES.iter().find(|p| entity.starts_with(*p))?;
+    let data_len = entity[prefix.len()..]
+        .find(|c: char| !is_bech32_char(c))
+        .unwrap_or(entity.len() - prefix.len());
+
+    // Shortest bech32 payload (note/npub) is 58 data characters
+    if data_len >= 58 { Some(scheme_len + prefix.len() + data_len) } else { None }
+}
+
+/// Length in bytes of the hashtag starting at the beginning of `rest`, if any
+fn match_hashtag(rest: &str) -> Option<usize> {
+    let tag = rest.strip_prefix('#')?;
+    let len = tag.find(|c: char| !is_hashtag_char(c)).unwrap_or(tag.len());
+
+    // Require at least one letter so "#1" (e.g. "issue #1") stays text
+    if tag[..len].chars().any(|c| c.is_alphabetic()) { Some(1 + len) } else { None }
+}
+
+/// Length in bytes of the emoji sequence or `:shortcode:` starting at the beginning of `rest`, if any
+fn match_emoji(rest: &str) -> Option<usize> {
+    if let Some(code) = rest.strip_prefix(':') {
+        let len = code.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-')).unwrap_or(code.len());
+        if len > 0 && code[len..].starts_with(':') {
+            return Some(len + 2);
+        }
+        return None;
+    }
+
+    let mut chars = rest.char_indices().peekable();
+    let (_, first) = chars.next()?;
+    if !is_emoji_char(first) {
+        return None;
+    }
+
+    let mut end = first.len_utf8();
+    let mut joined = false;
+    while let Some(&(i, c)) = chars.peek() {
+        if is_emoji_modifier(c) {
+            joined = c == '\u{200D}';
+        } else if joined && is_emoji_char(c) {
+            joined = false;
+        } else if (0x1F1E6..=0x1F1FF).contains(&(first as u32)) && (0x1F1E6..=0x1F1FF).contains(&(c as u32)) && i == end && end == first.len_utf8() {
+            // Second regional indicator of a flag
+        } else {
+            break;
+        }
+        end = i + c.len_utf8();
+        chars.next();
+    }
+    Some(end)
+}
+
+fn push_segment(segments: &mut Vec<ContentSegment>, kind: ContentSegmentKind, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(last) = segments.last_mut() {
+        if kind == ContentSegmentKind::Text && last.kind == ContentSegmentKind::Text {
+            last.text.push_str(text);
+            return;
+        }
+    }
+    segments.push(ContentSegment { kind, text: text.to_string() });
+}
+
+/// Split note content into translatable text runs and non-translatable entities
+///
+/// Entities are URLs, NIP-19/NIP-21 references, hashtags and emoji (unicode or NIP-30
+/// shortcodes). Concatenating the `text` of all segments gives back the original content.
+#[flutter_rust_bridge::frb(sync)]
+pub fn segment_content(content: String) -> Vec<ContentSegment> {
+    let mut segments = Vec::new();
+    let mut text_start = 0;
+    let mut pos = 0;
+    let mut prev: Option<char> = None;
+
+    while pos < content.len() {
+        let rest = &content[pos..];
+        let at_boundary = prev.map_or(true, |c| !c.is_alphanumeric());
+
+        let entity = if at_boundary {
+            match_url(rest).map(|len| (ContentSegmentKind::Url, len))
+                .or_else(|| match_nostr_ref(rest).map(|len| (ContentSegmentKind::NostrRef, len)))
+                .or_else(|| match_hashtag(rest).map(|len| (ContentSegmentKind::Hashtag, len)))
+                .or_else(|| match_emoji(rest).map(|len| (ContentSegmentKind::Emoji, len)))
+        } else {
+            match_emoji(rest).filter(|_| !rest.starts_with(':')).map(|len| (ContentSegmentKind::Emoji, len))
+        };
+
+        match entity {
+            Some((kind, len)) => {
+                push_segment(&mut segments, ContentSegmentKind::Text, &content[text_start..pos]);
+                push_segment(&mut segments, kind, &rest[..len]);
+                pos += len;
+                text_start = pos;
+                prev = content[..pos].chars().next_back();
+            }
+            None => {
+                let c = rest.chars().next().unwrap();
+                pos += c.len_utf8();
+                prev = Some(c);
+            }
+        }
+    }
+    push_segment(&mut segments, ContentSegmentKind::Text, &content[text_start..]);
+
+    segments
+}
+
+/// Cut `content` to at most `max_bytes` (on a character boundary) and mark it as truncated
+pub(crate) fn truncate_content(content: &str, max_bytes: usize) -> String {
+    let mut end = max_bytes.min(content.len());
+    while !content.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}\n[truncated, {} bytes]", &content[..end], content.len())
+}
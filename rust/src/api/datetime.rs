@@ -0,0 +1,65 @@
+use chrono::{DateTime, Local, Locale, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How a timestamp is written by `format_timestamp`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimestampStyle {
+    /// Time of day in the locale's format
+    Time,
+    /// Date in the locale's format
+    Date,
+    /// Date and time in the locale's format
+    DateTime,
+    /// Weekday, day, month name, year and time
+    Full,
+    /// Time of day with milliseconds, as written in the relay log
+    Log,
+    /// RFC 3339 in UTC, for exporting and sorting
+    Iso8601,
+}
+
+impl TimestampStyle {
+    fn pattern(self) -> &'static str {
+        match self {
+            TimestampStyle::Time => "%X",
+            TimestampStyle::Date => "%x",
+            TimestampStyle::DateTime => "%x %X",
+            TimestampStyle::Full => "%A %-d %B %Y %X",
+            TimestampStyle::Log => "%H:%M:%S%.3f",
+            TimestampStyle::Iso8601 => "%Y-%m-%dT%H:%M:%SZ",
+        }
+    }
+}
+
+/// Locale from a tag like "de_DE" or "de-DE", POSIX ("C") when unknown
+fn parse_locale(locale: &str) -> Locale {
+    let locale = locale.trim().replace('-', "_");
+    // Drop encoding and modifier ("en_US.UTF-8", "ca_ES@valencia")
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    Locale::try_from(locale).unwrap_or(Locale::POSIX)
+}
+
+/// Format a local time, shared by the relay log and `format_timestamp`
+pub(crate) fn format_local(time: &DateTime<Local>, locale: &str, style: TimestampStyle) -> String {
+    match style {
+        TimestampStyle::Iso8601 => time.with_timezone(&Utc).format(style.pattern()).to_string(),
+        _ => time.format_localized(style.pattern(), parse_locale(locale)).to_string(),
+    }
+}
+
+/// Timestamp of a relay log line, in the device's time zone
+pub(crate) fn log_timestamp() -> String {
+    format_local(&Local::now(), "", TimestampStyle::Log)
+}
+
+/// Format a Unix timestamp (seconds) in the device's time zone
+///
+/// `locale` is a tag like "en_US" or "de-DE" and selects month and weekday names and
+/// the date and time layout. Unknown locales fall back to POSIX.
+#[flutter_rust_bridge::frb(sync)]
+pub fn format_timestamp(unix_secs: i64, locale: String, style: TimestampStyle) -> Result<String, String> {
+    let time = Local.timestamp_opt(unix_secs, 0)
+        .single()
+        .ok_or_else(|| format!("Timestamp out of range: {}", unix_secs))?;
+    Ok(format_local(&time, &locale, style))
+}
@@ -0,0 +1,142 @@
+use nostr::event::Event;
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::secp256k1::schnorr::Signature;
+use nostr::secp256k1::{Message, Secp256k1};
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+
+/// What a delegatee may publish on behalf of the delegator (NIP-26)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DelegationConditions {
+    /// Allowed kind, at most one (empty for any kind)
+    ///
+    /// NIP-26 conditions must all hold, so a delegation listing several kinds would allow none.
+    pub kinds: Vec<u16>,
+    /// Only events created after this unix timestamp
+    pub created_after: Option<u64>,
+    /// Only events created before this unix timestamp
+    pub created_before: Option<u64>,
+}
+
+impl DelegationConditions {
+    /// Query string form ("kind=1&created_at>1700000000")
+    fn to_query(&self) -> String {
+        let mut parts: Vec<String> = self.kinds.iter().map(|kind| format!("kind={}", kind)).collect();
+        if let Some(after) = self.created_after {
+            parts.push(format!("created_at>{}", after));
+        }
+        if let Some(before) = self.created_before {
+            parts.push(format!("created_at<{}", before));
+        }
+        parts.join("&")
+    }
+    
+    fn parse(query: &str) -> Result<Self, String> {
+        let mut conditions = DelegationConditions::default();
+        for part in query.split('&').filter(|part| !part.is_empty()) {
+            let number = |value: &str| value.parse::<u64>().map_err(|_| format!("Invalid condition '{}'", part));
+            if let Some(kind) = part.strip_prefix("kind=") {
+                conditions.kinds.push(kind.parse().map_err(|_| format!("Invalid condition '{}'", part))?);
+            } else if let Some(after) = part.strip_prefix("created_at>") {
+                let after = number(after)?;
+                conditions.created_after = Some(conditions.created_after.map_or(after, |current| current.max(after)));
+            } else if let Some(before) = part.strip_prefix("created_at<") {
+                let before = number(before)?;
+                conditions.created_before = Some(conditions.created_before.map_or(before, |current| current.min(before)));
+            } else {
+                return Err(format!("Unknown condition '{}'", part));
+            }
+        }
+        Ok(conditions)
+    }
+    
+    /// Whether every condition holds (repeated `kind=` conditions all have to match)
+    fn allows(&self, kind: u16, created_at: u64) -> bool {
+        self.kinds.iter().all(|allowed| *allowed == kind)
+            && self.created_after.map_or(true, |after| created_at > after)
+            && self.created_before.map_or(true, |before| created_at < before)
+    }
+}
+
+/// Message signed by the delegator
+fn delegation_message(delegatee: &PublicKey, conditions: &str) -> Message {
+    let token = format!("nostr:delegation:{}:{}", delegatee.to_hex(), conditions);
+    Message::from_digest(Sha256Hash::hash(token.as_bytes()).to_byte_array())
+}
+
+/// Create a `delegation` tag letting another key publish on behalf of this one (NIP-26)
+///
+/// The delegatee adds the tag to its events; clients supporting NIP-26 attribute them to the
+/// delegator. Keep the conditions narrow (kinds and an expiry), a delegation can't be revoked.
+///
+/// # Arguments
+/// * `delegatee_pubkey` - Public key (hex or npub) of the key allowed to publish, e.g. a hot key on the device
+/// * `conditions` - What the delegatee may publish
+/// * `delegator_private_key` - Private key (hex or nsec) of the delegating key, e.g. a cold key
+#[flutter_rust_bridge::frb(sync)]
+pub fn create_delegation_tag(
+    delegatee_pubkey: String,
+    conditions: DelegationConditions,
+    delegator_private_key: String,
+) -> Result<Vec<String>, String> {
+    let delegatee = PublicKey::parse(&delegatee_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let secret_key = SecretKey::parse(&delegator_private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let keys = Keys::new(secret_key);
+    if conditions.kinds.len() > 1 {
+        return Err("A delegation can allow at most one kind".to_string());
+    }
+    
+    let query = conditions.to_query();
+    let signature = keys.sign_schnorr(&delegation_message(&delegatee, &query));
+    audit::record(KeyOperation::SignDelegation, &keys.public_key().to_hex(), None);
+    
+    Ok(vec![
+        "delegation".to_string(),
+        keys.public_key().to_hex(),
+        query,
+        signature.to_string(),
+    ])
+}
+
+/// Verify a delegated event, returns the hex public key of the delegator
+///
+/// Returns None for events without a `delegation` tag, and an error if the event signature,
+/// the delegation signature or the conditions don't check out.
+#[flutter_rust_bridge::frb(sync)]
+pub fn verify_delegated_event(event_json: String) -> Result<Option<String>, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    event.verify()
+        .map_err(|e| format!("Invalid event: {}", e))?;
+    
+    let tag = match event.tags.iter().find(|tag| tag.as_slice().first().map(String::as_str) == Some("delegation")) {
+        Some(tag) => tag.as_slice(),
+        None => return Ok(None),
+    };
+    let [_, delegator, query, signature] = tag else {
+        return Err("Delegation tag must have a delegator, conditions and a signature".to_string());
+    };
+    
+    let delegator = PublicKey::from_str(delegator)
+        .map_err(|e| format!("Invalid delegator: {}", e))?;
+    let signature = Signature::from_str(signature)
+        .map_err(|e| format!("Invalid delegation signature: {}", e))?;
+    let xonly = delegator.xonly()
+        .map_err(|e| format!("Invalid delegator: {}", e))?;
+    Secp256k1::verification_only()
+        .verify_schnorr(&signature, &delegation_message(&event.pubkey, query), &xonly)
+        .map_err(|_| "Delegation signature does not match".to_string())?;
+    
+    let conditions = DelegationConditions::parse(query)?;
+    if !conditions.allows(event.kind.as_u16(), event.created_at.as_u64()) {
+        return Err("Event does not meet the delegation conditions".to_string());
+    }
+    
+    Ok(Some(delegator.to_hex()))
+}
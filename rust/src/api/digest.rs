@@ -0,0 +1,116 @@
+use nostr::event::EventId;
+use serde::{Deserialize, Serialize};
+
+/// Number of id bytes kept per event in a prefix set digest
+const PREFIX_LEN: usize = 8;
+/// False positive rate used when none is given
+const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Encoding of an event id digest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IdDigestFormat {
+    /// Bloom filter (smallest, may report ids as present that are missing)
+    Bloom,
+    /// Sorted 8-byte id prefixes (larger, collisions practically impossible)
+    PrefixSet,
+}
+
+/// Compact digest of a set of event ids
+///
+/// A peer holding the digest can tell which of its own events the exporter is missing
+/// without transferring the full id list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventIdDigest {
+    pub format: IdDigestFormat,
+    /// Number of ids in the digest
+    pub count: u32,
+    /// Number of hash functions (bloom only)
+    pub num_hashes: u8,
+    pub data: Vec<u8>,
+}
+
+impl EventIdDigest {
+    /// Build a digest of the given ids
+    pub(crate) fn build(ids: &[[u8; 32]], format: IdDigestFormat, false_positive_rate: Option<f64>) -> Result<Self, String> {
+        match format {
+            IdDigestFormat::Bloom => {
+                let rate = false_positive_rate.unwrap_or(DEFAULT_FALSE_POSITIVE_RATE);
+                if !(rate > 0.0 && rate < 1.0) {
+                    return Err("False positive rate must be between 0 and 1".to_string());
+                }
+                
+                // Optimal size and hash count for the expected number of ids
+                let n = ids.len().max(1) as f64;
+                let ln2 = std::f64::consts::LN_2;
+                let bits = (-(n * rate.ln()) / (ln2 * ln2)).ceil().max(64.0) as usize;
+                let num_hashes = ((bits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u8;
+                
+                let mut digest = Self {
+                    format,
+                    count: ids.len() as u32,
+                    num_hashes,
+                    data: vec![0; (bits + 7) / 8],
+                };
+                for id in ids {
+                    for bit in digest.bloom_bits(id) {
+                        digest.data[bit / 8] |= 1 << (bit % 8);
+                    }
+                }
+                Ok(digest)
+            }
+            IdDigestFormat::PrefixSet => {
+                let mut prefixes: Vec<&[u8]> = ids.iter().map(|id| &id[..PREFIX_LEN]).collect();
+                prefixes.sort_unstable();
+                prefixes.dedup();
+                Ok(Self {
+                    format,
+                    count: prefixes.len() as u32,
+                    num_hashes: 0,
+                    data: prefixes.concat(),
+                })
+            }
+        }
+    }
+    
+    /// Bit positions of an id in the bloom filter
+    ///
+    /// Event ids are already uniformly distributed hashes, so two words of the id are
+    /// combined with double hashing instead of hashing again.
+    fn bloom_bits<'a>(&self, id: &'a [u8; 32]) -> impl Iterator<Item = usize> + 'a {
+        let bits = (self.data.len() * 8) as u64;
+        let h1 = u64::from_le_bytes(id[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(id[8..16].try_into().unwrap()) | 1;
+        (0..self.num_hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+    
+    /// Whether the id is (probably) in the digest
+    pub(crate) fn contains(&self, id: &[u8; 32]) -> bool {
+        match self.format {
+            IdDigestFormat::Bloom => {
+                !self.data.is_empty()
+                    && self.bloom_bits(id).all(|bit| self.data[bit / 8] & (1 << (bit % 8)) != 0)
+            }
+            IdDigestFormat::PrefixSet => self.data
+                .chunks_exact(PREFIX_LEN)
+                .collect::<Vec<_>>()
+                .binary_search(&&id[..PREFIX_LEN])
+                .is_ok(),
+        }
+    }
+}
+
+/// Get the ids (hex) that are not in a digest received from another device
+///
+/// The returned events are the ones the other device is missing. With a bloom digest a
+/// small fraction of missing ids may be skipped, according to its false positive rate.
+#[flutter_rust_bridge::frb(sync)]
+pub fn id_digest_missing(digest: EventIdDigest, event_ids: Vec<String>) -> Result<Vec<String>, String> {
+    event_ids.into_iter()
+        .map(|id| {
+            let event_id = EventId::from_hex(&id)
+                .map_err(|e| format!("Invalid event ID: {}", e))?;
+            Ok((!digest.contains(event_id.as_bytes())).then_some(id))
+        })
+        .filter_map(Result::transpose)
+        .collect()
+}
@@ -0,0 +1,262 @@
+use nostr::event::{Event, Kind};
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::nip01::Metadata;
+use nostr::nips::nip04;
+use nostr::nips::nip19::{FromBech32, Nip19Profile};
+use nostr::nips::nip44;
+use nostr::JsonUtil;
+use nostr_database::prelude::{Filter as DbFilter, Kind as DbKind, PublicKey as DbPublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+use super::content::{segment_content, ContentSegment, ContentSegmentKind};
+use super::names::resolve_display_names;
+use super::relay::query_local_events_json;
+
+/// Profile referenced by a note, resolved from the local database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplayProfile {
+    /// Hex public key
+    pub pubkey: String,
+    /// Display name, or name, of the cached kind 0 (None if not cached)
+    pub name: Option<String>,
+    pub picture: Option<String>,
+}
+
+/// Note ready to be rendered
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplayNote {
+    pub id: String,
+    pub kind: u16,
+    pub created_at: u64,
+    pub author: DisplayProfile,
+    /// Whether the id and signature are valid
+    pub valid: bool,
+    /// Content to show (decrypted for direct messages)
+    pub content: String,
+    pub decrypted: bool,
+    pub decryption_error: Option<String>,
+    pub segments: Vec<ContentSegment>,
+    /// Profiles mentioned in the content or `p` tags, in order of first appearance
+    pub mentions: Vec<DisplayProfile>,
+    pub is_mine: bool,
+    /// Whether the note mentions or is addressed to `my_pubkey`
+    pub mentions_me: bool,
+}
+
+/// Public key referenced by a `npub`/`nprofile` segment
+fn mentioned_pubkey(segment: &ContentSegment) -> Option<PublicKey> {
+    if segment.kind != ContentSegmentKind::NostrRef {
+        return None;
+    }
+    let entity = segment.text.strip_prefix("nostr:").unwrap_or(&segment.text);
+    if entity.starts_with("npub1") {
+        PublicKey::from_bech32(entity).ok()
+    } else if entity.starts_with("nprofile1") {
+        Nip19Profile::from_bech32(entity).ok().map(|profile| profile.public_key)
+    } else {
+        None
+    }
+}
+
+/// Latest cached metadata of each pubkey (empty if the relay database isn't open)
+pub(crate) fn cached_profiles(pubkeys: &[PublicKey]) -> HashMap<PublicKey, Metadata> {
+    let authors: Vec<DbPublicKey> = pubkeys.iter()
+        .filter_map(|pk| DbPublicKey::from_slice(&pk.to_bytes()).ok())
+        .collect();
+    let filter = DbFilter::new().kind(DbKind::Metadata).authors(authors);
+    let events = query_local_events_json(filter).unwrap_or_default();
+    
+    let mut latest: HashMap<PublicKey, Event> = HashMap::new();
+    for event in events.iter().filter_map(|json| Event::from_json(json).ok()) {
+        let newer = latest.get(&event.pubkey).map_or(true, |current| event.created_at > current.created_at);
+        if newer {
+            latest.insert(event.pubkey, event);
+        }
+    }
+    
+    latest.into_iter()
+        .filter_map(|(pubkey, event)| Metadata::from_json(&event.content).ok().map(|metadata| (pubkey, metadata)))
+        .collect()
+}
+
+fn display_profile(pubkey: &PublicKey, profiles: &HashMap<PublicKey, Metadata>) -> DisplayProfile {
+    let metadata = profiles.get(pubkey);
+    DisplayProfile {
+        pubkey: pubkey.to_hex(),
+        name: metadata.and_then(|m| m.display_name.clone().filter(|n| !n.is_empty()).or_else(|| m.name.clone())),
+        picture: metadata.and_then(|m| m.picture.clone()),
+    }
+}
+
+/// Decrypt the content of a direct message (kind 4) sent to or by `secret_key`
+fn decrypt_direct_message(event: &Event, my_pubkey: &PublicKey, secret_key: &SecretKey) -> Result<String, String> {
+    let counterparty = if event.pubkey == *my_pubkey {
+        event.tags.public_keys().next().copied()
+            .ok_or_else(|| "Direct message has no recipient".to_string())?
+    } else {
+        event.pubkey
+    };
+    
+    // Some clients send NIP-44 payloads in kind 4, NIP-04 payloads contain "?iv="
+    if event.content.contains("?iv=") {
+        let plaintext = nip04::decrypt(secret_key, &counterparty, &event.content)
+            .map_err(|e| format!("Decryption failed: {}", e))?;
+        audit::record(KeyOperation::Nip04Decrypt, &my_pubkey.to_hex(), Some(event.kind.as_u16()));
+        Ok(plaintext)
+    } else {
+        let plaintext = nip44::decrypt(secret_key, &counterparty, &event.content)
+            .map_err(|e| format!("NIP-44 decryption failed: {}", e))?;
+        audit::record(KeyOperation::Nip44Decrypt, &my_pubkey.to_hex(), Some(event.kind.as_u16()));
+        Ok(plaintext)
+    }
+}
+
+/// Prepare a note for rendering in a single call
+///
+/// Verifies the event, decrypts direct messages when `private_key` is given, splits the
+/// content into entities and resolves the names of the author and mentioned profiles from
+/// the local database. Invalid events are still returned (with `valid` false) so the UI
+/// can decide how to show them.
+///
+/// # Arguments
+/// * `event_json` - Event to render
+/// * `my_pubkey` - Public key (hex or npub) of the current user
+/// * `private_key` - Private key (hex) of the current user, needed to decrypt direct messages;
+///   an error is returned if it isn't the key of `my_pubkey`
+#[flutter_rust_bridge::frb(sync)]
+pub fn prepare_note_for_display(
+    event_json: String,
+    my_pubkey: String,
+    private_key: Option<String>,
+) -> Result<DisplayNote, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    let my_pubkey = PublicKey::parse(&my_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let secret_key = private_key
+        .map(|private_key| SecretKey::from_str(&private_key).map_err(|e| format!("Invalid private key: {}", e)))
+        .transpose()?;
+    if let Some(secret_key) = &secret_key {
+        if Keys::new(secret_key.clone()).public_key() != my_pubkey {
+            return Err("Private key does not belong to my_pubkey".to_string());
+        }
+    }
+    let valid = event.verify().is_ok();
+    
+    let (content, decrypted, decryption_error) = match (event.kind, secret_key) {
+        (Kind::EncryptedDirectMessage, Some(secret_key)) if valid => {
+            match decrypt_direct_message(&event, &my_pubkey, &secret_key) {
+                Ok(plaintext) => (plaintext, true, None),
+                Err(e) => (String::new(), false, Some(e)),
+            }
+        }
+        (Kind::EncryptedDirectMessage, _) => (String::new(), false, Some("Not decrypted".to_string())),
+        _ => (event.content.clone(), false, None),
+    };
+    
+    let segments = segment_content(content.clone());
+    
+    // Mentions in the content first, then the remaining p tags
+    let mut mentioned: Vec<PublicKey> = Vec::new();
+    for pubkey in segments.iter().filter_map(mentioned_pubkey).chain(event.tags.public_keys().copied()) {
+        if !mentioned.contains(&pubkey) {
+            mentioned.push(pubkey);
+        }
+    }
+    
+    let mut lookup = mentioned.clone();
+    lookup.push(event.pubkey);
+    let profiles = cached_profiles(&lookup);
+    
+    Ok(DisplayNote {
+        id: event.id.to_hex(),
+        kind: event.kind.as_u16(),
+        created_at: event.created_at.as_u64(),
+        author: display_profile(&event.pubkey, &profiles),
+        valid,
+        content,
+        decrypted,
+        decryption_error,
+        mentions: mentioned.iter().map(|pk| display_profile(pk, &profiles)).collect(),
+        is_mine: event.pubkey == my_pubkey,
+        mentions_me: mentioned.contains(&my_pubkey),
+        segments,
+    })
+}
+
+/// File extensions of media links left out of previews
+const MEDIA_EXTENSIONS: [&str; 14] = [
+    "jpg", "jpeg", "png", "gif", "webp", "avif", "svg", "mp4", "webm", "mov", "m3u8", "mp3", "ogg", "wav",
+];
+
+fn is_media_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.rsplit_once('.')
+        .map_or(false, |(_, extension)| MEDIA_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+}
+
+/// Cut `text` to at most `max_len` characters, at a word boundary when there is one
+fn truncate_preview(text: &str, max_len: usize) -> String {
+    if text.chars().count() <= max_len {
+        return text.to_string();
+    }
+    // Keep room for the ellipsis
+    let cut: String = text.chars().take(max_len.saturating_sub(1)).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > cut.len() / 2 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+/// Plain-text snippet of a note for share sheets and notifications
+///
+/// Mentions are replaced with `@name` (see `resolve_display_name`), media links and quoted
+/// events are left out and whitespace is collapsed to single spaces. Notes with a content
+/// warning (NIP-36) only show the warning.
+///
+/// # Arguments
+/// * `event_json` - Note to preview
+/// * `max_len` - Maximum length in characters, including the trailing "…"
+#[flutter_rust_bridge::frb(sync)]
+pub fn generate_note_preview(event_json: String, max_len: u32) -> Result<String, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    
+    if let Some(warning) = event.tags.iter().find(|tag| tag.as_slice().first().map(String::as_str) == Some("content-warning")) {
+        let preview = match warning.as_slice().get(1).filter(|reason| !reason.trim().is_empty()) {
+            Some(reason) => format!("Content warning: {}", reason.trim()),
+            None => "Content warning".to_string(),
+        };
+        return Ok(truncate_preview(&preview, max_len as usize));
+    }
+    
+    let segments = segment_content(event.content.clone());
+    let mentioned: Vec<String> = segments.iter()
+        .filter_map(mentioned_pubkey)
+        .map(|pubkey| pubkey.to_hex())
+        .collect();
+    let names: HashMap<String, String> = resolve_display_names(mentioned)?
+        .into_iter()
+        .map(|resolved| (resolved.pubkey, resolved.name))
+        .collect();
+    
+    let mut text = String::new();
+    for segment in segments.iter() {
+        match segment.kind {
+            ContentSegmentKind::NostrRef => {
+                if let Some(name) = mentioned_pubkey(segment).and_then(|pubkey| names.get(&pubkey.to_hex())) {
+                    text.push('@');
+                    text.push_str(name);
+                }
+            }
+            ContentSegmentKind::Url if is_media_url(&segment.text) => {}
+            _ => text.push_str(&segment.text),
+        }
+    }
+    
+    let collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    Ok(truncate_preview(&collapsed, max_len as usize))
+}
@@ -0,0 +1,479 @@
+use nostr_database::prelude::{Event, EventId, Filter, JsonUtil, Kind, PublicKey, Timestamp};
+use nostr_database::NostrDatabase;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use crate::frb_generated::StreamSink;
+use super::relay::{get_database, get_or_create_runtime};
+
+/// Events queried per page
+const EXPORT_PAGE_SIZE: usize = 500;
+
+/// Progress of an export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportProgress {
+    pub exported: u64,
+    /// Number of matching events when the export started
+    pub total: u64,
+    pub percent: f64,
+    pub done: bool,
+    pub error: Option<String>,
+}
+
+/// Position of an interrupted export, stored next to the output file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExportCheckpoint {
+    filter_json: String,
+    /// Events are exported newest first, everything newer than this was written
+    until: u64,
+    /// Ids already written with created_at == `until`
+    ids_at_until: Vec<String>,
+    exported: u64,
+    total: u64,
+    /// Length of the output file when the checkpoint was written
+    bytes_written: u64,
+}
+
+fn checkpoint_path(path: &str) -> String {
+    format!("{}.checkpoint", path)
+}
+
+fn load_checkpoint(path: &str, filter_json: &str) -> Option<ExportCheckpoint> {
+    let content = std::fs::read_to_string(checkpoint_path(path)).ok()?;
+    let checkpoint: ExportCheckpoint = serde_json::from_str(&content).ok()?;
+    // A checkpoint of another export can't be continued
+    (checkpoint.filter_json == filter_json).then_some(checkpoint)
+}
+
+fn save_checkpoint(path: &str, checkpoint: &ExportCheckpoint) -> Result<(), String> {
+    let content = serde_json::to_string(checkpoint)
+        .map_err(|e| format!("Failed to serialize checkpoint: {}", e))?;
+    // Write then rename, so a kill mid-write leaves the previous checkpoint intact
+    let tmp = format!("{}.tmp", checkpoint_path(path));
+    std::fs::write(&tmp, content)
+        .map_err(|e| format!("Failed to write checkpoint: {}", e))?;
+    std::fs::rename(&tmp, checkpoint_path(path))
+        .map_err(|e| format!("Failed to write checkpoint: {}", e))
+}
+
+fn progress(checkpoint: &ExportCheckpoint, done: bool) -> ExportProgress {
+    let percent = if checkpoint.total == 0 {
+        100.0
+    } else {
+        (checkpoint.exported as f64 / checkpoint.total as f64 * 100.0).min(100.0)
+    };
+    ExportProgress {
+        exported: checkpoint.exported,
+        total: checkpoint.total,
+        percent: if done { 100.0 } else { percent },
+        done,
+        error: None,
+    }
+}
+
+async fn run_export(path: String, filter_json: String, resume: bool, sink: &StreamSink<ExportProgress>) -> Result<(), String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    let database = get_database()?;
+    
+    let resumed = if resume { load_checkpoint(&path, &filter_json) } else { None };
+    let (mut checkpoint, mut file) = match resumed {
+        Some(checkpoint) => {
+            // Drop events written after the last checkpoint, they are exported again
+            let file = OpenOptions::new().write(true).open(&path)
+                .map_err(|e| format!("Failed to open export file: {}", e))?;
+            file.set_len(checkpoint.bytes_written)
+                .map_err(|e| format!("Failed to truncate export file: {}", e))?;
+            let file = OpenOptions::new().append(true).open(&path)
+                .map_err(|e| format!("Failed to open export file: {}", e))?;
+            tracing::info!("Resuming export at {} of {} events", checkpoint.exported, checkpoint.total);
+            (checkpoint, file)
+        }
+        None => {
+            let total = database.count(filter.clone())
+                .await
+                .map_err(|e| format!("Failed to count events: {}", e))? as u64;
+            let file = File::create(&path)
+                .map_err(|e| format!("Failed to create export file: {}", e))?;
+            let checkpoint = ExportCheckpoint {
+                filter_json: filter_json.clone(),
+                until: filter.until.map_or(u64::MAX, |until| until.as_u64()),
+                ids_at_until: Vec::new(),
+                exported: 0,
+                total,
+                bytes_written: 0,
+            };
+            (checkpoint, file)
+        }
+    };
+    let _ = sink.add(progress(&checkpoint, false));
+    
+    let mut page_size = EXPORT_PAGE_SIZE;
+    loop {
+        let page_filter = filter.clone()
+            .until(Timestamp::from(checkpoint.until))
+            .limit(page_size);
+        let events = database.query(page_filter)
+            .await
+            .map_err(|e| format!("Failed to query events: {}", e))?;
+        let page_len = events.len();
+        
+        let skip: HashSet<EventId> = checkpoint.ids_at_until.iter()
+            .filter_map(|id| EventId::from_hex(id).ok())
+            .collect();
+        let new_events: Vec<_> = events.into_iter().filter(|event| !skip.contains(&event.id)).collect();
+        
+        if new_events.is_empty() {
+            if page_len < page_size {
+                break;
+            }
+            // The whole page has the checkpoint timestamp and was exported already
+            page_size *= 2;
+            continue;
+        }
+        page_size = EXPORT_PAGE_SIZE;
+        
+        let mut chunk = String::new();
+        for event in new_events.iter() {
+            chunk.push_str(&event.as_json());
+            chunk.push('\n');
+            
+            let created_at = event.created_at.as_u64();
+            if created_at < checkpoint.until {
+                checkpoint.until = created_at;
+                checkpoint.ids_at_until.clear();
+            }
+            checkpoint.ids_at_until.push(event.id.to_hex());
+        }
+        file.write_all(chunk.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|e| format!("Failed to write export file: {}", e))?;
+        
+        checkpoint.exported += new_events.len() as u64;
+        checkpoint.bytes_written += chunk.len() as u64;
+        save_checkpoint(&path, &checkpoint)?;
+        let _ = sink.add(progress(&checkpoint, false));
+    }
+    
+    let _ = std::fs::remove_file(checkpoint_path(&path));
+    let _ = sink.add(progress(&checkpoint, true));
+    tracing::info!("Exported {} events to {}", checkpoint.exported, path);
+    Ok(())
+}
+
+/// Export events of the relay database as JSONL, streaming progress to Dart
+///
+/// A checkpoint is written next to the file after every page, so an export interrupted
+/// by the app being backgrounded or killed can continue with `resume` instead of starting
+/// over. The checkpoint is removed once the export is done.
+///
+/// # Arguments
+/// * `path` - Output file
+/// * `filter_json` - NIP-01 filter selecting the events (e.g. "{}" for all)
+/// * `resume` - Continue from the checkpoint of an earlier export with the same filter, if any
+pub fn relay_export_events(
+    path: String,
+    filter_json: String,
+    resume: bool,
+    sink: StreamSink<ExportProgress>,
+) -> Result<(), String> {
+    // Fail early if the relay isn't running
+    get_database()?;
+    
+    let runtime = get_or_create_runtime()?;
+    runtime.spawn(async move {
+        if let Err(e) = run_export(path, filter_json, resume, &sink).await {
+            tracing::warn!("Export failed: {}", e);
+            let _ = sink.add(ExportProgress {
+                exported: 0,
+                total: 0,
+                percent: 0.0,
+                done: true,
+                error: Some(e),
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Deepest social graph export, each level multiplies the number of users
+const MAX_SOCIAL_GRAPH_DEPTH: u32 = 3;
+/// Authors per database query of the social graph export
+const SOCIAL_GRAPH_AUTHORS_PER_QUERY: usize = 500;
+/// Format version of the social graph file
+const SOCIAL_GRAPH_VERSION: u32 = 1;
+
+/// Kinds of the lists exported with a social graph (NIP-51 standard lists and sets)
+const LIST_KINDS: [u16; 14] = [
+    10001, 10003, 10004, 10005, 10006, 10007, 10015, 10030,
+    30000, 30002, 30003, 30004, 30015, 30030,
+];
+
+/// Result of `export_social_graph`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocialGraphExport {
+    pub path: String,
+    /// Users in the file (the root user included)
+    pub users: u32,
+    /// Follow edges in the file
+    pub follows: u32,
+    /// Users of the graph without a cached follow list
+    pub missing_follow_lists: u32,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct GraphRelay {
+    url: String,
+    read: bool,
+    write: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct GraphMutes {
+    pubkeys: Vec<String>,
+    events: Vec<String>,
+    hashtags: Vec<String>,
+    words: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct GraphList {
+    kind: u16,
+    /// `d` tag of sets, empty for standard lists
+    identifier: String,
+    created_at: u64,
+    tags: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct GraphUser {
+    /// Follow hops from the root user
+    depth: u32,
+    follows: Vec<String>,
+    follow_list_created_at: Option<u64>,
+    /// Public part of the mute list (kind 10000), the encrypted part isn't readable here
+    mutes: Option<GraphMutes>,
+    /// Relay list (kind 10002)
+    relays: Vec<GraphRelay>,
+    /// DM relays (kind 10050)
+    dm_relays: Vec<String>,
+    lists: Vec<GraphList>,
+}
+
+#[derive(Debug, Serialize)]
+struct SocialGraph {
+    version: u32,
+    root: String,
+    depth: u32,
+    exported_at: u64,
+    users: BTreeMap<String, GraphUser>,
+}
+
+/// Values of the tags named `name`
+fn tag_values(event: &Event, name: &str) -> Vec<String> {
+    event.tags.iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [tag_name, value, ..] if tag_name == name => Some(value.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn add_event(user: &mut GraphUser, event: &Event) {
+    match event.kind.as_u16() {
+        3 => {
+            user.follows = tag_values(event, "p").into_iter()
+                .filter(|pubkey| PublicKey::from_hex(pubkey).is_ok())
+                .collect();
+            user.follow_list_created_at = Some(event.created_at.as_u64());
+        }
+        10000 => {
+            user.mutes = Some(GraphMutes {
+                pubkeys: tag_values(event, "p"),
+                events: tag_values(event, "e"),
+                hashtags: tag_values(event, "t"),
+                words: tag_values(event, "word"),
+            });
+        }
+        10002 => {
+            user.relays = event.tags.iter()
+                .filter_map(|tag| match tag.as_slice() {
+                    [name, url] if name == "r" => Some(GraphRelay { url: url.clone(), read: true, write: true }),
+                    [name, url, marker, ..] if name == "r" => Some(GraphRelay {
+                        url: url.clone(),
+                        read: marker != "write",
+                        write: marker != "read",
+                    }),
+                    _ => None,
+                })
+                .collect();
+        }
+        10050 => user.dm_relays = tag_values(event, "relay"),
+        kind => user.lists.push(GraphList {
+            kind,
+            identifier: event.tags.identifier().unwrap_or_default().to_string(),
+            created_at: event.created_at.as_u64(),
+            tags: event.tags.iter().map(|tag| tag.as_slice().to_vec()).collect(),
+        }),
+    }
+}
+
+/// Newest version of each replaceable event (kinds 3, 10000-19999) and addressable event
+/// (per `d` tag) of each author, of two versions with the same created_at the one with the
+/// lowest id (NIP-01)
+pub(crate) fn newest_versions(events: impl IntoIterator<Item = Event>) -> Vec<Event> {
+    let mut newest: HashMap<(PublicKey, u16, String), Event> = HashMap::new();
+    for event in events {
+        let identifier = if event.kind.is_addressable() {
+            event.tags.identifier().unwrap_or_default().to_string()
+        } else {
+            String::new()
+        };
+        let key = (event.pubkey, event.kind.as_u16(), identifier);
+        let newer = newest.get(&key).map_or(true, |current| {
+            (event.created_at, Reverse(event.id)) > (current.created_at, Reverse(current.id))
+        });
+        if newer {
+            newest.insert(key, event);
+        }
+    }
+    newest.into_values().collect()
+}
+
+/// Progress of a social graph export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SocialGraphProgress {
+    /// Follow hops loaded so far
+    pub depth: u32,
+    /// Users found so far
+    pub users: u32,
+    pub done: bool,
+    /// Summary of the written file, once done
+    pub export: Option<SocialGraphExport>,
+    pub error: Option<String>,
+}
+
+async fn run_social_graph_export(
+    root: PublicKey,
+    depth: u32,
+    path: String,
+    sink: &StreamSink<SocialGraphProgress>,
+) -> Result<SocialGraphExport, String> {
+    let database = get_database()?;
+    let kinds: Vec<Kind> = [3, 10000, 10002, 10050].into_iter()
+        .chain(LIST_KINDS)
+        .map(Kind::from)
+        .collect();
+    let mut users: BTreeMap<String, GraphUser> = BTreeMap::new();
+    let mut level = vec![root];
+    for level_depth in 0..=depth {
+        for pubkey in level.iter() {
+            users.entry(pubkey.to_hex()).or_insert_with(|| GraphUser { depth: level_depth, ..Default::default() });
+        }
+        for authors in level.chunks(SOCIAL_GRAPH_AUTHORS_PER_QUERY) {
+            let filter = Filter::new().authors(authors.iter().copied()).kinds(kinds.iter().copied());
+            let events = database.query(filter)
+                .await
+                .map_err(|e| format!("Failed to query events: {}", e))?;
+            for event in newest_versions(events) {
+                if let Some(user) = users.get_mut(&event.pubkey.to_hex()) {
+                    add_event(user, &event);
+                }
+            }
+        }
+        let _ = sink.add(SocialGraphProgress {
+            depth: level_depth,
+            users: users.len() as u32,
+            done: false,
+            export: None,
+            error: None,
+        });
+        if level_depth == depth {
+            break;
+        }
+        
+        // Users first reached at the next level
+        let next: HashSet<String> = level.iter()
+            .filter_map(|pubkey| users.get(&pubkey.to_hex()))
+            .flat_map(|user| user.follows.iter().cloned())
+            .filter(|followed| !users.contains_key(followed))
+            .collect();
+        level = next.iter().filter_map(|followed| PublicKey::from_hex(followed).ok()).collect();
+    }
+    for user in users.values_mut() {
+        user.lists.sort_by(|a, b| (a.kind, &a.identifier).cmp(&(b.kind, &b.identifier)));
+    }
+    
+    let summary = SocialGraphExport {
+        path: path.clone(),
+        users: users.len() as u32,
+        follows: users.values().map(|user| user.follows.len() as u32).sum(),
+        missing_follow_lists: users.values().filter(|user| user.follow_list_created_at.is_none()).count() as u32,
+    };
+    let graph = SocialGraph {
+        version: SOCIAL_GRAPH_VERSION,
+        root: root.to_hex(),
+        depth,
+        exported_at: Timestamp::now().as_u64(),
+        users,
+    };
+    
+    let file = File::create(&path)
+        .map_err(|e| format!("Failed to create export file: {}", e))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &graph)
+        .map_err(|e| format!("Failed to write social graph: {}", e))?;
+    writer.flush()
+        .map_err(|e| format!("Failed to write social graph: {}", e))?;
+    
+    tracing::info!("Exported social graph of {} ({} users) to {}", graph.root, summary.users, path);
+    Ok(summary)
+}
+
+/// Export the social graph of a user from the local database as portable JSON, streaming
+/// progress to Dart
+///
+/// The file holds, for each user, the follow list, the public mutes, the relay and DM
+/// relay lists and the NIP-51 lists cached by the relay (the newest version of each).
+/// Users followed by the root are at depth 1, users they follow at depth 2, and so on.
+/// Progress is sent after each depth, the last update carries the summary of the file.
+///
+/// # Arguments
+/// * `pubkey` - Hex public key of the root user
+/// * `depth` - Follow hops to include (0 for the root user only, at most 3)
+/// * `path` - Output file
+pub fn export_social_graph(
+    pubkey: String,
+    depth: u32,
+    path: String,
+    sink: StreamSink<SocialGraphProgress>,
+) -> Result<(), String> {
+    if depth > MAX_SOCIAL_GRAPH_DEPTH {
+        return Err(format!("Depth must be at most {}", MAX_SOCIAL_GRAPH_DEPTH));
+    }
+    let root = PublicKey::from_hex(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    // Fail early if the relay isn't running
+    get_database()?;
+    
+    let runtime = get_or_create_runtime()?;
+    runtime.spawn(async move {
+        let (export, error) = match run_social_graph_export(root, depth, path, &sink).await {
+            Ok(export) => (Some(export), None),
+            Err(e) => {
+                tracing::warn!("Social graph export failed: {}", e);
+                (None, Some(e))
+            }
+        };
+        let _ = sink.add(SocialGraphProgress {
+            depth,
+            users: export.as_ref().map_or(0, |export| export.users),
+            done: true,
+            export,
+            error,
+        });
+    });
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use super::policy::{self, ConnectionOrigin, OriginAccess};
+use super::relay::RelayConnection;
+use super::tokens;
+use super::trace::FrameReader;
+
+// Gated connections, keyed by the local port of their connection to the relay
+// (the address the relay sees)
+static CONNECTIONS: Mutex<Option<HashMap<u16, GatedConnection>>> = Mutex::new(None);
+
+/// Maximum size of the HTTP upgrade request head
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+const UNAUTHORIZED_RESPONSE: &[u8] = b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+const FORBIDDEN_RESPONSE: &[u8] = b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Headers added by reverse proxies and tunnels in front of the relay
+const FORWARDING_HEADERS: [&str; 4] = ["forwarded", "x-forwarded-for", "x-real-ip", "cf-connecting-ip"];
+
+#[derive(Debug, Clone)]
+struct GatedConnection {
+    peer: SocketAddr,
+    /// Id of the capability token the client connected with
+    token_id: Option<String>,
+    origin: ConnectionOrigin,
+    connected_at: u64,
+}
+
+fn gated_connection(addr: &SocketAddr) -> Option<GatedConnection> {
+    if !addr.ip().is_loopback() {
+        return None;
+    }
+    CONNECTIONS.lock()
+        .ok()
+        .and_then(|connections| connections.as_ref().and_then(|connections| connections.get(&addr.port()).cloned()))
+}
+
+/// Real address of a client, given the address the relay sees
+pub(crate) fn real_peer_addr(addr: &SocketAddr) -> SocketAddr {
+    gated_connection(addr).map_or(*addr, |connection| connection.peer)
+}
+
+/// Whether a connection to the relay came through the gate (and wasn't made to the relay's
+/// loopback port directly)
+pub(crate) fn is_gated(addr: &SocketAddr) -> bool {
+    gated_connection(addr).is_some()
+}
+
+/// Capability token a client connected with, given the address the relay sees
+pub(crate) fn connection_token(addr: &SocketAddr) -> Option<String> {
+    gated_connection(addr).and_then(|connection| connection.token_id)
+}
+
+/// Origin of a client, given the address the relay sees
+pub(crate) fn connection_origin(addr: &SocketAddr) -> ConnectionOrigin {
+    gated_connection(addr).map_or_else(|| classify(addr.ip(), false), |connection| connection.origin)
+}
+
+/// Connections currently forwarded to the relay
+pub(crate) fn connections() -> Vec<RelayConnection> {
+    CONNECTIONS.lock()
+        .ok()
+        .and_then(|connections| connections.as_ref().map(|connections| {
+            connections.values()
+                .map(|connection| RelayConnection {
+                    peer: connection.peer.to_string(),
+                    origin: connection.origin,
+                    token_id: connection.token_id.clone(),
+                    connected_at: connection.connected_at,
+                })
+                .collect()
+        }))
+        .unwrap_or_default()
+}
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local(),
+        // Unique local (fc00::/7) and link-local (fe80::/10)
+        IpAddr::V6(ip) => (ip.segments()[0] & 0xfe00) == 0xfc00 || (ip.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// Classify a peer; tunnel clients running on the device connect from loopback but
+/// add forwarding headers
+fn classify(ip: IpAddr, forwarded: bool) -> ConnectionOrigin {
+    if ip.is_loopback() {
+        if forwarded { ConnectionOrigin::Tunnel } else { ConnectionOrigin::Loopback }
+    } else if is_private(ip) {
+        ConnectionOrigin::Lan
+    } else {
+        ConnectionOrigin::Tunnel
+    }
+}
+
+fn has_forwarding_header(head: &str) -> bool {
+    head.lines().skip(1).any(|line| {
+        line.split_once(':')
+            .map_or(false, |(name, _)| FORWARDING_HEADERS.iter().any(|h| name.trim().eq_ignore_ascii_case(h)))
+    })
+}
+
+/// Accept connections and forward them to the relay listening on loopback
+///
+/// Clients present a token either as `Authorization: Bearer <token>` or as a `token` query
+/// parameter of the WebSocket URL. Capability tokens (see `relay_issue_token`) are accepted
+/// from anywhere and restrict the connection. Without one, connections from loopback get
+/// full access, and other connections (LAN, reverse tunnel) must present the configured
+/// access token once it is set, or a capability token once one was issued. Origins with
+/// `OriginAccess::Denied` are refused.
+pub(crate) async fn run_gate(listener: TcpListener, relay_port: u16) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, relay_port).await {
+                tracing::debug!("Connection from {} closed: {}", peer, e);
+            }
+        });
+    }
+}
+
+/// Read the HTTP request head (up to the empty line)
+async fn read_request_head(stream: &mut TcpStream) -> Result<Vec<u8>, String> {
+    let mut head = Vec::with_capacity(1024);
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err("Request head too large".to_string());
+        }
+        let n = stream.read(&mut buf)
+            .await
+            .map_err(|e| format!("Failed to read request: {}", e))?;
+        if n == 0 {
+            return Err("Connection closed before request".to_string());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    Ok(head)
+}
+
+/// Token presented by the client, from the Authorization header or the `token` query parameter
+fn presented_token(head: &str) -> Option<String> {
+    let mut lines = head.lines();
+    let request_line = lines.next()?;
+    
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("authorization") {
+                if let Some(token) = value.trim().strip_prefix("Bearer ") {
+                    return Some(token.trim().to_string());
+                }
+            }
+        }
+    }
+    
+    let target = request_line.split_whitespace().nth(1)?;
+    let (_, query) = target.split_once('?')?;
+    query.split('&')
+        .find_map(|pair| pair.strip_prefix("token="))
+        .map(|token| token.to_string())
+}
+
+/// Compare without short-circuiting on the first differing byte
+pub(crate) fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Copy both directions until both are closed, following client frames for the protocol trace
+async fn forward(stream: &mut TcpStream, relay: &mut TcpStream, peer: SocketAddr) -> std::io::Result<()> {
+    let (mut client_read, mut client_write) = stream.split();
+    let (mut relay_read, mut relay_write) = relay.split();
+    
+    let upstream = async {
+        let mut frames = FrameReader::new(peer);
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = client_read.read(&mut buf).await?;
+            if n == 0 {
+                return relay_write.shutdown().await;
+            }
+            relay_write.write_all(&buf[..n]).await?;
+            frames.feed(&buf[..n]);
+        }
+    };
+    let downstream = async {
+        tokio::io::copy(&mut relay_read, &mut client_write).await?;
+        client_write.shutdown().await
+    };
+    tokio::try_join!(upstream, downstream).map(|_| ())
+}
+
+async fn handle_connection(mut stream: TcpStream, peer: SocketAddr, relay_port: u16) -> Result<(), String> {
+    let head = read_request_head(&mut stream).await?;
+    let head_text = String::from_utf8_lossy(&head);
+    
+    let config = policy::current_config();
+    let origin = classify(peer.ip(), has_forwarding_header(&head_text));
+    if config.origin_access(origin) == OriginAccess::Denied {
+        tracing::info!("Rejected connection from {} ({:?}): origin is denied", peer, origin);
+        let _ = stream.write_all(FORBIDDEN_RESPONSE).await;
+        return Ok(());
+    }
+    
+    let access_token = config.remote_access_token;
+    let presented = presented_token(&head_text);
+    let token_id = presented.as_deref().and_then(tokens::find_token);
+    
+    let authorized = match presented.as_deref() {
+        _ if token_id.is_some() => true,
+        Some(presented) if !access_token.is_empty() && tokens_match(presented, &access_token) => true,
+        // Unknown tokens are rejected even from loopback
+        Some(_) => false,
+        // Once clients are handed tokens, remote ones must present theirs
+        None => origin == ConnectionOrigin::Loopback || (access_token.is_empty() && !tokens::any_issued()),
+    };
+    if !authorized {
+        tracing::info!("Rejected connection from {} ({:?}): missing or invalid access token", peer, origin);
+        let _ = stream.write_all(UNAUTHORIZED_RESPONSE).await;
+        return Ok(());
+    }
+    tracing::info!("Accepted connection from {} ({:?}, token: {})", peer, origin, token_id.as_deref().unwrap_or("none"));
+    
+    let mut relay = TcpStream::connect((Ipv4Addr::LOCALHOST, relay_port))
+        .await
+        .map_err(|e| format!("Failed to connect to relay: {}", e))?;
+    let local_port = relay.local_addr()
+        .map_err(|e| format!("Failed to get local address: {}", e))?
+        .port();
+    
+    if let Ok(mut connections) = CONNECTIONS.lock() {
+        connections.get_or_insert_with(HashMap::new).insert(local_port, GatedConnection {
+            peer,
+            token_id,
+            origin,
+            connected_at: nostr_database::prelude::Timestamp::now().as_u64(),
+        });
+    }
+    
+    let result = async {
+        relay.write_all(&head)
+            .await
+            .map_err(|e| format!("Failed to forward request: {}", e))?;
+        forward(&mut stream, &mut relay, peer)
+            .await
+            .map_err(|e| e.to_string())
+    }.await;
+    
+    if let Ok(mut connections) = CONNECTIONS.lock() {
+        if let Some(connections) = connections.as_mut() {
+            connections.remove(&local_port);
+        }
+    }
+    
+    result.map(|_| ())
+}
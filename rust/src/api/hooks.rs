@@ -0,0 +1,219 @@
+use nostr_database::prelude::{Event, JsonUtil, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use crate::frb_generated::StreamSink;
+use super::watchdog::{emit, RelayStatusEvent};
+
+// One bit per kind with at least one hook, checked without locking on every write
+static KIND_BITS: [AtomicU64; 1024] = [const { AtomicU64::new(0) }; 1024];
+// Kind -> (hook id, sink)
+static KIND_HOOKS: Mutex<Option<HashMap<u16, Vec<(String, StreamSink<String>)>>>> = Mutex::new(None);
+// Whether a firehose is open, checked without locking on every write
+static FIREHOSE_OPEN: AtomicBool = AtomicBool::new(false);
+// (sampling threshold, sink)
+static FIREHOSES: Mutex<Vec<(u64, StreamSink<String>)>> = Mutex::new(Vec::new());
+// Whether write acks are configured, checked without locking on every write
+static ACKS_ENABLED: AtomicBool = AtomicBool::new(false);
+static WRITE_ACKS: RwLock<Option<WriteAckConfig>> = RwLock::new(None);
+
+fn set_bit(kind: u16, hooked: bool) {
+    let mask = 1u64 << (kind % 64);
+    if hooked {
+        KIND_BITS[kind as usize / 64].fetch_or(mask, Ordering::Relaxed);
+    } else {
+        KIND_BITS[kind as usize / 64].fetch_and(!mask, Ordering::Relaxed);
+    }
+}
+
+fn is_hooked(kind: u16) -> bool {
+    KIND_BITS[kind as usize / 64].load(Ordering::Relaxed) & (1u64 << (kind % 64)) != 0
+}
+
+/// Remove a hook from all kinds, clearing the bits of kinds left without hooks
+fn remove_hook(hooks: &mut HashMap<u16, Vec<(String, StreamSink<String>)>>, hook_id: &str) -> bool {
+    let mut removed = false;
+    hooks.retain(|kind, sinks| {
+        let before = sinks.len();
+        sinks.retain(|(id, _)| id != hook_id);
+        removed |= sinks.len() != before;
+        if sinks.is_empty() {
+            set_bit(*kind, false);
+        }
+        !sinks.is_empty()
+    });
+    removed
+}
+
+/// Forward an event stored (or relayed, for ephemeral kinds) by the relay to the hooks of its kind
+pub(crate) fn dispatch(event: &Event) {
+    let kind = event.kind.as_u16();
+    if !is_hooked(kind) {
+        return;
+    }
+    
+    let mut hooks = match KIND_HOOKS.lock() {
+        Ok(hooks) => hooks,
+        Err(_) => return,
+    };
+    let hooks = match hooks.as_mut() {
+        Some(hooks) => hooks,
+        None => return,
+    };
+    
+    let json = event.as_json();
+    let closed: Vec<String> = hooks.get(&kind)
+        .map(|sinks| {
+            sinks.iter()
+                .filter(|(_, sink)| sink.add(json.clone()).is_err())
+                .map(|(id, _)| id.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    // Dart side went away
+    for id in closed.iter() {
+        remove_hook(hooks, id);
+    }
+}
+
+/// Stream events (JSON) of the given kinds as the relay accepts them
+///
+/// Lighter than a REQ subscription for app-level reactions such as incoming DMs (1059) or
+/// zaps (9735): writes of other kinds only pay a bit lookup. Ephemeral kinds are delivered
+/// too, although the relay doesn't store them.
+///
+/// # Arguments
+/// * `hook_id` - Caller-chosen id, used to remove the hook (an existing hook with the same id is replaced)
+/// * `kinds` - Kinds to deliver
+pub fn relay_on_kind(hook_id: String, kinds: Vec<u16>, sink: StreamSink<String>) -> Result<(), String> {
+    if kinds.is_empty() {
+        return Err("No kinds given".to_string());
+    }
+    
+    let mut hooks = KIND_HOOKS.lock()
+        .map_err(|e| format!("Failed to lock kind hooks: {}", e))?;
+    let hooks = hooks.get_or_insert_with(HashMap::new);
+    remove_hook(hooks, &hook_id);
+    for kind in kinds {
+        hooks.entry(kind).or_default().push((hook_id.clone(), sink.clone()));
+        set_bit(kind, true);
+    }
+    Ok(())
+}
+
+/// Remove a kind hook, returns false if there was none with this id
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_remove_kind_hook(hook_id: String) -> Result<bool, String> {
+    let mut hooks = KIND_HOOKS.lock()
+        .map_err(|e| format!("Failed to lock kind hooks: {}", e))?;
+    Ok(hooks.as_mut().map_or(false, |hooks| remove_hook(hooks, &hook_id)))
+}
+
+/// Forward an event reaching the relay's database to the open firehoses it is sampled into
+pub(crate) fn tap(event: &Event) {
+    if !FIREHOSE_OPEN.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut firehoses = match FIREHOSES.lock() {
+        Ok(firehoses) => firehoses,
+        Err(_) => return,
+    };
+    
+    // Sample on the event id: random enough, and a copy arriving twice is sampled the same way
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&event.id.as_bytes()[..8]);
+    let sample = u64::from_be_bytes(prefix);
+    
+    let mut json = None;
+    firehoses.retain(|(threshold, sink)| {
+        if sample > *threshold {
+            return true;
+        }
+        let json = json.get_or_insert_with(|| event.as_json());
+        // Dart side went away
+        sink.add(json.clone()).is_ok()
+    });
+    FIREHOSE_OPEN.store(!firehoses.is_empty(), Ordering::Relaxed);
+}
+
+/// Stream a sample of every event (JSON) written to the relay, for debugging and activity views
+///
+/// Events are sampled before the relay decides whether to store them, so duplicates,
+/// ephemeral and replaced events show up too. Close the stream on the Dart side to stop it.
+///
+/// # Arguments
+/// * `sample_rate` - Share of events to deliver, from 0 (exclusive) to 1 (every event)
+pub fn relay_firehose(sample_rate: f64, sink: StreamSink<String>) -> Result<(), String> {
+    if !(sample_rate > 0.0 && sample_rate <= 1.0) {
+        return Err("Sample rate must be greater than 0 and at most 1".to_string());
+    }
+    let threshold = (sample_rate * u64::MAX as f64) as u64;
+    
+    let mut firehoses = FIREHOSES.lock()
+        .map_err(|e| format!("Failed to lock firehoses: {}", e))?;
+    firehoses.push((threshold, sink));
+    FIREHOSE_OPEN.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Own events to acknowledge on the status stream (see `relay_set_write_acks`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WriteAckConfig {
+    /// Hex public keys of the user's accounts
+    pub pubkeys: Vec<String>,
+    /// Kinds to acknowledge, empty for all kinds
+    pub kinds: Vec<u16>,
+    /// Also acknowledge when remote relays accept the event (outbox flush, DM relays, ...)
+    pub mirrored: bool,
+}
+
+/// Whether own events of the configured kinds are acknowledged, for an event of `pubkey` (hex)
+pub(crate) fn wants_ack(pubkey: &str, kind: u16, mirrored: bool) -> bool {
+    if !ACKS_ENABLED.load(Ordering::Relaxed) {
+        return false;
+    }
+    WRITE_ACKS.read()
+        .ok()
+        .and_then(|config| config.as_ref().map(|config| {
+            (!mirrored || config.mirrored)
+                && config.pubkeys.iter().any(|own| own == pubkey)
+                && (config.kinds.is_empty() || config.kinds.contains(&kind))
+        }))
+        .unwrap_or(false)
+}
+
+/// Acknowledge an own event once the relay stored it
+pub(crate) fn acknowledge(event: &Event) {
+    let kind = event.kind.as_u16();
+    if wants_ack(&event.pubkey.to_hex(), kind, false) {
+        emit(RelayStatusEvent::EventStored { event_id: event.id.to_hex(), kind });
+    }
+}
+
+/// Emit `RelayStatusEvent::EventStored` (and `EventMirrored`) on `relay_status_events` when
+/// the user's own events are saved, so optimistic UI can mark them as saved without polling
+///
+/// Replaces the previous configuration, empty `pubkeys` turns acknowledgments off.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_set_write_acks(config: WriteAckConfig) -> Result<(), String> {
+    let pubkeys = config.pubkeys.iter()
+        .map(|pubkey| PublicKey::from_hex(pubkey).map(|pubkey| pubkey.to_hex()))
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    
+    let mut acks = WRITE_ACKS.write()
+        .map_err(|e| format!("Failed to lock write acks: {}", e))?;
+    ACKS_ENABLED.store(!pubkeys.is_empty(), Ordering::Relaxed);
+    *acks = Some(WriteAckConfig { pubkeys, ..config });
+    Ok(())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_write_acks() -> WriteAckConfig {
+    WRITE_ACKS.read()
+        .ok()
+        .and_then(|config| config.clone())
+        .unwrap_or_default()
+}
@@ -0,0 +1,174 @@
+use nostr_database::prelude::{Event, Filter, JsonUtil, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use super::relay::query_local_events;
+use super::storage;
+
+/// Account id -> hex public key
+const IDENTITIES_TREE: &str = "identities";
+/// Account id + 0 + event id -> empty, events addressed to or sent by the identity
+const IDENTITY_EVENTS_TREE: &str = "identity_events";
+
+/// Kinds only visible to their participants: DMs (4), seals (13), chat and file
+/// messages (14, 15) and gift wraps (1059)
+pub(crate) const PRIVATE_KINDS: [u16; 5] = [4, 13, 14, 15, 1059];
+
+// Registered identities, loaded on first use (checked for every stored event)
+static IDENTITIES: Mutex<Option<Vec<Identity>>> = Mutex::new(None);
+
+/// Identity of a multi-account app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    /// App-chosen account id
+    pub account_id: String,
+    /// Hex public key
+    pub pubkey: String,
+}
+
+fn load_identities() -> Result<Vec<Identity>, String> {
+    let mut cache = IDENTITIES.lock()
+        .map_err(|e| format!("Failed to lock identities: {}", e))?;
+    if let Some(identities) = cache.as_ref() {
+        return Ok(identities.clone());
+    }
+    
+    let identities = storage::open_tree(IDENTITIES_TREE)?
+        .iter()
+        .map(|entry| {
+            let (account_id, pubkey) = entry.map_err(|e| format!("Failed to read identities: {}", e))?;
+            Ok(Identity {
+                account_id: String::from_utf8_lossy(&account_id).to_string(),
+                pubkey: String::from_utf8_lossy(&pubkey).to_string(),
+            })
+        })
+        .collect::<Result<Vec<Identity>, String>>()?;
+    *cache = Some(identities.clone());
+    Ok(identities)
+}
+
+fn invalidate_cache() {
+    if let Ok(mut cache) = IDENTITIES.lock() {
+        *cache = None;
+    }
+}
+
+fn event_key(account_id: &str, event_id: &[u8; 32]) -> Vec<u8> {
+    let mut key = account_id.as_bytes().to_vec();
+    key.push(0);
+    key.extend_from_slice(event_id);
+    key
+}
+
+/// Whether the event is sent by `pubkey` or addressed to it (`p` tag)
+fn involves(event: &Event, pubkey: &str) -> bool {
+    event.pubkey.to_hex() == pubkey
+        || event.tags.iter().any(|tag| matches!(tag.as_slice(), [name, value, ..] if name == "p" && value == pubkey))
+}
+
+fn is_private(event: &Event) -> bool {
+    PRIVATE_KINDS.contains(&event.kind.as_u16())
+}
+
+/// Tag a newly stored event with the identities it involves
+pub(crate) fn tag_event(event: &Event) -> Result<(), String> {
+    let identities = load_identities()?;
+    if identities.is_empty() {
+        return Ok(());
+    }
+    
+    let tree = storage::open_tree(IDENTITY_EVENTS_TREE)?;
+    for identity in identities.iter().filter(|identity| involves(event, &identity.pubkey)) {
+        tree.insert(event_key(&identity.account_id, event.id.as_bytes()), &[])
+            .map_err(|e| format!("Failed to tag event: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Register an identity and tag the events already stored for it
+///
+/// Once registered, newly stored DMs and mentions are tagged with the account, and
+/// `relay_query_for_identity` only returns the private events of that account.
+///
+/// # Arguments
+/// * `account_id` - App-chosen id of the account
+/// * `pubkey` - Hex public key of the account
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_register_identity(account_id: String, pubkey: String) -> Result<(), String> {
+    if account_id.is_empty() || account_id.contains('\0') {
+        return Err("Invalid account id".to_string());
+    }
+    let author = PublicKey::from_hex(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let pubkey = author.to_hex();
+    
+    storage::open_tree(IDENTITIES_TREE)?
+        .insert(account_id.as_bytes(), pubkey.as_bytes())
+        .map_err(|e| format!("Failed to store identity: {}", e))?;
+    invalidate_cache();
+    
+    // Backfill: events by the identity and events mentioning it
+    let tree = storage::open_tree(IDENTITY_EVENTS_TREE)?;
+    let mut events = query_local_events(Filter::new().author(author))?;
+    events.extend(query_local_events(Filter::new().pubkey(author))?);
+    for event in events.iter() {
+        tree.insert(event_key(&account_id, event.id.as_bytes()), &[])
+            .map_err(|e| format!("Failed to tag event: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Remove an identity and its event tags (the events themselves are kept)
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_unregister_identity(account_id: String) -> Result<bool, String> {
+    let previous = storage::open_tree(IDENTITIES_TREE)?
+        .remove(account_id.as_bytes())
+        .map_err(|e| format!("Failed to remove identity: {}", e))?;
+    invalidate_cache();
+    
+    let tree = storage::open_tree(IDENTITY_EVENTS_TREE)?;
+    let mut prefix = account_id.as_bytes().to_vec();
+    prefix.push(0);
+    for key in tree.scan_prefix(prefix).keys() {
+        let key = key.map_err(|e| format!("Failed to read event tags: {}", e))?;
+        tree.remove(key)
+            .map_err(|e| format!("Failed to remove event tag: {}", e))?;
+    }
+    Ok(previous.is_some())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_list_identities() -> Result<Vec<Identity>, String> {
+    load_identities()
+}
+
+/// Query the local database as seen by one identity
+///
+/// Public events are returned as by a normal query; private events (DMs, seals, gift
+/// wraps) only if they are tagged with `account_id`, so one account's cached DMs never
+/// show up in another account's views.
+///
+/// # Arguments
+/// * `account_id` - Id given to `relay_register_identity`
+/// * `filter_json` - NIP-01 filter
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_query_for_identity(account_id: String, filter_json: String) -> Result<Vec<String>, String> {
+    if !load_identities()?.iter().any(|identity| identity.account_id == account_id) {
+        return Err(format!("Unknown identity: {}", account_id));
+    }
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    
+    let tree = storage::open_tree(IDENTITY_EVENTS_TREE)?;
+    let mut events = Vec::new();
+    for event in query_local_events(filter)? {
+        if is_private(&event) {
+            let tagged = tree.contains_key(event_key(&account_id, event.id.as_bytes()))
+                .map_err(|e| format!("Failed to read event tags: {}", e))?;
+            if !tagged {
+                continue;
+            }
+        }
+        events.push(event.as_json());
+    }
+    Ok(events)
+}
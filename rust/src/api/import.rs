@@ -0,0 +1,178 @@
+use nostr_database::prelude::Event;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read};
+use std::time::Instant;
+use super::json;
+use super::relay::{get_database, run_blocking, save_parsed_event};
+
+/// Errors kept in the import result
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Result of an event import
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ImportResult {
+    /// Non-empty lines read
+    pub total: u32,
+    pub imported: u32,
+    /// Events already in the database (or rejected by it, e.g. deleted)
+    pub skipped: u32,
+    pub invalid: u32,
+    /// First errors, formatted as "line <n>: <error>"
+    pub errors: Vec<String>,
+}
+
+impl ImportResult {
+    fn record_error(&mut self, line: usize, error: String) {
+        self.invalid += 1;
+        if self.errors.len() < MAX_REPORTED_ERRORS {
+            self.errors.push(format!("line {}: {}", line, error));
+        }
+    }
+}
+
+/// Verify and save a batch of parsed events, events with a bad id or signature count as invalid
+fn save_batch(batch: Vec<(usize, Event)>, result: &mut ImportResult) -> Result<(), String> {
+    // Verified here, off the runtime, before anything reaches the database
+    let batch: Vec<(usize, Event)> = batch.into_iter()
+        .filter_map(|(line, event)| match event.verify() {
+            Ok(()) => Some((line, event)),
+            Err(e) => {
+                result.record_error(line, format!("Invalid event: {}", e));
+                None
+            }
+        })
+        .collect();
+    if batch.is_empty() {
+        return Ok(());
+    }
+    
+    let outcomes = run_blocking(async move {
+        let mut outcomes = Vec::with_capacity(batch.len());
+        for (line, event) in batch {
+            outcomes.push((line, save_parsed_event(&event).await));
+        }
+        outcomes
+    })?;
+    
+    for (line, outcome) in outcomes {
+        match outcome {
+            Ok(true) => result.imported += 1,
+            Ok(false) => result.skipped += 1,
+            Err(e) => result.record_error(line, e),
+        }
+    }
+    Ok(())
+}
+
+/// Import events from a JSONL stream (one event per line) into the relay database
+///
+/// The stream is parsed line by line, so memory use doesn't grow with its size. Lines are
+/// parsed and verified here, off the runtime, with the JSON backend of the build (see
+/// `json::BACKEND`). Events failing id or signature verification are counted as invalid.
+fn import_jsonl<R: Read>(reader: R) -> Result<ImportResult, String> {
+    // Fail early instead of reporting every line as an error
+    get_database()?;
+    
+    let mut result = ImportResult::default();
+    // Events saved per database round trip
+    let batch_size = super::system::resource_limits().import_batch_size.max(1) as usize;
+    let mut batch = Vec::with_capacity(batch_size);
+    
+    for (index, line) in BufReader::new(reader).split(b'\n').enumerate() {
+        let line_number = index + 1;
+        let mut line = line.map_err(|e| format!("Failed to read events: {}", e))?;
+        if line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        
+        result.total += 1;
+        let event = match json::parse_event(&mut line) {
+            Ok(event) => event,
+            Err(e) => {
+                result.record_error(line_number, e);
+                continue;
+            }
+        };
+        batch.push((line_number, event));
+        if batch.len() >= batch_size {
+            save_batch(std::mem::take(&mut batch), &mut result)?;
+        }
+    }
+    if !batch.is_empty() {
+        save_batch(batch, &mut result)?;
+    }
+    
+    tracing::info!("Imported {} of {} events", result.imported, result.total);
+    Ok(result)
+}
+
+/// Import events from JSONL bytes (e.g. read from a `content://` URI on the Dart side)
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_import_events_from_bytes(data: Vec<u8>) -> Result<ImportResult, String> {
+    import_jsonl(data.as_slice())
+}
+
+/// Import events from JSONL read from a platform file descriptor
+///
+/// Takes ownership of the descriptor and closes it when done (on Android, pass the result
+/// of `ParcelFileDescriptor.detachFd()`).
+#[cfg(unix)]
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_import_events_from_fd(fd: i32) -> Result<ImportResult, String> {
+    use std::os::fd::FromRawFd;
+    
+    if fd < 0 {
+        return Err(format!("Invalid file descriptor: {}", fd));
+    }
+    // SAFETY: the caller transfers ownership of an open descriptor
+    let file = unsafe { std::fs::File::from_raw_fd(fd) };
+    import_jsonl(file)
+}
+
+#[cfg(not(unix))]
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_import_events_from_fd(_fd: i32) -> Result<ImportResult, String> {
+    Err("File descriptors are not supported on this platform".to_string())
+}
+
+/// Time spent parsing and verifying a sample of events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportBenchmark {
+    /// JSON parser of this build ("serde_json" or "simd-json")
+    pub json_backend: String,
+    pub events: u32,
+    pub invalid: u32,
+    pub parse_ms: f64,
+    /// Id and signature verification of the parsed events
+    pub verify_ms: f64,
+}
+
+/// Measure where import time goes, without writing to the database
+///
+/// Parses JSONL `data` with the JSON backend of the build and verifies every event, so
+/// apps can tell whether imports are bound by JSON parsing or by signature checks.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_benchmark_import(data: Vec<u8>) -> ImportBenchmark {
+    let mut lines: Vec<Vec<u8>> = data.split(|byte| *byte == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| line.to_vec())
+        .collect();
+    
+    let started = Instant::now();
+    let events: Vec<Event> = lines.iter_mut()
+        .filter_map(|line| json::parse_event(line).ok())
+        .collect();
+    let parse_ms = started.elapsed().as_secs_f64() * 1000.0;
+    
+    let started = Instant::now();
+    let invalid_signatures = events.iter().filter(|event| event.verify().is_err()).count();
+    let verify_ms = started.elapsed().as_secs_f64() * 1000.0;
+    
+    ImportBenchmark {
+        json_backend: json::BACKEND.to_string(),
+        events: events.len() as u32,
+        invalid: (lines.len() - events.len() + invalid_signatures) as u32,
+        parse_ms,
+        verify_ms,
+    }
+}
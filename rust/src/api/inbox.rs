@@ -0,0 +1,294 @@
+use nostr::event::{Event, EventBuilder, Kind, Tag, UnsignedEvent};
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::{nip04, nip44};
+use nostr::JsonUtil;
+use nostr_database::prelude::{Filter as DbFilter, Kind as DbKind, PublicKey as DbPublicKey, Timestamp as DbTimestamp};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+use super::nostr::randomized_created_at;
+use super::relay::query_local_events_json;
+use super::signer::Signer;
+
+/// Gift wraps are backdated by up to two days (NIP-59)
+const GIFT_WRAP_BACKDATE_SECS: u64 = 2 * 24 * 60 * 60;
+
+/// Encryption scheme of a direct message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DmProtocol {
+    /// Kind 4 (NIP-04)
+    Nip04,
+    /// Gift-wrapped rumor (NIP-17/NIP-59)
+    GiftWrap,
+}
+
+/// Decrypted direct message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxMessage {
+    /// Id of the stored event (the gift wrap for NIP-17)
+    pub event_id: String,
+    pub protocol: DmProtocol,
+    /// Kind of the message (e.g. 14 for NIP-17 chat messages)
+    pub kind: u16,
+    /// Hex public key of the author
+    pub sender: String,
+    /// Canonical timestamp of the message (the rumor's, not the randomized wrap's)
+    pub created_at: u64,
+    pub content: String,
+    /// Unsigned rumor JSON for gift wraps, event JSON for NIP-04
+    pub event_json: String,
+}
+
+/// Decrypted messages of one account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboxAccount {
+    /// Hex public key of the account
+    pub pubkey: String,
+    /// Messages oldest first
+    pub messages: Vec<InboxMessage>,
+    /// Events addressed to the account that could not be decrypted
+    pub failed: u32,
+}
+
+/// Seal a rumor and gift wrap it for `receiver` (NIP-59)
+///
+/// The seal is signed by `sender`, the wrap by a throwaway key; both get a randomized created_at.
+pub(crate) fn gift_wrap_rumor(sender: &Keys, receiver: &PublicKey, mut rumor: UnsignedEvent) -> Result<Event, String> {
+    rumor.ensure_id();
+    
+    let sealed = nip44::encrypt(sender.secret_key(), receiver, rumor.as_json(), nip44::Version::V2)
+        .map_err(|e| format!("NIP-44 encryption failed: {}", e))?;
+    let seal = EventBuilder::new(Kind::Seal, sealed)
+        .custom_created_at(randomized_created_at())
+        .sign_with_keys(sender)
+        .map_err(|e| format!("Failed to sign seal: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &sender.public_key().to_hex(), Some(seal.kind.as_u16()));
+    
+    wrap_seal(&seal, receiver)
+}
+
+/// `gift_wrap_rumor` with a platform or in-memory signer sealing the rumor
+pub(crate) async fn gift_wrap_rumor_with_signer(sender: &Signer, receiver: &PublicKey, mut rumor: UnsignedEvent) -> Result<Event, String> {
+    rumor.ensure_id();
+    
+    let sealed = sender.encrypt(receiver, rumor.as_json(), true).await?;
+    let seal = EventBuilder::new(Kind::Seal, sealed)
+        .custom_created_at(randomized_created_at())
+        .build(sender.nostr_public_key());
+    let seal = sender.sign_unsigned(seal).await?;
+    
+    wrap_seal(&seal, receiver)
+}
+
+/// Gift wrap a seal for `receiver` with a throwaway key
+fn wrap_seal(seal: &Event, receiver: &PublicKey) -> Result<Event, String> {
+    let wrap_keys = super::session_keys::one_time_keys();
+    let wrapped = nip44::encrypt(wrap_keys.secret_key(), receiver, seal.as_json(), nip44::Version::V2)
+        .map_err(|e| format!("NIP-44 encryption failed: {}", e))?;
+    EventBuilder::new(Kind::GiftWrap, wrapped)
+        .tag(Tag::public_key(*receiver))
+        .custom_created_at(randomized_created_at())
+        .sign_with_keys(&wrap_keys)
+        .map_err(|e| format!("Failed to sign gift wrap: {}", e))
+}
+
+/// Unwrap a gift wrap (kind 1059) addressed to `keys`, returns the seal author and the rumor
+pub(crate) fn unwrap_gift_wrap(keys: &Keys, gift_wrap: &Event) -> Result<(PublicKey, UnsignedEvent), String> {
+    let seal_json = nip44::decrypt(keys.secret_key(), &gift_wrap.pubkey, &gift_wrap.content)
+        .map_err(|e| format!("Failed to decrypt gift wrap: {}", e))?;
+    audit::record(KeyOperation::Nip44Decrypt, &keys.public_key().to_hex(), Some(gift_wrap.kind.as_u16()));
+    
+    let seal = Event::from_json(&seal_json)
+        .map_err(|e| format!("Invalid seal: {}", e))?;
+    if seal.kind != Kind::Seal {
+        return Err(format!("Invalid seal kind {}", seal.kind));
+    }
+    seal.verify()
+        .map_err(|e| format!("Invalid seal: {}", e))?;
+    
+    let rumor_json = nip44::decrypt(keys.secret_key(), &seal.pubkey, &seal.content)
+        .map_err(|e| format!("Failed to decrypt seal: {}", e))?;
+    audit::record(KeyOperation::Nip44Decrypt, &keys.public_key().to_hex(), Some(seal.kind.as_u16()));
+    
+    let rumor = UnsignedEvent::from_json(&rumor_json)
+        .map_err(|e| format!("Invalid rumor: {}", e))?;
+    // The rumor must be from the one who signed the seal
+    if rumor.pubkey != seal.pubkey {
+        return Err("Rumor author does not match seal signer".to_string());
+    }
+    
+    Ok((seal.pubkey, rumor))
+}
+
+fn decrypt_nip04(keys: &Keys, event: &Event) -> Result<InboxMessage, String> {
+    let me = keys.public_key();
+    let counterparty = if event.pubkey == me {
+        event.tags.public_keys().next().copied()
+            .ok_or_else(|| "Direct message has no recipient".to_string())?
+    } else {
+        event.pubkey
+    };
+    
+    let content = nip04::decrypt(keys.secret_key(), &counterparty, &event.content)
+        .map_err(|e| format!("Decryption failed: {}", e))?;
+    audit::record(KeyOperation::Nip04Decrypt, &me.to_hex(), Some(event.kind.as_u16()));
+    
+    Ok(InboxMessage {
+        event_id: event.id.to_hex(),
+        protocol: DmProtocol::Nip04,
+        kind: event.kind.as_u16(),
+        sender: event.pubkey.to_hex(),
+        created_at: event.created_at.as_u64(),
+        content,
+        event_json: event.as_json(),
+    })
+}
+
+fn decrypt_gift_wrap(keys: &Keys, event: &Event) -> Result<InboxMessage, String> {
+    let (sender, rumor) = unwrap_gift_wrap(keys, event)?;
+    Ok(InboxMessage {
+        event_id: event.id.to_hex(),
+        protocol: DmProtocol::GiftWrap,
+        kind: rumor.kind.as_u16(),
+        sender: sender.to_hex(),
+        created_at: rumor.created_at.as_u64(),
+        content: rumor.content.clone(),
+        event_json: rumor.as_json(),
+    })
+}
+
+/// Stored direct messages (kind 4 and 1059) involving an account
+fn stored_messages(pubkey: &PublicKey, since: u64) -> Result<Vec<Event>, String> {
+    let db_pubkey = DbPublicKey::from_slice(&pubkey.to_bytes())
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    
+    let received = DbFilter::new()
+        .kinds([DbKind::EncryptedDirectMessage, DbKind::GiftWrap])
+        .pubkey(db_pubkey)
+        .since(DbTimestamp::from(since.saturating_sub(GIFT_WRAP_BACKDATE_SECS)));
+    let sent = DbFilter::new()
+        .kind(DbKind::EncryptedDirectMessage)
+        .author(db_pubkey)
+        .since(DbTimestamp::from(since));
+    
+    let mut events = query_local_events_json(received)?;
+    events.extend(query_local_events_json(sent)?);
+    
+    Ok(events.iter()
+        .filter_map(|json| Event::from_json(json).ok())
+        .collect())
+}
+
+/// Decrypt the direct messages of several accounts from the local database in one call
+///
+/// Scans NIP-04 (kind 4) and gift-wrapped (kind 1059) messages sent to or by each account
+/// and returns the decrypted messages grouped by account.
+///
+/// # Arguments
+/// * `secrets` - Private keys (hex or nsec) of the accounts
+/// * `since` - Only messages created at or after this unix timestamp
+#[flutter_rust_bridge::frb(sync)]
+pub fn decrypt_inbox(secrets: Vec<String>, since: u64) -> Result<Vec<InboxAccount>, String> {
+    secrets.iter()
+        .map(|secret| {
+            let secret_key = SecretKey::from_str(secret)
+                .map_err(|e| format!("Invalid private key: {}", e))?;
+            let keys = Keys::new(secret_key);
+            let pubkey = keys.public_key();
+            
+            let mut account = InboxAccount {
+                pubkey: pubkey.to_hex(),
+                messages: Vec::new(),
+                failed: 0,
+            };
+            let mut seen = std::collections::HashSet::new();
+            for event in stored_messages(&pubkey, since)? {
+                if !seen.insert(event.id) {
+                    continue;
+                }
+                let message = match event.kind {
+                    Kind::GiftWrap => decrypt_gift_wrap(&keys, &event),
+                    _ => decrypt_nip04(&keys, &event),
+                };
+                match message {
+                    Ok(message) if message.created_at >= since => account.messages.push(message),
+                    Ok(_) => {}
+                    Err(_) => account.failed += 1,
+                }
+            }
+            account.messages.sort_by_key(|message| message.created_at);
+            
+            Ok(account)
+        })
+        .collect()
+}
+
+/// Rumor taken out of a gift wrap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnwrappedGift {
+    /// Hex public key of the sender (the seal signer)
+    pub sender: String,
+    /// Unsigned rumor JSON
+    pub rumor_json: String,
+}
+
+fn parse_secret_keys(private_key: &str) -> Result<Keys, String> {
+    SecretKey::from_str(private_key)
+        .map(Keys::new)
+        .map_err(|e| format!("Invalid private key: {}", e))
+}
+
+/// Seal a rumor and gift wrap it for a receiver (NIP-59)
+///
+/// The rumor stays unsigned so it is deniable. Its `pubkey` must be the sender's, the id
+/// is computed if missing. Returns the gift wrap (kind 1059) JSON, ready to publish.
+///
+/// # Arguments
+/// * `rumor_json` - Unsigned event JSON (a `sig` is rejected)
+/// * `receiver_pubkey` - Public key of the receiver (hex or npub)
+/// * `sender_private_key` - Private key of the sender (hex or nsec)
+#[flutter_rust_bridge::frb(sync)]
+pub fn gift_wrap(rumor_json: String, receiver_pubkey: String, sender_private_key: String) -> Result<String, String> {
+    let sender = parse_secret_keys(&sender_private_key)?;
+    let receiver = PublicKey::parse(&receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    
+    // A signed rumor would be attributable if it leaked
+    let value: serde_json::Value = serde_json::from_str(&rumor_json)
+        .map_err(|e| format!("Invalid rumor JSON: {}", e))?;
+    if value.get("sig").is_some() {
+        return Err("Rumor must not be signed".to_string());
+    }
+    let rumor = UnsignedEvent::from_json(&rumor_json)
+        .map_err(|e| format!("Invalid rumor JSON: {}", e))?;
+    if rumor.pubkey != sender.public_key() {
+        return Err("Rumor author does not match the sender key".to_string());
+    }
+    
+    gift_wrap_rumor(&sender, &receiver, rumor).map(|wrap| wrap.as_json())
+}
+
+/// Open a gift wrap (NIP-59) addressed to the receiver key
+///
+/// Checks the seal signature and that the rumor is from the seal signer.
+///
+/// # Arguments
+/// * `event_json` - Gift wrap (kind 1059)
+/// * `receiver_private_key` - Private key of the receiver (hex or nsec)
+#[flutter_rust_bridge::frb(sync)]
+pub fn unwrap_gift(event_json: String, receiver_private_key: String) -> Result<UnwrappedGift, String> {
+    let keys = parse_secret_keys(&receiver_private_key)?;
+    let gift_wrap = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if gift_wrap.kind != Kind::GiftWrap {
+        return Err(format!("Not a gift wrap: kind {}", gift_wrap.kind));
+    }
+    gift_wrap.verify()
+        .map_err(|e| format!("Invalid gift wrap: {}", e))?;
+    
+    let (sender, rumor) = unwrap_gift_wrap(&keys, &gift_wrap)?;
+    Ok(UnwrappedGift {
+        sender: sender.to_hex(),
+        rumor_json: rumor.as_json(),
+    })
+}
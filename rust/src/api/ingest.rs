@@ -0,0 +1,239 @@
+use nostr_database::prelude::*;
+use nostr_ndb::NdbDatabase;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::Instrument;
+use super::metrics::{self, IngestStage};
+use super::{hooks, identities, policy, proxy, storage};
+
+/// Side effect of ingesting an event, run right away or held back (see `IngestDatabase::deferred`)
+#[derive(Debug)]
+enum IngestEffect {
+    /// Seen by the firehose
+    Tap(Event),
+    /// Relayed without being stored (ephemeral)
+    Dispatch(Event),
+    /// Stored
+    Saved(Event),
+    /// Replaced by a newer version, or arrived out of date
+    Superseded(Event),
+}
+
+/// Database handed to the relay: delegates to NDB and hooks into event ingestion
+#[derive(Debug)]
+pub(crate) struct IngestDatabase {
+    inner: Arc<NdbDatabase>,
+    /// Side effects held back until `release_deferred`, None to run them right away
+    deferred: Option<Mutex<Vec<IngestEffect>>>,
+}
+
+impl IngestDatabase {
+    pub(crate) fn new(inner: Arc<NdbDatabase>) -> Self {
+        Self { inner, deferred: None }
+    }
+    
+    /// Ingestion that holds back hooks, acknowledgements, identity tags and history until
+    /// `release_deferred`, for transactions that may still be rolled back (dropping the
+    /// database discards them)
+    pub(crate) fn deferred(inner: Arc<NdbDatabase>) -> Self {
+        Self { inner, deferred: Some(Mutex::new(Vec::new())) }
+    }
+    
+    /// Run the side effects held back so far
+    pub(crate) fn release_deferred(&self) {
+        let effects = match &self.deferred {
+            Some(deferred) => deferred.lock().map(|mut effects| std::mem::take(&mut *effects)).unwrap_or_default(),
+            None => return,
+        };
+        for effect in effects {
+            self.run_effect(effect);
+        }
+    }
+    
+    fn effect(&self, effect: IngestEffect) {
+        match &self.deferred {
+            Some(deferred) => match deferred.lock() {
+                Ok(mut effects) => effects.push(effect),
+                Err(e) => tracing::warn!("Failed to lock deferred effects: {}", e),
+            },
+            None => self.run_effect(effect),
+        }
+    }
+    
+    fn run_effect(&self, effect: IngestEffect) {
+        match effect {
+            IngestEffect::Tap(event) => hooks::tap(&event),
+            IngestEffect::Dispatch(event) => {
+                let started = Instant::now();
+                hooks::dispatch(&event);
+                metrics::record(IngestStage::FanOut, started.elapsed());
+            }
+            IngestEffect::Saved(event) => self.on_event_saved(&event),
+            IngestEffect::Superseded(event) => record_superseded(&event),
+        }
+    }
+    
+    /// Called after an event has been stored
+    fn on_event_saved(&self, event: &Event) {
+        let _span = tracing::debug_span!("ingest.fan_out").entered();
+        let started = Instant::now();
+        if let Err(e) = storage::record_received_at(event.id.as_bytes(), Timestamp::now().as_u64()) {
+            tracing::warn!("Failed to record received_at for {}: {}", event.id, e);
+        }
+        if let Err(e) = identities::tag_event(event) {
+            tracing::warn!("Failed to tag {} with identities: {}", event.id, e);
+        }
+        hooks::dispatch(event);
+        hooks::acknowledge(event);
+        metrics::record(IngestStage::FanOut, started.elapsed());
+    }
+}
+
+/// Current stored version of a replaceable or addressable event
+pub(crate) async fn current_version(database: &NdbDatabase, event: &Event) -> Option<Event> {
+    let mut filter = Filter::new().author(event.pubkey).kind(event.kind).limit(1);
+    if event.kind.is_addressable() {
+        filter = filter.identifier(event.tags.identifier().unwrap_or_default());
+    }
+    database.query(filter).await.ok()?.into_iter().next()
+}
+
+/// Keep the version that lost when a replaceable event was saved
+pub(crate) fn record_superseded(superseded: &Event) {
+    let identifier = if superseded.kind.is_addressable() {
+        superseded.tags.identifier().unwrap_or_default()
+    } else {
+        ""
+    };
+    let result = storage::record_history(
+        &superseded.pubkey.to_hex(),
+        superseded.kind.as_u16(),
+        identifier,
+        superseded.created_at.as_u64(),
+        superseded.id.as_bytes(),
+        &superseded.as_json(),
+    );
+    if let Err(e) = result {
+        tracing::warn!("Failed to record history of {}: {}", superseded.id, e);
+    }
+}
+
+/// Delete events matching the filter, keeping tombstones of them when enabled
+pub(crate) async fn delete_events(database: &NdbDatabase, filter: Filter) -> Result<(), DatabaseError> {
+    if policy::current_config().record_tombstones {
+        let ids: Vec<[u8; 32]> = database.negentropy_items(filter.clone())
+            .await?
+            .into_iter()
+            .map(|(id, _)| id.to_bytes())
+            .collect();
+        if let Err(e) = storage::record_tombstones(&ids, Timestamp::now().as_u64()) {
+            tracing::warn!("Failed to record {} tombstones: {}", ids.len(), e);
+        }
+    }
+    database.delete(filter).await
+}
+
+impl NostrDatabase for IngestDatabase {
+    fn backend(&self) -> Backend {
+        self.inner.backend()
+    }
+    
+    fn features(&self) -> Features {
+        self.inner.features()
+    }
+    
+    fn save_event<'a>(&'a self, event: &'a Event) -> BoxedFuture<'a, Result<SaveEventStatus, DatabaseError>> {
+        Box::pin(async move {
+            self.effect(IngestEffect::Tap(event.clone()));
+            // Ephemeral events (typing indicators, ...) are only relayed
+            if event.kind.is_ephemeral() {
+                self.effect(IngestEffect::Dispatch(event.clone()));
+                return Ok(SaveEventStatus::Rejected(RejectedReason::Ephemeral));
+            }
+            // Deleted on purpose, don't let a sync bring it back
+            if storage::is_tombstoned(event.id.as_bytes()).unwrap_or(false) {
+                return Ok(SaveEventStatus::Rejected(RejectedReason::Deleted));
+            }
+            
+            let keep_history = (event.kind.is_replaceable() || event.kind.is_addressable())
+                && policy::current_config().keep_replaceable_history;
+            let previous = if keep_history { current_version(&self.inner, event).await } else { None };
+            
+            let started = Instant::now();
+            let status = self.inner.save_event(event)
+                .instrument(tracing::debug_span!("ingest.store", kind = event.kind.as_u16()))
+                .await?;
+            metrics::record(IngestStage::Store, started.elapsed());
+            if status.is_success() {
+                self.effect(IngestEffect::Saved(event.clone()));
+            }
+            
+            // Either the previous version was replaced, or the new one arrived out of date
+            if let Some(previous) = previous.filter(|previous| previous.id != event.id) {
+                if status.is_success() {
+                    self.effect(IngestEffect::Superseded(previous));
+                } else if event.created_at < previous.created_at {
+                    self.effect(IngestEffect::Superseded(event.clone()));
+                }
+            }
+            Ok(status)
+        })
+    }
+    
+    fn check_id<'a>(&'a self, event_id: &'a EventId) -> BoxedFuture<'a, Result<DatabaseEventStatus, DatabaseError>> {
+        self.inner.check_id(event_id)
+    }
+    
+    fn event_by_id<'a>(&'a self, event_id: &'a EventId) -> BoxedFuture<'a, Result<Option<Event>, DatabaseError>> {
+        self.inner.event_by_id(event_id)
+    }
+    
+    fn count(&self, filter: Filter) -> BoxedFuture<Result<usize, DatabaseError>> {
+        self.inner.count(filter)
+    }
+    
+    fn query(&self, filter: Filter) -> BoxedFuture<Result<Events, DatabaseError>> {
+        Box::pin(async move {
+            let events = self.inner.query(filter.clone()).await?;
+            if !proxy::should_forward(&filter, events.len()) {
+                return Ok(events);
+            }
+            
+            // Cache miss: fetch from upstream relays, store, and answer from the database
+            let fetched = match proxy::fetch_upstream(&filter).await {
+                Ok(fetched) => fetched,
+                Err(e) => {
+                    tracing::warn!("Upstream query failed: {}", e);
+                    return Ok(events);
+                }
+            };
+            
+            let mut stored = 0;
+            for json in fetched {
+                if let Ok(event) = Event::from_json(&json) {
+                    if event.verify().is_ok() && self.save_event(&event).await.map_or(false, |s| s.is_success()) {
+                        stored += 1;
+                    }
+                }
+            }
+            
+            if stored == 0 {
+                return Ok(events);
+            }
+            tracing::info!("Cached {} events from upstream relays", stored);
+            self.inner.query(filter).await
+        })
+    }
+    
+    fn negentropy_items(&self, filter: Filter) -> BoxedFuture<Result<Vec<(EventId, Timestamp)>, DatabaseError>> {
+        self.inner.negentropy_items(filter)
+    }
+    
+    fn delete(&self, filter: Filter) -> BoxedFuture<Result<(), DatabaseError>> {
+        Box::pin(delete_events(&self.inner, filter))
+    }
+    
+    fn wipe(&self) -> BoxedFuture<Result<(), DatabaseError>> {
+        self.inner.wipe()
+    }
+}
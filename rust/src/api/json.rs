@@ -0,0 +1,21 @@
+use nostr_database::prelude::Event;
+
+/// JSON parser used for imported events
+#[cfg(feature = "simd-json")]
+pub(crate) const BACKEND: &str = "simd-json";
+#[cfg(not(feature = "simd-json"))]
+pub(crate) const BACKEND: &str = "serde_json";
+
+/// Parse an event from JSON bytes, the hot path of imports
+///
+/// Deserializes straight from the buffer (no intermediate `String` or `Value`). With the
+/// `simd-json` feature the buffer is parsed in place, which is why it must be mutable.
+#[cfg(feature = "simd-json")]
+pub(crate) fn parse_event(json: &mut [u8]) -> Result<Event, String> {
+    simd_json::serde::from_slice(json).map_err(|e| format!("Invalid event JSON: {}", e))
+}
+
+#[cfg(not(feature = "simd-json"))]
+pub(crate) fn parse_event(json: &mut [u8]) -> Result<Event, String> {
+    serde_json::from_slice(json).map_err(|e| format!("Invalid event JSON: {}", e))
+}
@@ -0,0 +1,122 @@
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::nip04;
+use nostr::nips::nip44;
+use nostr::nips::nip49::{EncryptedSecretKey, KeySecurity};
+use nostr::nips::nip19::{FromBech32, ToBech32};
+use std::str::FromStr;
+use zeroize::Zeroizing;
+use super::audit::{self, KeyOperation};
+use super::nostr::{parse_secret_key, sign_event_with_keys, KeyImportError, NIP49_DEFAULT_LOG_N};
+
+/// Private key kept on the Rust side, Dart only holds an opaque reference to it
+///
+/// The key bytes are zeroized when the handle is dropped (when Dart disposes it or it's
+/// garbage collected). Operations build the signing keys on the stack for the duration of
+/// the call only.
+#[flutter_rust_bridge::frb(opaque)]
+pub struct SecretKeyHandle {
+    secret: Zeroizing<[u8; 32]>,
+    public_key: PublicKey,
+}
+
+impl SecretKeyHandle {
+    fn new(secret_key: &SecretKey) -> Self {
+        let keys = Keys::new(secret_key.clone());
+        Self {
+            secret: Zeroizing::new(secret_key.to_secret_bytes()),
+            public_key: keys.public_key(),
+        }
+    }
+    
+    fn keys(&self) -> Result<Keys, String> {
+        let secret_key = SecretKey::from_slice(&self.secret[..])
+            .map_err(|e| format!("Invalid key handle: {}", e))?;
+        Ok(Keys::new(secret_key))
+    }
+    
+    fn peer(public_key: &str) -> Result<PublicKey, String> {
+        PublicKey::from_str(public_key).map_err(|e| format!("Invalid public key: {}", e))
+    }
+    
+    /// Hex public key of the handle
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn public_key(&self) -> String {
+        self.public_key.to_hex()
+    }
+    
+    /// Bech32 public key (npub) of the handle
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn npub(&self) -> Result<String, String> {
+        self.public_key.to_bech32()
+            .map_err(|e| format!("Failed to encode npub: {}", e))
+    }
+    
+    /// Sign an unsigned event (JSON), like `sign_event`
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn sign_event(&self, event_json: String) -> Result<String, String> {
+        sign_event_with_keys(&event_json, &self.keys()?)
+    }
+    
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn nip04_encrypt(&self, plaintext: String, public_key: String) -> Result<String, String> {
+        nip04::encrypt(self.keys()?.secret_key(), &Self::peer(&public_key)?, plaintext)
+            .map_err(|e| format!("Encryption failed: {}", e))
+    }
+    
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn nip04_decrypt(&self, ciphertext: String, public_key: String) -> Result<String, String> {
+        let decrypted = nip04::decrypt(self.keys()?.secret_key(), &Self::peer(&public_key)?, ciphertext)
+            .map_err(|e| format!("Decryption failed: {}", e))?;
+        audit::record(KeyOperation::Nip04Decrypt, &self.public_key.to_hex(), None);
+        Ok(decrypted)
+    }
+    
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn nip44_encrypt(&self, plaintext: String, public_key: String) -> Result<String, String> {
+        nip44::encrypt(self.keys()?.secret_key(), &Self::peer(&public_key)?, plaintext, nip44::Version::V2)
+            .map_err(|e| format!("NIP-44 encryption failed: {}", e))
+    }
+    
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn nip44_decrypt(&self, ciphertext: String, public_key: String) -> Result<String, String> {
+        let decrypted = nip44::decrypt(self.keys()?.secret_key(), &Self::peer(&public_key)?, ciphertext)
+            .map_err(|e| format!("NIP-44 decryption failed: {}", e))?;
+        audit::record(KeyOperation::Nip44Decrypt, &self.public_key.to_hex(), None);
+        Ok(decrypted)
+    }
+    
+    /// Export the key encrypted with a password (NIP-49), the only way to get it out of the handle
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn export_ncryptsec(&self, password: String, log_n: Option<u8>) -> Result<String, String> {
+        let keys = self.keys()?;
+        let encrypted = EncryptedSecretKey::new(keys.secret_key(), password, log_n.unwrap_or(NIP49_DEFAULT_LOG_N), KeySecurity::Unknown)
+            .map_err(|e| format!("NIP-49 encryption failed: {}", e))?;
+        encrypted.to_bech32()
+            .map_err(|e| format!("Failed to encode ncryptsec: {}", e))
+    }
+}
+
+/// Generate a new key, returning only a handle to it
+#[flutter_rust_bridge::frb(sync)]
+pub fn key_handle_generate() -> SecretKeyHandle {
+    SecretKeyHandle::new(Keys::generate().secret_key())
+}
+
+/// Move an existing key (hex or nsec) into a handle
+///
+/// The string passed in is still on the Dart heap, drop it right after the call.
+#[flutter_rust_bridge::frb(sync)]
+pub fn key_handle_import(secret: String) -> Result<SecretKeyHandle, KeyImportError> {
+    let secret = Zeroizing::new(secret);
+    Ok(SecretKeyHandle::new(&parse_secret_key(&secret)?))
+}
+
+/// Decrypt a NIP-49 ncryptsec straight into a handle, the key never reaches Dart
+#[flutter_rust_bridge::frb(sync)]
+pub fn key_handle_from_ncryptsec(ncryptsec: String, password: String) -> Result<SecretKeyHandle, String> {
+    let encrypted = EncryptedSecretKey::from_bech32(ncryptsec.trim())
+        .map_err(|e| format!("Invalid ncryptsec: {}", e))?;
+    let secret_key = encrypted.decrypt(password)
+        .map_err(|e| format!("NIP-49 decryption failed: {}", e))?;
+    Ok(SecretKeyHandle::new(&secret_key))
+}
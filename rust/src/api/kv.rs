@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use super::storage;
+
+/// Trees of the auxiliary store holding KV namespaces are prefixed to keep them
+/// apart from the plugin's internal trees
+const NAMESPACE_PREFIX: &str = "kv:";
+
+/// Key-value pair of a namespace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KvEntry {
+    pub key: String,
+    pub value: String,
+}
+
+fn namespace_tree(namespace: &str) -> Result<sled::Tree, String> {
+    if namespace.is_empty() {
+        return Err("Namespace must not be empty".to_string());
+    }
+    storage::open_tree(&format!("{}{}", NAMESPACE_PREFIX, namespace))
+}
+
+/// Open the KV store next to the event database
+///
+/// Not needed once the relay is running (it opens the same store), but allows using the
+/// KV store before the relay is started.
+///
+/// # Arguments
+/// * `db_path` - Database path, as passed to `relay_start`
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_open(db_path: String) -> Result<(), String> {
+    let path = storage::aux_store_path(&db_path)?;
+    storage::open_aux_store(&path)?;
+    Ok(())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_set(namespace: String, key: String, value: String) -> Result<(), String> {
+    namespace_tree(&namespace)?
+        .insert(key.as_bytes(), value.as_bytes())
+        .map_err(|e| format!("Failed to set value: {}", e))?;
+    Ok(())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_get(namespace: String, key: String) -> Result<Option<String>, String> {
+    let value = namespace_tree(&namespace)?
+        .get(key.as_bytes())
+        .map_err(|e| format!("Failed to get value: {}", e))?;
+    
+    value
+        .map(|v| String::from_utf8(v.to_vec()).map_err(|e| format!("Invalid stored value: {}", e)))
+        .transpose()
+}
+
+/// Delete a key, returns true if it existed
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_delete(namespace: String, key: String) -> Result<bool, String> {
+    let previous = namespace_tree(&namespace)?
+        .remove(key.as_bytes())
+        .map_err(|e| format!("Failed to delete value: {}", e))?;
+    Ok(previous.is_some())
+}
+
+/// List the entries of a namespace (sorted by key), optionally only keys with a prefix
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_list(namespace: String, prefix: Option<String>) -> Result<Vec<KvEntry>, String> {
+    let tree = namespace_tree(&namespace)?;
+    let prefix = prefix.unwrap_or_default();
+    
+    tree.scan_prefix(prefix.as_bytes())
+        .map(|entry| {
+            let (key, value) = entry.map_err(|e| format!("Failed to list values: {}", e))?;
+            Ok(KvEntry {
+                key: String::from_utf8_lossy(&key).to_string(),
+                value: String::from_utf8_lossy(&value).to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Delete a whole namespace
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_clear(namespace: String) -> Result<(), String> {
+    namespace_tree(&namespace)?
+        .clear()
+        .map_err(|e| format!("Failed to clear namespace: {}", e))
+}
+
+/// List all namespaces
+#[flutter_rust_bridge::frb(sync)]
+pub fn kv_namespaces() -> Result<Vec<String>, String> {
+    let store = storage::aux_store()?;
+    Ok(store.tree_names()
+        .into_iter()
+        .filter_map(|name| {
+            String::from_utf8_lossy(&name)
+                .strip_prefix(NAMESPACE_PREFIX)
+                .map(|ns| ns.to_string())
+        })
+        .collect())
+}
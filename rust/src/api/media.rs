@@ -0,0 +1,131 @@
+use image::metadata::Orientation;
+use image::{DynamicImage, GenericImageView, ImageDecoder, ImageFormat, ImageReader};
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use serde::{Deserialize, Serialize};
+use super::relay::get_or_create_runtime;
+
+/// Longest side of the image the blurhash is computed from
+const BLURHASH_SOURCE_SIZE: u32 = 64;
+/// Blurhash components along the longest side
+const BLURHASH_MAX_COMPONENTS: u32 = 4;
+
+/// Metadata of an image, as used in `imeta` tags (NIP-92) and file metadata (NIP-94)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageInfo {
+    /// MIME type (e.g. "image/jpeg")
+    pub mime_type: String,
+    pub width: u32,
+    pub height: u32,
+    /// SHA-256 (hex) of the file
+    pub sha256: String,
+    /// File size in bytes
+    pub size: u64,
+    pub blurhash: String,
+}
+
+impl ImageInfo {
+    /// Dimensions as "<width>x<height>"
+    fn dimensions(&self) -> String {
+        format!("{}x{}", self.width, self.height)
+    }
+}
+
+fn mime_type(format: ImageFormat) -> Result<&'static str, String> {
+    match format {
+        ImageFormat::Jpeg => Ok("image/jpeg"),
+        ImageFormat::Png => Ok("image/png"),
+        ImageFormat::Gif => Ok("image/gif"),
+        ImageFormat::WebP => Ok("image/webp"),
+        other => Err(format!("Unsupported image format {:?}", other)),
+    }
+}
+
+/// Blurhash of a downscaled copy, with more components along the longest side
+fn compute_blurhash(image: &image::DynamicImage) -> Result<String, String> {
+    let small = image.thumbnail(BLURHASH_SOURCE_SIZE, BLURHASH_SOURCE_SIZE).to_rgba8();
+    let (width, height) = small.dimensions();
+    let (components_x, components_y) = if width >= height {
+        (BLURHASH_MAX_COMPONENTS, (BLURHASH_MAX_COMPONENTS * height / width.max(1)).clamp(1, BLURHASH_MAX_COMPONENTS))
+    } else {
+        ((BLURHASH_MAX_COMPONENTS * width / height.max(1)).clamp(1, BLURHASH_MAX_COMPONENTS), BLURHASH_MAX_COMPONENTS)
+    };
+    blurhash::encode(components_x, components_y, width, height, small.as_raw())
+        .map_err(|e| format!("Failed to compute blurhash: {:?}", e))
+}
+
+/// Decode an image upright, applying its EXIF orientation (camera photos are often stored
+/// sideways)
+fn decode_upright(bytes: &[u8], format: ImageFormat) -> Result<DynamicImage, String> {
+    let mut decoder = ImageReader::with_format(std::io::Cursor::new(bytes), format)
+        .into_decoder()
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| format!("Failed to decode image: {}", e))?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+fn analyze(bytes: &[u8]) -> Result<ImageInfo, String> {
+    let format = image::guess_format(bytes)
+        .map_err(|e| format!("Unknown image format: {}", e))?;
+    let mime_type = mime_type(format)?;
+    let image = decode_upright(bytes, format)?;
+    let (width, height) = image.dimensions();
+    
+    Ok(ImageInfo {
+        mime_type: mime_type.to_string(),
+        width,
+        height,
+        sha256: Sha256Hash::hash(bytes).to_string(),
+        size: bytes.len() as u64,
+        blurhash: compute_blurhash(&image)?,
+    })
+}
+
+/// Dimensions, MIME type, SHA-256 and blurhash of an image (JPEG, PNG, GIF or WebP)
+///
+/// The image is turned upright first (EXIF orientation), so the dimensions are the displayed
+/// ones. The blurhash is computed from a 64 px copy; decoding a camera photo still takes a
+/// while, so it runs on a blocking thread. For GIFs the first frame is used.
+///
+/// # Arguments
+/// * `bytes` - Content of the image file
+pub async fn analyze_image(bytes: Vec<u8>) -> Result<ImageInfo, String> {
+    get_or_create_runtime()?
+        .spawn_blocking(move || analyze(&bytes))
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// Same as `analyze_image`, reading the image from a file
+///
+/// Saves copying the file over the bridge.
+pub async fn analyze_image_file(path: String) -> Result<ImageInfo, String> {
+    get_or_create_runtime()?
+        .spawn_blocking(move || {
+            let bytes = std::fs::read(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path, e))?;
+            analyze(&bytes)
+        })
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+/// `imeta` tag (NIP-92) of an uploaded image, to attach to the event mentioning `url`
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_image_imeta_tag(url: String, info: ImageInfo) -> Result<Vec<String>, String> {
+    if url.is_empty() {
+        return Err("Image URL must not be empty".to_string());
+    }
+    Ok(vec![
+        "imeta".to_string(),
+        format!("url {}", url),
+        format!("m {}", info.mime_type),
+        format!("dim {}", info.dimensions()),
+        format!("x {}", info.sha256),
+        format!("size {}", info.size),
+        format!("blurhash {}", info.blurhash),
+    ])
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Latest samples kept per stage, older ones are dropped
+const MAX_SAMPLES: usize = 1024;
+
+// Latency samples in microseconds, indexed by stage
+static SAMPLES: Mutex<[VecDeque<u32>; 4]> = Mutex::new([const { VecDeque::new() }; 4]);
+
+/// Stage of the relay write path, in pipeline order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IngestStage {
+    /// Event id and signature verification
+    Validate,
+    /// Token, origin, allow/block lists, rate limit and spam checks
+    Policy,
+    /// Write to the database
+    Store,
+    /// Hooks, firehoses and post-save bookkeeping
+    FanOut,
+}
+
+impl IngestStage {
+    const ALL: [IngestStage; 4] = [IngestStage::Validate, IngestStage::Policy, IngestStage::Store, IngestStage::FanOut];
+    
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Latency percentiles of a stage over its latest samples
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestStageLatency {
+    pub stage: IngestStage,
+    /// Number of samples the percentiles are computed from
+    pub samples: u32,
+    pub p50_micros: u32,
+    pub p90_micros: u32,
+    pub p99_micros: u32,
+    pub max_micros: u32,
+}
+
+/// Record how long an event spent in a stage
+pub(crate) fn record(stage: IngestStage, elapsed: Duration) {
+    let micros = elapsed.as_micros().min(u32::MAX as u128) as u32;
+    if let Ok(mut samples) = SAMPLES.lock() {
+        let samples = &mut samples[stage.index()];
+        if samples.len() >= MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(micros);
+    }
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[u32], percent: usize) -> u32 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (sorted.len() * percent).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Latency percentiles of every stage of the write path, in pipeline order
+///
+/// Computed over the latest 1024 events of each stage. Stages without samples report zeros.
+pub fn ingest_latency() -> Vec<IngestStageLatency> {
+    let samples = match SAMPLES.lock() {
+        Ok(samples) => samples.clone(),
+        Err(_) => return Vec::new(),
+    };
+    IngestStage::ALL.iter()
+        .map(|stage| {
+            let mut sorted: Vec<u32> = samples[stage.index()].iter().copied().collect();
+            sorted.sort_unstable();
+            IngestStageLatency {
+                stage: *stage,
+                samples: sorted.len() as u32,
+                p50_micros: percentile(&sorted, 50),
+                p90_micros: percentile(&sorted, 90),
+                p99_micros: percentile(&sorted, 99),
+                max_micros: sorted.last().copied().unwrap_or(0),
+            }
+        })
+        .collect()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_ingest_latency() -> Vec<IngestStageLatency> {
+    ingest_latency()
+}
+
+/// Drop the latency samples, e.g. before measuring a change
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_reset_ingest_latency() {
+    if let Ok(mut samples) = SAMPLES.lock() {
+        samples.iter_mut().for_each(VecDeque::clear);
+    }
+}
@@ -0,0 +1,168 @@
+use bip39::Language;
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::key::Keys;
+use nostr::nips::nip06::FromMnemonic;
+use serde::{Deserialize, Serialize};
+use super::nostr::NostrKeys;
+
+/// Word counts allowed by BIP-39
+const VALID_WORD_COUNTS: [usize; 5] = [12, 15, 18, 21, 24];
+/// Maximum number of suggestions per word
+const MAX_SUGGESTIONS: usize = 5;
+/// Maximum number of accounts derived in one call
+const MAX_DERIVED_ACCOUNTS: u32 = 100;
+
+/// Validation result of one mnemonic word
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MnemonicWord {
+    pub word: String,
+    /// Whether the word is in the BIP-39 English wordlist
+    pub valid: bool,
+    /// Closest wordlist entries for invalid words (prefix matches first)
+    pub suggestions: Vec<String>,
+}
+
+/// Validation result of a mnemonic phrase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MnemonicValidation {
+    pub words: Vec<MnemonicWord>,
+    /// Whether the number of words is 12, 15, 18, 21 or 24
+    pub word_count_valid: bool,
+    /// Checksum status, None until all words are valid and the count is valid
+    pub checksum_valid: Option<bool>,
+    /// Whether the phrase can be used to restore keys
+    pub is_valid: bool,
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+fn suggestions(word: &str) -> Vec<String> {
+    let wordlist = Language::English.word_list();
+    
+    // Words being typed: complete the prefix
+    let mut found: Vec<String> = wordlist.iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .take(MAX_SUGGESTIONS)
+        .map(|candidate| candidate.to_string())
+        .collect();
+    if !found.is_empty() {
+        return found;
+    }
+    
+    // Typos: closest words within two edits
+    let mut close: Vec<(usize, &str)> = wordlist.iter()
+        .map(|candidate| (edit_distance(word, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .collect();
+    close.sort();
+    found.extend(close.into_iter().take(MAX_SUGGESTIONS).map(|(_, candidate)| candidate.to_string()));
+    found
+}
+
+/// Check the BIP-39 checksum of a phrase given the wordlist index of each word
+fn checksum_matches(indices: &[u16]) -> bool {
+    let total_bits = indices.len() * 11;
+    let checksum_bits = total_bits / 33;
+    let entropy_bits = total_bits - checksum_bits;
+    
+    let bit = |i: usize| (indices[i / 11] >> (10 - i % 11)) & 1 == 1;
+    let mut entropy = vec![0u8; entropy_bits / 8];
+    for i in 0..entropy_bits {
+        if bit(i) {
+            entropy[i / 8] |= 0x80 >> (i % 8);
+        }
+    }
+    
+    let hash = Sha256Hash::hash(&entropy).to_byte_array();
+    (0..checksum_bits).all(|i| bit(entropy_bits + i) == ((hash[i / 8] >> (7 - i % 8)) & 1 == 1))
+}
+
+/// Validate a BIP-39 (English) mnemonic while it's being typed
+///
+/// Returns the status of every word with suggestions for unknown ones, and the checksum
+/// status once the phrase is complete, so the restore UI can point at the exact problem.
+///
+/// # Arguments
+/// * `words` - Mnemonic phrase, words separated by whitespace
+#[flutter_rust_bridge::frb(sync)]
+pub fn validate_mnemonic(words: String) -> MnemonicValidation {
+    let words: Vec<String> = words.split_whitespace().map(|word| word.to_lowercase()).collect();
+    
+    let indices: Vec<Option<u16>> = words.iter()
+        .map(|word| Language::English.find_word(word))
+        .collect();
+    let word_count_valid = VALID_WORD_COUNTS.contains(&words.len());
+    
+    let checksum_valid = match indices.iter().copied().collect::<Option<Vec<u16>>>() {
+        Some(indices) if word_count_valid => Some(checksum_matches(&indices)),
+        _ => None,
+    };
+    
+    MnemonicValidation {
+        words: words.into_iter()
+            .zip(indices)
+            .map(|(word, index)| MnemonicWord {
+                valid: index.is_some(),
+                suggestions: if index.is_some() { Vec::new() } else { suggestions(&word) },
+                word,
+            })
+            .collect(),
+        word_count_valid,
+        checksum_valid,
+        is_valid: checksum_valid == Some(true),
+    }
+}
+
+/// Derive the keys of an account from a mnemonic (NIP-06, `m/44'/1237'/<account>'/0/0`)
+///
+/// Account 0 is the key other NIP-06 wallets restore by default; other accounts give
+/// deterministic sub-accounts (profiles) restorable from the same phrase.
+///
+/// # Arguments
+/// * `mnemonic` - BIP-39 phrase
+/// * `passphrase` - Optional BIP-39 passphrase
+/// * `account` - Account index
+#[flutter_rust_bridge::frb(sync)]
+pub fn derive_keys_from_mnemonic(mnemonic: String, passphrase: Option<String>, account: u32) -> Result<NostrKeys, String> {
+    let keys = Keys::from_mnemonic_with_account(mnemonic.trim(), passphrase.as_deref(), Some(account))
+        .map_err(|e| format!("Failed to derive keys: {}", e))?;
+    NostrKeys::from_keys(&keys)
+}
+
+/// Derive the keys of consecutive accounts from a mnemonic, e.g. to list the profiles to restore
+///
+/// # Arguments
+/// * `mnemonic` - BIP-39 phrase
+/// * `passphrase` - Optional BIP-39 passphrase
+/// * `first_account` - Index of the first account
+/// * `count` - Number of accounts (at most 100)
+#[flutter_rust_bridge::frb(sync)]
+pub fn derive_accounts_from_mnemonic(
+    mnemonic: String,
+    passphrase: Option<String>,
+    first_account: u32,
+    count: u32,
+) -> Result<Vec<NostrKeys>, String> {
+    if count > MAX_DERIVED_ACCOUNTS {
+        return Err(format!("At most {} accounts can be derived at once", MAX_DERIVED_ACCOUNTS));
+    }
+    let last_account = first_account.checked_add(count)
+        .ok_or_else(|| "Account index out of range".to_string())?;
+    (first_account..last_account)
+        .map(|account| derive_keys_from_mnemonic(mnemonic.clone(), passphrase.clone(), account))
+        .collect()
+}
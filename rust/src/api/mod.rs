@@ -1,2 +1,54 @@
+pub mod audit;
+pub mod background;
+pub mod bitcoin;
+pub mod bunker;
+pub mod chat;
+pub mod client;
+pub mod conflicts;
+pub mod content;
+pub mod datetime;
+pub mod delegation;
+pub mod digest;
+pub mod display;
+pub mod export;
+mod gate;
+pub mod hooks;
+pub mod identities;
+pub mod import;
+pub mod inbox;
+mod ingest;
+mod json;
+pub mod keyhandle;
+pub mod kv;
+pub mod media;
+pub mod metrics;
+pub mod mnemonic;
+pub mod names;
+pub mod nip05;
 pub mod nostr;
+pub mod orders;
+pub mod pairing;
+pub mod policy;
+mod proxy;
 pub mod relay;
+pub mod relay_info;
+pub mod relay_scores;
+pub mod schedule;
+pub mod session_keys;
+pub mod signer;
+pub mod signer_service;
+pub mod spam;
+mod storage;
+pub mod structured;
+pub mod system;
+pub mod tags;
+pub mod threshold;
+pub mod tokens;
+pub mod trace;
+pub mod transaction;
+pub mod vanity;
+pub mod vectors;
+mod verify;
+pub mod video;
+pub mod watchdog;
+pub mod wipe;
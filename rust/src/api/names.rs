@@ -0,0 +1,125 @@
+use nostr::key::PublicKey;
+use nostr::nips::nip01::Metadata;
+use nostr::nips::nip19::ToBech32;
+use serde::{Deserialize, Serialize};
+use super::display::cached_profiles;
+use super::kv;
+use super::nip05;
+
+/// KV namespace holding the user's petnames, keyed by hex public key
+const PETNAME_NAMESPACE: &str = "petnames";
+
+/// Where a resolved display name comes from, in priority order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NameSource {
+    /// Set by the user with `set_petname`
+    Petname,
+    /// NIP-05 identifier of the cached profile, once verified with `verify_nip05`
+    Nip05,
+    /// Display name, or name, of the cached profile
+    Profile,
+    /// No name known, abbreviated npub
+    Npub,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResolvedName {
+    /// Hex public key
+    pub pubkey: String,
+    pub name: String,
+    pub source: NameSource,
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<PublicKey, String> {
+    PublicKey::parse(pubkey).map_err(|e| format!("Invalid public key: {}", e))
+}
+
+/// Set the petname of a user, None or an empty name removes it
+///
+/// # Arguments
+/// * `pubkey` - Public key (hex or npub)
+/// * `petname` - Name chosen by the user
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_petname(pubkey: String, petname: Option<String>) -> Result<(), String> {
+    let pubkey = parse_pubkey(&pubkey)?.to_hex();
+    match petname.map(|name| name.trim().to_string()).filter(|name| !name.is_empty()) {
+        Some(name) => kv::kv_set(PETNAME_NAMESPACE.to_string(), pubkey, name),
+        None => kv::kv_delete(PETNAME_NAMESPACE.to_string(), pubkey).map(|_| ()),
+    }
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_petname(pubkey: String) -> Result<Option<String>, String> {
+    kv::kv_get(PETNAME_NAMESPACE.to_string(), parse_pubkey(&pubkey)?.to_hex())
+}
+
+/// NIP-05 identifier as shown to users ("_@example.com" is shown as "example.com")
+fn nip05_display(nip05: &str) -> Option<String> {
+    let nip05 = nip05.trim();
+    if !nip05.contains('@') {
+        return None;
+    }
+    Some(nip05.strip_prefix("_@").unwrap_or(nip05).to_string())
+}
+
+/// "npub1abcdefgh…wxyz"
+fn abbreviated_npub(pubkey: &PublicKey) -> String {
+    let npub = pubkey.to_bech32().unwrap_or_else(|_| pubkey.to_hex());
+    match (npub.get(..13), npub.get(npub.len().saturating_sub(4)..)) {
+        (Some(start), Some(end)) => format!("{}…{}", start, end),
+        _ => npub,
+    }
+}
+
+fn resolve(pubkey: &PublicKey, metadata: Option<&Metadata>) -> ResolvedName {
+    let non_empty = |value: &Option<String>| value.as_ref().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let hex = pubkey.to_hex();
+    // The KV store is only available once opened, no petnames otherwise
+    let petname = kv::kv_get(PETNAME_NAMESPACE.to_string(), hex.clone()).ok().flatten();
+    let verified_nip05 = metadata
+        .and_then(|m| m.nip05.as_deref())
+        .filter(|nip05| nip05::is_verified(nip05, &hex))
+        .and_then(nip05_display);
+    
+    let (name, source) = if let Some(petname) = petname {
+        (petname, NameSource::Petname)
+    } else if let Some(nip05) = verified_nip05 {
+        (nip05, NameSource::Nip05)
+    } else if let Some(name) = metadata.and_then(|m| non_empty(&m.display_name).or_else(|| non_empty(&m.name))) {
+        (name, NameSource::Profile)
+    } else {
+        (abbreviated_npub(pubkey), NameSource::Npub)
+    };
+    
+    ResolvedName {
+        pubkey: hex,
+        name,
+        source,
+    }
+}
+
+/// Resolve the names of several users at once, in the order given
+///
+/// Uses one database query for all the cached profiles.
+#[flutter_rust_bridge::frb(sync)]
+pub fn resolve_display_names(pubkeys: Vec<String>) -> Result<Vec<ResolvedName>, String> {
+    let pubkeys = pubkeys.iter()
+        .map(|pubkey| parse_pubkey(pubkey))
+        .collect::<Result<Vec<PublicKey>, String>>()?;
+    let profiles = cached_profiles(&pubkeys);
+    
+    Ok(pubkeys.iter()
+        .map(|pubkey| resolve(pubkey, profiles.get(pubkey)))
+        .collect())
+}
+
+/// Resolve the name to show for a user: petname > verified NIP-05 > profile name > abbreviated npub
+///
+/// # Arguments
+/// * `pubkey` - Public key (hex or npub)
+#[flutter_rust_bridge::frb(sync)]
+pub fn resolve_display_name(pubkey: String) -> Result<ResolvedName, String> {
+    resolve_display_names(vec![pubkey])?
+        .pop()
+        .ok_or_else(|| "No name resolved".to_string())
+}
@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use super::kv;
+use super::nostr::parse_public_key;
+use super::relay::run_async_with_timeout;
+use super::system::call_timeout;
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+/// KV namespace of the identifiers last verified, keyed by hex public key
+const VERIFIED_NAMESPACE: &str = "nip05_verified";
+/// Largest `nostr.json` read, bigger documents are refused
+const MAX_DOCUMENT_BYTES: usize = 64 * 1024;
+
+/// Result of checking a NIP-05 identifier against a public key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nip05Verification {
+    /// Local part, lowercase ("_" for bare domains)
+    pub name: String,
+    pub domain: String,
+    /// Whether the domain maps `name` to the given public key
+    pub verified: bool,
+    /// Public key (hex) the domain advertises for `name`, None if it lists none
+    pub advertised_pubkey: Option<String>,
+    /// Relays the domain advertises for the advertised public key
+    pub relays: Vec<String>,
+}
+
+/// `.well-known/nostr.json` document
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Nip05Document {
+    names: HashMap<String, String>,
+    relays: HashMap<String, Vec<String>>,
+}
+
+/// Split "name@domain" (or a bare domain, meaning "_@domain") into lowercase parts
+///
+/// The domain must be a host name: ports and IP literals are refused, so identifiers can't
+/// point the fetch at arbitrary services (e.g. on the local network).
+pub(crate) fn parse_identifier(identifier: &str) -> Result<(String, String), String> {
+    let identifier = identifier.trim().to_lowercase();
+    let (name, domain) = identifier.split_once('@').unwrap_or(("_", identifier.as_str()));
+    
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        return Err(format!("Invalid NIP-05 name '{}'", name));
+    }
+    if domain.is_empty() || !domain.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.')) {
+        return Err(format!("Invalid NIP-05 domain '{}'", domain));
+    }
+    // A numeric last label makes URL parsers read the host as an IPv4 address ("127.1")
+    let last_label = domain.trim_end_matches('.').rsplit('.').next().unwrap_or_default();
+    if last_label.chars().all(|c| c.is_ascii_digit()) || last_label.starts_with("0x") {
+        return Err(format!("NIP-05 domain '{}' is an IP address", domain));
+    }
+    Ok((name.to_string(), domain.to_string()))
+}
+
+async fn fetch_document(name: &str, domain: &str) -> Result<Nip05Document, String> {
+    let url = format!("https://{}/.well-known/nostr.json?name={}", domain, name);
+    let mut response = super::client::http_client()?
+        .get(&url)
+        .timeout(FETCH_TIMEOUT)
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch NIP-05 document: {}", e))?;
+    // NIP-05 requires ignoring redirects, the shared client follows them
+    if response.url().as_str() != url {
+        return Err(format!("{} redirected to {}", domain, response.url()));
+    }
+    if !response.status().is_success() {
+        return Err(format!("{} returned HTTP {}", domain, response.status()));
+    }
+    
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk()
+        .await
+        .map_err(|e| format!("Failed to fetch NIP-05 document: {}", e))?
+    {
+        if body.len() + chunk.len() > MAX_DOCUMENT_BYTES {
+            return Err(format!("NIP-05 document of {} is over {} KiB", domain, MAX_DOCUMENT_BYTES / 1024));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&body)
+        .map_err(|e| format!("Invalid NIP-05 document: {}", e))
+}
+
+/// Check the verification of a document already fetched for `name`
+fn check_document(document: Nip05Document, name: String, domain: String, pubkey: &str) -> Nip05Verification {
+    let advertised_pubkey = document.names.get(&name)
+        .and_then(|advertised| parse_public_key(advertised).ok())
+        .map(|advertised| advertised.to_hex());
+    let relays = advertised_pubkey.as_ref()
+        .and_then(|advertised| document.relays.get(advertised))
+        .cloned()
+        .unwrap_or_default();
+    
+    Nip05Verification {
+        verified: advertised_pubkey.as_deref() == Some(pubkey),
+        name,
+        domain,
+        advertised_pubkey,
+        relays,
+    }
+}
+
+/// Verify a NIP-05 identifier ("name@domain") by fetching `https://<domain>/.well-known/nostr.json`
+///
+/// Returns an error only if the identifier is malformed or the document can't be fetched;
+/// a document that maps the name to another key (or to none) gives `verified: false`.
+///
+/// # Arguments
+/// * `identifier` - NIP-05 identifier, a bare domain stands for "_@domain"
+/// * `pubkey` - Public key the identifier is claimed for (hex or npub)
+pub async fn verify_nip05(identifier: String, pubkey: String) -> Result<Nip05Verification, String> {
+    let (name, domain) = parse_identifier(&identifier)?;
+    let pubkey = parse_public_key(&pubkey)?.to_hex();
+    
+    let (fetch_name, fetch_domain) = (name.clone(), domain.clone());
+    let document = run_async_with_timeout(call_timeout(), async move { fetch_document(&fetch_name, &fetch_domain).await })
+        .await??;
+    let verification = check_document(document, name, domain, &pubkey);
+    remember_verification(&pubkey, &verification);
+    Ok(verification)
+}
+
+/// Keep the outcome for `verified_identifier`, best effort as the KV store may not be open
+fn remember_verification(pubkey: &str, verification: &Nip05Verification) {
+    let identifier = format!("{}@{}", verification.name, verification.domain);
+    let result = if verification.verified {
+        kv::kv_set(VERIFIED_NAMESPACE.to_string(), pubkey.to_string(), identifier)
+    } else {
+        // Only forget the identifier that failed, not another one verified since
+        match kv::kv_get(VERIFIED_NAMESPACE.to_string(), pubkey.to_string()) {
+            Ok(Some(stored)) if stored == identifier => kv::kv_delete(VERIFIED_NAMESPACE.to_string(), pubkey.to_string()).map(|_| ()),
+            other => other.map(|_| ()),
+        }
+    };
+    if let Err(e) = result {
+        tracing::debug!("Failed to remember NIP-05 verification of {}: {}", pubkey, e);
+    }
+}
+
+/// Whether `identifier` is the one last verified for `pubkey` (hex) with `verify_nip05`
+pub(crate) fn is_verified(identifier: &str, pubkey: &str) -> bool {
+    let Ok((name, domain)) = parse_identifier(identifier) else {
+        return false;
+    };
+    kv::kv_get(VERIFIED_NAMESPACE.to_string(), pubkey.to_string())
+        .ok()
+        .flatten()
+        .is_some_and(|verified| verified == format!("{}@{}", name, domain))
+}
@@ -1,11 +1,26 @@
 use nostr::key::{Keys, PublicKey, SecretKey};
 use nostr::nips::nip04;
 use nostr::nips::nip44;
-use nostr::event::{EventBuilder, EventId, Kind, Tag};
+use nostr::nips::nip44::v2::{ConversationKey, ErrorV2};
+use nostr::nips::nip19::{FromBech32, ToBech32};
+use nostr::nips::nip49::{EncryptedSecretKey, KeySecurity};
+use nostr::event::{Event, EventBuilder, EventId, Kind, Tag};
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::JsonUtil;
 use nostr::types::time::Timestamp;
+use nostr::types::RelayUrl;
 use nostr::secp256k1::schnorr::Signature;
+use nostr::secp256k1::{ecdh, Message, Parity, PublicKey as SecpPublicKey, Secp256k1, SecretKey as SecpSecretKey};
+use base64::Engine;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use zeroize::Zeroizing;
+use super::audit::{self, KeyOperation};
+use super::signer::Signer;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NostrEvent {
@@ -22,15 +37,167 @@ pub struct NostrEvent {
 pub struct NostrKeys {
     pub public_key: String,
     pub private_key: String,
+    /// Bech32 public key (NIP-19)
+    pub npub: String,
+    /// Bech32 private key (NIP-19)
+    pub nsec: String,
 }
 
+impl NostrKeys {
+    pub(crate) fn from_keys(keys: &Keys) -> Result<Self, String> {
+        Ok(NostrKeys {
+            public_key: keys.public_key().to_hex(),
+            private_key: keys.secret_key().to_secret_hex(),
+            npub: keys.public_key().to_bech32()
+                .map_err(|e| format!("Failed to encode npub: {}", e))?,
+            nsec: keys.secret_key().to_bech32()
+                .map_err(|e| format!("Failed to encode nsec: {}", e))?,
+        })
+    }
+}
+
+/// Fixed created_at of built events in deterministic test mode, 0 when off
+static FIXED_CREATED_AT: AtomicU64 = AtomicU64::new(0);
+
 #[flutter_rust_bridge::frb(sync)]
 pub fn generate_keys() -> Result<NostrKeys, String> {
-    let keys = Keys::generate();
-    Ok(NostrKeys {
-        public_key: keys.public_key().to_hex(),
-        private_key: keys.secret_key().to_secret_hex(),
-    })
+    NostrKeys::from_keys(&Keys::generate())
+}
+
+/// Keys derived from a seed, the same on every run (for integration tests)
+///
+/// The secret key is the SHA-256 of the seed. Never use it for real accounts: anyone who
+/// knows the seed has the key.
+#[flutter_rust_bridge::frb(sync)]
+pub fn generate_keys_from_seed(seed: Vec<u8>) -> Result<NostrKeys, String> {
+    if seed.is_empty() {
+        return Err("Seed must not be empty".to_string());
+    }
+    let mut hash = Sha256Hash::hash(&seed).to_byte_array();
+    // Hash again in the (astronomically unlikely) case the hash is not a valid key
+    loop {
+        if let Ok(secret_key) = SecretKey::from_slice(&hash) {
+            return NostrKeys::from_keys(&Keys::new(secret_key));
+        }
+        hash = Sha256Hash::hash(&hash).to_byte_array();
+    }
+}
+
+/// Deterministic test mode: events built by the plugin get `created_at` instead of the
+/// current time (None turns it off)
+///
+/// Together with `generate_keys_from_seed` this makes the ids of built events the same on
+/// every run. Signatures still differ, signing uses fresh randomness.
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_fixed_created_at(created_at: Option<u64>) {
+    FIXED_CREATED_AT.store(created_at.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Apply the fixed `created_at` of the deterministic test mode, if on
+pub(crate) fn with_fixed_created_at(builder: EventBuilder) -> EventBuilder {
+    match FIXED_CREATED_AT.load(Ordering::Relaxed) {
+        0 => builder,
+        created_at => builder.custom_created_at(Timestamp::from(created_at)),
+    }
+}
+
+/// Why a secret key could not be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyImportErrorReason {
+    Empty,
+    /// 64 characters but not hex
+    InvalidHex,
+    /// Looks like bech32 but the checksum or encoding is wrong
+    InvalidBech32,
+    /// A public key (npub) was given instead of a secret key
+    PublicKeyGiven,
+    /// Valid encoding, but not a valid secp256k1 secret key (e.g. zero)
+    InvalidKey,
+    /// Neither hex nor nsec
+    UnknownFormat,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyImportError {
+    pub reason: KeyImportErrorReason,
+    pub message: String,
+}
+
+impl std::fmt::Display for KeyImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Import an existing secret key given as hex or nsec
+///
+/// # Arguments
+/// * `secret` - 64 character hex or bech32 (nsec) secret key, surrounding whitespace is ignored
+#[flutter_rust_bridge::frb(sync)]
+pub fn import_keys(secret: String) -> Result<NostrKeys, KeyImportError> {
+    let secret_key = parse_secret_key(&secret)?;
+    NostrKeys::from_keys(&Keys::new(secret_key))
+        .map_err(|message| KeyImportError { reason: KeyImportErrorReason::InvalidKey, message })
+}
+
+/// Parse a hex or nsec secret key, see `import_keys`
+pub(crate) fn parse_secret_key(secret: &str) -> Result<SecretKey, KeyImportError> {
+    let error = |reason: KeyImportErrorReason, message: String| KeyImportError { reason, message };
+    let secret = secret.trim();
+    
+    if secret.is_empty() {
+        Err(error(KeyImportErrorReason::Empty, "No secret key given".to_string()))
+    } else if secret.to_lowercase().starts_with("npub1") {
+        Err(error(KeyImportErrorReason::PublicKeyGiven, "This is a public key (npub), not a secret key".to_string()))
+    } else if secret.to_lowercase().starts_with("nsec1") {
+        SecretKey::from_bech32(secret)
+            .map_err(|e| error(KeyImportErrorReason::InvalidBech32, format!("Invalid nsec: {}", e)))
+    } else if secret.len() == 64 {
+        if !secret.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(error(KeyImportErrorReason::InvalidHex, "Secret key is not valid hex".to_string()));
+        }
+        SecretKey::from_hex(secret)
+            .map_err(|e| error(KeyImportErrorReason::InvalidKey, format!("Invalid private key: {}", e)))
+    } else {
+        Err(error(KeyImportErrorReason::UnknownFormat, "Expected a 64 character hex key or an nsec".to_string()))
+    }
+}
+
+/// Parse a public key as users paste it: hex (any case) or npub, optionally with a
+/// `nostr:` prefix and surrounding whitespace
+pub(crate) fn parse_public_key(input: &str) -> Result<PublicKey, String> {
+    let input = input.trim();
+    let input = input.strip_prefix("nostr:").unwrap_or(input).to_lowercase();
+    
+    if input.starts_with("npub1") {
+        PublicKey::from_bech32(&input).map_err(|e| format!("Invalid npub: {}", e))
+    } else if input.len() == 64 && input.chars().all(|c| c.is_ascii_hexdigit()) {
+        PublicKey::from_hex(&input).map_err(|e| format!("Invalid public key: {}", e))
+    } else if input.starts_with("nsec1") {
+        Err("This is a secret key (nsec), not a public key".to_string())
+    } else {
+        Err("Expected a 64 character hex key or an npub".to_string())
+    }
+}
+
+/// Whether the input is a valid public key (hex in any case or npub, see `normalize_pubkey`)
+#[flutter_rust_bridge::frb(sync)]
+pub fn is_valid_public_key(public_key: String) -> bool {
+    parse_public_key(&public_key).is_ok()
+}
+
+/// Whether the input is a valid secret key (hex or nsec), see `import_keys` for the reason it isn't
+#[flutter_rust_bridge::frb(sync)]
+pub fn is_valid_secret_key(secret_key: String) -> bool {
+    parse_secret_key(&secret_key).is_ok()
+}
+
+/// Canonical form (lowercase hex) of a public key given as hex, uppercase hex, npub or `nostr:npub`
+///
+/// Also checks that the key is a point on the curve, so it can't fail later in encryption.
+#[flutter_rust_bridge::frb(sync)]
+pub fn normalize_pubkey(public_key: String) -> Result<String, String> {
+    parse_public_key(&public_key).map(|public_key| public_key.to_hex())
 }
 
 #[flutter_rust_bridge::frb(sync)]
@@ -42,10 +209,76 @@ pub fn get_public_key_from_private(private_key: String) -> Result<String, String
     Ok(keys.public_key().to_hex())
 }
 
+/// Convert a bech32 public key (npub) to hex (NIP-19)
 #[flutter_rust_bridge::frb(sync)]
-pub fn nip04_encrypt(plaintext: String, public_key: String, private_key: String) -> Result<String, String> {
-    let public_key = PublicKey::from_str(&public_key)
+pub fn npub_to_hex(npub: String) -> Result<String, String> {
+    let public_key = PublicKey::from_bech32(npub.trim())
+        .map_err(|e| format!("Invalid npub: {}", e))?;
+    Ok(public_key.to_hex())
+}
+
+/// Convert a hex public key to bech32 (npub) (NIP-19)
+#[flutter_rust_bridge::frb(sync)]
+pub fn hex_to_npub(public_key: String) -> Result<String, String> {
+    let public_key = PublicKey::from_hex(public_key.trim())
         .map_err(|e| format!("Invalid public key: {}", e))?;
+    public_key.to_bech32()
+        .map_err(|e| format!("Failed to encode npub: {}", e))
+}
+
+/// Convert a bech32 private key (nsec) to hex (NIP-19)
+#[flutter_rust_bridge::frb(sync)]
+pub fn nsec_to_hex(nsec: String) -> Result<String, String> {
+    let secret_key = SecretKey::from_bech32(nsec.trim())
+        .map_err(|e| format!("Invalid nsec: {}", e))?;
+    let hex = secret_key.to_secret_hex();
+    audit::record(KeyOperation::ExportSecret, &Keys::new(secret_key).public_key().to_hex(), None);
+    Ok(hex)
+}
+
+/// Convert a hex private key to bech32 (nsec) (NIP-19)
+#[flutter_rust_bridge::frb(sync)]
+pub fn hex_to_nsec(private_key: String) -> Result<String, String> {
+    let secret_key = SecretKey::from_hex(private_key.trim())
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let nsec = secret_key.to_bech32()
+        .map_err(|e| format!("Failed to encode nsec: {}", e))?;
+    audit::record(KeyOperation::ExportSecret, &Keys::new(secret_key).public_key().to_hex(), None);
+    Ok(nsec)
+}
+
+/// Default scrypt cost (log2 of the rounds) of NIP-49 encryption, about 0.1 s on phones
+pub(crate) const NIP49_DEFAULT_LOG_N: u8 = 16;
+
+/// Encrypt a private key with a password (NIP-49), returns the ncryptsec string
+///
+/// # Arguments
+/// * `private_key` - Private key (hex or nsec)
+/// * `password` - Password (normalized to NFKC)
+/// * `log_n` - scrypt cost, each step doubles time and memory (defaults to 16, 64 MiB)
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip49_encrypt_key(private_key: String, password: String, log_n: Option<u8>) -> Result<String, String> {
+    let secret_key = SecretKey::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let encrypted = EncryptedSecretKey::new(&secret_key, password, log_n.unwrap_or(NIP49_DEFAULT_LOG_N), KeySecurity::Unknown)
+        .map_err(|e| format!("NIP-49 encryption failed: {}", e))?;
+    encrypted.to_bech32()
+        .map_err(|e| format!("Failed to encode ncryptsec: {}", e))
+}
+
+/// Decrypt a NIP-49 ncryptsec with its password, returns the hex private key
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip49_decrypt_key(ncryptsec: String, password: String) -> Result<String, String> {
+    let encrypted = EncryptedSecretKey::from_bech32(ncryptsec.trim())
+        .map_err(|e| format!("Invalid ncryptsec: {}", e))?;
+    let secret_key = encrypted.decrypt(password)
+        .map_err(|e| format!("NIP-49 decryption failed: {}", e))?;
+    Ok(secret_key.to_secret_hex())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip04_encrypt(plaintext: String, public_key: String, private_key: String) -> Result<String, String> {
+    let public_key = parse_public_key(&public_key)?;
     let private_key = SecretKey::from_str(&private_key)
         .map_err(|e| format!("Invalid private key: {}", e))?;
     
@@ -57,25 +290,99 @@ pub fn nip04_encrypt(plaintext: String, public_key: String, private_key: String)
     Ok(encrypted)
 }
 
+/// Why a NIP-04/NIP-44 payload could not be decrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecryptErrorReason {
+    /// The given public or private key is not a valid key
+    InvalidKey,
+    /// Well-formed payload encrypted for another key pair ("not for you"); for NIP-44 the
+    /// MAC check failed, which also happens when the payload was tampered with
+    WrongKey,
+    /// Not a payload of this scheme (missing IV, bad length)
+    MalformedPayload,
+    /// Payload (or its IV) is not valid base64
+    InvalidBase64,
+    /// Payload of an encryption version this library doesn't support
+    VersionUnsupported,
+    /// Authenticated payload whose padding is invalid (corrupted by the sender)
+    PaddingError,
+    /// Decrypted content is not UTF-8 text
+    InvalidUtf8,
+    /// Decryption failed unexpectedly (a worker of a batch panicked)
+    Internal,
+}
+
+/// Decryption failure with a reason the UI can act on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptError {
+    pub reason: DecryptErrorReason,
+    pub message: String,
+    /// Version byte of the NIP-44 payload, when it could be read
+    pub version: Option<u8>,
+}
+
+impl DecryptError {
+    fn new(reason: DecryptErrorReason, message: impl Into<String>) -> Self {
+        Self { reason, message: message.into(), version: None }
+    }
+    
+    fn with_version(mut self, version: u8) -> Self {
+        self.version = Some(version);
+        self
+    }
+}
+
+impl std::fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+fn parse_decrypt_keys(public_key: &str, private_key: &str) -> Result<(PublicKey, Keys), DecryptError> {
+    let public_key = parse_public_key(public_key)
+        .map_err(|e| DecryptError::new(DecryptErrorReason::InvalidKey, e))?;
+    let private_key = SecretKey::from_str(private_key)
+        .map_err(|e| DecryptError::new(DecryptErrorReason::InvalidKey, format!("Invalid private key: {}", e)))?;
+    Ok((public_key, Keys::new(private_key)))
+}
+
+/// Check the shape of a NIP-04 payload (`<base64 ciphertext>?iv=<base64 iv>`)
+fn check_nip04_payload(ciphertext: &str) -> Result<(), DecryptError> {
+    let malformed = |message: &str| DecryptError::new(DecryptErrorReason::MalformedPayload, message);
+    let not_base64 = |message: &str| DecryptError::new(DecryptErrorReason::InvalidBase64, message);
+    let (data, iv) = ciphertext.split_once("?iv=")
+        .ok_or_else(|| malformed("Payload has no IV"))?;
+    let iv = base64::engine::general_purpose::STANDARD.decode(iv)
+        .map_err(|_| not_base64("IV is not base64"))?;
+    let data = base64::engine::general_purpose::STANDARD.decode(data)
+        .map_err(|_| not_base64("Ciphertext is not base64"))?;
+    if iv.len() != 16 {
+        return Err(malformed("IV must be 16 bytes"));
+    }
+    if data.is_empty() || data.len() % 16 != 0 {
+        return Err(malformed("Ciphertext is not a whole number of AES blocks"));
+    }
+    Ok(())
+}
+
 #[flutter_rust_bridge::frb(sync)]
-pub fn nip04_decrypt(ciphertext: String, public_key: String, private_key: String) -> Result<String, String> {
-    let public_key = PublicKey::from_str(&public_key)
-        .map_err(|e| format!("Invalid public key: {}", e))?;
-    let private_key = SecretKey::from_str(&private_key)
-        .map_err(|e| format!("Invalid private key: {}", e))?;
+pub fn nip04_decrypt(ciphertext: String, public_key: String, private_key: String) -> Result<String, DecryptError> {
+    let (public_key, keys) = parse_decrypt_keys(&public_key, &private_key)?;
+    check_nip04_payload(&ciphertext)?;
     
-    let keys = Keys::new(private_key);
+    // NIP-04 has no MAC: a well-formed payload failing to decrypt was almost always
+    // encrypted for someone else
     let secret_key = keys.secret_key();
     let decrypted = nip04::decrypt(secret_key, &public_key, ciphertext)
-        .map_err(|e| format!("Decryption failed: {}", e))?;
+        .map_err(|e| DecryptError::new(DecryptErrorReason::WrongKey, format!("Decryption failed: {}", e)))?;
+    audit::record(KeyOperation::Nip04Decrypt, &keys.public_key().to_hex(), None);
     
     Ok(decrypted)
 }
 
 #[flutter_rust_bridge::frb(sync)]
 pub fn nip44_encrypt(plaintext: String, public_key: String, private_key: String) -> Result<String, String> {
-    let public_key = PublicKey::from_str(&public_key)
-        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let public_key = parse_public_key(&public_key)?;
     let private_key = SecretKey::from_str(&private_key)
         .map_err(|e| format!("Invalid private key: {}", e))?;
     
@@ -87,35 +394,419 @@ pub fn nip44_encrypt(plaintext: String, public_key: String, private_key: String)
     Ok(encrypted)
 }
 
+/// Check the version and length of a NIP-44 payload, returns its version
+fn check_nip44_payload(ciphertext: &str) -> Result<u8, DecryptError> {
+    // Future versions are announced with a non-base64 prefix
+    if ciphertext.starts_with('#') {
+        return Err(DecryptError::new(DecryptErrorReason::VersionUnsupported, "Unsupported NIP-44 version"));
+    }
+    let payload = base64::engine::general_purpose::STANDARD.decode(ciphertext)
+        .map_err(|_| DecryptError::new(DecryptErrorReason::InvalidBase64, "Payload is not base64"))?;
+    let version = match payload.first() {
+        Some(2) => 2,
+        Some(version) => {
+            return Err(DecryptError::new(
+                DecryptErrorReason::VersionUnsupported,
+                format!("Unsupported NIP-44 version {}", version),
+            ).with_version(*version));
+        }
+        None => return Err(DecryptError::new(DecryptErrorReason::MalformedPayload, "Payload is empty")),
+    };
+    // version + nonce + (length prefix + padded plaintext) + MAC
+    let max_len = 1 + 32 + 2 + nip44_padded_len(NIP44_MAX_PLAINTEXT_LEN) as usize + 32;
+    if payload.len() < 1 + 32 + 2 + 32 + 32 || payload.len() > max_len {
+        return Err(DecryptError::new(
+            DecryptErrorReason::MalformedPayload,
+            format!("Invalid payload length {}", payload.len()),
+        ).with_version(version));
+    }
+    Ok(version)
+}
+
+/// Version of a NIP-44 payload, e.g. to tell which scheme another client used
+///
+/// Errors when the payload is not NIP-44 at all, or of a version this library can't
+/// decrypt (the error then carries the version when it could be read).
 #[flutter_rust_bridge::frb(sync)]
-pub fn nip44_decrypt(ciphertext: String, public_key: String, private_key: String) -> Result<String, String> {
-    let public_key = PublicKey::from_str(&public_key)
-        .map_err(|e| format!("Invalid public key: {}", e))?;
-    let private_key = SecretKey::from_str(&private_key)
-        .map_err(|e| format!("Invalid private key: {}", e))?;
+pub fn nip44_payload_version(ciphertext: String) -> Result<u8, DecryptError> {
+    check_nip44_payload(&ciphertext)
+}
+
+/// Map a NIP-44 decryption failure to its reason
+fn nip44_decrypt_error(e: nip44::Error) -> DecryptError {
+    // The MAC is checked first: failing it means the conversation key differs
+    let reason = match &e {
+        nip44::Error::V2(ErrorV2::InvalidHmac) => DecryptErrorReason::WrongKey,
+        nip44::Error::V2(ErrorV2::Utf8Encode(_)) | nip44::Error::Utf8Encode => DecryptErrorReason::InvalidUtf8,
+        nip44::Error::V2(ErrorV2::Base64Decode(_)) | nip44::Error::Base64Decode(_) => DecryptErrorReason::InvalidBase64,
+        nip44::Error::V2(ErrorV2::MessageEmpty | ErrorV2::MessageTooLong) => DecryptErrorReason::MalformedPayload,
+        nip44::Error::UnknownVersion(_) | nip44::Error::VersionNotFound => DecryptErrorReason::VersionUnsupported,
+        nip44::Error::Secp256k1(_) => DecryptErrorReason::InvalidKey,
+        _ => DecryptErrorReason::PaddingError,
+    };
+    DecryptError::new(reason, format!("NIP-44 decryption failed: {}", e)).with_version(2)
+}
+
+/// Decrypt a checked NIP-44 v2 payload with a conversation key
+fn nip44_decrypt_with_key(ciphertext: &str, conversation_key: &ConversationKey) -> Result<String, DecryptError> {
+    let payload = base64::engine::general_purpose::STANDARD.decode(ciphertext)
+        .map_err(|_| DecryptError::new(DecryptErrorReason::InvalidBase64, "Payload is not base64"))?;
+    let decrypted = nip44::v2::decrypt_to_bytes(conversation_key, &payload)
+        .map_err(nip44_decrypt_error)?;
+    String::from_utf8(decrypted)
+        .map_err(|_| DecryptError::new(DecryptErrorReason::InvalidUtf8, "Decrypted content is not UTF-8").with_version(2))
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_decrypt(ciphertext: String, public_key: String, private_key: String) -> Result<String, DecryptError> {
+    let (public_key, keys) = parse_decrypt_keys(&public_key, &private_key)?;
+    check_nip44_payload(&ciphertext)?;
     
-    let keys = Keys::new(private_key);
-    let secret_key = keys.secret_key();
-    let decrypted = nip44::decrypt(secret_key, &public_key, ciphertext)
-        .map_err(|e| format!("NIP-44 decryption failed: {}", e))?;
+    let conversation_key = cached_conversation_key(&keys, &public_key)?;
+    let decrypted = nip44_decrypt_with_key(&ciphertext, &conversation_key)?;
+    audit::record(KeyOperation::Nip44Decrypt, &keys.public_key().to_hex(), None);
+    
+    Ok(decrypted)
+}
+
+/// NIP-44 conversation keys by (our hex public key, peer hex public key), zeroized when dropped
+static CONVERSATION_KEYS: Mutex<Option<HashMap<(String, String), Zeroizing<[u8; 32]>>>> = Mutex::new(None);
+
+type ConversationKeys = Option<HashMap<(String, String), Zeroizing<[u8; 32]>>>;
+
+fn lock_conversation_keys() -> Result<std::sync::MutexGuard<'static, ConversationKeys>, String> {
+    CONVERSATION_KEYS.lock()
+        .map_err(|e| format!("Failed to lock conversation keys: {}", e))
+}
+
+/// Conversation key between our keys and a peer, derived once and then cached
+fn cached_conversation_key(keys: &Keys, public_key: &PublicKey) -> Result<ConversationKey, DecryptError> {
+    let to_decrypt_error = |e: nip44::Error| DecryptError::new(DecryptErrorReason::InvalidKey, format!("Invalid conversation key: {}", e));
+    let lock_error = |e: String| DecryptError::new(DecryptErrorReason::InvalidKey, e);
+    let id = (keys.public_key().to_hex(), public_key.to_hex());
+    if let Some(bytes) = lock_conversation_keys().map_err(lock_error)?.as_ref().and_then(|cache| cache.get(&id).cloned()) {
+        return ConversationKey::from_slice(&bytes[..]).map_err(to_decrypt_error);
+    }
+    
+    // ECDH is the expensive part, done outside of the lock
+    let conversation_key = ConversationKey::derive(keys.secret_key(), public_key)
+        .map_err(to_decrypt_error)?;
+    let mut bytes = Zeroizing::new([0u8; 32]);
+    bytes.copy_from_slice(conversation_key.as_bytes());
+    
+    let mut cache = lock_conversation_keys().map_err(lock_error)?;
+    let cache = cache.get_or_insert_with(HashMap::new);
+    // Bounded by the resource profile, cleared when full
+    if cache.len() >= super::system::resource_limits().conversation_key_cache as usize {
+        cache.clear();
+    }
+    cache.insert(id, bytes);
+    Ok(conversation_key)
+}
+
+/// Derive (or get from the cache) the NIP-44 conversation key with a peer
+///
+/// Returns the hex conversation key for `nip44_decrypt_with_conversation_key`. It decrypts
+/// every message between the two keys, treat it like a private key.
+///
+/// # Arguments
+/// * `public_key` - Peer public key
+/// * `private_key` - Our private key
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_conversation_key(public_key: String, private_key: String) -> Result<String, DecryptError> {
+    let (public_key, keys) = parse_decrypt_keys(&public_key, &private_key)?;
+    let conversation_key = cached_conversation_key(&keys, &public_key)?;
+    Ok(hex::encode(conversation_key.as_bytes()))
+}
+
+/// Decrypt a NIP-44 payload with a conversation key, skipping the key derivation
+///
+/// Audited under our public key while the key is cached by `nip44_conversation_key`.
+///
+/// # Arguments
+/// * `ciphertext` - NIP-44 v2 payload
+/// * `conversation_key` - Hex key from `nip44_conversation_key`
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_decrypt_with_conversation_key(ciphertext: String, conversation_key: String) -> Result<String, DecryptError> {
+    let invalid_key = || DecryptError::new(DecryptErrorReason::InvalidKey, "Conversation key must be 32 hex bytes");
+    let bytes: [u8; 32] = hex::decode(conversation_key.trim())
+        .map_err(|_| invalid_key())?
+        .try_into()
+        .map_err(|_| invalid_key())?;
+    let conversation_key = ConversationKey::from_slice(&bytes)
+        .map_err(|_| invalid_key())?;
+    check_nip44_payload(&ciphertext)?;
+    
+    let decrypted = nip44_decrypt_with_key(&ciphertext, &conversation_key)?;
+    // Audited under the owner of the key; keys not derived here don't involve our private keys
+    let owner = lock_conversation_keys()
+        .map_err(|e| DecryptError::new(DecryptErrorReason::InvalidKey, e))?
+        .as_ref()
+        .and_then(|cache| cache.iter().find(|(_, key)| ***key == bytes).map(|((ours, _), _)| ours.clone()));
+    if let Some(owner) = owner {
+        audit::record(KeyOperation::Nip44Decrypt, &owner, None);
+    }
     
     Ok(decrypted)
 }
 
+/// Forget the cached conversation keys (on logout, account switch, ...), zeroizing them
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_clear_conversation_keys() -> Result<(), String> {
+    *lock_conversation_keys()? = None;
+    Ok(())
+}
+
+/// Zeroize and drop the cached conversation keys of an account, returns how many were dropped
+pub(crate) fn forget_conversation_keys(public_key: &str) -> Result<u32, String> {
+    let mut cache = lock_conversation_keys()?;
+    let Some(cache) = cache.as_mut() else {
+        return Ok(0);
+    };
+    let count = cache.len();
+    cache.retain(|(ours, _), _| ours != public_key);
+    Ok((count - cache.len()) as u32)
+}
+
+/// Payload to decrypt in a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptRequest {
+    pub ciphertext: String,
+    /// Public key of the peer
+    pub public_key: String,
+}
+
+/// Outcome of one payload of a batch: either the plaintext or the error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecryptOutcome {
+    pub plaintext: Option<String>,
+    pub error: Option<DecryptError>,
+}
+
+/// Map `items` on all cores, results are in the order of the items
+///
+/// Each item of a chunk whose worker panicked gets `failed(item)`, so there is always one
+/// result per item.
+fn map_parallel<T: Sync, R: Send>(items: &[T], map: impl Fn(&T) -> R + Sync, failed: impl Fn(&T) -> R) -> Vec<R> {
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_size = items.len().div_ceil(threads).max(1);
+    let map = &map;
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = items.chunks(chunk_size)
+            .map(|chunk| (chunk, scope.spawn(move || chunk.iter().map(map).collect::<Vec<_>>())))
+            .collect();
+        workers.into_iter()
+            .flat_map(|(chunk, worker)| worker.join().unwrap_or_else(|_| chunk.iter().map(&failed).collect()))
+            .collect()
+    })
+}
+
+/// Decrypt payloads on all cores, outcomes are in the order of the requests
+fn decrypt_batch(
+    requests: Vec<DecryptRequest>,
+    private_key: &str,
+    decrypt: fn(String, String, String) -> Result<String, DecryptError>,
+) -> Vec<DecryptOutcome> {
+    map_parallel(
+        &requests,
+        |request| match decrypt(request.ciphertext.clone(), request.public_key.clone(), private_key.to_string()) {
+            Ok(plaintext) => DecryptOutcome { plaintext: Some(plaintext), error: None },
+            Err(error) => DecryptOutcome { plaintext: None, error: Some(error) },
+        },
+        |_| DecryptOutcome {
+            plaintext: None,
+            error: Some(DecryptError::new(DecryptErrorReason::Internal, "Decryption failed unexpectedly")),
+        },
+    )
+}
+
+/// Decrypt many NIP-04 payloads in one call, in parallel
+///
+/// A payload failing to decrypt doesn't fail the batch, its outcome holds the error.
+///
+/// # Arguments
+/// * `requests` - Payloads with the public key of their peer
+/// * `private_key` - Our private key
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip04_decrypt_batch(requests: Vec<DecryptRequest>, private_key: String) -> Vec<DecryptOutcome> {
+    decrypt_batch(requests, &private_key, nip04_decrypt)
+}
+
+/// Decrypt many NIP-44 payloads in one call, in parallel
+///
+/// Conversation keys are derived once per peer. A payload failing to decrypt doesn't
+/// fail the batch, its outcome holds the error.
+///
+/// # Arguments
+/// * `requests` - Payloads with the public key of their peer
+/// * `private_key` - Our private key
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_decrypt_batch(requests: Vec<DecryptRequest>, private_key: String) -> Vec<DecryptOutcome> {
+    decrypt_batch(requests, &private_key, nip44_decrypt)
+}
+
+/// Largest plaintext NIP-44 v2 can encrypt, in bytes
+const NIP44_MAX_PLAINTEXT_LEN: u32 = 65535;
+
+/// Padded length of a NIP-44 v2 plaintext
+pub(crate) fn nip44_padded_len(plaintext_len: u32) -> u32 {
+    if plaintext_len <= 32 {
+        return 32;
+    }
+    let next_power = 1u32 << (32 - (plaintext_len - 1).leading_zeros());
+    let chunk = if next_power <= 256 { 32 } else { next_power / 8 };
+    chunk * ((plaintext_len - 1) / chunk + 1)
+}
+
+/// Length of the NIP-44 v2 payload (base64) for a plaintext of `plaintext_len` UTF-8 bytes
+///
+/// The plaintext is padded, so the length only changes at padding boundaries.
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_ciphertext_len(plaintext_len: u32) -> Result<u32, String> {
+    if plaintext_len == 0 || plaintext_len > NIP44_MAX_PLAINTEXT_LEN {
+        return Err(format!("Plaintext must be 1 to {} bytes", NIP44_MAX_PLAINTEXT_LEN));
+    }
+    // version + nonce + (length prefix + padded plaintext) + MAC
+    let payload_len = 1 + 32 + 2 + nip44_padded_len(plaintext_len) + 32;
+    Ok(payload_len.div_ceil(3) * 4)
+}
+
+/// Longest plaintext (UTF-8 bytes) whose NIP-44 v2 payload fits in `size_budget` bytes
+///
+/// Returns 0 if not even a single byte fits. Composer UIs can count down from it; note the
+/// limit is in bytes, not characters.
+#[flutter_rust_bridge::frb(sync)]
+pub fn nip44_max_plaintext_for(size_budget: u32) -> u32 {
+    let fits = |len: u32| nip44_ciphertext_len(len).map_or(false, |ciphertext_len| ciphertext_len <= size_budget);
+    if !fits(1) {
+        return 0;
+    }
+    
+    // Payload length grows with the plaintext length, binary search the last one that fits
+    let (mut low, mut high) = (1, NIP44_MAX_PLAINTEXT_LEN);
+    while low < high {
+        let mid = low + (high - low + 1) / 2;
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
 #[flutter_rust_bridge::frb(sync)]
 pub fn sign_event(event_json: String, private_key: String) -> Result<String, String> {
     let private_key = SecretKey::from_str(&private_key)
         .map_err(|e| format!("Invalid private key: {}", e))?;
     
     let keys = Keys::new(private_key);
-    
+    sign_event_with_keys(&event_json, &keys)
+}
+
+/// Sign an unsigned event (JSON), see `sign_event`
+pub(crate) fn sign_event_with_keys(event_json: &str, keys: &Keys) -> Result<String, String> {
     // Parse the event from JSON
+    let event_data: serde_json::Value = serde_json::from_str(event_json)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+    event_data["pubkey"].as_str()
+        .ok_or("Missing pubkey field")?;
+    
+    // Create and sign the event using EventBuilder
+    let event = event_builder_from_json(&event_data)?
+        .sign_with_keys(keys)
+        .map_err(|e| format!("Failed to create and sign event: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(event.kind.as_u16()));
+    
+    // Convert back to JSON string
+    let signed_event_json = serde_json::to_string(&event)
+        .map_err(|e| format!("Failed to serialize signed event: {}", e))?;
+    
+    Ok(signed_event_json)
+}
+
+/// Sign an unsigned event (JSON) with a platform or in-memory signer, see `sign_event`
+///
+/// The `pubkey` field is optional, the event is signed for the signer's public key.
+pub async fn sign_event_with_signer(event_json: String, signer: &Signer) -> Result<String, String> {
     let event_data: serde_json::Value = serde_json::from_str(&event_json)
         .map_err(|e| format!("Invalid JSON: {}", e))?;
+    let event = signer.sign_builder(event_builder_from_json(&event_data)?).await?;
     
+    serde_json::to_string(&event)
+        .map_err(|e| format!("Failed to serialize signed event: {}", e))
+}
+
+/// Event created by `build_event`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuiltEvent {
+    /// Event id (hex)
+    pub id: String,
+    /// Signed event JSON, ready to publish
+    pub event_json: String,
+}
+
+/// Create and sign an event of any kind
+///
+/// # Arguments
+/// * `kind` - Event kind
+/// * `content` - Event content
+/// * `tags` - Tags, each a list of strings (e.g. `["p", "<hex pubkey>"]`)
+/// * `private_key` - Private key (hex or nsec) signing the event
+/// * `created_at` - Unix timestamp (seconds), None for now
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_event(
+    kind: u16,
+    content: String,
+    tags: Vec<Vec<String>>,
+    private_key: String,
+    created_at: Option<u64>,
+) -> Result<BuiltEvent, String> {
+    let keys = Keys::new(parse_secret_key(&private_key).map_err(|e| e.message)?);
+    let event = event_builder(kind, content, tags, created_at)?
+        .sign_with_keys(&keys)
+        .map_err(|e| format!("Failed to create and sign event: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(kind));
+    
+    Ok(BuiltEvent {
+        id: event.id.to_hex(),
+        event_json: event.as_json(),
+    })
+}
+
+/// `build_event` with a platform or in-memory signer
+pub async fn build_event_with_signer(
+    kind: u16,
+    content: String,
+    tags: Vec<Vec<String>>,
+    signer: &Signer,
+    created_at: Option<u64>,
+) -> Result<BuiltEvent, String> {
+    let unsigned = event_builder(kind, content, tags, created_at)?.build(signer.nostr_public_key());
+    let event = signer.sign_unsigned(unsigned).await?;
+    
+    Ok(BuiltEvent {
+        id: event.id.to_hex(),
+        event_json: event.as_json(),
+    })
+}
+
+fn event_builder(kind: u16, content: String, tags: Vec<Vec<String>>, created_at: Option<u64>) -> Result<EventBuilder, String> {
+    let tags = tags.into_iter()
+        .map(|tag| Tag::parse(tag))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid tags: {}", e))?;
+    
+    let builder = EventBuilder::new(Kind::from(kind), content).tags(tags);
+    Ok(match created_at {
+        Some(created_at) => builder.custom_created_at(Timestamp::from(created_at)),
+        None => with_fixed_created_at(builder),
+    })
+}
+
+/// Event builder for the `created_at`, `kind`, `content` and `tags` fields of an unsigned event
+fn event_builder_from_json(event_data: &serde_json::Value) -> Result<EventBuilder, String> {
     // Extract fields
-    let pubkey = event_data["pubkey"].as_str()
-        .ok_or("Missing pubkey field")?;
     let created_at = event_data["created_at"].as_u64()
         .ok_or("Missing or invalid created_at field")?;
     let kind = event_data["kind"].as_u64()
@@ -148,47 +839,652 @@ pub fn sign_event(event_json: String, private_key: String) -> Result<String, Str
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| format!("Invalid tags: {}", e))?;
     
-    // Create and sign the event using EventBuilder
-    let event = EventBuilder::new(Kind::from(kind as u16), content)
+    Ok(EventBuilder::new(Kind::from(kind as u16), content)
         .tags(nostr_tags)
-        .custom_created_at(Timestamp::from(created_at))
+        .custom_created_at(Timestamp::from(created_at)))
+}
+
+/// Verify the id and signature of an event
+///
+/// Returns false if either doesn't match, an error if a field is malformed.
+#[flutter_rust_bridge::frb(sync)]
+pub fn verify_event(event: NostrEvent) -> Result<bool, String> {
+    let event_json = serde_json::to_string(&event)
+        .map_err(|e| format!("Failed to serialize event: {}", e))?;
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event: {}", e))?;
+    Ok(event.verify_id() && event.verify_signature())
+}
+
+/// Outcome of verifying one event of a batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventVerification {
+    /// Id claimed by the event, None if the JSON couldn't be parsed
+    pub event_id: Option<String>,
+    /// Whether both the id and the signature are valid
+    pub valid: bool,
+    /// Why the event is invalid
+    pub error: Option<String>,
+}
+
+fn verify_event_json(event_json: &str) -> EventVerification {
+    let event = match Event::from_json(event_json) {
+        Ok(event) => event,
+        Err(e) => return EventVerification {
+            event_id: None,
+            valid: false,
+            error: Some(format!("Invalid event JSON: {}", e)),
+        },
+    };
+    let error = if !event.verify_id() {
+        Some("Id doesn't match the event".to_string())
+    } else if !event.verify_signature() {
+        Some("Invalid signature".to_string())
+    } else {
+        None
+    };
+    EventVerification {
+        event_id: Some(event.id.to_hex()),
+        valid: error.is_none(),
+        error,
+    }
+}
+
+/// Parse an event (JSON) and verify its id and signature
+///
+/// Events from the local relay or remote relays should go through this (or
+/// `verify_events_batch`) before they are trusted; an event with a wrong id or signature
+/// is an error.
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_and_verify_event(event_json: String) -> Result<NostrEvent, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if !event.verify_id() {
+        return Err("Id doesn't match the event".to_string());
+    }
+    if !event.verify_signature() {
+        return Err("Invalid signature".to_string());
+    }
+    
+    Ok(NostrEvent {
+        id: event.id.to_hex(),
+        pubkey: event.pubkey.to_hex(),
+        created_at: event.created_at.as_u64(),
+        kind: event.kind.as_u16() as u64,
+        tags: event.tags.iter().map(|tag| tag.as_slice().to_vec()).collect(),
+        content: event.content,
+        sig: event.sig.to_string(),
+    })
+}
+
+/// Verify the ids and signatures of many events in one call, in parallel
+///
+/// Results are in the order of the events; an invalid event doesn't fail the batch.
+#[flutter_rust_bridge::frb(sync)]
+pub fn verify_events_batch(events_json: Vec<String>) -> Vec<EventVerification> {
+    map_parallel(
+        &events_json,
+        |json| verify_event_json(json),
+        |_| EventVerification {
+            event_id: None,
+            valid: false,
+            error: Some("Verification failed unexpectedly".to_string()),
+        },
+    )
+}
+
+/// BIP-340 message of an arbitrary UTF-8 message: its tagged hash
+///
+/// The tag keeps message signatures apart from event signatures (the plain SHA-256 of an
+/// event serialization), so a challenge can't be used to get an event signed.
+fn message_digest(message: &str) -> Message {
+    Message::from_digest(super::bitcoin::tagged_hash("nostr:message", message.as_bytes()))
+}
+
+/// Sign an arbitrary message (login challenge, HTTP auth, ...) with BIP-340 Schnorr
+///
+/// The BIP-340 tagged hash (tag "nostr:message") of the UTF-8 message is signed, returns the
+/// hex signature.
+#[flutter_rust_bridge::frb(sync)]
+pub fn sign_message(message: String, private_key: String) -> Result<String, String> {
+    let keys = Keys::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let signature = keys.sign_schnorr(&message_digest(&message));
+    audit::record(KeyOperation::SignMessage, &keys.public_key().to_hex(), None);
+    Ok(signature.to_string())
+}
+
+/// Verify a signature made by `sign_message`
+///
+/// Returns false if the signature does not match, an error if an argument is malformed.
+#[flutter_rust_bridge::frb(sync)]
+pub fn verify_message(signature: String, message: String, public_key: String) -> Result<bool, String> {
+    let signature = Signature::from_str(&signature)
+        .map_err(|e| format!("Invalid signature: {}", e))?;
+    let xonly = PublicKey::parse(&public_key)
+        .and_then(|pubkey| pubkey.xonly())
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    Ok(Secp256k1::verification_only()
+        .verify_schnorr(&signature, &message_digest(&message), &xonly)
+        .is_ok())
+}
+
+/// HTTP auth event kind (NIP-98)
+const HTTP_AUTH_KIND: u16 = 27235;
+
+/// Create a NIP-98 HTTP auth event and return the `Authorization` header value for it
+///
+/// The value is `Nostr <base64 event JSON>`, as expected by NIP-96 file servers and other
+/// HTTP APIs. Servers only accept the event for about a minute, create it right before the
+/// request.
+///
+/// # Arguments
+/// * `url` - Absolute request URL, including the query string
+/// * `method` - HTTP method (e.g. "POST")
+/// * `payload_hash` - Hex SHA-256 of the request body, for requests with one
+/// * `private_key` - Private key (hex or nsec) signing the event
+#[flutter_rust_bridge::frb(sync)]
+pub fn create_http_auth_event(
+    url: String,
+    method: String,
+    payload_hash: Option<String>,
+    private_key: String,
+) -> Result<String, String> {
+    let keys = Keys::new(parse_secret_key(&private_key).map_err(|e| e.message)?);
+    let event = http_auth_builder(url, method, payload_hash)?
         .sign_with_keys(&keys)
-        .map_err(|e| format!("Failed to create and sign event: {}", e))?;
+        .map_err(|e| format!("Failed to sign HTTP auth event: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(HTTP_AUTH_KIND));
     
-    // Convert back to JSON string
-    let signed_event_json = serde_json::to_string(&event)
-        .map_err(|e| format!("Failed to serialize signed event: {}", e))?;
+    Ok(format!("Nostr {}", base64::engine::general_purpose::STANDARD.encode(event.as_json())))
+}
+
+/// `create_http_auth_event` with a platform or in-memory signer
+pub async fn create_http_auth_event_with_signer(
+    url: String,
+    method: String,
+    payload_hash: Option<String>,
+    signer: &Signer,
+) -> Result<String, String> {
+    let unsigned = http_auth_builder(url, method, payload_hash)?.build(signer.nostr_public_key());
+    let event = signer.sign_unsigned(unsigned).await?;
     
-    Ok(signed_event_json)
+    Ok(format!("Nostr {}", base64::engine::general_purpose::STANDARD.encode(event.as_json())))
 }
 
+fn http_auth_builder(url: String, method: String, payload_hash: Option<String>) -> Result<EventBuilder, String> {
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!("Invalid URL '{}': expected http:// or https://", url));
+    }
+    let method = method.trim().to_uppercase();
+    if method.is_empty() {
+        return Err("Method must not be empty".to_string());
+    }
+    
+    let mut tags = vec![vec!["u".to_string(), url], vec!["method".to_string(), method]];
+    if let Some(payload_hash) = payload_hash {
+        let payload_hash = payload_hash.trim().to_lowercase();
+        if payload_hash.len() != 64 || !payload_hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Payload hash must be a hex SHA-256".to_string());
+        }
+        tags.push(vec!["payload".to_string(), payload_hash]);
+    }
+    let tags = tags.into_iter()
+        .map(|tag| Tag::parse(tag))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid tags: {}", e))?;
+    
+    Ok(EventBuilder::new(Kind::from(HTTP_AUTH_KIND), "").tags(tags))
+}
+
+/// ECDH shared secret between two keys
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedSecret {
+    /// Hex x coordinate of the shared point (the secret NIP-04 and NIP-44 build on)
+    pub x: String,
+    /// Hex SHA-256 of the x coordinate
+    pub hashed: String,
+}
+
+/// Derive the ECDH shared secret with a peer, for encryption schemes not covered by NIP-04/44
+///
+/// Nostr public keys are x-only, the point with an even y coordinate is used (as NIP-04 and
+/// NIP-44 do), so both sides derive the same x coordinate. Only x is hashed: the parity of
+/// the shared point depends on which side derives it. Use a KDF on it, never use it as a key
+/// directly.
+///
+/// # Arguments
+/// * `private_key` - Our private key (hex or nsec)
+/// * `public_key` - Peer public key (hex or npub)
 #[flutter_rust_bridge::frb(sync)]
-pub fn verify_event(event: NostrEvent) -> Result<bool, String> {
-    let event_id = EventId::from_str(&event.id)
-        .map_err(|e| format!("Invalid event ID: {}", e))?;
-    let pubkey = PublicKey::from_str(&event.pubkey)
+pub fn derive_shared_secret(private_key: String, public_key: String) -> Result<SharedSecret, String> {
+    let keys = Keys::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let xonly = PublicKey::parse(&public_key)
+        .and_then(|pubkey| pubkey.xonly())
         .map_err(|e| format!("Invalid public key: {}", e))?;
-    let sig = Signature::from_str(&event.sig)
-        .map_err(|e| format!("Invalid signature: {}", e))?;
+    let secret = SecpSecretKey::from_slice(&keys.secret_key().to_secret_bytes())
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let point = SecpPublicKey::from_x_only_public_key(xonly, Parity::Even);
     
-    // Convert tags back to nostr format
-    let tags: Vec<Tag> = event.tags.into_iter()
-        .map(|tag_vec| {
-            let tag_strings: Vec<String> = tag_vec.into_iter().map(|s| s.to_string()).collect();
-            Tag::parse(&tag_strings)
+    let x = Zeroizing::new(ecdh::shared_secret_point(&point, &secret));
+    let hashed = Sha256Hash::hash(&x[..32]);
+    audit::record(KeyOperation::DeriveSharedSecret, &keys.public_key().to_hex(), None);
+    
+    Ok(SharedSecret {
+        x: hex::encode(&x[..32]),
+        hashed: hashed.to_string(),
+    })
+}
+
+/// Number of leading zero bits of an event id (NIP-13 difficulty)
+pub(crate) fn leading_zero_bits(id: &[u8; 32]) -> u32 {
+    let mut bits = 0;
+    for byte in id {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+/// Target difficulty committed to in the `nonce` tag (`["nonce", <nonce>, <target>]`)
+pub(crate) fn nonce_target<'a>(mut tags: impl Iterator<Item = &'a [String]>) -> Option<u32> {
+    tags.find(|tag| tag.first().map(String::as_str) == Some("nonce"))
+        .and_then(|tag| tag.get(2))
+        .and_then(|target| target.parse().ok())
+}
+
+/// Proof of work an event can be credited with: its difficulty, capped by the committed target
+///
+/// The cap keeps spammers mining for a lower target from passing when they get lucky (NIP-13).
+pub(crate) fn effective_pow(id: &[u8; 32], target: Option<u32>) -> u32 {
+    let difficulty = leading_zero_bits(id);
+    target.map_or(difficulty, |target| difficulty.min(target))
+}
+
+/// Difficulty of an event id (NIP-13), i.e. its number of leading zero bits
+#[flutter_rust_bridge::frb(sync)]
+pub fn event_pow_difficulty(event_id: String) -> Result<u32, String> {
+    let id = EventId::from_hex(&event_id)
+        .map_err(|e| format!("Invalid event ID: {}", e))?;
+    Ok(leading_zero_bits(id.as_bytes()))
+}
+
+/// Check that an event carries at least `min_difficulty` bits of proof of work (NIP-13)
+///
+/// The id and signature are verified too. When the `nonce` tag commits to a target lower
+/// than `min_difficulty`, the event is refused even if its id happens to be harder.
+#[flutter_rust_bridge::frb(sync)]
+pub fn verify_pow(event_json: String, min_difficulty: u32) -> Result<bool, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if event.verify().is_err() {
+        return Ok(false);
+    }
+    let target = nonce_target(event.tags.iter().map(|tag| tag.as_slice()));
+    Ok(effective_pow(event.id.as_bytes(), target) >= min_difficulty)
+}
+
+/// Default maximum content size (in bytes) of a single chunk event
+const DEFAULT_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Split content into UTF-8 safe pieces of at most `max_bytes` bytes each
+fn split_utf8(content: &str, max_bytes: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < content.len() {
+        let mut end = (start + max_bytes).min(content.len());
+        while !content.is_char_boundary(end) {
+            end -= 1;
+        }
+        parts.push(&content[start..end]);
+        start = end;
+    }
+    parts
+}
+
+/// Split large content into multiple signed chunk events
+///
+/// Every chunk carries an `x` tag with the SHA-256 of the full content (used as group id,
+/// so the chunks can be queried from the relay) and a `chunk` tag with `[index, total]`.
+///
+/// # Arguments
+/// * `content` - Full content to split
+/// * `kind` - Kind of the chunk events
+/// * `private_key` - Hex private key used to sign the chunks
+/// * `max_chunk_size` - Maximum content bytes per chunk (defaults to 32 KiB)
+#[flutter_rust_bridge::frb(sync)]
+pub fn split_content_into_chunks(
+    content: String,
+    kind: u16,
+    private_key: String,
+    max_chunk_size: Option<u32>,
+) -> Result<Vec<String>, String> {
+    let private_key = SecretKey::from_str(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let keys = Keys::new(private_key);
+    
+    chunk_builders(&content, kind, max_chunk_size)?
+        .into_iter()
+        .map(|builder| {
+            let event = with_fixed_created_at(builder)
+                .sign_with_keys(&keys)
+                .map_err(|e| format!("Failed to sign chunk event: {}", e))?;
+            audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(kind));
+            
+            Ok(event.as_json())
         })
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(|e| format!("Invalid tags: {}", e))?;
+        .collect()
+}
+
+/// `split_content_into_chunks` with a platform or in-memory signer
+pub async fn split_content_into_chunks_with_signer(
+    content: String,
+    kind: u16,
+    signer: &Signer,
+    max_chunk_size: Option<u32>,
+) -> Result<Vec<String>, String> {
+    let mut chunks = Vec::new();
+    for builder in chunk_builders(&content, kind, max_chunk_size)? {
+        chunks.push(signer.sign_builder(builder).await?.as_json());
+    }
+    Ok(chunks)
+}
+
+fn chunk_builders(content: &str, kind: u16, max_chunk_size: Option<u32>) -> Result<Vec<EventBuilder>, String> {
+    let max_chunk_size = max_chunk_size.map(|n| n as usize).unwrap_or(DEFAULT_CHUNK_SIZE);
+    if max_chunk_size < 4 {
+        return Err("Chunk size must be at least 4 bytes".to_string());
+    }
+    
+    let group_id = Sha256Hash::hash(content.as_bytes()).to_string();
+    let parts = split_utf8(content, max_chunk_size);
+    let total = parts.len().max(1);
+    let parts = if parts.is_empty() { vec![""] } else { parts };
+    
+    parts.into_iter()
+        .enumerate()
+        .map(|(index, part)| {
+            let tags = vec![
+                Tag::parse(["x", group_id.as_str()]),
+                Tag::parse(["chunk", &index.to_string(), &total.to_string()]),
+            ]
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid tags: {}", e))?;
+            
+            Ok(EventBuilder::new(Kind::from(kind), part).tags(tags))
+        })
+        .collect()
+}
+
+/// Reassemble the content of chunk events created by `split_content_into_chunks`
+///
+/// Chunks can be given in any order. Signatures are verified, all chunks must have the same
+/// author, duplicates are ignored and the reassembled content is checked against the group id
+/// (SHA-256 of the full content).
+#[flutter_rust_bridge::frb(sync)]
+pub fn reassemble_chunks(events_json: Vec<String>) -> Result<String, String> {
+    let events = events_json.iter()
+        .map(|json| Event::from_json(json).map_err(|e| format!("Invalid event JSON: {}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    let mut group_id: Option<String> = None;
+    let mut author: Option<PublicKey> = None;
+    let mut total: Option<usize> = None;
+    let mut parts: std::collections::BTreeMap<usize, String> = std::collections::BTreeMap::new();
+    
+    for event in events {
+        event.verify()
+            .map_err(|e| format!("Invalid chunk event {}: {}", event.id, e))?;
+        // Otherwise anyone could slip chunks into someone else's content
+        if *author.get_or_insert(event.pubkey) != event.pubkey {
+            return Err("Chunk events have different authors".to_string());
+        }
+        
+        let mut event_group = None;
+        let mut event_position = None;
+        for tag in event.tags.iter() {
+            match tag.as_slice() {
+                [name, value, ..] if name == "x" => event_group = Some(value.clone()),
+                [name, index, count, ..] if name == "chunk" => {
+                    let index = index.parse::<usize>()
+                        .map_err(|_| format!("Invalid chunk index: {}", index))?;
+                    let count = count.parse::<usize>()
+                        .map_err(|_| format!("Invalid chunk total: {}", count))?;
+                    event_position = Some((index, count));
+                }
+                _ => {}
+            }
+        }
+        
+        let event_group = event_group.ok_or("Chunk event is missing x tag")?;
+        let (index, count) = event_position.ok_or("Chunk event is missing chunk tag")?;
+        
+        if *group_id.get_or_insert_with(|| event_group.clone()) != event_group {
+            return Err("Chunk events belong to different groups".to_string());
+        }
+        if *total.get_or_insert(count) != count {
+            return Err("Chunk events disagree on the total number of chunks".to_string());
+        }
+        if index >= count {
+            return Err(format!("Chunk index {} out of range (total {})", index, count));
+        }
+        
+        parts.entry(index).or_insert(event.content);
+    }
     
-    // Create the event for verification using EventBuilder
-    let nostr_event = EventBuilder::new(Kind::from(event.kind as u16), event.content)
-        .tags(tags)
-        .custom_created_at(Timestamp::from(event.created_at))
-        .sign_with_keys(&Keys::new(SecretKey::from_str(&event.pubkey).unwrap()))
-        .unwrap();
+    let group_id = group_id.ok_or("No chunk events provided")?;
+    let total = total.unwrap_or(0);
+    if parts.len() != total {
+        return Err(format!("Missing chunks: have {} of {}", parts.len(), total));
+    }
     
-    // Verify the signature
-    Ok(nostr_event.verify().is_ok())
+    let content: String = parts.into_values().collect();
+    if Sha256Hash::hash(content.as_bytes()).to_string() != group_id {
+        return Err("Reassembled content does not match chunk group hash".to_string());
+    }
+    
+    Ok(content)
+}
+
+/// How complete a copy of an event is: valid signature, then number of tags and tag values
+fn completeness(event: &Event) -> (bool, usize, usize) {
+    let values = event.tags.iter().map(|tag| tag.as_slice().len()).sum();
+    (event.verify().is_ok(), event.tags.len(), values)
+}
+
+/// Merge events fetched from several relays: one copy per id, newest first
+///
+/// When copies of an event differ (e.g. a relay stripped or altered tags), the one with a
+/// valid signature and the most complete tags is kept. Events are returned as canonical
+/// JSON; unparseable ones are dropped. Ties on `created_at` are ordered by id, so the
+/// result doesn't depend on the input order.
+#[flutter_rust_bridge::frb(sync)]
+pub fn dedupe_events(events_json: Vec<String>) -> Vec<String> {
+    let mut unique: std::collections::HashMap<EventId, Event> = std::collections::HashMap::new();
+    
+    for json in events_json.iter() {
+        let event = match Event::from_json(json) {
+            Ok(event) => event,
+            Err(_) => continue,
+        };
+        match unique.get(&event.id) {
+            // Identical copies are the common case, skip verifying them
+            Some(kept) if kept.tags == event.tags && kept.sig == event.sig => {}
+            Some(kept) if completeness(kept) >= completeness(&event) => {}
+            _ => {
+                unique.insert(event.id, event);
+            }
+        }
+    }
+    
+    let mut events: Vec<Event> = unique.into_values().collect();
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+    events.iter().map(|event| event.as_json()).collect()
+}
+
+/// Kind of NIP-17 DM relay list events
+pub(crate) const DM_RELAY_LIST_KIND: u16 = 10050;
+
+/// Build and sign a NIP-17 DM relay list (kind 10050)
+///
+/// # Arguments
+/// * `relays` - Relay URLs where the user wants to receive gift-wrapped DMs
+/// * `private_key` - Hex private key used to sign the event
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_dm_relay_list(relays: Vec<String>, private_key: String) -> Result<String, String> {
+    let private_key = SecretKey::from_str(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let keys = Keys::new(private_key);
+    
+    let event = with_fixed_created_at(dm_relay_list_builder(&relays)?)
+        .sign_with_keys(&keys)
+        .map_err(|e| format!("Failed to sign DM relay list: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(DM_RELAY_LIST_KIND));
+    
+    Ok(event.as_json())
+}
+
+/// `build_dm_relay_list` with a platform or in-memory signer
+pub async fn build_dm_relay_list_with_signer(relays: Vec<String>, signer: &Signer) -> Result<String, String> {
+    Ok(signer.sign_builder(dm_relay_list_builder(&relays)?).await?.as_json())
+}
+
+fn dm_relay_list_builder(relays: &[String]) -> Result<EventBuilder, String> {
+    let tags = relays.iter()
+        .map(|url| {
+            let url = RelayUrl::parse(url)
+                .map_err(|e| format!("Invalid relay URL '{}': {}", url, e))?;
+            Tag::parse(["relay", url.as_str()])
+                .map_err(|e| format!("Invalid tags: {}", e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    
+    Ok(EventBuilder::new(Kind::from(DM_RELAY_LIST_KIND), "").tags(tags))
+}
+
+/// Parse the relay URLs of a NIP-17 DM relay list (kind 10050)
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_dm_relay_list(event_json: String) -> Result<Vec<String>, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if event.kind != Kind::from(DM_RELAY_LIST_KIND) {
+        return Err(format!("Not a DM relay list: kind {}", event.kind));
+    }
+    event.verify()
+        .map_err(|e| format!("Invalid DM relay list: {}", e))?;
+    
+    Ok(dm_relays_from_event(&event))
+}
+
+pub(crate) fn dm_relays_from_event(event: &Event) -> Vec<String> {
+    event.tags.iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, url, ..] if name == "relay" => RelayUrl::parse(url).ok().map(|url| url.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Window (seconds into the past) of randomized created_at values, NIP-59 recommends two days
+static TIMESTAMP_RANDOMIZATION_SECS: AtomicU64 = AtomicU64::new(2 * 24 * 60 * 60);
+
+/// Random created_at within the configured window before now, to hide when a message was sent
+pub(crate) fn randomized_created_at() -> Timestamp {
+    let window = TIMESTAMP_RANDOMIZATION_SECS.load(Ordering::Relaxed);
+    if window == 0 {
+        return Timestamp::now();
+    }
+    Timestamp::tweaked(0..window)
+}
+
+/// Set how far in the past randomized created_at values may be (0 disables randomization)
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_timestamp_randomization_window(window_secs: u64) {
+    TIMESTAMP_RANDOMIZATION_SECS.store(window_secs, Ordering::Relaxed);
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_timestamp_randomization_window() -> u64 {
+    TIMESTAMP_RANDOMIZATION_SECS.load(Ordering::Relaxed)
+}
+
+/// Signed direct message with the time it was actually written
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectMessageOutput {
+    pub event_json: String,
+    /// When the message was written, for ordering it locally (the event's created_at may be randomized)
+    pub canonical_created_at: u64,
+}
+
+/// Build and sign a NIP-04 direct message (kind 4)
+///
+/// # Arguments
+/// * `content` - Plaintext message
+/// * `receiver_pubkey` - Hex public key of the receiver
+/// * `private_key` - Hex private key of the sender
+/// * `randomize_created_at` - Publish with a random created_at within the configured window
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_encrypted_dm(
+    content: String,
+    receiver_pubkey: String,
+    private_key: String,
+    randomize_created_at: bool,
+) -> Result<DirectMessageOutput, String> {
+    let receiver = PublicKey::from_str(&receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let private_key = SecretKey::from_str(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let keys = Keys::new(private_key);
+    
+    let encrypted = nip04::encrypt(keys.secret_key(), &receiver, content)
+        .map_err(|e| format!("Encryption failed: {}", e))?;
+    
+    let (builder, canonical) = encrypted_dm_builder(encrypted, receiver, randomize_created_at);
+    let event = builder.sign_with_keys(&keys)
+        .map_err(|e| format!("Failed to sign direct message: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(event.kind.as_u16()));
+    
+    Ok(DirectMessageOutput {
+        event_json: event.as_json(),
+        canonical_created_at: canonical.as_u64(),
+    })
+}
+
+/// `build_encrypted_dm` with a platform or in-memory signer (one that can encrypt)
+pub async fn build_encrypted_dm_with_signer(
+    content: String,
+    receiver_pubkey: String,
+    signer: &Signer,
+    randomize_created_at: bool,
+) -> Result<DirectMessageOutput, String> {
+    let receiver = PublicKey::from_str(&receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let encrypted = signer.encrypt(&receiver, content, false).await?;
+    
+    let (builder, canonical) = encrypted_dm_builder(encrypted, receiver, randomize_created_at);
+    let event = signer.sign_unsigned(builder.build(signer.nostr_public_key())).await?;
+    
+    Ok(DirectMessageOutput {
+        event_json: event.as_json(),
+        canonical_created_at: canonical.as_u64(),
+    })
+}
+
+/// Kind 4 event builder, with the canonical time the message was written
+fn encrypted_dm_builder(encrypted: String, receiver: PublicKey, randomize_created_at: bool) -> (EventBuilder, Timestamp) {
+    let canonical = match FIXED_CREATED_AT.load(Ordering::Relaxed) {
+        0 => Timestamp::now(),
+        created_at => Timestamp::from(created_at),
+    };
+    let created_at = if randomize_created_at { randomized_created_at() } else { canonical };
+    let builder = EventBuilder::new(Kind::EncryptedDirectMessage, encrypted)
+        .tag(Tag::public_key(receiver))
+        .custom_created_at(created_at);
+    (builder, canonical)
 }
 
 #[flutter_rust_bridge::frb(sync)]
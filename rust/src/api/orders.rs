@@ -0,0 +1,381 @@
+use nostr::event::{Event, EventBuilder, Kind, Tag, UnsignedEvent};
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::types::time::Timestamp;
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+use super::inbox::{gift_wrap_rumor, gift_wrap_rumor_with_signer, unwrap_gift_wrap};
+use super::nostr::with_fixed_created_at;
+use super::signer::Signer;
+
+/// Kind of P2P order events (NIP-69)
+const ORDER_KIND: u16 = 38383;
+/// Version of the trade message format
+const TRADE_MESSAGE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderType {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Pending,
+    Canceled,
+    InProgress,
+    Success,
+    Expired,
+}
+
+impl OrderStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::Canceled => "canceled",
+            OrderStatus::InProgress => "in-progress",
+            OrderStatus::Success => "success",
+            OrderStatus::Expired => "expired",
+        }
+    }
+    
+    fn parse(value: &str) -> Option<Self> {
+        [
+            OrderStatus::Pending,
+            OrderStatus::Canceled,
+            OrderStatus::InProgress,
+            OrderStatus::Success,
+            OrderStatus::Expired,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == value)
+    }
+}
+
+/// Peer-to-peer order (NIP-69)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct P2pOrder {
+    /// Order id (`d` tag)
+    pub id: String,
+    pub order_type: OrderType,
+    /// ISO 4217 currency code of the fiat side (e.g. "VES")
+    pub currency: String,
+    pub status: OrderStatus,
+    /// Amount in sats, 0 to use the market price
+    pub amount: u64,
+    /// Fiat amount, or minimum and maximum for range orders
+    pub fiat_amount: Vec<f64>,
+    pub payment_methods: Vec<String>,
+    /// Premium over the market price in percent (may be negative)
+    pub premium: f64,
+    /// Bitcoin network (e.g. "mainnet")
+    pub network: String,
+    /// Layer the sats move on (e.g. "lightning", "onchain", "liquid")
+    pub layer: String,
+    /// Platform publishing the order (`y` tag, e.g. "mostro")
+    pub platform: String,
+    /// Link to the order on the platform
+    pub source: Option<String>,
+    /// Name of the maker
+    pub name: Option<String>,
+    pub geohash: Option<String>,
+    /// Bond the maker has to lock, in sats
+    pub bond: Option<u64>,
+    /// Unix timestamp after which relays may drop the order (NIP-40)
+    pub expiration: Option<u64>,
+    /// Hex public key of the maker (set when parsing)
+    pub pubkey: Option<String>,
+    /// Set when parsing
+    pub created_at: Option<u64>,
+}
+
+fn parse_keys(private_key: &str) -> Result<Keys, String> {
+    let secret_key = SecretKey::from_str(private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    Ok(Keys::new(secret_key))
+}
+
+/// Build and sign a P2P order event (kind 38383, NIP-69)
+///
+/// Publishing again with the same `id` replaces the order, e.g. to change its status.
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_p2p_order(order: P2pOrder, private_key: String) -> Result<String, String> {
+    let keys = parse_keys(&private_key)?;
+    let event = with_fixed_created_at(p2p_order_builder(&order)?)
+        .sign_with_keys(&keys)
+        .map_err(|e| format!("Failed to sign order: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(ORDER_KIND));
+    
+    Ok(event.as_json())
+}
+
+/// `build_p2p_order` with a platform or in-memory signer
+pub async fn build_p2p_order_with_signer(order: P2pOrder, signer: &Signer) -> Result<String, String> {
+    Ok(signer.sign_builder(p2p_order_builder(&order)?).await?.as_json())
+}
+
+fn p2p_order_builder(order: &P2pOrder) -> Result<EventBuilder, String> {
+    if order.id.is_empty() {
+        return Err("Order id must not be empty".to_string());
+    }
+    if order.fiat_amount.is_empty() || order.fiat_amount.len() > 2 {
+        return Err("Fiat amount must be one value or a range of two".to_string());
+    }
+    
+    let mut values: Vec<Vec<String>> = vec![
+        vec!["d".to_string(), order.id.clone()],
+        vec!["k".to_string(), match order.order_type { OrderType::Buy => "buy", OrderType::Sell => "sell" }.to_string()],
+        vec!["f".to_string(), order.currency.to_uppercase()],
+        vec!["s".to_string(), order.status.as_str().to_string()],
+        vec!["amt".to_string(), order.amount.to_string()],
+    ];
+    let mut fa = vec!["fa".to_string()];
+    fa.extend(order.fiat_amount.iter().map(|amount| amount.to_string()));
+    values.push(fa);
+    let mut pm = vec!["pm".to_string()];
+    pm.extend(order.payment_methods.iter().cloned());
+    values.push(pm);
+    values.push(vec!["premium".to_string(), order.premium.to_string()]);
+    values.push(vec!["network".to_string(), order.network.clone()]);
+    values.push(vec!["layer".to_string(), order.layer.clone()]);
+    values.push(vec!["y".to_string(), order.platform.clone()]);
+    values.push(vec!["z".to_string(), "order".to_string()]);
+    for (name, value) in [("source", &order.source), ("name", &order.name), ("g", &order.geohash)] {
+        if let Some(value) = value {
+            values.push(vec![name.to_string(), value.clone()]);
+        }
+    }
+    if let Some(bond) = order.bond {
+        values.push(vec!["bond".to_string(), bond.to_string()]);
+    }
+    
+    let mut tags = values.into_iter()
+        .map(|tag| Tag::parse(tag).map_err(|e| format!("Invalid tags: {}", e)))
+        .collect::<Result<Vec<Tag>, String>>()?;
+    if let Some(expiration) = order.expiration {
+        tags.push(Tag::expiration(Timestamp::from(expiration)));
+    }
+    
+    Ok(EventBuilder::new(Kind::from(ORDER_KIND), "").tags(tags))
+}
+
+/// Parse a P2P order event (kind 38383, NIP-69)
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_p2p_order(event_json: String) -> Result<P2pOrder, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    if event.kind != Kind::from(ORDER_KIND) {
+        return Err(format!("Not a P2P order: kind {}", event.kind));
+    }
+    
+    let tag = |name: &str| -> Option<&[String]> {
+        event.tags.iter()
+            .map(|tag| tag.as_slice())
+            .find(|tag| tag.len() >= 2 && tag[0] == name)
+            .map(|tag| &tag[1..])
+    };
+    let value = |name: &str| tag(name).map(|values| values[0].clone());
+    let required = |name: &str| value(name).ok_or_else(|| format!("Order has no '{}' tag", name));
+    let number = |name: &str| -> Result<Option<f64>, String> {
+        value(name)
+            .map(|value| value.parse::<f64>().map_err(|e| format!("Invalid '{}' tag: {}", name, e)))
+            .transpose()
+    };
+    
+    let order_type = match required("k")?.as_str() {
+        "buy" => OrderType::Buy,
+        "sell" => OrderType::Sell,
+        other => return Err(format!("Invalid order type '{}'", other)),
+    };
+    let status = required("s")?;
+    let status = OrderStatus::parse(&status)
+        .ok_or_else(|| format!("Invalid order status '{}'", status))?;
+    let fiat_amount = tag("fa")
+        .ok_or_else(|| "Order has no 'fa' tag".to_string())?
+        .iter()
+        .map(|amount| amount.parse::<f64>().map_err(|e| format!("Invalid 'fa' tag: {}", e)))
+        .collect::<Result<Vec<f64>, String>>()?;
+    
+    Ok(P2pOrder {
+        id: required("d")?,
+        order_type,
+        currency: required("f")?,
+        status,
+        amount: required("amt")?.parse().map_err(|e| format!("Invalid 'amt' tag: {}", e))?,
+        fiat_amount,
+        payment_methods: tag("pm").map(|methods| methods.to_vec()).unwrap_or_default(),
+        premium: number("premium")?.unwrap_or(0.0),
+        network: value("network").unwrap_or_else(|| "mainnet".to_string()),
+        layer: value("layer").unwrap_or_else(|| "lightning".to_string()),
+        platform: value("y").unwrap_or_default(),
+        source: value("source"),
+        name: value("name"),
+        geohash: value("g"),
+        bond: number("bond")?.map(|bond| bond as u64),
+        expiration: number("expiration")?.map(|expiration| expiration as u64),
+        pubkey: Some(event.pubkey.to_hex()),
+        created_at: Some(event.created_at.as_u64()),
+    })
+}
+
+/// Step of the trade negotiation between maker and taker
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TradeAction {
+    /// Taker takes a sell order (taker buys)
+    TakeSell,
+    /// Taker takes a buy order (taker sells)
+    TakeBuy,
+    /// Buyer sends the invoice the sats are paid to
+    AddInvoice,
+    /// Seller locks the sats (e.g. pays a hold invoice)
+    PayInvoice,
+    /// Buyer says the fiat payment was sent
+    FiatSent,
+    /// Seller confirms the fiat arrived and releases the sats
+    Release,
+    /// Either side rates the other once done
+    RateUser,
+    Cancel,
+    /// Either side asks the platform to arbitrate
+    Dispute,
+}
+
+/// Message exchanged over encrypted DMs while negotiating a trade
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeMessage {
+    pub order_id: String,
+    pub action: TradeAction,
+    /// Action specific data as JSON (e.g. the invoice for `AddInvoice`)
+    pub payload: Option<String>,
+}
+
+/// Trade message received from a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceivedTradeMessage {
+    /// Hex public key of the sender
+    pub sender: String,
+    pub created_at: u64,
+    pub message: TradeMessage,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TradeMessageContent {
+    version: u32,
+    id: String,
+    action: TradeAction,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payload: Option<serde_json::Value>,
+}
+
+/// Actions that may follow the given trade history, in protocol order
+///
+/// After the order is taken, the invoice and the locked sats may come in either order; the
+/// fiat is sent once both are there, then the seller releases. Cancelling is possible
+/// until the fiat is sent, a dispute once sats are locked and until they are released.
+#[flutter_rust_bridge::frb(sync)]
+pub fn next_trade_actions(history: Vec<TradeAction>) -> Vec<TradeAction> {
+    let done = |action: TradeAction| history.contains(&action);
+    if done(TradeAction::Cancel) {
+        return Vec::new();
+    }
+    if !done(TradeAction::TakeSell) && !done(TradeAction::TakeBuy) {
+        return vec![TradeAction::TakeSell, TradeAction::TakeBuy];
+    }
+    if done(TradeAction::Release) {
+        return vec![TradeAction::RateUser];
+    }
+    
+    let mut next = Vec::new();
+    if !done(TradeAction::AddInvoice) {
+        next.push(TradeAction::AddInvoice);
+    }
+    if !done(TradeAction::PayInvoice) {
+        next.push(TradeAction::PayInvoice);
+    }
+    if next.is_empty() {
+        next.push(if done(TradeAction::FiatSent) { TradeAction::Release } else { TradeAction::FiatSent });
+    }
+    if !done(TradeAction::FiatSent) {
+        next.push(TradeAction::Cancel);
+    }
+    if done(TradeAction::PayInvoice) && !done(TradeAction::Dispute) {
+        next.push(TradeAction::Dispute);
+    }
+    next
+}
+
+/// Build a gift-wrapped trade message for the other side of a trade
+///
+/// The message is a NIP-59 rumor (kind 1) so it doesn't show up as a chat message in
+/// NIP-17 clients.
+///
+/// # Arguments
+/// * `message` - Message to send
+/// * `receiver_pubkey` - Hex public key of the peer (or of the platform's daemon)
+/// * `private_key` - Hex private key of the sender
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_trade_message(message: TradeMessage, receiver_pubkey: String, private_key: String) -> Result<String, String> {
+    let keys = parse_keys(&private_key)?;
+    let receiver = PublicKey::from_str(&receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let rumor = trade_message_rumor(message, receiver, keys.public_key())?;
+    
+    gift_wrap_rumor(&keys, &receiver, rumor).map(|wrap| wrap.as_json())
+}
+
+/// `build_trade_message` with a platform or in-memory signer (one that can encrypt)
+pub async fn build_trade_message_with_signer(message: TradeMessage, receiver_pubkey: String, signer: &Signer) -> Result<String, String> {
+    let receiver = PublicKey::from_str(&receiver_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let rumor = trade_message_rumor(message, receiver, signer.nostr_public_key())?;
+    
+    Ok(gift_wrap_rumor_with_signer(signer, &receiver, rumor).await?.as_json())
+}
+
+fn trade_message_rumor(message: TradeMessage, receiver: PublicKey, sender: PublicKey) -> Result<UnsignedEvent, String> {
+    let payload = message.payload
+        .map(|payload| serde_json::from_str(&payload).map_err(|e| format!("Invalid payload JSON: {}", e)))
+        .transpose()?;
+    
+    let content = serde_json::to_string(&TradeMessageContent {
+        version: TRADE_MESSAGE_VERSION,
+        id: message.order_id,
+        action: message.action,
+        payload,
+    })
+    .map_err(|e| format!("Failed to serialize trade message: {}", e))?;
+    Ok(EventBuilder::new(Kind::TextNote, content)
+        .tag(Tag::public_key(receiver))
+        .build(sender))
+}
+
+/// Unwrap and parse a trade message sent with `build_trade_message`
+///
+/// # Arguments
+/// * `gift_wrap_json` - Gift wrap (kind 1059) addressed to the key
+/// * `private_key` - Hex private key of the receiver
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_trade_message(gift_wrap_json: String, private_key: String) -> Result<ReceivedTradeMessage, String> {
+    let keys = parse_keys(&private_key)?;
+    let gift_wrap = Event::from_json(&gift_wrap_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    let (sender, rumor) = unwrap_gift_wrap(&keys, &gift_wrap)?;
+    
+    let content: TradeMessageContent = serde_json::from_str(&rumor.content)
+        .map_err(|e| format!("Not a trade message: {}", e))?;
+    if content.version > TRADE_MESSAGE_VERSION {
+        return Err(format!("Unsupported trade message version {}", content.version));
+    }
+    
+    Ok(ReceivedTradeMessage {
+        sender: sender.to_hex(),
+        created_at: rumor.created_at.as_u64(),
+        message: TradeMessage {
+            order_id: content.id,
+            action: content.action,
+            payload: content.payload.map(|payload| payload.to_string()),
+        },
+    })
+}
@@ -0,0 +1,121 @@
+use nostr::types::RelayUrl;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use super::policy;
+use super::relay::relay_start_args;
+use super::tokens::{relay_issue_token, TokenCapabilities};
+
+/// Scheme of pairing payloads, followed by the relay URL and the `t`/`f` parameters
+const PAIRING_PREFIX: &str = "nostrpair:";
+
+/// What a companion device needs to connect to the relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingInfo {
+    /// Relay URL on the local network
+    pub url: String,
+    /// Token to connect with, if the relay requires one
+    pub token: Option<String>,
+    /// SHA-256 fingerprint (hex) of the TLS certificate, for `wss` URLs
+    pub tls_fingerprint: Option<String>,
+    /// URL with the token as query parameter, ready to connect to
+    pub connect_url: String,
+}
+
+/// Address of this device on the local network, the one its default route goes through
+fn lan_address() -> Result<IpAddr, String> {
+    // Connecting a UDP socket only selects the route, nothing is sent
+    let socket = UdpSocket::bind("0.0.0.0:0")
+        .map_err(|e| format!("Failed to find the LAN address: {}", e))?;
+    socket.connect("192.0.2.1:9")
+        .map_err(|_| "No network connection".to_string())?;
+    socket.local_addr()
+        .map(|addr| addr.ip())
+        .map_err(|e| format!("Failed to find the LAN address: {}", e))
+}
+
+fn normalize_fingerprint(fingerprint: &str) -> Result<String, String> {
+    let hex: String = fingerprint.chars().filter(|c| *c != ':').collect::<String>().to_lowercase();
+    if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("TLS fingerprint must be a SHA-256 hash (64 hex digits)".to_string());
+    }
+    Ok(hex)
+}
+
+fn connect_url(url: &str, token: Option<&str>) -> String {
+    match token {
+        Some(token) => format!("{}?token={}", url, token),
+        None => url.to_string(),
+    }
+}
+
+/// Payload to show as a QR code so another device can connect to the relay
+///
+/// Bundles the relay URL on the local network with the token to connect with:
+/// a new capability token if `capabilities` are given, the configured remote access token
+/// otherwise (if set). The relay must listen on a LAN address (e.g. started on "0.0.0.0").
+///
+/// # Arguments
+/// * `capabilities` - Issue a token with these capabilities for the paired device
+/// * `tls_fingerprint` - SHA-256 of the certificate when TLS is terminated in front of the relay
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_pairing_payload(
+    capabilities: Option<TokenCapabilities>,
+    tls_fingerprint: Option<String>,
+) -> Result<String, String> {
+    let (host, port, _) = relay_start_args()
+        .ok_or_else(|| "Relay is not running".to_string())?;
+    let host: IpAddr = host.parse()
+        .map_err(|e| format!("Invalid relay host: {}", e))?;
+    let ip = match host {
+        host if host.is_loopback() => {
+            return Err("Relay only listens on loopback, start it on 0.0.0.0 to pair devices".to_string());
+        }
+        host if host.is_unspecified() => lan_address()?,
+        host => host,
+    };
+    
+    let tls_fingerprint = tls_fingerprint.as_deref().map(normalize_fingerprint).transpose()?;
+    let scheme = if tls_fingerprint.is_some() { "wss" } else { "ws" };
+    let token = match capabilities {
+        Some(capabilities) => Some(relay_issue_token(capabilities)?.token),
+        None => Some(policy::current_config().remote_access_token).filter(|token| !token.is_empty()),
+    };
+    
+    let mut payload = format!("{}{}://{}", PAIRING_PREFIX, scheme, SocketAddr::new(ip, port));
+    let params: Vec<String> = token.map(|token| format!("t={}", token)).into_iter()
+        .chain(tls_fingerprint.map(|fingerprint| format!("f={}", fingerprint)))
+        .collect();
+    if !params.is_empty() {
+        payload.push('?');
+        payload.push_str(&params.join("&"));
+    }
+    Ok(payload)
+}
+
+/// Parse a payload made by `relay_get_pairing_payload` (on the companion device)
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_pairing_payload(payload: String) -> Result<PairingInfo, String> {
+    let rest = payload.trim()
+        .strip_prefix(PAIRING_PREFIX)
+        .ok_or_else(|| "Not a pairing payload".to_string())?;
+    let (url, query) = rest.split_once('?').unwrap_or((rest, ""));
+    RelayUrl::parse(url)
+        .map_err(|e| format!("Invalid relay URL: {}", e))?;
+    
+    let mut token = None;
+    let mut tls_fingerprint = None;
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "t" if !value.is_empty() => token = Some(value.to_string()),
+            "f" => tls_fingerprint = Some(normalize_fingerprint(value)?),
+            _ => {}
+        }
+    }
+    
+    Ok(PairingInfo {
+        url: url.to_string(),
+        connect_url: connect_url(url, token.as_deref()),
+        token,
+        tls_fingerprint,
+    })
+}
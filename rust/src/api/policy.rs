@@ -0,0 +1,576 @@
+use nostr_database::prelude::{BoxedFuture, Event, Filter};
+use nostr_relay_builder::prelude::{PolicyResult, QueryPolicy, WritePolicy};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tracing::Instrument;
+use super::metrics::{self, IngestStage};
+use super::nostr::{effective_pow, nonce_target};
+use super::tokens::TokenCapabilities;
+
+// Live settings consulted by the relay on every write/query
+static LIVE_CONFIG: RwLock<Option<LiveRelayConfig>> = RwLock::new(None);
+// Events accepted per client IP in the current minute
+static WRITE_COUNTERS: Mutex<Option<HashMap<IpAddr, (u64, u32)>>> = Mutex::new(None);
+// Events over `max_content_bytes` since the app started
+static OVERSIZED_REJECTED: AtomicU64 = AtomicU64::new(0);
+static OVERSIZED_TRUNCATED: AtomicU64 = AtomicU64::new(0);
+
+/// Log level of the relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelayLogLevel {
+    /// No logging at all: when the relay starts with it, no log file is created and nothing
+    /// is written to stderr (choosing another level then takes effect on the next start)
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// Where a relay connection comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionOrigin {
+    /// Apps on this device
+    Loopback,
+    /// Private network (Wi-Fi, hotspot)
+    Lan,
+    /// Public internet, directly or through a local reverse tunnel (forwarding headers)
+    Tunnel,
+}
+
+/// What happens to events whose content is larger than `max_content_bytes`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OversizedContentAction {
+    Reject,
+    /// Reject the event with a "truncated:" OK message but keep a local copy with the content
+    /// cut at the limit and a marker appended (see `relay_list_truncated_events`)
+    ///
+    /// The copy no longer matches its id and signature, so it is kept out of the event
+    /// database, isn't served to queries and isn't passed to hooks. Copies are pruned with
+    /// `retention_days` and by `wipe_account_data`.
+    Truncate,
+}
+
+/// Events caught by the content size limit since the app started
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContentLimitStats {
+    pub rejected: u64,
+    pub truncated: u64,
+}
+
+/// Access granted to connections of an origin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OriginAccess {
+    Full,
+    ReadOnly,
+    /// Connections are refused
+    Denied,
+}
+
+/// Cause of a rejected event or query
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectionReason {
+    PubkeyBlocked,
+    PubkeyNotAllowed,
+    KindBlocked,
+    KindNotAllowed,
+    EventTooLarge,
+    RateLimited,
+    InvalidSignature,
+    Spam,
+    /// Capability token missing the needed access, or revoked
+    TokenDenied,
+    FilterLimitExceeded,
+    /// Write from an origin limited to `OriginAccess::ReadOnly`
+    OriginReadOnly,
+    /// Less proof of work (NIP-13) than `min_pow_difficulty`
+    InsufficientPow,
+    /// Content larger than `max_content_bytes`
+    ContentTooLarge,
+}
+
+/// Custom text of the OK/CLOSED message sent for a rejection
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectionMessage {
+    pub reason: RejectionReason,
+    /// Machine-readable prefix (None keeps the NIP-01 one, e.g. "blocked")
+    ///
+    /// Clients branch on the standard prefixes, only change it for clients you control.
+    pub prefix: Option<String>,
+    /// Human-readable message (None keeps the default), `{detail}` is replaced by the
+    /// default message (e.g. "kind 7 is not accepted")
+    pub message: Option<String>,
+}
+
+/// Relay settings that can be changed while the relay is running
+///
+/// Empty lists and zero limits mean "unrestricted".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayPolicyConfig {
+    /// Only these pubkeys (hex) may write
+    pub allowed_pubkeys: Vec<String>,
+    /// These pubkeys (hex) may never write
+    pub blocked_pubkeys: Vec<String>,
+    /// Only these kinds are accepted
+    pub allowed_kinds: Vec<u16>,
+    /// These kinds are always rejected
+    pub blocked_kinds: Vec<u16>,
+    /// Maximum size of an event (serialized JSON) in bytes
+    pub max_event_bytes: u32,
+    /// Maximum size of the content of an event in bytes, see `oversized_content`
+    pub max_content_bytes: u32,
+    pub oversized_content: OversizedContentAction,
+    /// Maximum `limit` a REQ filter may ask for
+    pub max_filter_limit: u32,
+    /// Maximum events accepted per client IP per minute
+    pub max_events_per_minute: u32,
+    /// Events older than this many days are deleted by the maintenance task
+    pub retention_days: u32,
+    pub log_level: RelayLogLevel,
+    /// Relays queried when a REQ misses in the local database (hybrid/caching proxy mode),
+    /// except for REQs of private kinds or `#p` scopes
+    pub upstream_relays: Vec<String>,
+    /// How long a proxied REQ waits for upstream relays (0 = default of 3 seconds)
+    pub upstream_timeout_ms: u32,
+    /// Token required from non-loopback connections (e.g. through a reverse tunnel), empty for none
+    ///
+    /// Clients send it as `Authorization: Bearer <token>` or as `?token=<token>` in the relay
+    /// URL. Loopback connections don't need it unless they carry forwarding headers (local
+    /// tunnel clients). TLS, and client certificates if wanted, are expected to be handled by
+    /// the tunnel; the relay itself doesn't check certificates.
+    pub remote_access_token: String,
+    /// Keep superseded versions of replaceable and addressable events (see `relay_get_event_history`)
+    pub keep_replaceable_history: bool,
+    /// Reject events with a spam score (0-100, see `score_event_spam`) at or above this, 0 to disable
+    pub spam_reject_threshold: u32,
+    /// Minimum proof of work (NIP-13 leading zero bits, see `verify_pow`) of accepted events, 0 to disable
+    pub min_pow_difficulty: u32,
+    /// Remember the ids of deleted events so syncs don't download them again (see `relay_purge_tombstones`)
+    pub record_tombstones: bool,
+    /// Replacements of the default rejection messages (e.g. localized)
+    pub rejection_messages: Vec<RejectionMessage>,
+    /// Access of connections from the local network (loopback always has full access)
+    pub lan_access: OriginAccess,
+    /// Access of connections from the internet or through a tunnel
+    pub tunnel_access: OriginAccess,
+}
+
+impl Default for RelayPolicyConfig {
+    fn default() -> Self {
+        Self {
+            allowed_pubkeys: Vec::new(),
+            blocked_pubkeys: Vec::new(),
+            allowed_kinds: Vec::new(),
+            blocked_kinds: Vec::new(),
+            max_event_bytes: 0,
+            max_content_bytes: 0,
+            oversized_content: OversizedContentAction::Reject,
+            max_filter_limit: 0,
+            max_events_per_minute: 0,
+            retention_days: 0,
+            log_level: RelayLogLevel::Info,
+            upstream_relays: Vec::new(),
+            upstream_timeout_ms: 0,
+            remote_access_token: String::new(),
+            keep_replaceable_history: false,
+            spam_reject_threshold: 0,
+            min_pow_difficulty: 0,
+            record_tombstones: false,
+            rejection_messages: Vec::new(),
+            lan_access: OriginAccess::Full,
+            tunnel_access: OriginAccess::Full,
+        }
+    }
+}
+
+/// Partial update of the relay settings (None leaves a setting unchanged)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RelayConfigUpdate {
+    pub allowed_pubkeys: Option<Vec<String>>,
+    pub blocked_pubkeys: Option<Vec<String>>,
+    pub allowed_kinds: Option<Vec<u16>>,
+    pub blocked_kinds: Option<Vec<u16>>,
+    pub max_event_bytes: Option<u32>,
+    pub max_content_bytes: Option<u32>,
+    pub oversized_content: Option<OversizedContentAction>,
+    pub max_filter_limit: Option<u32>,
+    pub max_events_per_minute: Option<u32>,
+    pub retention_days: Option<u32>,
+    pub log_level: Option<RelayLogLevel>,
+    pub upstream_relays: Option<Vec<String>>,
+    pub upstream_timeout_ms: Option<u32>,
+    pub remote_access_token: Option<String>,
+    pub keep_replaceable_history: Option<bool>,
+    pub spam_reject_threshold: Option<u32>,
+    pub min_pow_difficulty: Option<u32>,
+    pub record_tombstones: Option<bool>,
+    pub rejection_messages: Option<Vec<RejectionMessage>>,
+    pub lan_access: Option<OriginAccess>,
+    pub tunnel_access: Option<OriginAccess>,
+}
+
+/// Settings in the shape used by the policy checks
+#[derive(Debug, Clone)]
+struct LiveRelayConfig {
+    config: RelayPolicyConfig,
+    allowed_pubkeys: HashSet<String>,
+    blocked_pubkeys: HashSet<String>,
+    allowed_kinds: HashSet<u16>,
+    blocked_kinds: HashSet<u16>,
+}
+
+impl From<RelayPolicyConfig> for LiveRelayConfig {
+    fn from(config: RelayPolicyConfig) -> Self {
+        Self {
+            allowed_pubkeys: config.allowed_pubkeys.iter().map(|pk| pk.to_lowercase()).collect(),
+            blocked_pubkeys: config.blocked_pubkeys.iter().map(|pk| pk.to_lowercase()).collect(),
+            allowed_kinds: config.allowed_kinds.iter().copied().collect(),
+            blocked_kinds: config.blocked_kinds.iter().copied().collect(),
+            config,
+        }
+    }
+}
+
+/// Get the current relay settings
+pub(crate) fn current_config() -> RelayPolicyConfig {
+    LIVE_CONFIG.read()
+        .ok()
+        .and_then(|config| config.as_ref().map(|live| live.config.clone()))
+        .unwrap_or_default()
+}
+
+/// Apply a partial update to the relay settings, returns the new settings
+pub(crate) fn apply_update(update: RelayConfigUpdate) -> Result<RelayPolicyConfig, String> {
+    let mut config = current_config();
+    
+    if let Some(pubkeys) = update.allowed_pubkeys {
+        config.allowed_pubkeys = pubkeys;
+    }
+    if let Some(pubkeys) = update.blocked_pubkeys {
+        config.blocked_pubkeys = pubkeys;
+    }
+    if let Some(kinds) = update.allowed_kinds {
+        config.allowed_kinds = kinds;
+    }
+    if let Some(kinds) = update.blocked_kinds {
+        config.blocked_kinds = kinds;
+    }
+    if let Some(max) = update.max_event_bytes {
+        config.max_event_bytes = max;
+    }
+    if let Some(max) = update.max_content_bytes {
+        config.max_content_bytes = max;
+    }
+    if let Some(action) = update.oversized_content {
+        config.oversized_content = action;
+    }
+    if let Some(max) = update.max_filter_limit {
+        config.max_filter_limit = max;
+    }
+    if let Some(max) = update.max_events_per_minute {
+        config.max_events_per_minute = max;
+    }
+    if let Some(days) = update.retention_days {
+        config.retention_days = days;
+    }
+    if let Some(level) = update.log_level {
+        config.log_level = level;
+    }
+    if let Some(relays) = update.upstream_relays {
+        config.upstream_relays = relays;
+    }
+    if let Some(timeout) = update.upstream_timeout_ms {
+        config.upstream_timeout_ms = timeout;
+    }
+    if let Some(token) = update.remote_access_token {
+        config.remote_access_token = token;
+    }
+    if let Some(keep) = update.keep_replaceable_history {
+        config.keep_replaceable_history = keep;
+    }
+    if let Some(threshold) = update.spam_reject_threshold {
+        config.spam_reject_threshold = threshold;
+    }
+    if let Some(difficulty) = update.min_pow_difficulty {
+        config.min_pow_difficulty = difficulty;
+    }
+    if let Some(record) = update.record_tombstones {
+        config.record_tombstones = record;
+    }
+    if let Some(messages) = update.rejection_messages {
+        config.rejection_messages = messages;
+    }
+    if let Some(access) = update.lan_access {
+        config.lan_access = access;
+    }
+    if let Some(access) = update.tunnel_access {
+        config.tunnel_access = access;
+    }
+    
+    let mut live = LIVE_CONFIG.write()
+        .map_err(|e| format!("Failed to lock relay config: {}", e))?;
+    *live = Some(LiveRelayConfig::from(config.clone()));
+    
+    Ok(config)
+}
+
+/// Keep a copy of a verified event with its content cut at `max_bytes`
+fn keep_truncated(event: &Event, max_bytes: usize) -> Result<(), String> {
+    use nostr_database::prelude::JsonUtil;
+    let mut truncated = event.clone();
+    truncated.content = super::content::truncate_content(&event.content, max_bytes);
+    super::storage::record_truncated(event.id.as_bytes(), &truncated.as_json())?;
+    OVERSIZED_TRUNCATED.fetch_add(1, Ordering::Relaxed);
+    tracing::info!("Truncated content of {} ({} bytes)", event.id, event.content.len());
+    Ok(())
+}
+
+pub(crate) fn content_limit_stats() -> ContentLimitStats {
+    ContentLimitStats {
+        rejected: OVERSIZED_REJECTED.load(Ordering::Relaxed),
+        truncated: OVERSIZED_TRUNCATED.load(Ordering::Relaxed),
+    }
+}
+
+/// Count an accepted event for the client, returns false if over the per-minute limit
+fn check_rate(ip: IpAddr, max_per_minute: u32) -> bool {
+    let minute = nostr_database::prelude::Timestamp::now().as_u64() / 60;
+    let mut counters = match WRITE_COUNTERS.lock() {
+        Ok(counters) => counters,
+        Err(_) => return true,
+    };
+    let counters = counters.get_or_insert_with(HashMap::new);
+    
+    // Drop counters of previous minutes
+    counters.retain(|_, (window, _)| *window == minute);
+    
+    let (_, count) = counters.entry(ip).or_insert((minute, 0));
+    *count += 1;
+    *count <= max_per_minute
+}
+
+impl RelayPolicyConfig {
+    /// Access granted to connections of `origin`
+    pub(crate) fn origin_access(&self, origin: ConnectionOrigin) -> OriginAccess {
+        match origin {
+            ConnectionOrigin::Loopback => OriginAccess::Full,
+            ConnectionOrigin::Lan => self.lan_access,
+            ConnectionOrigin::Tunnel => self.tunnel_access,
+        }
+    }
+}
+
+/// Build a rejection, applying the custom message configured for `reason` if any
+fn reject(messages: &[RejectionMessage], reason: RejectionReason, prefix: &str, detail: String) -> PolicyResult {
+    let custom = messages.iter().find(|message| message.reason == reason);
+    let prefix = custom.and_then(|custom| custom.prefix.as_deref()).unwrap_or(prefix);
+    let message = match custom.and_then(|custom| custom.message.as_ref()) {
+        Some(template) => template.replace("{detail}", &detail),
+        None => detail,
+    };
+    PolicyResult::Reject(format!("{}: {}", prefix, message))
+}
+
+/// Write and query policy of the relay, backed by the live settings
+#[derive(Debug, Default)]
+pub(crate) struct LivePolicy;
+
+impl LivePolicy {
+    fn check_event(event: &Event, addr: &SocketAddr) -> PolicyResult {
+        let live = match LIVE_CONFIG.read() {
+            Ok(live) => live,
+            Err(_) => return PolicyResult::Accept,
+        };
+        let live = match live.as_ref() {
+            Some(live) => live,
+            None => return PolicyResult::Accept,
+        };
+        
+        let messages = &live.config.rejection_messages;
+        let author = event.pubkey.to_hex();
+        if live.blocked_pubkeys.contains(&author) {
+            return reject(messages, RejectionReason::PubkeyBlocked, "blocked", "pubkey is blocked".to_string());
+        }
+        if !live.allowed_pubkeys.is_empty() && !live.allowed_pubkeys.contains(&author) {
+            return reject(messages, RejectionReason::PubkeyNotAllowed, "restricted", "pubkey is not allowed to write".to_string());
+        }
+        
+        let kind = event.kind.as_u16();
+        if live.blocked_kinds.contains(&kind) {
+            return reject(messages, RejectionReason::KindBlocked, "blocked", format!("kind {} is not accepted", kind));
+        }
+        if !live.allowed_kinds.is_empty() && !live.allowed_kinds.contains(&kind) {
+            return reject(messages, RejectionReason::KindNotAllowed, "restricted", format!("kind {} is not accepted", kind));
+        }
+        
+        let max_bytes = live.config.max_event_bytes as usize;
+        if max_bytes > 0 {
+            use nostr_database::prelude::JsonUtil;
+            if event.as_json().len() > max_bytes {
+                return reject(messages, RejectionReason::EventTooLarge, "invalid", format!("event is larger than {} bytes", max_bytes));
+            }
+        }
+        
+        let max_content = live.config.max_content_bytes as usize;
+        if max_content > 0 && event.content.len() > max_content && live.config.oversized_content == OversizedContentAction::Reject {
+            OVERSIZED_REJECTED.fetch_add(1, Ordering::Relaxed);
+            return reject(messages, RejectionReason::ContentTooLarge, "invalid", format!("content is larger than {} bytes", max_content));
+        }
+        
+        // The id itself is checked with the signature afterwards
+        let min_pow = live.config.min_pow_difficulty;
+        if min_pow > 0 {
+            let target = nonce_target(event.tags.iter().map(|tag| tag.as_slice()));
+            let pow = effective_pow(event.id.as_bytes(), target);
+            if pow < min_pow {
+                return reject(messages, RejectionReason::InsufficientPow, "pow", format!("difficulty {} is less than {}", pow, min_pow));
+            }
+        }
+        
+        // Connections through the access gate reach the relay from loopback
+        let max_per_minute = live.config.max_events_per_minute;
+        if max_per_minute > 0 && !check_rate(super::gate::real_peer_addr(addr).ip(), max_per_minute) {
+            return reject(messages, RejectionReason::RateLimited, "rate-limited", "too many events".to_string());
+        }
+        
+        PolicyResult::Accept
+    }
+    
+    fn check_query(filter: &Filter) -> PolicyResult {
+        let config = current_config();
+        let max_limit = config.max_filter_limit as usize;
+        match filter.limit {
+            Some(limit) if max_limit > 0 && limit > max_limit => reject(
+                &config.rejection_messages,
+                RejectionReason::FilterLimitExceeded,
+                "invalid",
+                format!("limit must not exceed {}", max_limit),
+            ),
+            _ => PolicyResult::Accept,
+        }
+    }
+}
+
+/// Check the capability token of a connection (connections without one are unrestricted)
+///
+/// Connections that didn't pass the gate are refused, they were never checked for a token.
+fn check_token(addr: &SocketAddr, check: impl FnOnce(&TokenCapabilities) -> Result<(), String>) -> PolicyResult {
+    if !super::gate::is_gated(addr) {
+        let detail = "connect through the relay URL".to_string();
+        return reject(&current_config().rejection_messages, RejectionReason::TokenDenied, "restricted", detail);
+    }
+    let token_id = match super::gate::connection_token(addr) {
+        Some(token_id) => token_id,
+        None => return PolicyResult::Accept,
+    };
+    let detail = match super::tokens::capabilities(&token_id) {
+        Some(capabilities) => match check(&capabilities) {
+            Ok(()) => return PolicyResult::Accept,
+            Err(detail) => detail,
+        },
+        None => "token was revoked".to_string(),
+    };
+    reject(&current_config().rejection_messages, RejectionReason::TokenDenied, "restricted", detail)
+}
+
+impl LivePolicy {
+    /// Admission of an event, `validation` is set to the time spent verifying it
+    async fn admit(&self, event: &Event, addr: &SocketAddr, validation: &mut Duration) -> PolicyResult {
+        let kind = event.kind.as_u16();
+        let allowed = check_token(addr, |capabilities| {
+            if !capabilities.can_write {
+                return Err("token has no write access".to_string());
+            }
+            if !capabilities.write_kinds.is_empty() && !capabilities.write_kinds.contains(&kind) {
+                return Err(format!("token may not write kind {}", kind));
+            }
+            Ok(())
+        });
+        if let PolicyResult::Reject(reason) = allowed {
+            return PolicyResult::Reject(reason);
+        }
+        
+        let config = current_config();
+        let messages = &config.rejection_messages;
+        let origin = super::gate::connection_origin(addr);
+        if config.origin_access(origin) != OriginAccess::Full {
+            let detail = format!("{:?} connections are read-only", origin).to_lowercase();
+            return reject(messages, RejectionReason::OriginReadOnly, "restricted", detail);
+        }
+        
+        // Cheap checks first, signatures are verified on the verification pool
+        match Self::check_event(event, addr) {
+            PolicyResult::Accept => {}
+            rejected => return rejected,
+        }
+        let verify_started = Instant::now();
+        let verified = super::verify::verify_event(event)
+            .instrument(tracing::debug_span!("ingest.validate"))
+            .await;
+        *validation = verify_started.elapsed();
+        match verified {
+            Ok(true) => {}
+            Ok(false) => {
+                return reject(messages, RejectionReason::InvalidSignature, "invalid", "bad event id or signature".to_string());
+            }
+            Err(e) => return reject(messages, RejectionReason::RateLimited, "rate-limited", e),
+        }
+        
+        let threshold = config.spam_reject_threshold;
+        if threshold > 0 {
+            let tags: Vec<Vec<String>> = event.tags.iter().map(|tag| tag.as_slice().to_vec()).collect();
+            let spam = super::spam::score(event.id.as_bytes(), &event.content, &tags);
+            if spam.score >= threshold {
+                let detail = format!("looks like spam ({})", spam.reasons.join("; "));
+                return reject(messages, RejectionReason::Spam, "blocked", detail);
+            }
+        }
+        
+        // Only verified events get a truncated copy
+        let max_content = config.max_content_bytes as usize;
+        if max_content > 0 && event.content.len() > max_content && config.oversized_content == OversizedContentAction::Truncate {
+            if let Err(e) = keep_truncated(event, max_content) {
+                return PolicyResult::Reject(format!("error: {}", e));
+            }
+            return PolicyResult::Reject(format!("truncated: content is larger than {} bytes, kept a truncated copy", max_content));
+        }
+        PolicyResult::Accept
+    }
+}
+
+impl WritePolicy for LivePolicy {
+    fn admit_event<'a>(&'a self, event: &'a Event, addr: &'a SocketAddr) -> BoxedFuture<'a, PolicyResult> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let mut validation = Duration::ZERO;
+            let result = self.admit(event, addr, &mut validation)
+                .instrument(tracing::debug_span!("ingest.policy", kind = event.kind.as_u16()))
+                .await;
+            
+            // Events rejected before verification have no validation time
+            if !validation.is_zero() {
+                metrics::record(IngestStage::Validate, validation);
+            }
+            metrics::record(IngestStage::Policy, started.elapsed().saturating_sub(validation));
+            result
+        })
+    }
+}
+
+impl QueryPolicy for LivePolicy {
+    fn admit_query<'a>(&'a self, query: &'a Filter, addr: &'a SocketAddr) -> BoxedFuture<'a, PolicyResult> {
+        Box::pin(async move {
+            let allowed = check_token(addr, |capabilities| {
+                if capabilities.can_read { Ok(()) } else { Err("token has no read access".to_string()) }
+            });
+            match allowed {
+                PolicyResult::Accept => Self::check_query(query),
+                rejected => rejected,
+            }
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use nostr_database::prelude::{Alphabet as DbAlphabet, Filter as DbFilter, JsonUtil as DbJsonUtil, SingleLetterTag as DbSingleLetterTag};
+use nostr_sdk::prelude::*;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use super::identities::PRIVATE_KINDS;
+use super::policy;
+
+// Client connected to the configured upstream relays (and the relays it was built for)
+static UPSTREAM_CLIENT: Mutex<Option<(Vec<String>, Client)>> = Mutex::new(None);
+// Filters recently forwarded upstream (filter JSON -> unix timestamp)
+static RECENT_FORWARDS: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+/// Default time a proxied REQ waits for upstream relays
+const DEFAULT_UPSTREAM_TIMEOUT_MS: u64 = 3000;
+/// The same filter isn't forwarded again within this many seconds
+const FORWARD_COOLDOWN_SECS: u64 = 60;
+
+/// Whether a filter asks for what only its participants may see: private kinds (DMs, seals,
+/// gift wraps, ...) or events addressed to someone (`#p`, the inbox scopes of the user)
+fn is_private_scope(filter: &DbFilter) -> bool {
+    let private_kind = filter.kinds.as_ref()
+        .map_or(false, |kinds| kinds.iter().any(|kind| PRIVATE_KINDS.contains(&kind.as_u16())));
+    let addressed = filter.generic_tags.get(&DbSingleLetterTag::lowercase(DbAlphabet::P))
+        .map_or(false, |values| !values.is_empty());
+    private_kind || addressed
+}
+
+/// Check whether a REQ should be forwarded upstream, given how many events it matched locally
+///
+/// Private scopes (see `is_private_scope`) are never forwarded: they'd tell upstream relays
+/// who the user talks to.
+pub(crate) fn should_forward(filter: &DbFilter, local_count: usize) -> bool {
+    let config = policy::current_config();
+    if config.upstream_relays.is_empty() || is_private_scope(filter) {
+        return false;
+    }
+    
+    // Miss: nothing found, or fewer events than asked for
+    let missed = match filter.limit {
+        Some(limit) => local_count < limit,
+        None => local_count == 0,
+    };
+    if !missed {
+        return false;
+    }
+    
+    // Don't hammer upstream relays with the same REQ (e.g. a client polling)
+    let now = Timestamp::now().as_u64();
+    let key = filter.as_json();
+    let mut recent = match RECENT_FORWARDS.lock() {
+        Ok(recent) => recent,
+        Err(_) => return false,
+    };
+    let recent = recent.get_or_insert_with(HashMap::new);
+    recent.retain(|_, at| now.saturating_sub(*at) < FORWARD_COOLDOWN_SECS);
+    if recent.contains_key(&key) {
+        return false;
+    }
+    recent.insert(key, now);
+    
+    true
+}
+
+/// Client for the configured upstream relays, rebuilt when the configuration changes
+async fn upstream_client(relays: &[String]) -> Result<Client, String> {
+    let previous = {
+        let mut client_guard = UPSTREAM_CLIENT.lock()
+            .map_err(|e| format!("Failed to lock upstream client: {}", e))?;
+        match client_guard.as_ref() {
+            Some((current, client)) if current.as_slice() == relays => return Ok(client.clone()),
+            _ => client_guard.take(),
+        }
+    };
+    if let Some((_, client)) = previous {
+        client.shutdown().await;
+    }
+    
+    let client = super::client::connect_client(relays, None).await?;
+    let mut client_guard = UPSTREAM_CLIENT.lock()
+        .map_err(|e| format!("Failed to lock upstream client: {}", e))?;
+    *client_guard = Some((relays.to_vec(), client.clone()));
+    
+    Ok(client)
+}
+
+/// Fetch events matching the filter from the upstream relays, as JSON
+pub(crate) async fn fetch_upstream(filter: &DbFilter) -> Result<Vec<String>, String> {
+    if is_private_scope(filter) {
+        return Err("Private filters are not forwarded upstream".to_string());
+    }
+    let config = policy::current_config();
+    let timeout = match config.upstream_timeout_ms {
+        0 => Duration::from_millis(DEFAULT_UPSTREAM_TIMEOUT_MS),
+        ms => Duration::from_millis(ms as u64),
+    };
+    
+    // The database crate has its own nostr types, convert through JSON
+    let filter = Filter::from_json(filter.as_json())
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    
+    let client = upstream_client(&config.upstream_relays).await?;
+    let events = client.fetch_events(filter, timeout)
+        .await
+        .map_err(|e| format!("Failed to fetch from upstream relays: {}", e))?;
+    
+    Ok(events.into_iter().map(|event| event.as_json()).collect())
+}
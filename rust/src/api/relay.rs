@@ -1,17 +1,26 @@
 use nostr_relay_builder::{LocalRelay, RelayBuilder};
 use nostr_ndb::NdbDatabase;
+use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::{Arc, Mutex};
 use std::net::IpAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::fs::OpenOptions;
 use tokio::runtime::Runtime;
 use serde::{Serialize, Deserialize};
-use nostr_database::prelude::Filter;
+use nostr_database::prelude::{Alphabet, Event, EventId, Filter, JsonUtil, Kind, PublicKey, SingleLetterTag};
 use nostr_database::NostrDatabase;
+use super::digest::{EventIdDigest, IdDigestFormat};
+use super::gate;
+use super::ingest::{self, IngestDatabase};
+use super::metrics::{self, IngestStageLatency};
+use super::policy::{self, ConnectionOrigin, ContentLimitStats, LivePolicy, RelayConfigUpdate, RelayLogLevel, RelayPolicyConfig};
+use super::storage;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::Layer;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::reload;
+use tracing_subscriber::Registry;
 use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::FormatFields;
 use tracing_appender::non_blocking::WorkerGuard;
@@ -61,9 +70,308 @@ fn clear_log_file() -> Result<(), String> {
 static RELAY_INSTANCE: Mutex<Option<Arc<LocalRelay>>> = Mutex::new(None);
 static RELAY_CLIENT_URL: Mutex<Option<String>> = Mutex::new(None);
 static RELAY_DATABASE: Mutex<Option<Arc<NdbDatabase>>> = Mutex::new(None);
+static RELAY_DB_PATH: Mutex<Option<String>> = Mutex::new(None);
 static RUNTIME: Mutex<Option<Arc<Runtime>>> = Mutex::new(None);
 static LOG_FILE_PATH: Mutex<Option<String>> = Mutex::new(None);
 static LOG_GUARD: Mutex<Option<WorkerGuard>> = Mutex::new(None);
+static LOG_LEVEL_HANDLE: Mutex<Option<reload::Handle<LevelFilter, Registry>>> = Mutex::new(None);
+static MAINTENANCE_TASK: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+static GATE_TASK: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+// Arguments of the last successful start (host, port, db_path), used to restart the relay
+static RELAY_START_ARGS: Mutex<Option<(String, u16, String)>> = Mutex::new(None);
+// Pre-bound socket of `start_relay_with_fd`, each start listens on a duplicate of it
+static RELAY_PREBOUND_LISTENER: Mutex<Option<std::net::TcpListener>> = Mutex::new(None);
+
+/// Interval between runs of the maintenance task
+const MAINTENANCE_INTERVAL_SECS: u64 = 60;
+/// Minimum interval between two retention passes
+const RETENTION_INTERVAL_SECS: u64 = 3600;
+
+/// Get the shared tokio runtime, creating it on first use
+pub(crate) fn get_or_create_runtime() -> Result<Arc<Runtime>, String> {
+    let mut rt_guard = RUNTIME.lock().map_err(|e| format!("Failed to lock runtime: {}", e))?;
+    if rt_guard.is_none() {
+        let threads = super::system::resource_limits().runtime_threads;
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        if threads > 0 {
+            builder.worker_threads(threads as usize);
+        }
+        let rt = builder.enable_all()
+            .build()
+            .map_err(|e| format!("Failed to create tokio runtime: {}", e))?;
+        *rt_guard = Some(Arc::new(rt));
+    }
+    Ok(rt_guard.as_ref().unwrap().clone())
+}
+
+/// Run a future on the shared runtime and wait for it, giving up after `timeout`
+///
+/// The future runs as a runtime task, so the caller (a Dart isolate thread) is released
+/// on timeout even if the task is stuck in blocking code; such a task keeps running in the
+/// background until it completes.
+pub(crate) fn run_blocking_with_timeout<F, T>(timeout: std::time::Duration, future: F) -> Result<T, String>
+where
+    F: std::future::Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let runtime = get_or_create_runtime()?;
+    let task = runtime.spawn(future);
+    
+    runtime.block_on(async move {
+        match tokio::time::timeout(timeout, task).await {
+            Ok(Ok(output)) => Ok(output),
+            Ok(Err(e)) => Err(format!("Task failed: {}", e)),
+            Err(_) => Err(format!("Timeout: operation did not complete within {} ms", timeout.as_millis())),
+        }
+    })
+}
+
+/// Run a future on the shared runtime from async code not running on it (e.g. an async
+/// bridge function, which must not block on the runtime)
+pub(crate) async fn run_async_with_timeout<F, T>(timeout: std::time::Duration, future: F) -> Result<T, String>
+where
+    F: std::future::Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    let runtime = get_or_create_runtime()?;
+    // The timer has to run on the runtime too
+    let task = runtime.spawn(async move { tokio::time::timeout(timeout, future).await });
+    match task.await {
+        Ok(Ok(output)) => Ok(output),
+        Ok(Err(_)) => Err(format!("Timeout: operation did not complete within {} ms", timeout.as_millis())),
+        Err(e) => Err(format!("Task failed: {}", e)),
+    }
+}
+
+/// Run a future on the shared runtime with the configured call timeout
+pub(crate) fn run_blocking<F, T>(future: F) -> Result<T, String>
+where
+    F: std::future::Future<Output = T> + Send + 'static,
+    T: Send + 'static,
+{
+    run_blocking_with_timeout(super::system::call_timeout(), future)
+}
+
+/// Get the shared tokio runtime (only available once the relay has been started)
+pub(crate) fn get_runtime() -> Result<Arc<Runtime>, String> {
+    let rt_guard = RUNTIME
+        .lock()
+        .map_err(|e| format!("Failed to lock runtime: {}", e))?;
+    rt_guard
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "Runtime not initialized".to_string())
+}
+
+/// Get the database of the running relay
+pub(crate) fn get_database() -> Result<Arc<NdbDatabase>, String> {
+    let db_guard = RELAY_DATABASE.lock()
+        .map_err(|e| format!("Failed to lock database: {}", e))?;
+    db_guard
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "Relay is not running".to_string())
+}
+
+/// Path of the database opened by the relay
+pub(crate) fn get_database_path() -> Result<String, String> {
+    let path_guard = RELAY_DB_PATH.lock()
+        .map_err(|e| format!("Failed to lock database path: {}", e))?;
+    path_guard
+        .as_ref()
+        .cloned()
+        .ok_or_else(|| "Relay is not running".to_string())
+}
+
+/// Query events from the relay database
+pub(crate) fn query_local_events(filter: Filter) -> Result<Vec<Event>, String> {
+    run_blocking(query_local_events_async(filter))?
+}
+
+/// Query events from the relay database, for code already running on the shared runtime
+/// (`query_local_events` would block the runtime on itself)
+pub(crate) async fn query_local_events_async(filter: Filter) -> Result<Vec<Event>, String> {
+    let events = get_database()?
+        .query(filter)
+        .await
+        .map_err(|e| format!("Failed to query events: {}", e))?;
+    
+    Ok(events.into_iter().collect())
+}
+
+/// Query events from the relay database, serialized as JSON
+///
+/// The database crate has its own nostr types, JSON is the bridge to the rest of the plugin.
+pub(crate) fn query_local_events_json(filter: Filter) -> Result<Vec<String>, String> {
+    Ok(query_local_events(filter)?
+        .into_iter()
+        .map(|event| event.as_json())
+        .collect())
+}
+
+/// Reason why the relay could not be started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RelayStartError {
+    /// Port is already bound by another socket (owning process id if it could be determined)
+    PortInUse { port: u16, pid: Option<u32> },
+    /// Missing permission to bind the port or to write the database/log directory
+    PermissionDenied { message: String },
+    /// Database is already opened by a running relay
+    DbLocked { message: String },
+    /// Database files exist but could not be opened
+    DbCorrupt { message: String },
+    /// Host is not a valid IP address
+    InvalidAddress { host: String, message: String },
+    /// Startup did not complete within the call timeout
+    Timeout { message: String },
+    /// Any other failure
+    Other { message: String },
+}
+
+impl std::fmt::Display for RelayStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RelayStartError::PortInUse { port, pid: Some(pid) } => write!(f, "Port {} is already in use by process {}", port, pid),
+            RelayStartError::PortInUse { port, pid: None } => write!(f, "Port {} is already in use", port),
+            RelayStartError::PermissionDenied { message } => write!(f, "Permission denied: {}", message),
+            RelayStartError::DbLocked { message } => write!(f, "Database is locked: {}", message),
+            RelayStartError::DbCorrupt { message } => write!(f, "Database is corrupt: {}", message),
+            RelayStartError::InvalidAddress { host, message } => write!(f, "Invalid IP address '{}': {}", host, message),
+            RelayStartError::Timeout { message } => write!(f, "{}", message),
+            RelayStartError::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<String> for RelayStartError {
+    fn from(message: String) -> Self {
+        RelayStartError::Other { message }
+    }
+}
+
+impl RelayStartError {
+    fn from_io(context: &str, error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::PermissionDenied => RelayStartError::PermissionDenied {
+                message: format!("{}: {}", context, error),
+            },
+            _ => RelayStartError::Other { message: format!("{}: {}", context, error) },
+        }
+    }
+    
+    fn from_ndb_error(db_path: &str, error: nostr_ndb::nostrdb::Error) -> Self {
+        let message = format!("Failed to open NDB database: {}", error);
+        if !matches!(error, nostr_ndb::nostrdb::Error::DbOpenFailed) {
+            return RelayStartError::Other { message };
+        }
+        // nostrdb doesn't tell why, look at the database itself
+        if RELAY_DB_PATH.lock().ok().and_then(|path| path.clone()).as_deref() == Some(db_path) {
+            return RelayStartError::DbLocked { message };
+        }
+        let data_file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(Path::new(db_path).join("data.mdb"));
+        match data_file.map_err(|e| e.kind()) {
+            Ok(_) => RelayStartError::DbCorrupt { message },
+            Err(std::io::ErrorKind::PermissionDenied) => RelayStartError::PermissionDenied { message },
+            Err(std::io::ErrorKind::WouldBlock) => RelayStartError::DbLocked { message },
+            Err(_) => RelayStartError::Other { message },
+        }
+    }
+    
+    fn from_aux_store_error(error: storage::AuxStoreError) -> Self {
+        let message = error.to_string();
+        match error {
+            storage::AuxStoreError::Open(sled::Error::Io(e)) => match e.kind() {
+                std::io::ErrorKind::PermissionDenied => RelayStartError::PermissionDenied { message },
+                // The store is locked by another process
+                std::io::ErrorKind::WouldBlock => RelayStartError::DbLocked { message },
+                _ => RelayStartError::Other { message },
+            },
+            storage::AuxStoreError::Open(sled::Error::Corruption { .. }) => RelayStartError::DbCorrupt { message },
+            _ => RelayStartError::Other { message },
+        }
+    }
+    
+    fn from_bind_error(port: u16, error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::AddrInUse => RelayStartError::PortInUse { port, pid: find_port_owner(port) },
+            std::io::ErrorKind::PermissionDenied => RelayStartError::PermissionDenied {
+                message: format!("Failed to start relay: {}", error),
+            },
+            _ => RelayStartError::Other { message: format!("Failed to start relay: {}", error) },
+        }
+    }
+}
+
+/// Find the process listening on a TCP port (best effort, reads /proc)
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn find_port_owner(port: u16) -> Option<u32> {
+    // Collect inodes of sockets listening on the port (state 0A is LISTEN)
+    let mut inodes = Vec::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        if let Ok(content) = std::fs::read_to_string(table) {
+            for line in content.lines().skip(1) {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.len() < 10 || fields[3] != "0A" {
+                    continue;
+                }
+                let local_port = fields[1].rsplit(':').next()
+                    .and_then(|p| u16::from_str_radix(p, 16).ok());
+                if local_port == Some(port) {
+                    inodes.push(format!("socket:[{}]", fields[9]));
+                }
+            }
+        }
+    }
+    if inodes.is_empty() {
+        return None;
+    }
+    
+    // Find the process owning one of these sockets
+    for entry in std::fs::read_dir("/proc").ok()?.flatten() {
+        let pid = match entry.file_name().to_string_lossy().parse::<u32>() {
+            Ok(pid) => pid,
+            Err(_) => continue,
+        };
+        if let Ok(fds) = std::fs::read_dir(entry.path().join("fd")) {
+            for fd in fds.flatten() {
+                if let Ok(target) = std::fs::read_link(fd.path()) {
+                    let target = target.to_string_lossy();
+                    if inodes.iter().any(|inode| *inode == target) {
+                        return Some(pid);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn find_port_owner(_port: u16) -> Option<u32> {
+    None
+}
+
+/// Save an event (JSON) into the relay database through the ingest hooks
+///
+/// Returns true if the event was new.
+pub(crate) async fn save_event_json(event_json: &str) -> Result<bool, String> {
+    let event = Event::from_json(event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    save_parsed_event(&event).await
+}
+
+/// Save an already parsed event through the ingest hooks, see `save_event_json`
+pub(crate) async fn save_parsed_event(event: &Event) -> Result<bool, String> {
+    let database = IngestDatabase::new(get_database()?);
+    
+    let status = database.save_event(event)
+        .await
+        .map_err(|e| format!("Failed to save event: {}", e))?;
+    Ok(status.is_success())
+}
 
 /// Relay configuration
 #[derive(Debug, Clone)]
@@ -81,15 +389,28 @@ impl Default for RelayConfig {
     }
 }
 
-/// Initialize and start the relay
-/// 
-/// # Arguments
-/// * `host` - IP address to bind (e.g. "127.0.0.1" or "0.0.0.0")
-/// * `port` - Port number (e.g. 8081)
-/// * `db_path` - Database path (reserved for future persistent storage)
-pub fn start_relay(host: String, port: u16, db_path: String) -> Result<String, String> {
+/// Stop writing logs anywhere, for privacy-sensitive deployments
+///
+/// No log file is created and nothing is written to stderr. A subscriber installed by an
+/// earlier start is silenced (it can't be uninstalled) and its log file is closed.
+fn disable_logging() {
+    if let Ok(handle_guard) = LOG_LEVEL_HANDLE.lock() {
+        if let Some(handle) = handle_guard.as_ref() {
+            let _ = handle.reload(LevelFilter::OFF);
+        }
+    }
+    if let Ok(mut guard_storage) = LOG_GUARD.lock() {
+        *guard_storage = None;
+    }
+    if let Ok(mut log_path_guard) = LOG_FILE_PATH.lock() {
+        *log_path_guard = None;
+    }
+}
+
+/// Set up logging to `relay.log` next to the database and to stderr, returns the log file path
+fn init_logging(db_path: &str) -> Result<String, RelayStartError> {
     // Setup log file path (in same directory as database)
-    let db_path_buf = PathBuf::from(&db_path);
+    let db_path_buf = PathBuf::from(db_path);
     let log_dir = db_path_buf.parent()
         .ok_or_else(|| "Invalid database path".to_string())?;
     let log_file_path = log_dir.join("relay.log");
@@ -123,7 +444,7 @@ pub fn start_relay(host: String, port: u16, db_path: String) -> Result<String, S
         .create(true)
         .append(true)
         .open(&log_file_path)
-        .map_err(|e| format!("Failed to open log file: {}", e))?;
+        .map_err(|e| RelayStartError::from_io("Failed to open log file", e))?;
     
     // Create non-blocking writer for file logging
     let (non_blocking, guard) = tracing_appender::non_blocking(log_file);
@@ -185,18 +506,8 @@ pub fn start_relay(host: String, port: u16, db_path: String) -> Result<String, S
                 return Ok(());
             }
             
-            // Format timestamp (simple format: HH:MM:SS.mmm)
-            use std::time::SystemTime;
-            if let Ok(elapsed) = SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
-                let total_secs = elapsed.as_secs();
-                let millis = elapsed.subsec_millis();
-                // Calculate time of day (seconds since midnight)
-                let time_of_day = total_secs % 86400;
-                let hours = time_of_day / 3600;
-                let minutes = (time_of_day % 3600) / 60;
-                let seconds = time_of_day % 60;
-                write!(writer, "{:02}:{:02}:{:02}.{:03} ", hours, minutes, seconds, millis)?;
-            }
+            // Format timestamp (local time: HH:MM:SS.mmm)
+            write!(writer, "{} ", super::datetime::log_timestamp())?;
             
             // Format level
             match level {
@@ -220,7 +531,10 @@ pub fn start_relay(host: String, port: u16, db_path: String) -> Result<String, S
     }
     
     // Initialize tracing subscriber with custom formatter for file output
-    let _ = tracing_subscriber::registry()
+    // The reloadable level filter allows changing the log level of the running relay
+    let (level_filter, level_handle) = reload::Layer::new(level_filter_for(policy::current_config().log_level));
+    let init_result = tracing_subscriber::registry()
+        .with(level_filter)
         .with(
             tracing_subscriber::fmt::layer()
                 .with_writer(non_blocking)
@@ -237,78 +551,340 @@ pub fn start_relay(host: String, port: u16, db_path: String) -> Result<String, S
         )
         .try_init();
     
-    // Get or create runtime
-    let runtime = {
-        let mut rt_guard = RUNTIME.lock().map_err(|e| format!("Failed to lock runtime: {}", e))?;
-        if rt_guard.is_none() {
-            let rt = Runtime::new().map_err(|e| format!("Failed to create tokio runtime: {}", e))?;
-            *rt_guard = Some(Arc::new(rt));
+    // Only the first initialization installs the subscriber, keep that handle
+    if init_result.is_ok() {
+        let mut handle_guard = LOG_LEVEL_HANDLE.lock()
+            .map_err(|e| format!("Failed to lock log level handle: {}", e))?;
+        *handle_guard = Some(level_handle);
+    } else if let Ok(handle_guard) = LOG_LEVEL_HANDLE.lock() {
+        // Undo `disable_logging` of an earlier start
+        if let Some(handle) = handle_guard.as_ref() {
+            let _ = handle.reload(level_filter_for(policy::current_config().log_level));
         }
-        rt_guard.as_ref().unwrap().clone()
-    };
+    }
+    
+    Ok(log_file_path_str)
+}
 
+/// Initialize and start the relay
+/// 
+/// # Arguments
+/// * `host` - IP address to bind (e.g. "127.0.0.1" or "0.0.0.0")
+/// * `port` - Port number (e.g. 8081)
+/// * `db_path` - Database path (reserved for future persistent storage)
+pub fn start_relay(host: String, port: u16, db_path: String) -> Result<String, RelayStartError> {
+    let log_file_path_str = start_logging(&db_path)?;
+    
     // Start relay in the runtime
-    let url = runtime.block_on(async {
-        start_relay_async(host, port, db_path, log_file_path_str.clone()).await
-    })?;
-
+    let start_args = (host.clone(), port, db_path.clone());
+    let url = run_relay_start(|start| start_relay_async(host, port, db_path, log_file_path_str, start))?;
+    
+    if let Ok(mut listener_guard) = RELAY_PREBOUND_LISTENER.lock() {
+        *listener_guard = None;
+    }
+    if let Ok(mut args_guard) = RELAY_START_ARGS.lock() {
+        *args_guard = Some(start_args);
+    }
     Ok(url)
 }
 
-async fn start_relay_async(host: String, port: u16, db_path: String, log_file_path: String) -> Result<String, String> {
-    // Parse IP address
-    let addr: IpAddr = host.parse()
-        .map_err(|e| format!("Invalid IP address '{}': {}", host, e))?;
+/// State of a relay start, shared between the caller and the start task
+const START_PENDING: u8 = 0;
+/// The task is installing the relay, it will be running once the task ends
+const START_COMMITTED: u8 = 1;
+/// The caller gave up, the task must not install the relay
+const START_CANCELLED: u8 = 2;
+
+/// Run a relay start on the shared runtime, giving up after the call timeout
+///
+/// A start that times out is aborted and can no longer install the relay (see `run_relay`),
+/// so the relay never comes up after the caller was told the start failed.
+fn run_relay_start<F, Fut>(start: F) -> Result<String, RelayStartError>
+where
+    F: FnOnce(Arc<AtomicU8>) -> Fut,
+    Fut: std::future::Future<Output = Result<String, RelayStartError>> + Send + 'static,
+{
+    let runtime = get_or_create_runtime()?;
+    let timeout = super::system::call_timeout();
+    let state = Arc::new(AtomicU8::new(START_PENDING));
+    let mut task = runtime.spawn(start(state.clone()));
     
-    // Create NDB database (nostrdb, persistent, cross-platform)
-    // NDB uses a string path instead of PathBuf
-    let db_path_str = db_path.clone();
+    runtime.block_on(async move {
+        let outcome = match tokio::time::timeout(timeout, &mut task).await {
+            Ok(outcome) => outcome,
+            Err(_) => {
+                let cancelled = state.compare_exchange(START_PENDING, START_CANCELLED, Ordering::SeqCst, Ordering::SeqCst);
+                if cancelled.is_ok() {
+                    task.abort();
+                    // The database may have been opened before the start was aborted
+                    close_headless_database();
+                    return Err(RelayStartError::Timeout {
+                        message: format!("Timeout: relay did not start within {} ms", timeout.as_millis()),
+                    });
+                }
+                // Already installing the relay, which doesn't wait on anything
+                task.await
+            }
+        };
+        outcome.map_err(|e| RelayStartError::Timeout { message: format!("Task failed: {}", e) })?
+    })
+}
+
+/// Set up logging for a relay start, returns the log file path (empty when logging is off)
+fn start_logging(db_path: &str) -> Result<String, RelayStartError> {
+    if policy::current_config().log_level == RelayLogLevel::Off {
+        disable_logging();
+        Ok(String::new())
+    } else {
+        Ok(init_logging(db_path)?)
+    }
+}
+
+/// Start the relay on an already bound socket, see `start_relay_with_fd`
+fn start_relay_on_listener(listener: std::net::TcpListener, db_path: String) -> Result<String, RelayStartError> {
+    let local_addr = listener.local_addr()
+        .map_err(|e| RelayStartError::from_io("Invalid listening socket", e))?;
+    listener.set_nonblocking(true)
+        .map_err(|e| RelayStartError::from_io("Invalid listening socket", e))?;
+    let log_file_path_str = start_logging(&db_path)?;
+    
+    let start_args = (local_addr.ip().to_string(), local_addr.port(), db_path.clone());
+    let url = run_relay_start(|start| async move {
+        let listener = tokio::net::TcpListener::from_std(listener)
+            .map_err(|e| RelayStartError::from_io("Invalid listening socket", e))?;
+        let database_arc = open_database(&db_path).await?;
+        run_relay(listener, database_arc, log_file_path_str, start).await
+    })?;
     
+    if let Ok(mut args_guard) = RELAY_START_ARGS.lock() {
+        *args_guard = Some(start_args);
+    }
+    Ok(url)
+}
+
+/// Start the relay on a socket bound by platform code (e.g. an iOS network extension or an
+/// Android VPN service that must create sockets itself)
+///
+/// The socket is duplicated: the caller keeps ownership of `fd` and may close it once this
+/// returns. It is put in listening state if it isn't yet. Restarts (e.g. by the watchdog)
+/// listen on the same socket until `stop_relay`. Only supported on Unix platforms (Android,
+/// iOS, macOS, Linux).
+///
+/// # Arguments
+/// * `fd` - File descriptor of a bound TCP socket
+/// * `db_path` - Database path
+pub fn start_relay_with_fd(fd: i32, db_path: String) -> Result<String, RelayStartError> {
+    #[cfg(unix)]
+    {
+        use std::os::fd::{BorrowedFd, FromRawFd, IntoRawFd};
+        if fd < 0 {
+            return Err(RelayStartError::Other { message: format!("Invalid file descriptor {}", fd) });
+        }
+        // SAFETY: the caller guarantees `fd` is open for the duration of this call, it is
+        // only borrowed to duplicate it
+        let owned = unsafe { BorrowedFd::borrow_raw(fd) }
+            .try_clone_to_owned()
+            .map_err(|e| RelayStartError::from_io("Failed to duplicate socket", e))?;
+        // SAFETY: `owned` is a descriptor of our own, its ownership moves to the socket
+        let socket = unsafe { tokio::net::TcpSocket::from_raw_fd(owned.into_raw_fd()) };
+        let bound = socket.local_addr()
+            .map_err(|e| RelayStartError::from_io("Invalid listening socket", e))?;
+        if bound.port() == 0 {
+            return Err(RelayStartError::Other { message: "Socket is not bound to a port".to_string() });
+        }
+        // Listening again on a listening socket only updates its backlog
+        let listener = {
+            let runtime = get_or_create_runtime()?;
+            let _context = runtime.enter();
+            socket.listen(1024)
+                .and_then(|listener| listener.into_std())
+                .map_err(|e| RelayStartError::from_io("Failed to listen on socket", e))?
+        };
+        let kept = listener.try_clone()
+            .map_err(|e| RelayStartError::from_io("Failed to duplicate socket", e))?;
+        
+        let url = start_relay_on_listener(listener, db_path)?;
+        if let Ok(mut listener_guard) = RELAY_PREBOUND_LISTENER.lock() {
+            *listener_guard = Some(kept);
+        }
+        Ok(url)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (fd, db_path);
+        Err(RelayStartError::Other { message: "Starting on a file descriptor is only supported on Unix".to_string() })
+    }
+}
+
+/// Start the relay again after it was stopped, on the pre-bound socket if it was started
+/// with `start_relay_with_fd`
+pub(crate) fn start_relay_again(host: String, port: u16, db_path: String) -> Result<String, RelayStartError> {
+    let prebound = RELAY_PREBOUND_LISTENER.lock()
+        .ok()
+        .and_then(|listener| listener.as_ref().and_then(|listener| listener.try_clone().ok()));
+    match prebound {
+        Some(listener) => start_relay_on_listener(listener, db_path),
+        None => start_relay(host, port, db_path),
+    }
+}
+
+/// Arguments (host, port, db_path) the running relay was started with
+pub(crate) fn relay_start_args() -> Option<(String, u16, String)> {
+    RELAY_START_ARGS.lock().ok().and_then(|args| args.clone())
+}
+
+/// Open NDB with the ingester threads of the resource profile
+///
+/// The map size stays the nostrdb default whatever the profile: it is the maximum database
+/// size, a smaller one would make a grown database fail to open.
+fn open_ndb(db_path: &str) -> Result<NdbDatabase, nostr_ndb::nostrdb::Error> {
+    let limits = super::system::resource_limits();
+    let config = nostr_ndb::nostrdb::Config::new()
+        .set_ingester_threads(limits.database_ingester_threads as i32);
+    let ndb = nostr_ndb::nostrdb::Ndb::new(db_path, &config)?;
+    Ok(NdbDatabase::from(ndb))
+}
+
+/// Open the event database and the auxiliary store next to it, and make them the relay database
+async fn open_database(db_path: &str) -> Result<Arc<NdbDatabase>, RelayStartError> {
     // Create parent directory if it doesn't exist
-    let db_path_buf = PathBuf::from(&db_path);
+    let db_path_buf = PathBuf::from(db_path);
     if let Some(parent) = db_path_buf.parent() {
         std::fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create database directory: {}", e))?;
+            .map_err(|e| RelayStartError::from_io("Failed to create database directory", e))?;
     }
     
-    // Create NDB database (sync operation)
-    // NdbDatabase::open expects a string path
-    let database = NdbDatabase::open(&db_path_str)
-        .map_err(|e| format!("Failed to open NDB database: {}", e))?;
+    // Create NDB database (nostrdb, persistent, cross-platform)
+    let database = open_ndb(db_path)
+        .map_err(|e| RelayStartError::from_ndb_error(db_path, e))?;
     
-    // Store database reference for querying
+    // Open auxiliary store (ingest timestamps) next to the database
+    let aux_path = storage::aux_store_path(db_path)?;
+    storage::open_aux_store(&aux_path)
+        .map_err(RelayStartError::from_aux_store_error)?;
+    
+    // Undo a store transaction interrupted by the app being killed
     let database_arc = Arc::new(database);
+    super::transaction::recover(&database_arc).await?;
+    
+    // Store database reference for querying
     {
         let mut db_guard = RELAY_DATABASE.lock()
             .map_err(|e| format!("Failed to lock database: {}", e))?;
         *db_guard = Some(database_arc.clone());
     }
+    {
+        let mut path_guard = RELAY_DB_PATH.lock()
+            .map_err(|e| format!("Failed to lock database path: {}", e))?;
+        *path_guard = Some(db_path.to_string());
+    }
     
-    // Build relay
-    let builder = RelayBuilder::default()
-        .addr(addr)
-        .port(port)
-        .database(database_arc);
+    Ok(database_arc)
+}
+
+/// Open the database without starting the relay (e.g. from a background task)
+///
+/// Returns false if the database was already open (the relay is running), in which case
+/// it must not be closed with `close_headless_database`.
+pub(crate) fn open_headless_database(db_path: &str) -> Result<bool, String> {
+    if get_database().is_ok() {
+        return Ok(false);
+    }
+    let db_path = db_path.to_string();
+    run_blocking(async move { open_database(&db_path).await })?
+        .map_err(|e| e.to_string())?;
+    Ok(true)
+}
+
+/// Close a database opened with `open_headless_database`
+pub(crate) fn close_headless_database() {
+    if is_relay_running() {
+        return;
+    }
+    if let Ok(mut db_guard) = RELAY_DATABASE.lock() {
+        *db_guard = None;
+    }
+    if let Ok(mut path_guard) = RELAY_DB_PATH.lock() {
+        *path_guard = None;
+    }
+}
+
+async fn start_relay_async(
+    host: String,
+    port: u16,
+    db_path: String,
+    log_file_path: String,
+    start: Arc<AtomicU8>,
+) -> Result<String, RelayStartError> {
+    // Parse IP address
+    let addr: IpAddr = host.parse()
+        .map_err(|e: std::net::AddrParseError| RelayStartError::InvalidAddress { host: host.clone(), message: e.to_string() })?;
     
-    // Create relay instance
-    let relay = LocalRelay::new(builder);
+    let database_arc = open_database(&db_path).await?;
     
-    // Start relay
-    relay.run()
+    let listener = tokio::net::TcpListener::bind((addr, port))
         .await
-        .map_err(|e| format!("Failed to start relay: {}", e))?;
+        .map_err(|e| RelayStartError::from_bind_error(port, e))?;
+    run_relay(listener, database_arc, log_file_path, start).await
+}
+
+/// Run the relay behind the access gate accepting connections on `listener`
+///
+/// `start` is the state of the start (see `run_relay_start`), the relay is only installed
+/// if the caller is still waiting for it.
+async fn run_relay(
+    listener: tokio::net::TcpListener,
+    database_arc: Arc<NdbDatabase>,
+    log_file_path: String,
+    start: Arc<AtomicU8>,
+) -> Result<String, RelayStartError> {
+    let local_addr = listener.local_addr()
+        .map_err(|e| RelayStartError::from_io("Failed to get listening address", e))?;
+    let (addr, port) = (local_addr.ip(), local_addr.port());
+    
+    // The relay sits on loopback behind the access gate, which checks the tokens of
+    // incoming connections. Its listener stays bound from here on, so no other process can
+    // take the port between picking and using it.
+    let relay_listener = tokio::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))
+        .await
+        .map_err(|e| RelayStartError::from_io("Failed to bind the relay behind the gate", e))?;
+    let relay_port = relay_listener.local_addr()
+        .map_err(|e| RelayStartError::from_io("Failed to get listening address", e))?
+        .port();
+    
+    // Build relay (writes go through the ingest hooks, policies read the live settings)
+    let builder = RelayBuilder::default()
+        .addr(IpAddr::from(std::net::Ipv4Addr::LOCALHOST))
+        .port(relay_port)
+        .database(Arc::new(IngestDatabase::new(database_arc)))
+        .write_policy(LivePolicy)
+        .query_policy(LivePolicy);
     
-    // Get URL (async method returns RelayUrl)
-    let relay_url = relay.url().await;
-    let url = relay_url.to_string();
+    // Create relay instance, fed the connections of our listener instead of binding its own
+    let relay = Arc::new(LocalRelay::new(builder));
     
     // Fix URL: Replace 0.0.0.0 with 127.0.0.1 for client connections
-    let client_url = if addr.to_string() == "0.0.0.0" {
+    let client_url = if addr.is_unspecified() {
         format!("ws://127.0.0.1:{}", port)
     } else {
-        url.clone()
+        format!("ws://{}", std::net::SocketAddr::new(addr, port))
     };
     
+    // Nothing below waits, so the relay is installed completely or not at all
+    if start.compare_exchange(START_PENDING, START_COMMITTED, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+        close_headless_database();
+        return Err(RelayStartError::Timeout { message: "Relay start was cancelled".to_string() });
+    }
+    
+    {
+        let mut task_guard = GATE_TASK.lock()
+            .map_err(|e| format!("Failed to lock gate task: {}", e))?;
+        let served = relay.clone();
+        *task_guard = Some(tokio::spawn(async move {
+            tokio::join!(gate::run_gate(listener, relay_port), serve_relay(relay_listener, served));
+        }));
+    }
+    
     // Log relay start
     tracing::info!("Relay started on {}", client_url);
     tracing::info!("Log file: {}", log_file_path);
@@ -317,7 +893,7 @@ async fn start_relay_async(host: String, port: u16, db_path: String, log_file_pa
     {
         let mut relay_guard = RELAY_INSTANCE.lock()
             .map_err(|e| format!("Failed to lock relay instance: {}", e))?;
-        *relay_guard = Some(Arc::new(relay));
+        *relay_guard = Some(relay);
     }
     
     // Store client URL
@@ -327,18 +903,202 @@ async fn start_relay_async(host: String, port: u16, db_path: String, log_file_pa
         *url_guard = Some(client_url.clone());
     }
     
+    // Start periodic maintenance (retention)
+    {
+        let mut task_guard = MAINTENANCE_TASK.lock()
+            .map_err(|e| format!("Failed to lock maintenance task: {}", e))?;
+        *task_guard = Some(tokio::spawn(run_maintenance()));
+    }
+    
     // Return the client-usable URL
     Ok(client_url)
 }
 
+/// Hand the connections the gate forwards to the relay
+async fn serve_relay(listener: tokio::net::TcpListener, relay: Arc<LocalRelay>) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                tracing::warn!("Failed to accept gated connection: {}", e);
+                continue;
+            }
+        };
+        let relay = relay.clone();
+        tokio::spawn(async move {
+            if let Err(e) = relay.take_connection(stream, addr).await {
+                tracing::debug!("Gated connection {} closed: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Check whether the relay could be started, without starting it
+///
+/// Returns every problem found (an empty list means the relay should start).
+pub fn preflight_relay(host: String, port: u16, db_path: String) -> Vec<RelayStartError> {
+    let mut problems = Vec::new();
+    
+    // Address and port
+    match host.parse::<IpAddr>() {
+        Ok(addr) => {
+            if let Err(e) = std::net::TcpListener::bind((addr, port)) {
+                match e.kind() {
+                    std::io::ErrorKind::AddrInUse => problems.push(RelayStartError::PortInUse {
+                        port,
+                        pid: find_port_owner(port),
+                    }),
+                    _ => problems.push(RelayStartError::from_io(&format!("Failed to bind {}:{}", addr, port), e)),
+                }
+            }
+        }
+        Err(e) => problems.push(RelayStartError::InvalidAddress { host: host.clone(), message: e.to_string() }),
+    }
+    
+    // Database directory must be writable (it also holds the log file)
+    let db_path_buf = PathBuf::from(&db_path);
+    match db_path_buf.parent() {
+        Some(parent) => {
+            let probe = parent.join(".relay_preflight");
+            let writable = std::fs::create_dir_all(parent)
+                .and_then(|_| std::fs::write(&probe, b""))
+                .and_then(|_| std::fs::remove_file(&probe));
+            if let Err(e) = writable {
+                problems.push(RelayStartError::from_io("Database directory is not writable", e));
+                return problems;
+            }
+        }
+        None => {
+            problems.push(RelayStartError::Other { message: "Invalid database path".to_string() });
+            return problems;
+        }
+    }
+    
+    // Database must not be in use by a running relay, and must open cleanly
+    if is_relay_running() {
+        problems.push(RelayStartError::DbLocked {
+            message: "Database is in use by the running relay".to_string(),
+        });
+    } else if db_path_buf.exists() {
+        if let Err(e) = open_ndb(&db_path) {
+            problems.push(RelayStartError::from_ndb_error(&db_path, e));
+        }
+    }
+    
+    problems
+}
+
+/// Periodic maintenance of the running relay
+async fn run_maintenance() {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(MAINTENANCE_INTERVAL_SECS));
+    let mut last_retention = 0;
+    
+    loop {
+        interval.tick().await;
+        let now = nostr_database::prelude::Timestamp::now().as_u64();
+        
+        if now.saturating_sub(last_retention) >= RETENTION_INTERVAL_SECS {
+            last_retention = now;
+            if let Err(e) = apply_retention(now).await {
+                tracing::warn!("Retention failed: {}", e);
+            }
+        }
+        
+        match super::schedule::publish_due(now).await {
+            Ok(0) => {}
+            Ok(published) => tracing::info!("Published {} scheduled events", published),
+            Err(e) => tracing::warn!("Publishing scheduled events failed: {}", e),
+        }
+    }
+}
+
+/// Delete events older than the configured retention
+async fn apply_retention(now: u64) -> Result<(), String> {
+    delete_events_older_than(policy::current_config().retention_days, now).await
+}
+
+/// Delete events older than `retention_days` (0 keeps everything)
+pub(crate) async fn delete_events_older_than(retention_days: u32, now: u64) -> Result<(), String> {
+    let retention_days = retention_days as u64;
+    if retention_days == 0 {
+        return Ok(());
+    }
+    
+    let cutoff = now.saturating_sub(retention_days * 86400);
+    let database = get_database()?;
+    ingest::delete_events(&database, Filter::new().until(nostr_database::prelude::Timestamp::from(cutoff)))
+        .await
+        .map_err(|e| format!("Failed to delete old events: {}", e))?;
+    storage::remove_truncated(|event| event["created_at"].as_u64().is_some_and(|created_at| created_at < cutoff))?;
+    
+    tracing::info!("Deleted events older than {} days", retention_days);
+    Ok(())
+}
+
+fn level_filter_for(level: RelayLogLevel) -> LevelFilter {
+    match level {
+        RelayLogLevel::Off => LevelFilter::OFF,
+        RelayLogLevel::Error => LevelFilter::ERROR,
+        RelayLogLevel::Warn => LevelFilter::WARN,
+        RelayLogLevel::Info => LevelFilter::INFO,
+        RelayLogLevel::Debug => LevelFilter::DEBUG,
+    }
+}
+
+/// Update settings of the relay without restarting it
+///
+/// Settings left as None are unchanged. Takes effect immediately for new events and
+/// queries; connected clients stay connected. Can also be called before `start_relay`.
+pub fn update_relay_config(update: RelayConfigUpdate) -> Result<RelayPolicyConfig, String> {
+    let log_level_changed = update.log_level.is_some();
+    let config = policy::apply_update(update)?;
+    
+    if log_level_changed {
+        let handle_guard = LOG_LEVEL_HANDLE.lock()
+            .map_err(|e| format!("Failed to lock log level handle: {}", e))?;
+        if let Some(handle) = handle_guard.as_ref() {
+            handle.reload(level_filter_for(config.log_level))
+                .map_err(|e| format!("Failed to change log level: {}", e))?;
+        }
+    }
+    
+    tracing::info!("Relay configuration updated");
+    Ok(config)
+}
+
+/// Get the current relay settings
+pub fn get_relay_config() -> RelayPolicyConfig {
+    policy::current_config()
+}
+
 /// Stop the relay
+///
+/// Forgets the socket of `start_relay_with_fd`, the next start binds its own.
 pub fn stop_relay() -> Result<(), String> {
+    if let Ok(mut listener_guard) = RELAY_PREBOUND_LISTENER.lock() {
+        *listener_guard = None;
+    }
+    stop_relay_for_restart()
+}
+
+/// Stop the relay to start it again with `start_relay_again`, keeping the socket of
+/// `start_relay_with_fd`
+pub(crate) fn stop_relay_for_restart() -> Result<(), String> {
     let mut relay_guard = RELAY_INSTANCE.lock()
         .map_err(|e| format!("Failed to lock relay instance: {}", e))?;
     
     if let Some(relay) = relay_guard.take() {
         relay.shutdown();
         
+        // Stop maintenance task and access gate
+        for task in [&MAINTENANCE_TASK, &GATE_TASK] {
+            if let Ok(mut task_guard) = task.lock() {
+                if let Some(task) = task_guard.take() {
+                    task.abort();
+                }
+            }
+        }
+        
         // Clear client URL
         if let Ok(mut url_guard) = RELAY_CLIENT_URL.lock() {
             *url_guard = None;
@@ -381,10 +1141,29 @@ pub fn is_relay_running() -> bool {
     }
 }
 
+/// Client connected to the relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayConnection {
+    /// Address of the client ("ip:port"), the tunnel's for tunneled connections
+    pub peer: String,
+    pub origin: ConnectionOrigin,
+    /// Id of the capability token used, if any
+    pub token_id: Option<String>,
+    pub connected_at: u64,
+}
+
+/// List the clients connected to the relay
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_list_connections() -> Vec<RelayConnection> {
+    gate::connections()
+}
+
 /// Relay statistics (event-focused)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RelayStats {
     pub total_events: u64,
+    /// Latency percentiles of the write path stages since the app started
+    pub ingest_latency: Vec<IngestStageLatency>,
 }
 
 /// Get relay statistics
@@ -398,7 +1177,7 @@ pub fn get_relay_stats(db_path: String) -> Result<RelayStats, String> {
             db.clone()
         } else {
             // Database not in memory, open it
-            let db = NdbDatabase::open(&db_path)
+            let db = open_ndb(&db_path)
                 .map_err(|e| format!("Failed to open database: {}", e))?;
             Arc::new(db)
         }
@@ -411,30 +1190,42 @@ pub fn get_relay_stats(db_path: String) -> Result<RelayStats, String> {
 }
 
 fn get_relay_stats_sync(database: Arc<NdbDatabase>) -> Result<RelayStats, String> {
-    let runtime = {
-        let rt_guard = RUNTIME
-            .lock()
-            .map_err(|e| format!("Failed to lock runtime: {}", e))?;
-        rt_guard
-            .as_ref()
-            .cloned()
-            .ok_or_else(|| "Runtime not initialized".to_string())?
-    };
-
     let db = database.clone();
-    let total_events = runtime
-        .block_on(async move { db.count(Filter::new()).await })
+    let total_events = run_blocking(async move { db.count(Filter::new()).await })?
         .map_err(|e| format!("Failed to count events: {}", e))? as u64;
-
-    Ok(RelayStats { total_events })
+    
+    Ok(RelayStats {
+        total_events,
+        ingest_latency: metrics::ingest_latency(),
+    })
 }
 
 // FFI-compatible functions using flutter_rust_bridge
 #[flutter_rust_bridge::frb(sync)]
-pub fn relay_start(host: String, port: u16, db_path: String) -> Result<String, String> {
+pub fn relay_start(host: String, port: u16, db_path: String) -> Result<String, RelayStartError> {
     start_relay(host, port, db_path)
 }
 
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_start_with_fd(fd: i32, db_path: String) -> Result<String, RelayStartError> {
+    start_relay_with_fd(fd, db_path)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_update_config(partial_config: RelayConfigUpdate) -> Result<RelayPolicyConfig, String> {
+    update_relay_config(partial_config)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_config() -> RelayPolicyConfig {
+    get_relay_config()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_preflight(host: String, port: u16, db_path: String) -> Vec<RelayStartError> {
+    preflight_relay(host, port, db_path)
+}
+
 #[flutter_rust_bridge::frb(sync)]
 pub fn relay_stop() -> Result<(), String> {
     stop_relay()
@@ -536,3 +1327,627 @@ pub fn relay_clear_log_file() -> Result<(), String> {
     clear_log_file()
 }
 
+/// Get the reassembled content of a chunked event group stored in the relay
+///
+/// # Arguments
+/// * `group_id` - SHA-256 of the full content (the `x` tag of the chunk events)
+/// * `author` - Hex public key of the author, chunks of other keys are ignored
+pub fn get_chunked_content(group_id: String, author: String) -> Result<String, String> {
+    let author = PublicKey::from_hex(&author)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let filter = Filter::new()
+        .author(author)
+        .custom_tag(SingleLetterTag::lowercase(Alphabet::X), group_id);
+    let events = query_local_events_json(filter)?;
+    
+    super::nostr::reassemble_chunks(events)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_chunked_content(group_id: String, author: String) -> Result<String, String> {
+    get_chunked_content(group_id, author)
+}
+
+/// Stored event with the time it was received by the relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceivedEvent {
+    pub event_json: String,
+    /// Unix timestamp (seconds) at which the relay stored the event
+    pub received_at: u64,
+}
+
+/// Query events by the time they were received (newest received first)
+///
+/// # Arguments
+/// * `filter_json` - NIP-01 filter the events must match (e.g. "{}" for all)
+/// * `since` - Only events received at or after this unix timestamp
+/// * `until` - Only events received at or before this unix timestamp
+/// * `limit` - Maximum number of events to return
+pub fn query_events_by_received_at(
+    filter_json: String,
+    since: Option<u64>,
+    until: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Vec<ReceivedEvent>, String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    let database = get_database()?;
+    
+    let ids = storage::received_event_ids(since.unwrap_or(0), until.unwrap_or(u64::MAX))?;
+    let limit = limit.map(|n| n as usize).unwrap_or(usize::MAX);
+    
+    run_blocking(async move {
+        let mut events = Vec::new();
+        // The index is read lazily, only as far as needed to fill the limit
+        for entry in ids {
+            if events.len() >= limit {
+                break;
+            }
+            let (id, received_at) = entry?;
+            
+            let event_id = EventId::from_byte_array(id);
+            let event = database.event_by_id(&event_id)
+                .await
+                .map_err(|e| format!("Failed to get event: {}", e))?;
+            
+            // Events deleted since they were received are skipped
+            if let Some(event) = event {
+                if filter.match_event(&event) {
+                    events.push(ReceivedEvent {
+                        event_json: event.as_json(),
+                        received_at,
+                    });
+                }
+            }
+        }
+        Ok(events)
+    })?
+}
+
+/// Get when an event was received by the relay (None if unknown)
+pub fn get_event_received_at(event_id: String) -> Result<Option<u64>, String> {
+    let event_id = EventId::from_hex(&event_id)
+        .map_err(|e| format!("Invalid event ID: {}", e))?;
+    storage::get_received_at(event_id.as_bytes())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_query_by_received_at(
+    filter_json: String,
+    since: Option<u64>,
+    until: Option<u64>,
+    limit: Option<u32>,
+) -> Result<Vec<ReceivedEvent>, String> {
+    query_events_by_received_at(filter_json, since, until, limit)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_event_received_at(event_id: String) -> Result<Option<u64>, String> {
+    get_event_received_at(event_id)
+}
+
+/// Query the events matching any of several filters, as a NIP-01 REQ with several filters
+///
+/// The limit of each filter applies to that filter (capped at `limit`, which also applies
+/// to filters without one), an event matching several filters is returned once. Events
+/// are sorted newest first (ties by id). Filters are queried one after the other.
+///
+/// # Arguments
+/// * `filters_json` - NIP-01 filters (e.g. notes, reposts and replies of a view)
+/// * `limit` - Maximum number of events to return in total
+pub fn query_events_multi(filters_json: Vec<String>, limit: Option<u32>) -> Result<Vec<String>, String> {
+    if filters_json.is_empty() {
+        return Err("At least one filter is required".to_string());
+    }
+    let mut filters = filters_json.iter()
+        .map(|filter_json| Filter::from_json(filter_json))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    // No filter has to load more than the total that is returned
+    if let Some(limit) = limit {
+        for filter in &mut filters {
+            filter.limit = Some(filter.limit.map_or(limit as usize, |own| own.min(limit as usize)));
+        }
+    }
+    let database = get_database()?;
+    
+    let mut events = run_blocking(async move {
+        let mut seen = std::collections::HashSet::new();
+        let mut events = Vec::new();
+        for filter in filters {
+            let matched = database.query(filter)
+                .await
+                .map_err(|e| format!("Failed to query events: {}", e))?;
+            events.extend(matched.into_iter().filter(|event| seen.insert(event.id)));
+        }
+        Ok::<_, String>(events)
+    })??;
+    
+    events.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+    events.truncate(limit.map(|n| n as usize).unwrap_or(usize::MAX));
+    Ok(events.into_iter().map(|event| event.as_json()).collect())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_query_multi(filters_json: Vec<String>, limit: Option<u32>) -> Result<Vec<String>, String> {
+    query_events_multi(filters_json, limit)
+}
+
+/// Export a digest of the ids of local events matching a filter
+///
+/// A companion device checks its own events against the digest (`id_digest_missing`)
+/// and only sends or requests the ones missing here, a lightweight alternative to negentropy.
+///
+/// # Arguments
+/// * `filter_json` - NIP-01 filter selecting the events (e.g. "{}" for all)
+/// * `format` - Bloom filter or prefix set
+/// * `false_positive_rate` - Bloom filter false positive rate (default 0.01)
+pub fn export_id_digest(
+    filter_json: String,
+    format: IdDigestFormat,
+    false_positive_rate: Option<f64>,
+) -> Result<EventIdDigest, String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    let database = get_database()?;
+    
+    let items = run_blocking(async move { database.negentropy_items(filter).await })?
+        .map_err(|e| format!("Failed to query event ids: {}", e))?;
+    let mut ids: Vec<[u8; 32]> = items.into_iter()
+        .map(|(id, _)| id.to_bytes())
+        .collect();
+    // Deleted events count as present, so the other device doesn't send them back
+    ids.extend(storage::tombstone_ids()?);
+    
+    EventIdDigest::build(&ids, format, false_positive_rate)
+}
+
+/// Get the local events matching a filter that are not in a digest from another device
+pub fn events_missing_from_digest(digest: EventIdDigest, filter_json: String) -> Result<Vec<String>, String> {
+    let filter = Filter::from_json(&filter_json)
+        .map_err(|e| format!("Invalid filter: {}", e))?;
+    
+    Ok(query_local_events(filter)?
+        .into_iter()
+        .filter(|event| !digest.contains(event.id.as_bytes()))
+        .map(|event| event.as_json())
+        .collect())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_export_id_digest(
+    filter_json: String,
+    format: IdDigestFormat,
+    false_positive_rate: Option<f64>,
+) -> Result<EventIdDigest, String> {
+    export_id_digest(filter_json, format, false_positive_rate)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_events_missing_from_digest(digest: EventIdDigest, filter_json: String) -> Result<Vec<String>, String> {
+    events_missing_from_digest(digest, filter_json)
+}
+
+/// Get all known versions of a replaceable or addressable event, newest first
+///
+/// Superseded versions are only kept while `keep_replaceable_history` is enabled in the relay
+/// settings; the current version comes from the database.
+///
+/// # Arguments
+/// * `pubkey` - Hex public key of the author
+/// * `kind` - Event kind (e.g. 0 for profiles, 30023 for articles)
+/// * `d_tag` - Identifier of addressable events (None for replaceable events)
+pub fn get_event_history(pubkey: String, kind: u16, d_tag: Option<String>) -> Result<Vec<String>, String> {
+    let author = PublicKey::from_hex(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let identifier = d_tag.unwrap_or_default();
+    
+    let mut filter = Filter::new().author(author).kind(Kind::from(kind)).limit(1);
+    if Kind::from(kind).is_addressable() {
+        filter = filter.identifier(identifier.clone());
+    }
+    
+    let mut versions = query_local_events(filter)?;
+    for json in storage::event_history(&author.to_hex(), kind, &identifier)? {
+        match Event::from_json(&json) {
+            Ok(event) if versions.iter().all(|known| known.id != event.id) => versions.push(event),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Skipping invalid history entry: {}", e),
+        }
+    }
+    versions.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    
+    Ok(versions.into_iter().map(|event| event.as_json()).collect())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_event_history(pubkey: String, kind: u16, d_tag: Option<String>) -> Result<Vec<String>, String> {
+    get_event_history(pubkey, kind, d_tag)
+}
+
+/// Number of deleted events remembered as tombstones (see `record_tombstones` in the relay settings)
+pub fn get_tombstone_count() -> Result<u64, String> {
+    storage::tombstone_count()
+}
+
+/// Forget tombstones, letting syncs download the deleted events again
+///
+/// # Arguments
+/// * `older_than_days` - Only forget events deleted more than this many days ago (None for all)
+///
+/// Returns the number of tombstones removed.
+pub fn purge_tombstones(older_than_days: Option<u32>) -> Result<u64, String> {
+    let before = match older_than_days {
+        Some(days) => nostr_database::prelude::Timestamp::now().as_u64().saturating_sub(days as u64 * 86400),
+        None => u64::MAX,
+    };
+    storage::purge_tombstones(before)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_tombstone_count() -> Result<u64, String> {
+    get_tombstone_count()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_purge_tombstones(older_than_days: Option<u32>) -> Result<u64, String> {
+    purge_tombstones(older_than_days)
+}
+
+/// Copy of an event truncated by the content size limit (see `OversizedContentAction::Truncate`)
+///
+/// The copy doesn't verify against its id and signature.
+pub fn get_truncated_event(event_id: String) -> Result<Option<String>, String> {
+    let event_id = EventId::from_hex(&event_id)
+        .map_err(|e| format!("Invalid event ID: {}", e))?;
+    storage::truncated_event(event_id.as_bytes())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_truncated_event(event_id: String) -> Result<Option<String>, String> {
+    get_truncated_event(event_id)
+}
+
+/// Ids of the events truncated by the content size limit, newest first
+///
+/// # Arguments
+/// * `limit` - Maximum number of ids, None for all of them
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_list_truncated_events(limit: Option<u32>) -> Result<Vec<String>, String> {
+    let mut events: Vec<(u64, String)> = storage::truncated_events()?
+        .iter()
+        .filter_map(|json| serde_json::from_str::<serde_json::Value>(json).ok())
+        .filter_map(|event| Some((event["created_at"].as_u64()?, event["id"].as_str()?.to_string())))
+        .collect();
+    events.sort_unstable_by(|a, b| b.cmp(a));
+    events.truncate(limit.map(|n| n as usize).unwrap_or(usize::MAX));
+    Ok(events.into_iter().map(|(_, id)| id).collect())
+}
+
+/// Events rejected or truncated by the content size limit since the app started
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_content_limit_stats() -> ContentLimitStats {
+    policy::content_limit_stats()
+}
+
+/// Activity of a user on one day (UTC)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyActivity {
+    /// Unix timestamp of the start of the day
+    pub day_start: u64,
+    pub posts: u32,
+    pub replies: u32,
+    pub reposts: u32,
+    pub reactions: u32,
+    /// Direct messages sent (NIP-04; gift wraps are signed by throwaway keys and can't be counted)
+    pub direct_messages: u32,
+}
+
+/// Whether a text note replies to another event (NIP-10 `e` tag that isn't a mention)
+fn is_reply(event: &Event) -> bool {
+    event.tags.iter().any(|tag| match tag.as_slice() {
+        [name, _, rest @ ..] if name == "e" => rest.get(1).map_or(true, |marker| marker != "mention"),
+        _ => false,
+    })
+}
+
+/// Summarize the user's own activity per day from the local database
+///
+/// # Arguments
+/// * `pubkey` - Hex public key of the user
+/// * `since` - Only events created at or after this unix timestamp
+///
+/// Returns one entry per day with activity, oldest first.
+pub fn get_my_activity(pubkey: String, since: u64) -> Result<Vec<DailyActivity>, String> {
+    let author = PublicKey::from_hex(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let filter = Filter::new()
+        .author(author)
+        .kinds([Kind::TextNote, Kind::EncryptedDirectMessage, Kind::Repost, Kind::Reaction, Kind::GenericRepost])
+        .since(nostr_database::prelude::Timestamp::from(since));
+    
+    let mut days: std::collections::BTreeMap<u64, DailyActivity> = std::collections::BTreeMap::new();
+    for event in query_local_events(filter)? {
+        let day_start = event.created_at.as_u64() / 86400 * 86400;
+        let day = days.entry(day_start).or_insert_with(|| DailyActivity { day_start, ..Default::default() });
+        match event.kind {
+            Kind::TextNote if is_reply(&event) => day.replies += 1,
+            Kind::TextNote => day.posts += 1,
+            Kind::Repost | Kind::GenericRepost => day.reposts += 1,
+            Kind::Reaction => day.reactions += 1,
+            Kind::EncryptedDirectMessage => day.direct_messages += 1,
+            _ => {}
+        }
+    }
+    
+    Ok(days.into_values().collect())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_my_activity(pubkey: String, since: u64) -> Result<Vec<DailyActivity>, String> {
+    get_my_activity(pubkey, since)
+}
+
+/// Public keys in the `p` tags of the latest cached follow list (kind 3) of a user
+fn cached_follows(pubkey: &PublicKey) -> Result<std::collections::HashSet<String>, String> {
+    let filter = Filter::new().author(*pubkey).kind(Kind::ContactList).limit(1);
+    Ok(query_local_events(filter)?
+        .into_iter()
+        .max_by_key(|event| event.created_at)
+        .map(|event| {
+            event.tags.iter()
+                .filter_map(|tag| match tag.as_slice() {
+                    [name, followed, ..] if name == "p" => PublicKey::from_hex(followed).ok(),
+                    _ => None,
+                })
+                .map(|followed| followed.to_hex())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Users following `pubkey` according to the follow lists (kind 3) in the local database
+///
+/// Follow lists are replaceable, the database only keeps the latest one of each user, so
+/// unfollows are taken into account. The result is limited to the follow lists the relay
+/// has seen.
+///
+/// Returns hex public keys, sorted.
+pub fn get_followers(pubkey: String) -> Result<Vec<String>, String> {
+    let target = PublicKey::from_hex(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let filter = Filter::new().kind(Kind::ContactList).pubkey(target);
+    
+    let mut followers: Vec<String> = query_local_events(filter)?
+        .into_iter()
+        .map(|event| event.pubkey.to_hex())
+        .collect();
+    followers.sort();
+    followers.dedup();
+    Ok(followers)
+}
+
+/// Users followed by both `a` and `b`, according to their latest cached follow lists
+///
+/// Returns hex public keys, sorted.
+pub fn get_mutual_follows(a: String, b: String) -> Result<Vec<String>, String> {
+    let a = PublicKey::from_hex(&a)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let b = PublicKey::from_hex(&b)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let follows_b = cached_follows(&b)?;
+    let mut mutual: Vec<String> = cached_follows(&a)?
+        .into_iter()
+        .filter(|followed| follows_b.contains(followed))
+        .collect();
+    mutual.sort();
+    Ok(mutual)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_followers(pubkey: String) -> Result<Vec<String>, String> {
+    get_followers(pubkey)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_mutual_follows(a: String, b: String) -> Result<Vec<String>, String> {
+    get_mutual_follows(a, b)
+}
+
+/// Result of moving the relay database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseMove {
+    pub old_path: String,
+    pub new_path: String,
+    /// Events in the database, checked after reopening it at the new location
+    pub events: u64,
+    pub bytes_copied: u64,
+    /// Whether the relay was running and has been restarted on the new database
+    pub relay_restarted: bool,
+}
+
+/// Copy a directory recursively, returns the number of bytes copied
+fn copy_dir(from: &std::path::Path, to: &std::path::Path) -> Result<u64, String> {
+    std::fs::create_dir_all(to)
+        .map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(from).map_err(|e| format!("Failed to read {}: {}", from.display(), e))? {
+        let entry = entry.map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            bytes += copy_dir(&entry.path(), &target)?;
+        } else {
+            bytes += std::fs::copy(entry.path(), &target)
+                .map_err(|e| format!("Failed to copy {}: {}", entry.path().display(), e))?;
+            if file_digest(&entry.path())? != file_digest(&target)? {
+                return Err(format!("Copy of {} does not match the original", entry.path().display()));
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+/// SHA-256 of a file, read in chunks so large databases don't have to fit in memory
+fn file_digest(path: &std::path::Path) -> Result<[u8; 32], String> {
+    use nostr::hashes::{Hash, HashEngine};
+    use std::io::Read;
+    
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut engine = nostr::hashes::sha256::Hash::engine();
+    let mut buffer = vec![0u8; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if read == 0 {
+            break;
+        }
+        engine.input(&buffer[..read]);
+    }
+    Ok(nostr::hashes::sha256::Hash::from_engine(engine).to_byte_array())
+}
+
+/// Close the database and the aux store, waiting for the stopped relay to release them
+fn close_database() -> Result<(), String> {
+    let database = RELAY_DATABASE.lock()
+        .map_err(|e| format!("Failed to lock database: {}", e))?
+        .take();
+    let db_path = RELAY_DB_PATH.lock()
+        .ok()
+        .and_then(|mut path_guard| path_guard.take());
+    if let Some(database) = database {
+        // NDB closes its files when the last reference is dropped
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while Arc::strong_count(&database) > 1 {
+            if std::time::Instant::now() > deadline {
+                // Still open, keep it reachable
+                if let Ok(mut db_guard) = RELAY_DATABASE.lock() {
+                    *db_guard = Some(database);
+                }
+                if let Ok(mut path_guard) = RELAY_DB_PATH.lock() {
+                    *path_guard = db_path;
+                }
+                return Err("Database is still in use".to_string());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+    }
+    storage::close_aux_store()
+}
+
+fn count_events() -> Result<u64, String> {
+    let database = get_database()?;
+    Ok(run_blocking(async move { database.count(Filter::new()).await })?
+        .map_err(|e| format!("Failed to count events: {}", e))? as u64)
+}
+
+/// Open the database at `db_path` (restarting the relay on it if `relay` is given)
+fn reopen_database(db_path: &str, relay: Option<&(String, u16, String)>) -> Result<(), String> {
+    match relay {
+        Some((host, port, _)) => start_relay_again(host.clone(), *port, db_path.to_string())
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        None => {
+            let db_path = db_path.to_string();
+            run_blocking(async move { open_database(&db_path).await })?
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Move the open database (events and aux store) to another location
+///
+/// Stops the relay, copies the files, checks every copied file and the event count at the
+/// new location, and restarts the relay there. If anything fails the copy is removed and
+/// the database reopened where it was. The old files are kept unless `delete_old` is set.
+///
+/// # Arguments
+/// * `new_path` - New database directory, must not exist or be empty (e.g. on the SD card)
+/// * `delete_old` - Remove the old files once the move succeeded
+pub fn move_database(new_path: String, delete_old: bool) -> Result<DatabaseMove, String> {
+    let old_path = get_database_path()?;
+    let new_dir = PathBuf::from(&new_path);
+    if new_dir.read_dir().map_or(false, |mut entries| entries.next().is_some()) {
+        return Err(format!("{} is not empty", new_path));
+    }
+    let old_aux = storage::aux_store_path(&old_path)?;
+    let new_aux = storage::aux_store_path(&new_path)?;
+    // Databases in the same directory share the aux store
+    let move_aux = new_aux != old_aux && old_aux.exists();
+    if move_aux && new_aux.exists() {
+        return Err(format!("{} already exists", new_aux.display()));
+    }
+    
+    let events = count_events()?;
+    let relay = if is_relay_running() { relay_start_args() } else { None };
+    if relay.is_some() {
+        stop_relay_for_restart()?;
+    }
+    tracing::info!("Moving database from {} to {}", old_path, new_path);
+    
+    // From here on every failure reopens the database where it was (and restarts the relay)
+    let copy = || -> Result<u64, String> {
+        close_database()?;
+        let mut bytes = copy_dir(std::path::Path::new(&old_path), &new_dir)?;
+        if move_aux {
+            bytes += copy_dir(&old_aux, &new_aux)?;
+        }
+        reopen_database(&new_path, None)?;
+        let moved_events = count_events()?;
+        if moved_events != events {
+            return Err(format!("Moved database has {} events instead of {}", moved_events, events));
+        }
+        
+        // The relay takes the database over from the headless open
+        if let Some(relay) = relay.as_ref() {
+            close_database()?;
+            reopen_database(&new_path, Some(relay))?;
+        }
+        Ok(bytes)
+    };
+    let bytes_copied = match copy() {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!("Moving database failed: {}", e);
+            if is_relay_running() {
+                let _ = stop_relay_for_restart();
+            }
+            let _ = close_database();
+            let _ = std::fs::remove_dir_all(&new_dir);
+            if move_aux {
+                let _ = std::fs::remove_dir_all(&new_aux);
+            }
+            // The old database is still open if it couldn't be closed
+            let still_open = get_database_path().map_or(false, |path| path == old_path);
+            if relay.is_some() || !still_open {
+                reopen_database(&old_path, relay.as_ref())
+                    .map_err(|reopen| format!("{} (reopening the old database failed too: {})", e, reopen))?;
+            }
+            return Err(e);
+        }
+    };
+    
+    if delete_old {
+        std::fs::remove_dir_all(&old_path)
+            .map_err(|e| format!("Moved, but failed to delete {}: {}", old_path, e))?;
+        if move_aux {
+            std::fs::remove_dir_all(&old_aux)
+                .map_err(|e| format!("Moved, but failed to delete {}: {}", old_aux.display(), e))?;
+        }
+    }
+    tracing::info!("Moved {} events ({} bytes) to {}", events, bytes_copied, new_path);
+    
+    Ok(DatabaseMove {
+        old_path,
+        new_path,
+        events,
+        bytes_copied,
+        relay_restarted: relay.is_some(),
+    })
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_move_database(new_path: String, delete_old: bool) -> Result<DatabaseMove, String> {
+    move_database(new_path, delete_old)
+}
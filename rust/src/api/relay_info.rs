@@ -0,0 +1,171 @@
+use nostr::types::time::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use super::kv;
+use super::relay::run_blocking;
+
+/// KV namespace caching NIP-11 documents, keyed by relay URL
+const RELAY_INFO_NAMESPACE: &str = "relay_info";
+/// Cached documents older than this are fetched again
+const RELAY_INFO_MAX_AGE_SECS: u64 = 24 * 60 * 60;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limits announced by a relay (NIP-11 `limitation`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayLimits {
+    pub max_message_length: Option<u64>,
+    pub max_subscriptions: Option<u64>,
+    pub max_limit: Option<u64>,
+    pub max_event_tags: Option<u64>,
+    pub max_content_length: Option<u64>,
+    pub min_pow_difficulty: Option<u32>,
+    pub auth_required: Option<bool>,
+    pub payment_required: Option<bool>,
+    pub restricted_writes: Option<bool>,
+    pub created_at_lower_limit: Option<u64>,
+    pub created_at_upper_limit: Option<u64>,
+}
+
+/// Fee of a relay (NIP-11 `fees`)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayFee {
+    pub amount: u64,
+    pub unit: String,
+    /// Seconds the fee covers (subscriptions)
+    pub period: Option<u64>,
+    /// Kinds the fee applies to (publication)
+    pub kinds: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayFees {
+    pub admission: Vec<RelayFee>,
+    pub subscription: Vec<RelayFee>,
+    pub publication: Vec<RelayFee>,
+}
+
+/// NIP-11 relay information document
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayInfo {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub pubkey: Option<String>,
+    pub contact: Option<String>,
+    pub supported_nips: Vec<u16>,
+    pub software: Option<String>,
+    pub version: Option<String>,
+    pub limitation: Option<RelayLimits>,
+    pub fees: Option<RelayFees>,
+    pub payments_url: Option<String>,
+    pub posting_policy: Option<String>,
+}
+
+/// Relay information with cache metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayInfoResult {
+    pub url: String,
+    pub info: RelayInfo,
+    /// Unix timestamp of the fetch
+    pub fetched_at: u64,
+    /// True if the relay could not be reached and an outdated cached copy is returned
+    pub stale: bool,
+    /// Document as returned by the relay
+    pub raw_json: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedRelayInfo {
+    fetched_at: u64,
+    raw_json: String,
+}
+
+/// HTTP URL serving the NIP-11 document of a relay
+fn info_url(url: &str) -> Result<String, String> {
+    if let Some(rest) = url.strip_prefix("wss://") {
+        Ok(format!("https://{}", rest))
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        Ok(format!("http://{}", rest))
+    } else {
+        Err(format!("Invalid relay URL '{}': expected ws:// or wss://", url))
+    }
+}
+
+async fn fetch_relay_info(url: &str) -> Result<String, String> {
+    let response = super::client::http_client()?
+        .get(info_url(url)?)
+        .timeout(FETCH_TIMEOUT)
+        .header("Accept", "application/nostr+json")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch relay information: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!("Relay returned HTTP {}", response.status()));
+    }
+    
+    response.text()
+        .await
+        .map_err(|e| format!("Failed to read relay information: {}", e))
+}
+
+fn to_result(url: String, cached: CachedRelayInfo, stale: bool) -> Result<RelayInfoResult, String> {
+    let info = serde_json::from_str(&cached.raw_json)
+        .map_err(|e| format!("Invalid relay information document: {}", e))?;
+    Ok(RelayInfoResult {
+        url,
+        info,
+        fetched_at: cached.fetched_at,
+        stale,
+        raw_json: cached.raw_json,
+    })
+}
+
+/// Get the NIP-11 information document of a remote relay
+///
+/// Documents are cached in the KV store for a day. If the relay can't be reached, an
+/// outdated cached copy is returned (marked `stale`) when available.
+///
+/// # Arguments
+/// * `url` - Relay URL (ws:// or wss://)
+/// * `force_refresh` - Fetch the document even if a fresh copy is cached
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_relay_info(url: String, force_refresh: bool) -> Result<RelayInfoResult, String> {
+    let url = url.trim_end_matches('/').to_string();
+    let now = Timestamp::now().as_u64();
+    
+    let cached: Option<CachedRelayInfo> = kv::kv_get(RELAY_INFO_NAMESPACE.to_string(), url.clone())
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str(&value).ok());
+    
+    if let Some(cached) = cached.as_ref() {
+        if !force_refresh && now.saturating_sub(cached.fetched_at) < RELAY_INFO_MAX_AGE_SECS {
+            return to_result(url, cached.clone(), false);
+        }
+    }
+    
+    let fetch_url = url.clone();
+    let fetched = run_blocking(async move { fetch_relay_info(&fetch_url).await })
+        .and_then(|result| result);
+    
+    match fetched {
+        Ok(raw_json) => {
+            let fresh = CachedRelayInfo { fetched_at: now, raw_json };
+            let result = to_result(url.clone(), fresh.clone(), false)?;
+            
+            // Caching is best effort (the KV store may not be open yet)
+            if let Ok(value) = serde_json::to_string(&fresh) {
+                let _ = kv::kv_set(RELAY_INFO_NAMESPACE.to_string(), url, value);
+            }
+            Ok(result)
+        }
+        Err(e) => match cached {
+            Some(cached) => to_result(url, cached, true),
+            None => Err(e),
+        },
+    }
+}
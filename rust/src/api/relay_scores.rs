@@ -0,0 +1,187 @@
+use nostr::event::Event;
+use nostr::types::time::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use super::kv;
+
+/// KV namespace of the per-relay statistics, keyed by relay URL
+const RELAY_SCORES_NAMESPACE: &str = "relay_scores";
+/// Events scoring at least this (see `score_event_spam`) count as spam of the relay
+const SPAM_EVENT_SCORE: u32 = 50;
+/// Weight of the newest sample in the latency moving average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+// Serializes read-modify-write of the statistics
+static SCORES_LOCK: Mutex<()> = Mutex::new(());
+
+/// What suggested relays are used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RelayPurpose {
+    Read,
+    Write,
+    /// Receiving direct messages (NIP-17 DM relays)
+    Dm,
+}
+
+/// Statistics of a remote relay, gathered from the requests made through the client
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteRelayStats {
+    pub url: String,
+    pub reads_ok: u64,
+    pub reads_failed: u64,
+    pub writes_ok: u64,
+    pub writes_failed: u64,
+    /// Gift-wrapped DMs (kind 1059) accepted and refused
+    pub dm_writes_ok: u64,
+    pub dm_writes_failed: u64,
+    pub events_received: u64,
+    /// Received events that looked like spam
+    pub spam_events: u64,
+    /// Moving average of the response time to fetches, None until the relay answered one
+    pub latency_ms: Option<f64>,
+    pub last_success_at: u64,
+    pub last_failure_at: u64,
+}
+
+/// Relay suggested by `suggest_relays`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelaySuggestion {
+    pub url: String,
+    /// 0 (unusable) to 100
+    pub score: u32,
+    pub stats: RemoteRelayStats,
+}
+
+fn update(url: &str, apply: impl FnOnce(&mut RemoteRelayStats)) {
+    let _guard = SCORES_LOCK.lock();
+    let mut stats = load(url).unwrap_or_else(|| RemoteRelayStats {
+        url: url.to_string(),
+        ..Default::default()
+    });
+    apply(&mut stats);
+    
+    // Best effort, the KV store may not be open yet
+    let result = serde_json::to_string(&stats)
+        .map_err(|e| e.to_string())
+        .and_then(|value| kv::kv_set(RELAY_SCORES_NAMESPACE.to_string(), url.to_string(), value));
+    if let Err(e) = result {
+        tracing::debug!("Failed to store statistics of {}: {}", url, e);
+    }
+}
+
+fn load(url: &str) -> Option<RemoteRelayStats> {
+    kv::kv_get(RELAY_SCORES_NAMESPACE.to_string(), url.to_string())
+        .ok()
+        .flatten()
+        .and_then(|value| serde_json::from_str(&value).ok())
+}
+
+/// `latency` is None when the time of this relay alone isn't known
+fn record_outcome(stats: &mut RemoteRelayStats, ok: bool, latency: Option<Duration>) {
+    let now = Timestamp::now().as_u64();
+    if ok {
+        if let Some(latency) = latency {
+            let sample = latency.as_secs_f64() * 1000.0;
+            stats.latency_ms = Some(match stats.latency_ms {
+                Some(average) => average + LATENCY_SMOOTHING * (sample - average),
+                None => sample,
+            });
+        }
+        stats.last_success_at = now;
+    } else {
+        stats.last_failure_at = now;
+    }
+}
+
+/// Record a fetch from a relay, `received` is None if it failed
+pub(crate) fn record_read(url: &str, received: Option<&[Event]>, latency: Duration) {
+    update(url, |stats| {
+        record_outcome(stats, received.is_some(), Some(latency));
+        match received {
+            Some(events) => {
+                stats.reads_ok += 1;
+                stats.events_received += events.len() as u64;
+                stats.spam_events += events.iter()
+                    .filter(|event| {
+                        let tags: Vec<Vec<String>> = event.tags.iter().map(|tag| tag.as_slice().to_vec()).collect();
+                        super::spam::score_content(&event.content, &tags).score >= SPAM_EVENT_SCORE
+                    })
+                    .count() as u64;
+            }
+            None => stats.reads_failed += 1,
+        }
+    });
+}
+
+/// Record whether a relay accepted a published event
+///
+/// Publishing waits for all relays at once, so writes don't contribute latency samples.
+pub(crate) fn record_write(url: &str, ok: bool, dm: bool) {
+    update(url, |stats| {
+        record_outcome(stats, ok, None);
+        match (dm, ok) {
+            (true, true) => stats.dm_writes_ok += 1,
+            (true, false) => stats.dm_writes_failed += 1,
+            (false, true) => stats.writes_ok += 1,
+            (false, false) => stats.writes_failed += 1,
+        }
+    });
+}
+
+/// Success rate with one success and one failure assumed, so few samples stay near 0.5
+fn success_rate(ok: u64, failed: u64) -> f64 {
+    (ok as f64 + 1.0) / ((ok + failed) as f64 + 2.0)
+}
+
+/// Score of a relay for a purpose, None if it was never used for it
+pub(crate) fn relay_score(stats: &RemoteRelayStats, purpose: RelayPurpose) -> Option<u32> {
+    let rate = match purpose {
+        RelayPurpose::Read if stats.reads_ok + stats.reads_failed > 0 => {
+            let spam_ratio = stats.spam_events as f64 / (stats.events_received as f64 + 10.0);
+            success_rate(stats.reads_ok, stats.reads_failed) * (1.0 - spam_ratio)
+        }
+        RelayPurpose::Write if stats.writes_ok + stats.writes_failed > 0 => success_rate(stats.writes_ok, stats.writes_failed),
+        RelayPurpose::Dm if stats.dm_writes_ok + stats.dm_writes_failed > 0 => success_rate(stats.dm_writes_ok, stats.dm_writes_failed),
+        _ => return None,
+    };
+    // A relay answering in a second loses a quarter of its score
+    let speed = stats.latency_ms.map_or(0.5, |latency| 1000.0 / (1000.0 + latency));
+    Some((rate * (0.5 + 0.5 * speed) * 100.0).round() as u32)
+}
+
+/// Statistics of all remote relays used so far
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_relay_stats() -> Result<Vec<RemoteRelayStats>, String> {
+    Ok(kv::kv_list(RELAY_SCORES_NAMESPACE.to_string(), None)?
+        .into_iter()
+        .filter_map(|entry| serde_json::from_str(&entry.value).ok())
+        .collect())
+}
+
+/// Best relays for a purpose, from the success rates, latencies and spam levels observed
+/// when reading from and publishing to them
+///
+/// Only relays already used for the purpose are suggested, so fewer than `count` may be
+/// returned. Statistics persist in the KV store across restarts.
+#[flutter_rust_bridge::frb(sync)]
+pub fn suggest_relays(purpose: RelayPurpose, count: u32) -> Result<Vec<RelaySuggestion>, String> {
+    let mut suggestions: Vec<RelaySuggestion> = get_relay_stats()?
+        .into_iter()
+        .filter_map(|stats| relay_score(&stats, purpose).map(|score| RelaySuggestion {
+            url: stats.url.clone(),
+            score,
+            stats,
+        }))
+        .collect();
+    suggestions.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.url.cmp(&b.url)));
+    suggestions.truncate(count as usize);
+    Ok(suggestions)
+}
+
+/// Forget the statistics of all remote relays
+#[flutter_rust_bridge::frb(sync)]
+pub fn clear_relay_stats() -> Result<(), String> {
+    kv::kv_clear(RELAY_SCORES_NAMESPACE.to_string())
+}
@@ -0,0 +1,160 @@
+use nostr_sdk::prelude::{Event, JsonUtil, Keys};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::client::{connect_client, publish_event};
+use super::kv;
+use super::nostr::sign_event;
+use super::relay::save_event_json;
+
+/// KV namespace holding the scheduled events, keyed by event id
+pub(crate) const SCHEDULED_NAMESPACE: &str = "scheduled";
+/// Failed publish attempts after which a scheduled event is no longer retried
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Event waiting to be published
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    pub event_id: String,
+    /// Signed event JSON
+    pub event_json: String,
+    /// Unix timestamp from which the event is published
+    pub publish_at: u64,
+    pub relays: Vec<String>,
+    /// Failed publish attempts (retried up to 5 times)
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+fn store(scheduled: &ScheduledEvent) -> Result<(), String> {
+    let json = serde_json::to_string(scheduled)
+        .map_err(|e| format!("Failed to serialize scheduled event: {}", e))?;
+    kv::kv_set(SCHEDULED_NAMESPACE.to_string(), scheduled.event_id.clone(), json)
+}
+
+/// Sign an unsigned event (with `created_at` set to the publish time), or check a signed one
+fn signed_event(event_json: &str, publish_at: u64, private_key: Option<String>) -> Result<Event, String> {
+    if let Ok(event) = Event::from_json(event_json) {
+        event.verify()
+            .map_err(|e| format!("Invalid event: {}", e))?;
+        return Ok(event);
+    }
+    
+    let private_key = private_key
+        .ok_or_else(|| "Unsigned events need the private key to sign them".to_string())?;
+    let keys = Keys::from_str(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let mut template: serde_json::Value = serde_json::from_str(event_json)
+        .map_err(|e| format!("Invalid JSON: {}", e))?;
+    let fields = template.as_object_mut()
+        .ok_or_else(|| "Event must be a JSON object".to_string())?;
+    fields.insert("pubkey".to_string(), keys.public_key().to_hex().into());
+    fields.insert("created_at".to_string(), publish_at.into());
+    fields.entry("tags").or_insert_with(|| serde_json::Value::Array(Vec::new()));
+    
+    let signed = sign_event(template.to_string(), private_key)?;
+    Event::from_json(&signed).map_err(|e| format!("Invalid signed event: {}", e))
+}
+
+/// Queue an event for publishing at a later time
+///
+/// The queue is kept in the KV store and processed by the relay's maintenance task (every
+/// minute while the relay runs) and by `run_background_sync`. Nothing is published while the
+/// process isn't running; overdue events go out on the next run.
+///
+/// # Arguments
+/// * `event_json` - Signed event, or an unsigned one (kind, content, tags) to sign now
+/// * `publish_at` - Unix timestamp, also used as `created_at` of unsigned events
+/// * `relays` - Relays to publish to
+/// * `private_key` - Private key signing unsigned events
+///
+/// Returns the event id.
+#[flutter_rust_bridge::frb(sync)]
+pub fn schedule_event(
+    event_json: String,
+    publish_at: u64,
+    relays: Vec<String>,
+    private_key: Option<String>,
+) -> Result<String, String> {
+    if relays.is_empty() {
+        return Err("No relays given".to_string());
+    }
+    let event = signed_event(&event_json, publish_at, private_key)?;
+    
+    let scheduled = ScheduledEvent {
+        event_id: event.id.to_hex(),
+        event_json: event.as_json(),
+        publish_at,
+        relays,
+        attempts: 0,
+        last_error: None,
+    };
+    store(&scheduled)?;
+    Ok(scheduled.event_id)
+}
+
+/// List the scheduled events, soonest first
+#[flutter_rust_bridge::frb(sync)]
+pub fn list_scheduled_events() -> Result<Vec<ScheduledEvent>, String> {
+    let mut scheduled = kv::kv_list(SCHEDULED_NAMESPACE.to_string(), None)?
+        .into_iter()
+        .filter_map(|entry| serde_json::from_str::<ScheduledEvent>(&entry.value).ok())
+        .collect::<Vec<_>>();
+    scheduled.sort_by_key(|scheduled| scheduled.publish_at);
+    Ok(scheduled)
+}
+
+/// Cancel a scheduled event, returns false if it wasn't scheduled (or was already published)
+#[flutter_rust_bridge::frb(sync)]
+pub fn cancel_scheduled_event(event_id: String) -> Result<bool, String> {
+    kv::kv_delete(SCHEDULED_NAMESPACE.to_string(), event_id)
+}
+
+/// Publish the scheduled events that are due, returns how many were published
+pub(crate) async fn publish_due(now: u64) -> Result<u32, String> {
+    let due: Vec<ScheduledEvent> = list_scheduled_events()?
+        .into_iter()
+        .filter(|scheduled| scheduled.publish_at <= now && scheduled.attempts < MAX_ATTEMPTS)
+        .collect();
+    
+    let mut published = 0;
+    for mut scheduled in due {
+        let event = match Event::from_json(&scheduled.event_json) {
+            Ok(event) => event,
+            Err(e) => {
+                tracing::warn!("Dropping invalid scheduled event {}: {}", scheduled.event_id, e);
+                cancel_scheduled_event(scheduled.event_id)?;
+                continue;
+            }
+        };
+        
+        let outcome = match connect_client(&scheduled.relays, None).await {
+            Ok(client) => {
+                let outcome = publish_event(&client, &event).await;
+                client.disconnect().await;
+                outcome
+            }
+            Err(e) => {
+                scheduled.attempts += 1;
+                scheduled.last_error = Some(e);
+                store(&scheduled)?;
+                continue;
+            }
+        };
+        
+        if outcome.accepted_relays.is_empty() {
+            scheduled.attempts += 1;
+            scheduled.last_error = Some(outcome.failed_relays.join("; "));
+            store(&scheduled)?;
+            continue;
+        }
+        
+        // Keep a copy locally, like events published by the app itself
+        if let Err(e) = save_event_json(&scheduled.event_json).await {
+            tracing::debug!("Scheduled event {} not saved locally: {}", scheduled.event_id, e);
+        }
+        cancel_scheduled_event(scheduled.event_id)?;
+        published += 1;
+    }
+    
+    Ok(published)
+}
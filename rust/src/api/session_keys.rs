@@ -0,0 +1,185 @@
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::nip44;
+use nostr::types::time::Timestamp;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+use super::nostr::sign_event_with_keys;
+
+/// Default lifetime of a session key without use, in seconds
+const DEFAULT_SESSION_KEY_TTL_SECS: u64 = 3600;
+
+static SESSION_KEY_TTL_SECS: AtomicU64 = AtomicU64::new(DEFAULT_SESSION_KEY_TTL_SECS);
+// Session key id -> key, expired keys are dropped on every access
+static SESSION_KEYS: Mutex<Option<HashMap<String, SessionKey>>> = Mutex::new(None);
+
+struct SessionKey {
+    secret: Zeroizing<[u8; 32]>,
+    public_key: PublicKey,
+    ttl: Duration,
+    expires_at: Instant,
+    /// Held by a live remote signer session: only destroyed explicitly, never expires
+    pinned: bool,
+}
+
+impl SessionKey {
+    fn keys(&self) -> Result<Keys, String> {
+        let secret_key = SecretKey::from_slice(&self.secret[..])
+            .map_err(|e| format!("Invalid session key: {}", e))?;
+        Ok(Keys::new(secret_key))
+    }
+    
+    fn info(&self, id: &str) -> SessionKeyInfo {
+        let remaining = self.expires_at.saturating_duration_since(Instant::now());
+        SessionKeyInfo {
+            id: id.to_string(),
+            public_key: self.public_key.to_hex(),
+            expires_at: if self.pinned { 0 } else { Timestamp::now().as_u64() + remaining.as_secs() },
+        }
+    }
+}
+
+/// Ephemeral key kept in Rust memory, see `session_key_generate`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionKeyInfo {
+    pub id: String,
+    /// Hex public key
+    pub public_key: String,
+    /// Unix timestamp (seconds) at which the key is destroyed unless used before, 0 for keys
+    /// of a remote signer session (destroyed with the session)
+    pub expires_at: u64,
+}
+
+/// Run `f` on the session keys, after dropping the expired ones (zeroizing them)
+fn with_session_keys<T>(f: impl FnOnce(&mut HashMap<String, SessionKey>) -> T) -> Result<T, String> {
+    let mut guard = SESSION_KEYS.lock()
+        .map_err(|e| format!("Failed to lock session keys: {}", e))?;
+    let keys = guard.get_or_insert_with(HashMap::new);
+    let now = Instant::now();
+    keys.retain(|_, key| key.pinned || key.expires_at > now);
+    Ok(f(keys))
+}
+
+fn default_ttl() -> Duration {
+    Duration::from_secs(SESSION_KEY_TTL_SECS.load(Ordering::Relaxed))
+}
+
+fn insert(ttl: Duration, pinned: bool) -> Result<(String, Keys), String> {
+    let keys = Keys::generate();
+    let id = SecretKey::generate().to_secret_hex()[..16].to_string();
+    let key = SessionKey {
+        secret: Zeroizing::new(keys.secret_key().to_secret_bytes()),
+        public_key: keys.public_key(),
+        ttl,
+        expires_at: Instant::now() + ttl,
+        pinned,
+    };
+    with_session_keys(|session_keys| session_keys.insert(id.clone(), key))?;
+    Ok((id, keys))
+}
+
+/// Generate a session key, returns its id and the keys for immediate use
+pub(crate) fn create(ttl: Option<Duration>) -> Result<(String, Keys), String> {
+    insert(ttl.unwrap_or_else(default_ttl), false)
+}
+
+/// Generate a session key that doesn't expire, for a remote signer session that destroys it
+/// when it closes
+pub(crate) fn create_pinned() -> Result<(String, Keys), String> {
+    insert(default_ttl(), true)
+}
+
+/// Keys of a session key, extending its lifetime by its TTL
+pub(crate) fn session_keys(id: &str) -> Result<Keys, String> {
+    with_session_keys(|session_keys| {
+        let key = session_keys.get_mut(id)
+            .ok_or_else(|| format!("Session key '{}' expired or destroyed", id))?;
+        key.expires_at = Instant::now() + key.ttl;
+        key.keys()
+    })?
+}
+
+/// Destroy a session key, returns false if it didn't exist (or already expired)
+pub(crate) fn destroy(id: &str) -> Result<bool, String> {
+    with_session_keys(|session_keys| session_keys.remove(id).is_some())
+}
+
+/// Keys for a single operation (e.g. signing one gift wrap), never stored
+pub(crate) fn one_time_keys() -> Keys {
+    Keys::generate()
+}
+
+/// Set the default lifetime of new session keys (each use extends a key by its lifetime)
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_session_key_ttl(ttl_secs: u64) -> Result<(), String> {
+    if ttl_secs == 0 {
+        return Err("TTL must be at least one second".to_string());
+    }
+    SESSION_KEY_TTL_SECS.store(ttl_secs, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Generate an ephemeral key kept only in Rust memory
+///
+/// The private key is never returned; use it by id with the `session_key_*` functions.
+/// It is zeroized when destroyed or when unused for `ttl_secs` (default set by
+/// `set_session_key_ttl`, one hour). Remote signer sessions opened with
+/// `remote_signer_connect_ephemeral` hold a session key until they are disconnected.
+#[flutter_rust_bridge::frb(sync)]
+pub fn session_key_generate(ttl_secs: Option<u64>) -> Result<SessionKeyInfo, String> {
+    if ttl_secs == Some(0) {
+        return Err("TTL must be at least one second".to_string());
+    }
+    let (id, _) = create(ttl_secs.map(Duration::from_secs))?;
+    with_session_keys(|session_keys| session_keys.get(&id).map(|key| key.info(&id)))?
+        .ok_or_else(|| format!("Session key '{}' expired or destroyed", id))
+}
+
+/// Session keys alive, e.g. to check which ones a flow still holds
+#[flutter_rust_bridge::frb(sync)]
+pub fn list_session_keys() -> Result<Vec<SessionKeyInfo>, String> {
+    with_session_keys(|session_keys| session_keys.iter().map(|(id, key)| key.info(id)).collect())
+}
+
+/// Sign an unsigned event (JSON) with a session key, like `sign_event`
+#[flutter_rust_bridge::frb(sync)]
+pub fn session_key_sign_event(id: String, event_json: String) -> Result<String, String> {
+    sign_event_with_keys(&event_json, &session_keys(&id)?)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn session_key_nip44_encrypt(id: String, plaintext: String, public_key: String) -> Result<String, String> {
+    let public_key = PublicKey::from_str(&public_key)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    nip44::encrypt(session_keys(&id)?.secret_key(), &public_key, plaintext, nip44::Version::V2)
+        .map_err(|e| format!("NIP-44 encryption failed: {}", e))
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn session_key_nip44_decrypt(id: String, ciphertext: String, public_key: String) -> Result<String, String> {
+    let public_key = PublicKey::from_str(&public_key)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    nip44::decrypt(session_keys(&id)?.secret_key(), &public_key, ciphertext)
+        .map_err(|e| format!("NIP-44 decryption failed: {}", e))
+}
+
+/// Destroy a session key now, returns false if it didn't exist (or already expired)
+#[flutter_rust_bridge::frb(sync)]
+pub fn session_key_destroy(id: String) -> Result<bool, String> {
+    destroy(&id)
+}
+
+/// Destroy all session keys (including those of remote signer sessions), returns how many
+/// were alive
+#[flutter_rust_bridge::frb(sync)]
+pub fn session_keys_destroy_all() -> Result<u32, String> {
+    with_session_keys(|session_keys| {
+        let count = session_keys.len() as u32;
+        session_keys.clear();
+        count
+    })
+}
@@ -0,0 +1,174 @@
+use flutter_rust_bridge::DartFnFuture;
+use nostr::event::{Event, EventBuilder, UnsignedEvent};
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::{nip04, nip44};
+use nostr::secp256k1::schnorr::Signature;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use super::audit::{self, KeyOperation};
+use super::nostr::with_fixed_created_at;
+
+type SignFuture<'a> = Pin<Box<dyn Future<Output = Result<Event, String>> + Send + 'a>>;
+type EncryptFuture<'a> = Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
+
+/// Something that can sign events for one public key
+///
+/// Implemented by in-memory keys and by signers delegating to Dart (platform keystores).
+pub(crate) trait EventSigner: Send + Sync {
+    fn public_key(&self) -> PublicKey;
+    
+    fn sign(&self, unsigned: UnsignedEvent) -> SignFuture<'_>;
+    
+    /// Encrypt for `receiver` with NIP-44 (v2), or NIP-04 when `nip44` is false
+    fn encrypt(&self, receiver: PublicKey, plaintext: String, nip44: bool) -> EncryptFuture<'_>;
+}
+
+impl EventSigner for Keys {
+    fn public_key(&self) -> PublicKey {
+        Keys::public_key(self)
+    }
+    
+    fn sign(&self, unsigned: UnsignedEvent) -> SignFuture<'_> {
+        Box::pin(async move {
+            unsigned.sign_with_keys(self)
+                .map_err(|e| format!("Failed to sign event: {}", e))
+        })
+    }
+    
+    fn encrypt(&self, receiver: PublicKey, plaintext: String, nip44: bool) -> EncryptFuture<'_> {
+        Box::pin(async move {
+            if nip44 {
+                nip44::encrypt(self.secret_key(), &receiver, plaintext, nip44::Version::V2)
+                    .map_err(|e| format!("NIP-44 encryption failed: {}", e))
+            } else {
+                nip04::encrypt(self.secret_key(), &receiver, plaintext)
+                    .map_err(|e| format!("Encryption failed: {}", e))
+            }
+        })
+    }
+}
+
+type EncryptCallback = Box<dyn Fn(String, String, bool) -> DartFnFuture<String> + Send + Sync>;
+
+/// Signer whose private key never leaves the platform: Dart is given the event id (hex)
+/// and returns the BIP-340 signature (hex)
+struct CallbackSigner {
+    public_key: PublicKey,
+    sign_id: Box<dyn Fn(String) -> DartFnFuture<String> + Send + Sync>,
+    /// Given the receiver (hex), the plaintext and whether to use NIP-44 (else NIP-04),
+    /// returns the ciphertext; None when the platform key can only sign
+    encrypt: Option<EncryptCallback>,
+}
+
+impl EventSigner for CallbackSigner {
+    fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+    
+    fn sign(&self, mut unsigned: UnsignedEvent) -> SignFuture<'_> {
+        Box::pin(async move {
+            let id = unsigned.id();
+            let signature = (self.sign_id)(id.to_hex()).await;
+            let signature = Signature::from_str(&signature)
+                .map_err(|e| format!("Invalid signature from signer: {}", e))?;
+            // Checks the signature against the id and our public key
+            unsigned.add_signature(signature)
+                .map_err(|e| format!("Signer returned a wrong signature: {}", e))
+        })
+    }
+    
+    fn encrypt(&self, receiver: PublicKey, plaintext: String, nip44: bool) -> EncryptFuture<'_> {
+        Box::pin(async move {
+            let encrypt = self.encrypt.as_ref()
+                .ok_or_else(|| "Signer can't encrypt, create it with Signer::from_callbacks".to_string())?;
+            Ok(encrypt(receiver.to_hex(), plaintext, nip44).await)
+        })
+    }
+}
+
+/// Signer accepted by the `*_with_signer` event-creation APIs
+///
+/// Either in-memory keys (`Signer::from_private_key`) or Dart callbacks wrapping the
+/// platform keystore (`Signer::from_callback`, or `Signer::from_callbacks` for the APIs that
+/// also encrypt).
+#[flutter_rust_bridge::frb(opaque)]
+pub struct Signer {
+    inner: Arc<dyn EventSigner>,
+}
+
+impl Signer {
+    /// Signer backed by a private key (hex or nsec) held in memory
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn from_private_key(private_key: String) -> Result<Signer, String> {
+        let secret_key = SecretKey::parse(&private_key)
+            .map_err(|e| format!("Invalid private key: {}", e))?;
+        Ok(Signer { inner: Arc::new(Keys::new(secret_key)) })
+    }
+    
+    /// Signer delegating to Dart, e.g. to the iOS Secure Enclave or an Android Keystore wrapper
+    ///
+    /// # Arguments
+    /// * `public_key` - Hex public key of the key held by the platform
+    /// * `sign_id` - Given an event id (hex), returns its BIP-340 signature (hex)
+    pub fn from_callback(
+        public_key: String,
+        sign_id: impl Fn(String) -> DartFnFuture<String> + Send + Sync + 'static,
+    ) -> Result<Signer, String> {
+        let public_key = PublicKey::from_str(&public_key)
+            .map_err(|e| format!("Invalid public key: {}", e))?;
+        Ok(Signer { inner: Arc::new(CallbackSigner { public_key, sign_id: Box::new(sign_id), encrypt: None }) })
+    }
+    
+    /// `from_callback` for a platform key that can also encrypt, needed by the APIs sending
+    /// encrypted or gift-wrapped messages
+    ///
+    /// # Arguments
+    /// * `public_key` - Hex public key of the key held by the platform
+    /// * `sign_id` - Given an event id (hex), returns its BIP-340 signature (hex)
+    /// * `encrypt` - Given the receiver's public key (hex), the plaintext and whether to use
+    ///   NIP-44 v2 (else NIP-04), returns the ciphertext
+    pub fn from_callbacks(
+        public_key: String,
+        sign_id: impl Fn(String) -> DartFnFuture<String> + Send + Sync + 'static,
+        encrypt: impl Fn(String, String, bool) -> DartFnFuture<String> + Send + Sync + 'static,
+    ) -> Result<Signer, String> {
+        let public_key = PublicKey::from_str(&public_key)
+            .map_err(|e| format!("Invalid public key: {}", e))?;
+        Ok(Signer {
+            inner: Arc::new(CallbackSigner {
+                public_key,
+                sign_id: Box::new(sign_id),
+                encrypt: Some(Box::new(encrypt)),
+            }),
+        })
+    }
+    
+    /// Hex public key of the signer
+    #[flutter_rust_bridge::frb(sync)]
+    pub fn public_key(&self) -> String {
+        self.inner.public_key().to_hex()
+    }
+    
+    pub(crate) fn nostr_public_key(&self) -> PublicKey {
+        self.inner.public_key()
+    }
+    
+    /// Build the event for the signer's public key and sign it
+    pub(crate) async fn sign_builder(&self, builder: EventBuilder) -> Result<Event, String> {
+        self.sign_unsigned(with_fixed_created_at(builder).build(self.inner.public_key())).await
+    }
+    
+    /// Sign an event of the signer's public key, keeping its created_at
+    pub(crate) async fn sign_unsigned(&self, unsigned: UnsignedEvent) -> Result<Event, String> {
+        let event = self.inner.sign(unsigned).await?;
+        audit::record(KeyOperation::SignEvent, &event.pubkey.to_hex(), Some(event.kind.as_u16()));
+        Ok(event)
+    }
+    
+    /// Encrypt for `receiver` with NIP-44 (v2), or NIP-04 when `nip44` is false
+    pub(crate) async fn encrypt(&self, receiver: &PublicKey, plaintext: String, nip44: bool) -> Result<String, String> {
+        self.inner.encrypt(*receiver, plaintext, nip44).await
+    }
+}
@@ -0,0 +1,308 @@
+use nostr_sdk::prelude::*;
+use nostr::nips::{nip04, nip44};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::RecvError;
+use crate::frb_generated::StreamSink;
+use super::audit::{self, KeyOperation};
+use super::bunker::{decrypt_nip46_content, nip46_event, percent_encode, request_id, Nip46Request, Nip46Response, NOSTR_CONNECT_KIND};
+use super::client::connect_client;
+use super::gate::tokens_match;
+use super::nostr::sign_event_with_keys;
+use super::relay::{get_or_create_runtime, run_blocking, run_blocking_with_timeout};
+
+/// Most requests waiting for the user at once, further ones are refused
+const MAX_PENDING_PROMPTS: usize = 32;
+/// Most prompts one client can cause per `PROMPT_RATE_WINDOW`
+const MAX_PROMPTS_PER_CLIENT: u32 = 10;
+const PROMPT_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+static SERVICE: Mutex<Option<SignerService>> = Mutex::new(None);
+
+struct SignerService {
+    client: Client,
+    keys: Keys,
+    relays: Vec<String>,
+    /// Secret of the bunker URI, a client presenting it is connected without a prompt. Single
+    /// use, a new one is drawn when a client connects with it
+    secret: String,
+    /// Hex public keys of the connected clients
+    clients: HashSet<String>,
+    /// Request id -> (client, request) waiting for the user
+    pending: HashMap<String, (PublicKey, Nip46Request)>,
+    /// Client -> (start of the rate window, prompts in it)
+    prompt_counts: HashMap<PublicKey, (Instant, u32)>,
+    sink: StreamSink<SignerPrompt>,
+}
+
+/// Request of a NIP-46 client waiting for the user's approval
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignerPrompt {
+    /// Id to pass to `signer_service_respond`
+    pub request_id: String,
+    /// Hex public key of the client app
+    pub client_pubkey: String,
+    /// NIP-46 method ("connect", "sign_event", "nip44_decrypt", ...)
+    pub method: String,
+    /// Kind of the event to sign, for "sign_event"
+    pub event_kind: Option<u16>,
+    /// Event to sign, plaintext to encrypt or peer public key, depending on the method
+    pub params: Vec<String>,
+}
+
+fn bunker_uri(pubkey: &PublicKey, relays: &[String], secret: &str) -> String {
+    let relay_params: Vec<String> = relays.iter().map(|relay| format!("relay={}", percent_encode(relay))).collect();
+    format!("bunker://{}?{}&secret={}", pubkey.to_hex(), relay_params.join("&"), percent_encode(secret))
+}
+
+fn prompt_id(client: &PublicKey, request_id: &str) -> String {
+    format!("{}:{}", client.to_hex(), request_id)
+}
+
+/// Perform an approved request with our keys
+fn perform(keys: &Keys, client: &PublicKey, request: &Nip46Request) -> Result<String, String> {
+    let peer = |index: usize| -> Result<PublicKey, String> {
+        request.params.get(index)
+            .ok_or_else(|| "Missing public key".to_string())
+            .and_then(|pubkey| PublicKey::parse(pubkey).map_err(|e| format!("Invalid public key: {}", e)))
+    };
+    let text = |index: usize| -> Result<&String, String> {
+        request.params.get(index).ok_or_else(|| "Missing parameter".to_string())
+    };
+    let me = keys.public_key().to_hex();
+    
+    match request.method.as_str() {
+        "connect" => Ok("ack".to_string()),
+        "ping" => Ok("pong".to_string()),
+        "get_public_key" => Ok(me),
+        "sign_event" => {
+            let mut unsigned: serde_json::Value = serde_json::from_str(text(0)?)
+                .map_err(|e| format!("Invalid event JSON: {}", e))?;
+            if let Some(object) = unsigned.as_object_mut() {
+                object.insert("pubkey".to_string(), serde_json::Value::String(me));
+            }
+            sign_event_with_keys(&unsigned.to_string(), keys)
+        }
+        "nip04_encrypt" => nip04::encrypt(keys.secret_key(), &peer(0)?, text(1)?)
+            .map_err(|e| format!("NIP-04 encryption failed: {}", e)),
+        "nip04_decrypt" => {
+            let plaintext = nip04::decrypt(keys.secret_key(), &peer(0)?, text(1)?)
+                .map_err(|e| format!("NIP-04 decryption failed: {}", e))?;
+            audit::record(KeyOperation::Nip04Decrypt, &me, None);
+            Ok(plaintext)
+        }
+        "nip44_encrypt" => nip44::encrypt(keys.secret_key(), &peer(0)?, text(1)?, nip44::Version::V2)
+            .map_err(|e| format!("NIP-44 encryption failed: {}", e)),
+        "nip44_decrypt" => {
+            let plaintext = nip44::decrypt(keys.secret_key(), &peer(0)?, text(1)?)
+                .map_err(|e| format!("NIP-44 decryption failed: {}", e))?;
+            audit::record(KeyOperation::Nip44Decrypt, &me, None);
+            Ok(plaintext)
+        }
+        method => Err(format!("Unsupported method '{}' from {}", method, client.to_hex())),
+    }
+}
+
+/// Build the response event to a request
+fn response_event(keys: &Keys, client: &PublicKey, id: &str, outcome: Result<String, String>) -> Result<Event, String> {
+    let (result, error) = match outcome {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    let response = serde_json::to_string(&Nip46Response { id: id.to_string(), result, error })
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+    nip46_event(keys, client, response)
+}
+
+/// Count a prompt of `client`, false if it exceeded its rate
+fn allow_prompt(prompt_counts: &mut HashMap<PublicKey, (Instant, u32)>, client: &PublicKey) -> bool {
+    let now = Instant::now();
+    prompt_counts.retain(|_, (started, _)| now.duration_since(*started) < PROMPT_RATE_WINDOW);
+    let (_, count) = prompt_counts.entry(*client).or_insert((now, 0));
+    *count += 1;
+    *count <= MAX_PROMPTS_PER_CLIENT
+}
+
+/// Handle a request event: answer right away, or prompt the user
+fn on_request(event: &Event) -> Option<Event> {
+    let mut service = SERVICE.lock().ok()?;
+    let service = service.as_mut()?;
+    let request: Nip46Request = decrypt_nip46_content(&service.keys, event)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())?;
+    let client = event.pubkey;
+    let connected = service.clients.contains(&client.to_hex());
+    
+    let answer = match request.method.as_str() {
+        "connect" if connected => Some(Ok("ack".to_string())),
+        // Knowing the secret of the bunker URI proves the user handed it to this client
+        "connect" if request.params.get(1).is_some_and(|secret| tokens_match(secret, &service.secret)) => {
+            service.clients.insert(client.to_hex());
+            service.secret = request_id();
+            Some(Ok("ack".to_string()))
+        }
+        // Anyone could send these, they are not worth a prompt
+        "connect" => Some(Err("Invalid secret".to_string())),
+        "ping" => Some(Ok("pong".to_string())),
+        _ if !connected => Some(Err("Not connected".to_string())),
+        "get_public_key" => Some(Ok(service.keys.public_key().to_hex())),
+        _ if service.pending.len() >= MAX_PENDING_PROMPTS => Some(Err("Too many pending requests".to_string())),
+        _ if !allow_prompt(&mut service.prompt_counts, &client) => Some(Err("Too many requests".to_string())),
+        _ => None,
+    };
+    if let Some(answer) = answer {
+        return response_event(&service.keys, &client, &request.id, answer).ok();
+    }
+    
+    let event_kind = (request.method == "sign_event")
+        .then(|| request.params.first())
+        .flatten()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(json).ok())
+        .and_then(|unsigned| unsigned["kind"].as_u64())
+        .map(|kind| kind as u16);
+    let request_id = prompt_id(&client, &request.id);
+    let prompt = SignerPrompt {
+        request_id: request_id.clone(),
+        client_pubkey: client.to_hex(),
+        method: request.method.clone(),
+        event_kind,
+        params: request.params.clone(),
+    };
+    service.pending.insert(request_id, (client, request));
+    let _ = service.sink.add(prompt);
+    None
+}
+
+/// Act as a remote signer (NIP-46) for other apps, like Amber or nsecbunker
+///
+/// Listens for requests sent to the key on the given relays after the start. "connect"
+/// requests are accepted only with the secret of the returned bunker URI, which connects a
+/// single client (see `signer_service_bunker_uri` for the next one), "ping" and
+/// "get_public_key" are answered right away; other requests of connected clients are
+/// streamed to `sink` and wait for `signer_service_respond` (at most 32 at once, and 10 per
+/// client and minute).
+///
+/// # Arguments
+/// * `private_key` - Private key (hex or nsec) to sign with
+/// * `relays` - Relays the clients send their requests to
+/// * `sink` - Requests waiting for approval
+///
+/// Returns the `bunker://` URI to hand to client apps.
+pub fn signer_service_start(private_key: String, relays: Vec<String>, sink: StreamSink<SignerPrompt>) -> Result<String, String> {
+    let keys = Keys::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    if relays.is_empty() {
+        return Err("No relay given".to_string());
+    }
+    let mut service_guard = SERVICE.lock()
+        .map_err(|e| format!("Failed to lock signer service: {}", e))?;
+    if service_guard.is_some() {
+        return Err("Signer service is already running".to_string());
+    }
+    
+    let connect_relays = relays.clone();
+    let pubkey = keys.public_key();
+    let client = run_blocking(async move {
+        let client = connect_client(&connect_relays, None).await?;
+        // Requests sent while the service wasn't running are stale
+        let filter = Filter::new()
+            .kind(Kind::from(NOSTR_CONNECT_KIND))
+            .pubkey(pubkey)
+            .since(Timestamp::now());
+        client.subscribe(filter, None)
+            .await
+            .map_err(|e| format!("Failed to subscribe: {}", e))?;
+        Ok::<Client, String>(client)
+    })??;
+    
+    let secret = request_id();
+    let uri = bunker_uri(&pubkey, &relays, &secret);
+    
+    let mut notifications = client.notifications();
+    let loop_client = client.clone();
+    get_or_create_runtime()?.spawn(async move {
+        loop {
+            match notifications.recv().await {
+                Ok(RelayPoolNotification::Event { event, .. }) if event.kind == Kind::from(NOSTR_CONNECT_KIND) => {
+                    if let Some(response) = on_request(&event) {
+                        let _ = loop_client.send_event(&response).await;
+                    }
+                }
+                Ok(RelayPoolNotification::Shutdown) | Err(RecvError::Closed) => break,
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+            }
+        }
+    });
+    
+    *service_guard = Some(SignerService {
+        client,
+        keys,
+        relays,
+        secret,
+        clients: HashSet::new(),
+        pending: HashMap::new(),
+        prompt_counts: HashMap::new(),
+        sink,
+    });
+    Ok(uri)
+}
+
+/// `bunker://` URI of the running signer service, with the secret for the next client
+///
+/// Each secret connects one client: once used, this returns a URI with a new one.
+#[flutter_rust_bridge::frb(sync)]
+pub fn signer_service_bunker_uri() -> Result<String, String> {
+    let service = SERVICE.lock()
+        .map_err(|e| format!("Failed to lock signer service: {}", e))?;
+    let service = service.as_ref()
+        .ok_or_else(|| "Signer service is not running".to_string())?;
+    Ok(bunker_uri(&service.keys.public_key(), &service.relays, &service.secret))
+}
+
+/// Approve or reject a request streamed by `signer_service_start`
+#[flutter_rust_bridge::frb(sync)]
+pub fn signer_service_respond(request_id: String, approve: bool) -> Result<(), String> {
+    let (client, response) = {
+        let mut service = SERVICE.lock()
+            .map_err(|e| format!("Failed to lock signer service: {}", e))?;
+        let service = service.as_mut()
+            .ok_or_else(|| "Signer service is not running".to_string())?;
+        let (client, request) = service.pending.remove(&request_id)
+            .ok_or_else(|| format!("No pending request '{}'", request_id))?;
+        
+        let outcome = if approve {
+            perform(&service.keys, &client, &request)
+        } else {
+            Err("Rejected by the user".to_string())
+        };
+        (service.client.clone(), response_event(&service.keys, &client, &request.id, outcome)?)
+    };
+    
+    run_blocking(async move { client.send_event(&response).await })?
+        .map(|_| ())
+        .map_err(|e| format!("Failed to send response: {}", e))
+}
+
+/// Stop the signer service, pending requests are dropped
+#[flutter_rust_bridge::frb(sync)]
+pub fn signer_service_stop() -> Result<(), String> {
+    let service = SERVICE.lock()
+        .map_err(|e| format!("Failed to lock signer service: {}", e))?
+        .take()
+        .ok_or_else(|| "Signer service is not running".to_string())?;
+    run_blocking_with_timeout(Duration::from_secs(10), async move { service.client.shutdown().await })
+}
+
+/// Stop the signer service if it signs for `public_key` (hex), returns whether it was running
+pub(crate) fn stop_for_key(public_key: &str) -> Result<bool, String> {
+    let running_for_key = SERVICE.lock()
+        .map_err(|e| format!("Failed to lock signer service: {}", e))?
+        .as_ref()
+        .is_some_and(|service| service.keys.public_key().to_hex() == public_key);
+    if running_for_key {
+        signer_service_stop()?;
+    }
+    Ok(running_for_key)
+}
@@ -0,0 +1,169 @@
+use nostr::event::Event;
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::types::time::Timestamp;
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use super::content::{segment_content, ContentSegmentKind};
+
+// Hash of recently seen content -> events carrying it
+static RECENT_CONTENT: Mutex<Option<HashMap<[u8; 32], RecentContent>>> = Mutex::new(None);
+
+/// Content shorter than this isn't checked for duplicates ("gm", "+", ...)
+const MIN_DUPLICATE_LEN: usize = 20;
+/// How long content is remembered for duplicate detection
+const DUPLICATE_WINDOW_SECS: u64 = 3600;
+const MAX_TRACKED_CONTENT: usize = 10_000;
+/// Event ids remembered per content, more repeats don't raise the score
+const MAX_TRACKED_EVENTS: usize = 16;
+
+/// Events seen with the same content within the window
+struct RecentContent {
+    event_ids: HashSet<[u8; 32]>,
+    last_seen: u64,
+}
+
+/// Phrases common in spam (matched case-insensitively)
+const SPAM_PATTERNS: [&str; 10] = [
+    "airdrop",
+    "free btc",
+    "free bitcoin",
+    "claim your",
+    "double your",
+    "guaranteed profit",
+    "investment opportunity",
+    "t.me/",
+    "wa.me/",
+    "dm me on telegram",
+];
+
+/// Spam score of an event
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpamScore {
+    /// 0 (no signal) to 100 (certainly spam)
+    pub score: u32,
+    /// Heuristics that contributed to the score
+    pub reasons: Vec<String>,
+}
+
+/// How many other events carried the same content within the window, recording this one
+/// when `record` is set
+///
+/// Events are counted by id: the same event received from several relays isn't a repeat.
+fn times_seen_before(content: &str, event_id: &[u8; 32], now: u64, record: bool) -> u32 {
+    let hash = Sha256Hash::hash(content.trim().to_lowercase().as_bytes()).to_byte_array();
+    let mut recent = match RECENT_CONTENT.lock() {
+        Ok(recent) => recent,
+        Err(_) => return 0,
+    };
+    let recent = recent.get_or_insert_with(HashMap::new);
+    let fresh = |entry: &RecentContent| now.saturating_sub(entry.last_seen) < DUPLICATE_WINDOW_SECS;
+    
+    if !record {
+        return recent.get(&hash)
+            .filter(|entry| fresh(entry))
+            .map_or(0, |entry| entry.event_ids.iter().filter(|id| *id != event_id).count() as u32);
+    }
+    
+    if recent.len() >= MAX_TRACKED_CONTENT {
+        recent.retain(|_, entry| fresh(entry));
+        if recent.len() >= MAX_TRACKED_CONTENT {
+            recent.clear();
+        }
+    }
+    
+    let entry = recent.entry(hash).or_insert_with(|| RecentContent { event_ids: HashSet::new(), last_seen: now });
+    if !fresh(entry) {
+        entry.event_ids.clear();
+    }
+    let seen = entry.event_ids.iter().filter(|id| *id != event_id).count() as u32;
+    if entry.event_ids.len() < MAX_TRACKED_EVENTS {
+        entry.event_ids.insert(*event_id);
+    }
+    entry.last_seen = now;
+    seen
+}
+
+/// Score an event with cheap spam heuristics, recording its content for duplicate detection
+pub(crate) fn score(event_id: &[u8; 32], content: &str, tags: &[Vec<String>]) -> SpamScore {
+    score_with(event_id, content, tags, true)
+}
+
+fn score_with(event_id: &[u8; 32], content: &str, tags: &[Vec<String>], record: bool) -> SpamScore {
+    let mut score = 0u32;
+    let mut reasons = Vec::new();
+    
+    if content.trim().chars().count() >= MIN_DUPLICATE_LEN {
+        let seen = times_seen_before(content, event_id, Timestamp::now().as_u64(), record);
+        if seen > 0 {
+            score += (seen * 10).min(40);
+            reasons.push(format!("duplicate content (seen {} times)", seen));
+        }
+    }
+    
+    let content_score = score_content(content, tags);
+    reasons.extend(content_score.reasons);
+    SpamScore {
+        score: (score + content_score.score).min(100),
+        reasons,
+    }
+}
+
+/// Heuristics of `score` that only look at the event itself, without recording its content
+/// for duplicate detection (for events seen several times, e.g. from many relays)
+pub(crate) fn score_content(content: &str, tags: &[Vec<String>]) -> SpamScore {
+    let mut score = 0u32;
+    let mut reasons = Vec::new();
+    
+    let segments = segment_content(content.to_string());
+    let has_url = segments.iter().any(|s| s.kind == ContentSegmentKind::Url);
+    let only_urls = segments.iter().all(|s| s.kind == ContentSegmentKind::Url || s.text.trim().is_empty());
+    if has_url && only_urls {
+        score += 25;
+        reasons.push("link-only content".to_string());
+    }
+    
+    let lower = content.to_lowercase();
+    let patterns: Vec<&str> = SPAM_PATTERNS.iter().copied().filter(|p| lower.contains(p)).collect();
+    if !patterns.is_empty() {
+        score += (patterns.len() as u32 * 20).min(40);
+        reasons.push(format!("spam phrases: {}", patterns.join(", ")));
+    }
+    
+    let count = |name: &str| tags.iter().filter(|tag| tag.first().map(String::as_str) == Some(name)).count();
+    if tags.len() > 100 {
+        score += 30;
+        reasons.push(format!("{} tags", tags.len()));
+    }
+    let mentions = count("p");
+    if mentions > 30 {
+        score += 25;
+        reasons.push(format!("mass mention ({} pubkeys)", mentions));
+    }
+    let hashtags = count("t");
+    if hashtags > 15 {
+        score += 15;
+        reasons.push(format!("{} hashtags", hashtags));
+    }
+    
+    SpamScore {
+        score: score.min(100),
+        reasons,
+    }
+}
+
+/// Score an event with cheap spam heuristics
+///
+/// Checks for content repeated across events recently received by the relay, link-only notes,
+/// excessive tags and mentions, and common spam phrases. Scoring doesn't record the event, so
+/// scoring it again gives the same result. Meant to be combined with web-of-trust signals; the
+/// relay can reject events above a threshold (`spam_reject_threshold` in the relay settings).
+#[flutter_rust_bridge::frb(sync)]
+pub fn score_event_spam(event_json: String) -> Result<SpamScore, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    let tags: Vec<Vec<String>> = event.tags.iter().map(|tag| tag.as_slice().to_vec()).collect();
+    Ok(score_with(event.id.as_bytes(), &event.content, &tags, false))
+}
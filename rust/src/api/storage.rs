@@ -0,0 +1,372 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Auxiliary database (sled) stored next to the NDB event database, with its path
+static AUX_STORE: Mutex<Option<(PathBuf, sled::Db)>> = Mutex::new(None);
+
+/// Event id -> received_at (big-endian u64 seconds)
+const RECEIVED_AT_TREE: &str = "received_at";
+/// received_at (big-endian u64) + event id -> empty, for range scans
+const RECEIVED_INDEX_TREE: &str = "received_index";
+/// "<pubkey>:<kind>:<d tag>" + 0 + created_at (big-endian u64) + event id -> event JSON
+const EVENT_HISTORY_TREE: &str = "event_history";
+/// Deleted event id -> deleted_at (big-endian u64 seconds)
+const TOMBSTONE_TREE: &str = "tombstones";
+/// Event id -> event JSON with truncated content (see `OversizedContentAction::Truncate`)
+const TRUNCATED_TREE: &str = "truncated_events";
+/// Store metadata (storage version)
+const META_TREE: &str = "meta";
+const STORAGE_VERSION_KEY: &str = "storage_version";
+
+/// Layout version of the auxiliary store written by this build
+///
+/// Bump it and append to `MIGRATIONS` whenever stored data changes shape.
+pub(crate) const STORAGE_VERSION: u32 = 1;
+
+type Migration = fn(&sled::Db) -> Result<(), String>;
+
+/// Forward migrations, `MIGRATIONS[n]` upgrades version n to n + 1
+const MIGRATIONS: [Migration; STORAGE_VERSION as usize] = [
+    migrate_to_v1,
+];
+
+/// Stores created before versioning already use the version 1 layout
+fn migrate_to_v1(_db: &sled::Db) -> Result<(), String> {
+    Ok(())
+}
+
+/// Version of the data in `db` (0 for stores created before versioning)
+fn read_storage_version(db: &sled::Db) -> Result<u32, String> {
+    let meta = db.open_tree(META_TREE)
+        .map_err(|e| format!("Failed to open tree '{}': {}", META_TREE, e))?;
+    let value = meta.get(STORAGE_VERSION_KEY)
+        .map_err(|e| format!("Failed to read storage version: {}", e))?;
+    
+    Ok(value.and_then(|v| <[u8; 4]>::try_from(v.as_ref()).ok()).map(u32::from_be_bytes).unwrap_or(0))
+}
+
+/// Run the migrations needed to bring `db` to `STORAGE_VERSION`
+///
+/// The version is persisted after every step, so an interrupted upgrade resumes where it
+/// stopped. Stores written by a newer plugin version are refused rather than misread.
+fn migrate(db: &sled::Db) -> Result<(), String> {
+    let version = read_storage_version(db)?;
+    if version > STORAGE_VERSION {
+        return Err(format!(
+            "Aux store has version {} but this build only supports up to {}",
+            version, STORAGE_VERSION
+        ));
+    }
+    
+    let meta = db.open_tree(META_TREE)
+        .map_err(|e| format!("Failed to open tree '{}': {}", META_TREE, e))?;
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(db)
+            .map_err(|e| format!("Migration of aux store to version {} failed: {}", from + 1, e))?;
+        meta.insert(STORAGE_VERSION_KEY, &(from as u32 + 1).to_be_bytes())
+            .map_err(|e| format!("Failed to store storage version: {}", e))?;
+        db.flush()
+            .map_err(|e| format!("Failed to flush aux store: {}", e))?;
+        tracing::info!("Migrated aux store to version {}", from + 1);
+    }
+    
+    Ok(())
+}
+
+/// Version of the active auxiliary store
+pub(crate) fn storage_version() -> Result<u32, String> {
+    read_storage_version(&aux_store()?)
+}
+
+/// Failure to open the auxiliary store
+#[derive(Debug)]
+pub(crate) enum AuxStoreError {
+    /// sled could not open the files
+    Open(sled::Error),
+    /// Migration or locking failure
+    Other(String),
+}
+
+impl std::fmt::Display for AuxStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuxStoreError::Open(e) => write!(f, "Failed to open aux store: {}", e),
+            AuxStoreError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<String> for AuxStoreError {
+    fn from(message: String) -> Self {
+        AuxStoreError::Other(message)
+    }
+}
+
+impl From<AuxStoreError> for String {
+    fn from(error: AuxStoreError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Open the auxiliary store at `path` and make it the active store
+///
+/// A store already active at another path is flushed and closed first.
+pub(crate) fn open_aux_store(path: &Path) -> Result<sled::Db, AuxStoreError> {
+    let mut store_guard = AUX_STORE.lock()
+        .map_err(|e| format!("Failed to lock aux store: {}", e))?;
+    
+    match store_guard.take() {
+        Some((open_path, db)) if open_path == path => {
+            *store_guard = Some((open_path, db.clone()));
+            return Ok(db);
+        }
+        Some((_, db)) => {
+            db.flush()
+                .map_err(|e| format!("Failed to flush aux store: {}", e))?;
+        }
+        None => {}
+    }
+    
+    let cache_bytes = super::system::resource_limits().aux_store_cache_mb as u64 * 1024 * 1024;
+    let db = sled::Config::new()
+        .path(path)
+        .cache_capacity(cache_bytes)
+        .open()
+        .map_err(AuxStoreError::Open)?;
+    migrate(&db)?;
+    *store_guard = Some((path.to_path_buf(), db.clone()));
+    
+    Ok(db)
+}
+
+/// Flush and close the active auxiliary store (e.g. before moving its files)
+pub(crate) fn close_aux_store() -> Result<(), String> {
+    let mut store_guard = AUX_STORE.lock()
+        .map_err(|e| format!("Failed to lock aux store: {}", e))?;
+    if let Some((_, db)) = store_guard.take() {
+        db.flush()
+            .map_err(|e| format!("Failed to flush aux store: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Location of the auxiliary store for a given NDB database path
+pub(crate) fn aux_store_path(db_path: &str) -> Result<PathBuf, String> {
+    PathBuf::from(db_path)
+        .parent()
+        .map(|parent| parent.join("aux"))
+        .ok_or_else(|| "Invalid database path".to_string())
+}
+
+/// Get the active auxiliary store
+pub(crate) fn aux_store() -> Result<sled::Db, String> {
+    let store_guard = AUX_STORE.lock()
+        .map_err(|e| format!("Failed to lock aux store: {}", e))?;
+    store_guard
+        .as_ref()
+        .map(|(_, db)| db.clone())
+        .ok_or_else(|| "Aux store not opened".to_string())
+}
+
+pub(crate) fn open_tree(name: &str) -> Result<sled::Tree, String> {
+    aux_store()?
+        .open_tree(name)
+        .map_err(|e| format!("Failed to open tree '{}': {}", name, e))
+}
+
+/// Record when an event was received by the relay
+pub(crate) fn record_received_at(event_id: &[u8; 32], received_at: u64) -> Result<(), String> {
+    let received = open_tree(RECEIVED_AT_TREE)?;
+    let index = open_tree(RECEIVED_INDEX_TREE)?;
+    
+    // Keep the first time we saw the event, atomically as the same event may arrive twice at once
+    let inserted = received.compare_and_swap(event_id, None as Option<&[u8]>, Some(&received_at.to_be_bytes()[..]))
+        .map_err(|e| format!("Failed to store received_at: {}", e))?;
+    if inserted.is_err() {
+        return Ok(());
+    }
+    
+    let mut index_key = Vec::with_capacity(40);
+    index_key.extend_from_slice(&received_at.to_be_bytes());
+    index_key.extend_from_slice(event_id);
+    
+    index.insert(index_key, &[])
+        .map_err(|e| format!("Failed to store received_at index: {}", e))?;
+    
+    Ok(())
+}
+
+/// Get when an event was received by the relay
+pub(crate) fn get_received_at(event_id: &[u8; 32]) -> Result<Option<u64>, String> {
+    let received = open_tree(RECEIVED_AT_TREE)?;
+    let value = received.get(event_id)
+        .map_err(|e| format!("Failed to read received_at: {}", e))?;
+    
+    Ok(value.and_then(|v| <[u8; 8]>::try_from(v.as_ref()).ok()).map(u64::from_be_bytes))
+}
+
+/// Event ids received within `[since, until]`, newest first, read from the index as iterated
+pub(crate) fn received_event_ids(
+    since: u64,
+    until: u64,
+) -> Result<impl Iterator<Item = Result<([u8; 32], u64), String>> + Send, String> {
+    let index = open_tree(RECEIVED_INDEX_TREE)?;
+    
+    let start = since.to_be_bytes().to_vec();
+    let mut end = until.to_be_bytes().to_vec();
+    end.extend_from_slice(&[0xff; 32]);
+    
+    Ok(index.range(start..=end).rev().filter_map(|entry| {
+        let key = match entry {
+            Ok((key, _)) => key,
+            Err(e) => return Some(Err(format!("Failed to scan received_at index: {}", e))),
+        };
+        if key.len() != 40 {
+            return None;
+        }
+        let received_at = u64::from_be_bytes(key[..8].try_into().unwrap());
+        let event_id: [u8; 32] = key[8..].try_into().unwrap();
+        Some(Ok((event_id, received_at)))
+    }))
+}
+
+fn history_prefix(pubkey: &str, kind: u16, identifier: &str) -> Vec<u8> {
+    let mut prefix = format!("{}:{}:{}", pubkey, kind, identifier).into_bytes();
+    prefix.push(0);
+    prefix
+}
+
+/// Keep a superseded version of a replaceable or addressable event
+pub(crate) fn record_history(
+    pubkey: &str,
+    kind: u16,
+    identifier: &str,
+    created_at: u64,
+    event_id: &[u8; 32],
+    event_json: &str,
+) -> Result<(), String> {
+    let history = open_tree(EVENT_HISTORY_TREE)?;
+    
+    let mut key = history_prefix(pubkey, kind, identifier);
+    key.extend_from_slice(&created_at.to_be_bytes());
+    key.extend_from_slice(event_id);
+    
+    history.insert(key, event_json.as_bytes())
+        .map_err(|e| format!("Failed to store event history: {}", e))?;
+    Ok(())
+}
+
+/// Superseded versions of a replaceable or addressable event (JSON), newest first
+pub(crate) fn event_history(pubkey: &str, kind: u16, identifier: &str) -> Result<Vec<String>, String> {
+    let history = open_tree(EVENT_HISTORY_TREE)?;
+    
+    history.scan_prefix(history_prefix(pubkey, kind, identifier))
+        .rev()
+        .map(|entry| {
+            let (_, value) = entry.map_err(|e| format!("Failed to read event history: {}", e))?;
+            String::from_utf8(value.to_vec()).map_err(|e| format!("Invalid stored event: {}", e))
+        })
+        .collect()
+}
+
+/// Remember that events were deleted on purpose
+pub(crate) fn record_tombstones(event_ids: &[[u8; 32]], deleted_at: u64) -> Result<(), String> {
+    let tombstones = open_tree(TOMBSTONE_TREE)?;
+    
+    let mut batch = sled::Batch::default();
+    for id in event_ids {
+        batch.insert(id, &deleted_at.to_be_bytes());
+    }
+    tombstones.apply_batch(batch)
+        .map_err(|e| format!("Failed to store tombstones: {}", e))
+}
+
+pub(crate) fn is_tombstoned(event_id: &[u8; 32]) -> Result<bool, String> {
+    open_tree(TOMBSTONE_TREE)?
+        .contains_key(event_id)
+        .map_err(|e| format!("Failed to read tombstones: {}", e))
+}
+
+/// Ids of all deleted events
+pub(crate) fn tombstone_ids() -> Result<Vec<[u8; 32]>, String> {
+    open_tree(TOMBSTONE_TREE)?
+        .iter()
+        .keys()
+        .filter_map(|key| match key {
+            Ok(key) => <[u8; 32]>::try_from(key.as_ref()).ok().map(Ok),
+            Err(e) => Some(Err(format!("Failed to read tombstones: {}", e))),
+        })
+        .collect()
+}
+
+pub(crate) fn tombstone_count() -> Result<u64, String> {
+    Ok(open_tree(TOMBSTONE_TREE)?.len() as u64)
+}
+
+/// Forget tombstones of events deleted before `before`, returns how many were removed
+pub(crate) fn purge_tombstones(before: u64) -> Result<u64, String> {
+    let tombstones = open_tree(TOMBSTONE_TREE)?;
+    
+    let mut batch = sled::Batch::default();
+    let mut removed = 0;
+    for entry in tombstones.iter() {
+        let (key, value) = entry.map_err(|e| format!("Failed to read tombstones: {}", e))?;
+        let deleted_at = <[u8; 8]>::try_from(value.as_ref()).map(u64::from_be_bytes).unwrap_or(0);
+        if deleted_at < before {
+            batch.remove(key);
+            removed += 1;
+        }
+    }
+    tombstones.apply_batch(batch)
+        .map_err(|e| format!("Failed to purge tombstones: {}", e))?;
+    
+    Ok(removed)
+}
+
+/// Keep the truncated copy of an event whose content was over the limit
+pub(crate) fn record_truncated(event_id: &[u8; 32], event_json: &str) -> Result<(), String> {
+    open_tree(TRUNCATED_TREE)?
+        .insert(event_id, event_json.as_bytes())
+        .map_err(|e| format!("Failed to store truncated event: {}", e))?;
+    Ok(())
+}
+
+/// JSON of every truncated copy
+pub(crate) fn truncated_events() -> Result<Vec<String>, String> {
+    open_tree(TRUNCATED_TREE)?
+        .iter()
+        .values()
+        .map(|value| {
+            let value = value.map_err(|e| format!("Failed to read truncated events: {}", e))?;
+            String::from_utf8(value.to_vec()).map_err(|e| format!("Invalid stored event: {}", e))
+        })
+        .collect()
+}
+
+/// Remove the truncated copies whose event JSON matches `predicate`, returns how many
+pub(crate) fn remove_truncated(predicate: impl Fn(&serde_json::Value) -> bool) -> Result<u64, String> {
+    let truncated = open_tree(TRUNCATED_TREE)?;
+    let mut batch = sled::Batch::default();
+    let mut removed = 0;
+    for entry in truncated.iter() {
+        let (key, value) = entry.map_err(|e| format!("Failed to read truncated events: {}", e))?;
+        // Unreadable copies go too
+        let matches = serde_json::from_slice::<serde_json::Value>(&value).map_or(true, |event| predicate(&event));
+        if matches {
+            batch.remove(key);
+            removed += 1;
+        }
+    }
+    truncated.apply_batch(batch)
+        .map_err(|e| format!("Failed to remove truncated events: {}", e))?;
+    
+    Ok(removed)
+}
+
+pub(crate) fn truncated_event(event_id: &[u8; 32]) -> Result<Option<String>, String> {
+    let value = open_tree(TRUNCATED_TREE)?
+        .get(event_id)
+        .map_err(|e| format!("Failed to read truncated event: {}", e))?;
+    value.map(|v| String::from_utf8(v.to_vec()).map_err(|e| format!("Invalid stored event: {}", e)))
+        .transpose()
+}
@@ -0,0 +1,216 @@
+use nostr::event::{Event, Kind};
+use nostr::key::{Keys, SecretKey};
+use nostr::nips::nip01::Metadata;
+use nostr::nips::nip04;
+use nostr::nips::nip44;
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+
+/// NWC (NIP-47) response kind
+const NWC_RESPONSE_KIND: u16 = 23195;
+
+/// Profile metadata of a kind 0 event
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProfileContent {
+    pub name: Option<String>,
+    pub display_name: Option<String>,
+    pub about: Option<String>,
+    pub website: Option<String>,
+    pub picture: Option<String>,
+    pub banner: Option<String>,
+    pub nip05: Option<String>,
+    pub lud06: Option<String>,
+    pub lud16: Option<String>,
+    /// Fields not listed above, as a JSON object (None if there are none)
+    pub custom_json: Option<String>,
+}
+
+/// Relay of a NIP-65 relay list (kind 10002)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RelayListEntry {
+    pub url: String,
+    pub read: bool,
+    pub write: bool,
+}
+
+/// Decrypted NWC response (NIP-47, kind 23195)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NwcResponse {
+    /// Method the response belongs to (e.g. "pay_invoice")
+    pub result_type: String,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    /// `preimage` of pay_invoice / pay_keysend results
+    pub preimage: Option<String>,
+    /// `balance` of get_balance results
+    pub balance_msats: Option<u64>,
+    /// Full `result` object, for the fields not extracted above
+    pub result_json: Option<String>,
+}
+
+/// Zap receipt (NIP-57, kind 9735) with its embedded zap request
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ZapReceiptContent {
+    /// Hex public key of the zap sender (author of the zap request)
+    pub sender: Option<String>,
+    /// Hex public key of the zapped user
+    pub recipient: Option<String>,
+    /// Zapped event id, if any
+    pub event_id: Option<String>,
+    /// Zapped addressable event (`a` tag), if any
+    pub address: Option<String>,
+    /// Amount requested by the sender, in millisats
+    pub amount_msats: Option<u64>,
+    /// Comment of the zap request
+    pub comment: String,
+    pub bolt11: Option<String>,
+    pub preimage: Option<String>,
+    /// Whether the embedded zap request has a valid signature
+    pub request_valid: bool,
+}
+
+/// Content of an event decoded according to its kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum StructuredContent {
+    /// Kind 0
+    Profile(ProfileContent),
+    /// Kind 10002
+    RelayList(Vec<RelayListEntry>),
+    /// Kind 23195
+    NwcResponse(NwcResponse),
+    /// Kind 9735
+    ZapReceipt(ZapReceiptContent),
+    /// Kind without structured content, use the content as text
+    None,
+}
+
+fn parse_profile(event: &Event) -> Result<ProfileContent, String> {
+    let metadata = Metadata::from_json(&event.content)
+        .map_err(|e| format!("Invalid profile metadata: {}", e))?;
+    
+    Ok(ProfileContent {
+        custom_json: if metadata.custom.is_empty() {
+            None
+        } else {
+            serde_json::to_string(&metadata.custom).ok()
+        },
+        name: metadata.name,
+        display_name: metadata.display_name,
+        about: metadata.about,
+        website: metadata.website,
+        picture: metadata.picture,
+        banner: metadata.banner,
+        nip05: metadata.nip05,
+        lud06: metadata.lud06,
+        lud16: metadata.lud16,
+    })
+}
+
+fn parse_relay_list(event: &Event) -> Vec<RelayListEntry> {
+    event.tags.iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, url, rest @ ..] if name == "r" => {
+                // No marker means both read and write
+                let marker = rest.first().map(String::as_str);
+                Some(RelayListEntry {
+                    url: url.clone(),
+                    read: marker != Some("write"),
+                    write: marker != Some("read"),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn parse_nwc_response(event: &Event, private_key: Option<&str>) -> Result<NwcResponse, String> {
+    let private_key = private_key
+        .ok_or_else(|| "A private key is needed to decrypt NWC responses".to_string())?;
+    let secret_key = SecretKey::from_str(private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let my_pubkey = Keys::new(secret_key.clone()).public_key().to_hex();
+    
+    // NIP-47 started with NIP-04 payloads (containing "?iv="), newer wallets use NIP-44
+    let plaintext = if event.content.contains("?iv=") {
+        let plaintext = nip04::decrypt(&secret_key, &event.pubkey, &event.content)
+            .map_err(|e| format!("Decryption failed: {}", e))?;
+        audit::record(KeyOperation::Nip04Decrypt, &my_pubkey, Some(NWC_RESPONSE_KIND));
+        plaintext
+    } else {
+        let plaintext = nip44::decrypt(&secret_key, &event.pubkey, &event.content)
+            .map_err(|e| format!("NIP-44 decryption failed: {}", e))?;
+        audit::record(KeyOperation::Nip44Decrypt, &my_pubkey, Some(NWC_RESPONSE_KIND));
+        plaintext
+    };
+    
+    let json: serde_json::Value = serde_json::from_str(&plaintext)
+        .map_err(|e| format!("Invalid NWC response: {}", e))?;
+    let result = json.get("result").filter(|result| !result.is_null());
+    let error = json.get("error").filter(|error| !error.is_null());
+    
+    Ok(NwcResponse {
+        result_type: json.get("result_type").and_then(|v| v.as_str()).unwrap_or_default().to_string(),
+        error_code: error.and_then(|e| e.get("code")).and_then(|v| v.as_str()).map(str::to_string),
+        error_message: error.and_then(|e| e.get("message")).and_then(|v| v.as_str()).map(str::to_string),
+        preimage: result.and_then(|r| r.get("preimage")).and_then(|v| v.as_str()).map(str::to_string),
+        balance_msats: result.and_then(|r| r.get("balance")).and_then(|v| v.as_u64()),
+        result_json: result.map(|r| r.to_string()),
+    })
+}
+
+fn tag_value(event: &Event, tag_name: &str) -> Option<String> {
+    event.tags.iter().find_map(|tag| match tag.as_slice() {
+        [name, value, ..] if name == tag_name => Some(value.clone()),
+        _ => None,
+    })
+}
+
+fn parse_zap_receipt(event: &Event) -> ZapReceiptContent {
+    let mut receipt = ZapReceiptContent {
+        recipient: tag_value(event, "p"),
+        event_id: tag_value(event, "e"),
+        address: tag_value(event, "a"),
+        bolt11: tag_value(event, "bolt11"),
+        preimage: tag_value(event, "preimage"),
+        ..Default::default()
+    };
+    
+    // The zap request is embedded as JSON in the description tag
+    if let Some(request) = tag_value(event, "description").and_then(|json| Event::from_json(json).ok()) {
+        receipt.request_valid = request.kind == Kind::ZapRequest && request.verify().is_ok();
+        receipt.sender = Some(request.pubkey.to_hex());
+        receipt.amount_msats = tag_value(&request, "amount").and_then(|amount| amount.parse().ok());
+        receipt.comment = request.content;
+    } else {
+        // Some providers omit the description, fall back to the uppercase P tag
+        receipt.sender = tag_value(event, "P");
+    }
+    
+    receipt
+}
+
+/// Decode the content of an event according to its kind
+///
+/// Supports profiles (kind 0), relay lists (kind 10002), NWC responses (kind 23195) and
+/// zap receipts (kind 9735). Other kinds return `StructuredContent::None`.
+///
+/// # Arguments
+/// * `event_json` - Event to decode
+/// * `private_key` - Private key (hex) of the NWC connection, only needed for NWC responses
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_structured_content(event_json: String, private_key: Option<String>) -> Result<StructuredContent, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    
+    Ok(match event.kind {
+        Kind::Metadata => StructuredContent::Profile(parse_profile(&event)?),
+        Kind::RelayList => StructuredContent::RelayList(parse_relay_list(&event)),
+        Kind::ZapReceipt => StructuredContent::ZapReceipt(parse_zap_receipt(&event)),
+        kind if kind.as_u16() == NWC_RESPONSE_KIND => {
+            StructuredContent::NwcResponse(parse_nwc_response(&event, private_key.as_deref())?)
+        }
+        _ => StructuredContent::None,
+    })
+}
@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use super::relay::{get_database_path, get_runtime};
+use super::storage;
+use super::verify;
+
+/// Resources used by the native layer (None when not available on the platform)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NativeResourceUsage {
+    /// Resident memory of the whole process
+    pub resident_memory_bytes: Option<u64>,
+    /// Resident part of the NDB memory map (included in `resident_memory_bytes`)
+    pub database_mapped_bytes: Option<u64>,
+    pub open_file_descriptors: Option<u32>,
+    pub thread_count: Option<u32>,
+    pub runtime_workers: Option<u32>,
+    pub runtime_alive_tasks: Option<u32>,
+    /// Incoming relay events waiting for signature verification
+    pub verification_queue_depth: u32,
+    pub verification_threads: u32,
+}
+
+/// Read a "<Key>: <value> [kB]" line of a /proc status-like file
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn read_proc_field(content: &str, key: &str) -> Option<u64> {
+    content.lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Sum of the resident size of all mappings of files under `dir`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn mapped_resident_bytes(dir: &str) -> Option<u64> {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").ok()?;
+    
+    let mut total_kb = 0;
+    let mut in_dir = false;
+    for line in smaps.lines() {
+        let first = line.split_whitespace().next().unwrap_or("");
+        if first.contains('-') && !first.ends_with(':') {
+            // Mapping header: "<range> <perms> <offset> <dev> <inode> [path]"
+            in_dir = line.split_whitespace().nth(5).map_or(false, |path| path.starts_with(dir));
+        } else if in_dir {
+            if let Some(kb) = read_proc_field(line, "Rss") {
+                total_kb += kb;
+            }
+        }
+    }
+    Some(total_kb * 1024)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn process_usage(usage: &mut NativeResourceUsage) {
+    if let Ok(status) = std::fs::read_to_string("/proc/self/status") {
+        usage.resident_memory_bytes = read_proc_field(&status, "VmRSS").map(|kb| kb * 1024);
+        usage.thread_count = read_proc_field(&status, "Threads").map(|n| n as u32);
+    }
+    usage.open_file_descriptors = std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|fds| fds.count() as u32);
+    
+    if let Ok(db_path) = get_database_path() {
+        usage.database_mapped_bytes = mapped_resident_bytes(&db_path);
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn process_usage(_usage: &mut NativeResourceUsage) {}
+
+/// Get memory, file descriptor and thread usage of the native layer
+///
+/// Process figures are read from /proc and are only available on Linux and Android.
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_native_resource_usage() -> NativeResourceUsage {
+    let mut usage = NativeResourceUsage {
+        resident_memory_bytes: None,
+        database_mapped_bytes: None,
+        open_file_descriptors: None,
+        thread_count: None,
+        runtime_workers: None,
+        runtime_alive_tasks: None,
+        verification_queue_depth: verify::queue_depth(),
+        verification_threads: verify::threads(),
+    };
+    
+    process_usage(&mut usage);
+    
+    if let Ok(runtime) = get_runtime() {
+        let metrics = runtime.metrics();
+        usage.runtime_workers = Some(metrics.num_workers() as u32);
+        usage.runtime_alive_tasks = Some(metrics.num_alive_tasks() as u32);
+    }
+    
+    usage
+}
+
+/// Default timeout of blocking FFI calls
+const DEFAULT_CALL_TIMEOUT_MS: u64 = 30_000;
+
+static CALL_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_CALL_TIMEOUT_MS);
+
+/// Timeout applied to blocking FFI calls
+pub(crate) fn call_timeout() -> Duration {
+    Duration::from_millis(CALL_TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+/// Set the timeout of blocking calls (database queries, network requests, relay start)
+///
+/// Calls that don't complete in time return a "Timeout: ..." error instead of blocking the
+/// Dart isolate. Calls that contact several relays get a proportionally larger budget.
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_call_timeout(timeout_ms: u64) -> Result<(), String> {
+    if timeout_ms == 0 {
+        return Err("Timeout must be greater than zero".to_string());
+    }
+    CALL_TIMEOUT_MS.store(timeout_ms, Ordering::Relaxed);
+    Ok(())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_call_timeout() -> u64 {
+    CALL_TIMEOUT_MS.load(Ordering::Relaxed)
+}
+
+/// Set the number of threads verifying signatures of events received by the relay (default 2)
+///
+/// Verification runs off the runtime workers, so bursts of incoming events don't delay
+/// queries. Events arriving while the queue is full are rejected as rate-limited.
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_verification_threads(threads: u32) -> Result<(), String> {
+    verify::set_threads(threads)
+}
+
+/// Storage version of the plugin's auxiliary data (KV store, cursors, history, tokens)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageVersion {
+    /// Version of the opened store
+    pub current: u32,
+    /// Latest version known to this build (stores are migrated to it when opened)
+    pub supported: u32,
+}
+
+/// Get the version of the auxiliary store (the relay or `kv_open` must have opened it)
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_storage_version() -> Result<StorageVersion, String> {
+    Ok(StorageVersion {
+        current: storage::storage_version()?,
+        supported: storage::STORAGE_VERSION,
+    })
+}
+
+static RESOURCE_PROFILE: Mutex<ResourceProfile> = Mutex::new(ResourceProfile::Normal);
+
+/// How much memory and CPU the native layer may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceProfile {
+    /// Devices under memory pressure: small caches, few threads, small batches
+    Low,
+    /// Defaults
+    Normal,
+    /// Devices with memory to spare: bigger caches and batches, more threads
+    High,
+}
+
+/// Settings applied by a resource profile
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// NDB threads ingesting events (next database open)
+    pub database_ingester_threads: u32,
+    /// Runtime worker threads, 0 for one per CPU core (when the runtime is created)
+    pub runtime_threads: u32,
+    /// Relay signature verification threads (applied immediately)
+    pub verification_threads: u32,
+    /// Page cache of the auxiliary store (next store open)
+    pub aux_store_cache_mb: u32,
+    /// NIP-44 conversation keys kept in memory (applied immediately)
+    pub conversation_key_cache: u32,
+    /// Events saved per database round trip by imports (applied immediately)
+    pub import_batch_size: u32,
+}
+
+impl ResourceProfile {
+    fn limits(self) -> ResourceLimits {
+        match self {
+            ResourceProfile::Low => ResourceLimits {
+                database_ingester_threads: 1,
+                runtime_threads: 2,
+                verification_threads: 1,
+                aux_store_cache_mb: 16,
+                conversation_key_cache: 128,
+                import_batch_size: 100,
+            },
+            ResourceProfile::Normal => ResourceLimits {
+                database_ingester_threads: 2,
+                runtime_threads: 0,
+                verification_threads: 2,
+                aux_store_cache_mb: 1024,
+                conversation_key_cache: 1024,
+                import_batch_size: 500,
+            },
+            ResourceProfile::High => ResourceLimits {
+                database_ingester_threads: 4,
+                runtime_threads: 0,
+                verification_threads: 4,
+                aux_store_cache_mb: 1024,
+                conversation_key_cache: 4096,
+                import_batch_size: 2000,
+            },
+        }
+    }
+}
+
+/// Limits of the current resource profile
+pub(crate) fn resource_limits() -> ResourceLimits {
+    get_resource_profile().limits()
+}
+
+/// Switch the memory and CPU footprint of the native layer in one call
+///
+/// Meant to downshift when the OS reports memory pressure. Thread counts, caches and batch
+/// sizes change right away; the database ingester threads and the runtime threads only
+/// change when the database is reopened or the runtime created (set the profile before
+/// starting the relay). The database map size doesn't depend on the profile: it caps the
+/// database size, and only reserves address space. Overrides `set_verification_threads`.
+///
+/// Returns the limits of the profile.
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_resource_profile(profile: ResourceProfile) -> Result<ResourceLimits, String> {
+    *RESOURCE_PROFILE.lock()
+        .map_err(|e| format!("Failed to lock resource profile: {}", e))? = profile;
+    let limits = profile.limits();
+    verify::set_threads(limits.verification_threads)?;
+    Ok(limits)
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_resource_profile() -> ResourceProfile {
+    RESOURCE_PROFILE.lock().map_or(ResourceProfile::Normal, |profile| *profile)
+}
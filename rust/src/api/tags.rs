@@ -0,0 +1,277 @@
+use nostr::event::Event;
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+
+/// Type of a NIP-73 external content id
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExternalIdKind {
+    /// Web page (`https://...`)
+    Url,
+    /// Hashtag (`#nostr`)
+    Hashtag,
+    /// Geohash (`geo:...`)
+    Geohash,
+    /// Book (`isbn:...`)
+    Isbn,
+    /// Podcast feed (`podcast:guid:...`)
+    PodcastFeed,
+    /// Podcast episode (`podcast:item:guid:...`)
+    PodcastEpisode,
+    /// Podcast publisher (`podcast:publisher:guid:...`)
+    PodcastPublisher,
+    /// Movie (`isan:...`)
+    Isan,
+    /// Paper (`doi:...`)
+    Doi,
+    /// Country or subdivision (`iso3166:...`)
+    Iso3166,
+}
+
+impl ExternalIdKind {
+    /// Prefix of the `i` tag value
+    fn prefix(&self) -> &'static str {
+        match self {
+            ExternalIdKind::Url => "",
+            ExternalIdKind::Hashtag => "#",
+            ExternalIdKind::Geohash => "geo:",
+            ExternalIdKind::Isbn => "isbn:",
+            ExternalIdKind::PodcastFeed => "podcast:guid:",
+            ExternalIdKind::PodcastEpisode => "podcast:item:guid:",
+            ExternalIdKind::PodcastPublisher => "podcast:publisher:guid:",
+            ExternalIdKind::Isan => "isan:",
+            ExternalIdKind::Doi => "doi:",
+            ExternalIdKind::Iso3166 => "iso3166:",
+        }
+    }
+    
+    /// Value of the `k` tag
+    fn k_value(&self) -> &'static str {
+        match self {
+            ExternalIdKind::Url => "web",
+            ExternalIdKind::Hashtag => "#",
+            ExternalIdKind::Geohash => "geo",
+            ExternalIdKind::Isbn => "isbn",
+            ExternalIdKind::PodcastFeed => "podcast:guid",
+            ExternalIdKind::PodcastEpisode => "podcast:item:guid",
+            ExternalIdKind::PodcastPublisher => "podcast:publisher:guid",
+            ExternalIdKind::Isan => "isan",
+            ExternalIdKind::Doi => "doi",
+            ExternalIdKind::Iso3166 => "iso3166",
+        }
+    }
+    
+    /// Detect the type of an `i` tag value (longest prefixes first)
+    fn detect(id: &str) -> Option<Self> {
+        const KINDS: [ExternalIdKind; 9] = [
+            ExternalIdKind::PodcastPublisher,
+            ExternalIdKind::PodcastEpisode,
+            ExternalIdKind::PodcastFeed,
+            ExternalIdKind::Iso3166,
+            ExternalIdKind::Geohash,
+            ExternalIdKind::Isbn,
+            ExternalIdKind::Isan,
+            ExternalIdKind::Doi,
+            ExternalIdKind::Hashtag,
+        ];
+        if id.starts_with("http://") || id.starts_with("https://") {
+            return Some(ExternalIdKind::Url);
+        }
+        KINDS.into_iter().find(|kind| id.starts_with(kind.prefix()))
+    }
+    
+    /// Normalize a value the way NIP-73 expects it
+    fn normalize(&self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        let value = value.strip_prefix(self.prefix()).unwrap_or(value);
+        if value.is_empty() {
+            return Err("External id value must not be empty".to_string());
+        }
+        
+        Ok(match self {
+            ExternalIdKind::Url => {
+                let url = nostr::Url::parse(value)
+                    .map_err(|e| format!("Invalid URL '{}': {}", value, e))?;
+                // Fragments don't identify a different resource
+                let mut url = url;
+                url.set_fragment(None);
+                url.to_string()
+            }
+            ExternalIdKind::Hashtag | ExternalIdKind::Doi => value.to_lowercase(),
+            ExternalIdKind::Isbn => value.chars().filter(|c| *c != '-' && *c != ' ').collect(),
+            ExternalIdKind::Iso3166 => value.to_uppercase(),
+            _ => value.to_string(),
+        })
+    }
+}
+
+/// External content id referenced by an event (NIP-73)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExternalContentId {
+    /// None if the id has an unknown prefix
+    pub kind: Option<ExternalIdKind>,
+    /// Full `i` tag value (e.g. "isbn:9780765382030")
+    pub id: String,
+    /// Value without the type prefix (e.g. "9780765382030")
+    pub value: String,
+    /// Optional URL hint (second value of the `i` tag)
+    pub url_hint: Option<String>,
+}
+
+/// Build the `i` and `k` tags referencing an external content id (NIP-73)
+///
+/// # Arguments
+/// * `kind` - Type of the external id
+/// * `value` - Id with or without its type prefix (e.g. "978-0-7653-8203-0" for an ISBN)
+/// * `url_hint` - Optional URL where the content can be found
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_external_id_tags(
+    kind: ExternalIdKind,
+    value: String,
+    url_hint: Option<String>,
+) -> Result<Vec<Vec<String>>, String> {
+    let id = format!("{}{}", kind.prefix(), kind.normalize(&value)?);
+    
+    let mut i_tag = vec!["i".to_string(), id];
+    if let Some(hint) = url_hint {
+        i_tag.push(hint);
+    }
+    
+    Ok(vec![i_tag, vec!["k".to_string(), kind.k_value().to_string()]])
+}
+
+/// Parse the external content ids (`i` tags) referenced by an event (NIP-73)
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_external_ids(event_json: String) -> Result<Vec<ExternalContentId>, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    
+    Ok(event.tags.iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, id, rest @ ..] if name == "i" => {
+                let kind = ExternalIdKind::detect(id);
+                let value = kind
+                    .map(|kind| id.strip_prefix(kind.prefix()).unwrap_or(id))
+                    .unwrap_or(id)
+                    .to_string();
+                Some(ExternalContentId {
+                    kind,
+                    id: id.clone(),
+                    value,
+                    url_hint: rest.first().filter(|hint| !hint.is_empty()).cloned(),
+                })
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+fn parse_event(event_json: &str) -> Result<Event, String> {
+    Event::from_json(event_json).map_err(|e| format!("Invalid event JSON: {}", e))
+}
+
+/// Get the first value of every tag with the given name (e.g. all `t` hashtags)
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_tag_values(event_json: String, tag_name: String) -> Result<Vec<String>, String> {
+    let event = parse_event(&event_json)?;
+    Ok(event.tags.iter()
+        .filter_map(|tag| match tag.as_slice() {
+            [name, value, ..] if *name == tag_name => Some(value.clone()),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Get the first tag with the given name, including its name (None if there is none)
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_first_tag(event_json: String, tag_name: String) -> Result<Option<Vec<String>>, String> {
+    let event = parse_event(&event_json)?;
+    Ok(event.tags.iter()
+        .find(|tag| tag.as_slice().first() == Some(&tag_name))
+        .map(|tag| tag.as_slice().to_vec()))
+}
+
+/// Role of an `e` tag (NIP-10)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EventRefMarker {
+    Root,
+    Reply,
+    Mention,
+}
+
+/// Event referenced by an `e` tag
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventRef {
+    pub event_id: String,
+    pub relay_url: Option<String>,
+    pub marker: EventRefMarker,
+    /// Author of the referenced event, if given
+    pub pubkey: Option<String>,
+}
+
+/// Thread position of an event, from its `e` tags (NIP-10)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThreadRefs {
+    pub root: Option<EventRef>,
+    /// Event replied to (same as the root for direct replies)
+    pub reply: Option<EventRef>,
+    pub mentions: Vec<EventRef>,
+}
+
+/// Get the `e` tags of an event with their NIP-10 roles
+///
+/// Marked tags (`root`, `reply`, `mention`) are used as given. Events without markers use
+/// the deprecated positional scheme: first tag is the root, last is the reply, the rest
+/// are mentions.
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_event_refs(event_json: String) -> Result<Vec<EventRef>, String> {
+    let event = parse_event(&event_json)?;
+    
+    let e_tags: Vec<&[String]> = event.tags.iter()
+        .map(|tag| tag.as_slice())
+        .filter(|tag| tag.len() >= 2 && tag[0] == "e")
+        .collect();
+    let marked = e_tags.iter()
+        .any(|tag| matches!(tag.get(3).map(String::as_str), Some("root" | "reply" | "mention")));
+    
+    let count = e_tags.len();
+    Ok(e_tags.into_iter()
+        .enumerate()
+        .map(|(index, tag)| {
+            let marker = if marked {
+                match tag.get(3).map(String::as_str) {
+                    Some("root") => EventRefMarker::Root,
+                    Some("reply") => EventRefMarker::Reply,
+                    _ => EventRefMarker::Mention,
+                }
+            } else if index == 0 {
+                EventRefMarker::Root
+            } else if index == count - 1 {
+                EventRefMarker::Reply
+            } else {
+                EventRefMarker::Mention
+            };
+            EventRef {
+                event_id: tag[1].clone(),
+                relay_url: tag.get(2).filter(|url| !url.is_empty()).cloned(),
+                marker,
+                pubkey: tag.get(4).filter(|pk| !pk.is_empty()).cloned(),
+            }
+        })
+        .collect())
+}
+
+/// Get the root, the replied-to event and the mentions of an event (NIP-10)
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_thread_refs(event_json: String) -> Result<ThreadRefs, String> {
+    let refs = get_event_refs(event_json)?;
+    
+    let root = refs.iter().find(|r| r.marker == EventRefMarker::Root).cloned();
+    // A direct reply to the root only has a root tag
+    let reply = refs.iter().find(|r| r.marker == EventRefMarker::Reply).cloned().or_else(|| root.clone());
+    
+    Ok(ThreadRefs {
+        root,
+        reply,
+        mentions: refs.into_iter().filter(|r| r.marker == EventRefMarker::Mention).collect(),
+    })
+}
@@ -0,0 +1,249 @@
+use nostr::event::UnsignedEvent;
+use nostr::key::{PublicKey, SecretKey};
+use nostr::secp256k1::schnorr::Signature;
+use nostr::secp256k1::{
+    Parity, PublicKey as SecpPublicKey, Scalar, Secp256k1, SecretKey as SecpSecretKey,
+};
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+use super::audit::{self, KeyOperation};
+use super::bitcoin::tagged_hash;
+
+// "<session id>:<share index>" -> secret nonce pair and its commitment, removed when used
+static NONCES: Mutex<Option<HashMap<String, SecretNonces>>> = Mutex::new(None);
+
+struct SecretNonces {
+    first: SecpSecretKey,
+    second: SecpSecretKey,
+    commitment: String,
+}
+
+/// One of the two shares of a split key (experimental)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyShare {
+    /// 1 or 2
+    pub index: u8,
+    /// Hex secret share, store it like a private key
+    pub secret_share: String,
+    /// Hex public key the shares sign for (the original Nostr public key)
+    pub group_pubkey: String,
+}
+
+fn random_scalar() -> Result<SecpSecretKey, String> {
+    SecpSecretKey::from_slice(&SecretKey::generate().to_secret_bytes())
+        .map_err(|e| format!("Failed to generate secret: {}", e))
+}
+
+fn parse_secret(hex: &str) -> Result<SecpSecretKey, String> {
+    SecpSecretKey::from_str(hex).map_err(|e| format!("Invalid secret share: {}", e))
+}
+
+/// Nonce points of a commitment (two compressed points, hex)
+fn parse_commitment(commitment: &str) -> Result<(SecpPublicKey, SecpPublicKey), String> {
+    if commitment.len() != 132 {
+        return Err("Invalid commitment: expected two nonce points".to_string());
+    }
+    let parse = |hex: &str| SecpPublicKey::from_str(hex).map_err(|e| format!("Invalid commitment: {}", e));
+    Ok((parse(&commitment[..66])?, parse(&commitment[66..])?))
+}
+
+/// Aggregated nonce of both commitments for an event, with the coefficient of the second
+/// nonces (MuSig2: R = R1 + b * R2, b bound to both commitments and the event)
+fn aggregate_nonce(commitments: &[String], event: &UnsignedEvent) -> Result<(SecpPublicKey, Scalar), String> {
+    if commitments.len() != 2 {
+        return Err("Expected the commitments of both shares".to_string());
+    }
+    let (first_a, second_a) = parse_commitment(&commitments[0])?;
+    let (first_b, second_b) = parse_commitment(&commitments[1])?;
+    let first = first_a.combine(&first_b)
+        .map_err(|e| format!("Invalid commitments: {}", e))?;
+    let second = second_a.combine(&second_b)
+        .map_err(|e| format!("Invalid commitments: {}", e))?;
+    
+    let id = event.id.ok_or_else(|| "Event has no id".to_string())?;
+    let mut message = Vec::with_capacity(130);
+    message.extend_from_slice(&first.serialize());
+    message.extend_from_slice(&second.serialize());
+    message.extend_from_slice(&event.pubkey.to_bytes());
+    message.extend_from_slice(id.as_bytes());
+    let coefficient = Scalar::from_be_bytes(tagged_hash("MuSig/noncecoef", &message))
+        .map_err(|e| format!("Invalid nonce coefficient: {}", e))?;
+    
+    let nonce = second.mul_tweak(&Secp256k1::new(), &coefficient)
+        .and_then(|tweaked| first.combine(&tweaked))
+        .map_err(|e| format!("Invalid commitments: {}", e))?;
+    Ok((nonce, coefficient))
+}
+
+/// Unsigned event to co-sign, with its id computed
+fn parse_unsigned(event_json: &str, group_pubkey: &str) -> Result<UnsignedEvent, String> {
+    let mut event = UnsignedEvent::from_json(event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    let group = PublicKey::parse(group_pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    if event.pubkey != group {
+        return Err("Event pubkey is not the group public key".to_string());
+    }
+    event.ensure_id();
+    Ok(event)
+}
+
+/// BIP-340 challenge of the aggregated nonce for an event
+fn challenge(nonce_x: &[u8; 32], event: &UnsignedEvent) -> Result<Scalar, String> {
+    let id = event.id.ok_or_else(|| "Event has no id".to_string())?;
+    let mut message = Vec::with_capacity(96);
+    message.extend_from_slice(nonce_x);
+    message.extend_from_slice(&event.pubkey.to_bytes());
+    message.extend_from_slice(id.as_bytes());
+    Scalar::from_be_bytes(tagged_hash("BIP0340/challenge", &message))
+        .map_err(|e| format!("Invalid challenge: {}", e))
+}
+
+/// Split a private key into two shares that must both approve every signature (2-of-2)
+///
+/// Experimental. The key is split with fresh randomness; keep the shares on two devices and
+/// delete the original key. Signing takes two rounds between the devices, see
+/// `threshold_commit`. The messages can be exchanged over the local relay, e.g. as NIP-44
+/// encrypted ephemeral events.
+///
+/// # Arguments
+/// * `private_key` - Private key (hex or nsec) to split
+#[flutter_rust_bridge::frb(sync)]
+pub fn threshold_split_key(private_key: String) -> Result<Vec<KeyShare>, String> {
+    let secret_key = SecretKey::parse(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let secret = SecpSecretKey::from_slice(&secret_key.to_secret_bytes())
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    
+    // Signatures are made for the x-only key, which the negated key shares
+    let secp = Secp256k1::new();
+    let (group, parity) = secret.x_only_public_key(&secp);
+    let secret = if parity == Parity::Odd { secret.negate() } else { secret };
+    
+    let first = random_scalar()?;
+    let second = secret.add_tweak(&Scalar::from(first.negate()))
+        .map_err(|e| format!("Failed to split key: {}", e))?;
+    
+    let group_pubkey = hex::encode(group.serialize());
+    Ok([first, second].iter()
+        .enumerate()
+        .map(|(index, share)| KeyShare {
+            index: index as u8 + 1,
+            secret_share: share.display_secret().to_string(),
+            group_pubkey: group_pubkey.clone(),
+        })
+        .collect())
+}
+
+/// Round 1: create this share's nonce commitment for a signing session
+///
+/// Send the returned commitment to the other device. A commitment can only be used once.
+/// Each commitment holds two nonces (MuSig2), so several sessions can run at the same time
+/// without letting the other party forge signatures.
+///
+/// # Arguments
+/// * `share` - This device's share
+/// * `session_id` - Id agreed on by both devices, unique per signature
+#[flutter_rust_bridge::frb(sync)]
+pub fn threshold_commit(share: KeyShare, session_id: String) -> Result<String, String> {
+    let secp = Secp256k1::new();
+    let first = random_scalar()?;
+    let second = random_scalar()?;
+    let commitment = format!(
+        "{}{}",
+        hex::encode(SecpPublicKey::from_secret_key(&secp, &first).serialize()),
+        hex::encode(SecpPublicKey::from_secret_key(&secp, &second).serialize()),
+    );
+    
+    let mut nonces = NONCES.lock()
+        .map_err(|e| format!("Failed to lock nonces: {}", e))?;
+    nonces.get_or_insert_with(HashMap::new)
+        .insert(format!("{}:{}", session_id, share.index), SecretNonces { first, second, commitment: commitment.clone() });
+    
+    Ok(commitment)
+}
+
+/// Round 2: sign the event with this share, returns the partial signature
+///
+/// Both devices must sign the same event (same created_at, tags and content).
+///
+/// # Arguments
+/// * `share` - This device's share
+/// * `session_id` - Session of the commitment made with `threshold_commit`
+/// * `event_json` - Unsigned event (pubkey, created_at, kind, tags, content) of the group public key
+/// * `commitments` - Commitments of share 1 and share 2, in that order
+#[flutter_rust_bridge::frb(sync)]
+pub fn threshold_sign_share(
+    share: KeyShare,
+    session_id: String,
+    event_json: String,
+    commitments: Vec<String>,
+) -> Result<String, String> {
+    let event = parse_unsigned(&event_json, &share.group_pubkey)?;
+    let secret_share = parse_secret(&share.secret_share)?;
+    let (nonce_point, coefficient) = aggregate_nonce(&commitments, &event)?;
+    
+    // Never sign twice with the same nonces
+    let nonces = NONCES.lock()
+        .map_err(|e| format!("Failed to lock nonces: {}", e))?
+        .as_mut()
+        .and_then(|nonces| nonces.remove(&format!("{}:{}", session_id, share.index)))
+        .ok_or_else(|| "No commitment for this session (already used?)".to_string())?;
+    let own = share.index.checked_sub(1).and_then(|index| commitments.get(index as usize));
+    if own != Some(&nonces.commitment) {
+        return Err("Commitment of this share does not match the one it made".to_string());
+    }
+    
+    let nonce = nonces.second.mul_tweak(&coefficient)
+        .and_then(|product| nonces.first.add_tweak(&Scalar::from(product)))
+        .map_err(|e| format!("Failed to sign: {}", e))?;
+    let (nonce_x, parity) = nonce_point.x_only_public_key();
+    let nonce = if parity == Parity::Odd { nonce.negate() } else { nonce };
+    let e = challenge(&nonce_x.serialize(), &event)?;
+    
+    let partial = secret_share.mul_tweak(&e)
+        .and_then(|product| nonce.add_tweak(&Scalar::from(product)))
+        .map_err(|e| format!("Failed to sign: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &event.pubkey.to_hex(), Some(event.kind.as_u16()));
+    
+    Ok(partial.display_secret().to_string())
+}
+
+/// Combine the partial signatures of both shares into the signed event
+///
+/// # Arguments
+/// * `event_json` - Unsigned event both shares signed
+/// * `commitments` - Commitments of share 1 and share 2, in that order
+/// * `partial_signatures` - Partial signatures of both shares
+#[flutter_rust_bridge::frb(sync)]
+pub fn threshold_combine(event_json: String, commitments: Vec<String>, partial_signatures: Vec<String>) -> Result<String, String> {
+    let mut event = UnsignedEvent::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    event.ensure_id();
+    if partial_signatures.len() != 2 {
+        return Err("Expected the partial signatures of both shares".to_string());
+    }
+    let (nonce_point, _) = aggregate_nonce(&commitments, &event)?;
+    let (nonce_x, _) = nonce_point.x_only_public_key();
+    
+    let first = parse_secret(&partial_signatures[0])?;
+    let second = parse_secret(&partial_signatures[1])?;
+    let s = first.add_tweak(&Scalar::from(second))
+        .map_err(|e| format!("Failed to combine signatures: {}", e))?;
+    
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(&nonce_x.serialize());
+    bytes[32..].copy_from_slice(&s.secret_bytes());
+    let signature = Signature::from_slice(&bytes)
+        .map_err(|e| format!("Invalid signature: {}", e))?;
+    
+    // Fails if a share signed another event or used other commitments
+    let signed = event.add_signature(signature)
+        .map_err(|e| format!("Combined signature is invalid: {}", e))?;
+    signed.verify()
+        .map_err(|e| format!("Combined signature is invalid: {}", e))?;
+    Ok(signed.as_json())
+}
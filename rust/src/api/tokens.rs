@@ -0,0 +1,145 @@
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::key::SecretKey;
+use nostr::types::time::Timestamp;
+use serde::{Deserialize, Serialize};
+use super::storage;
+
+/// Token id -> JSON encoded ClientToken
+const CLIENT_TOKENS_TREE: &str = "client_tokens";
+
+/// What a client connecting with a token may do
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenCapabilities {
+    /// Name shown in the token list (e.g. the companion app)
+    pub label: String,
+    pub can_read: bool,
+    pub can_write: bool,
+    /// Kinds the client may write (empty for any kind, if `can_write`)
+    pub write_kinds: Vec<u16>,
+}
+
+/// Token handed to a client
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedToken {
+    pub id: String,
+    /// Secret the client sends when connecting (`Authorization: Bearer` or `?token=`)
+    pub token: String,
+}
+
+/// Issued token without its secret
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientTokenInfo {
+    pub id: String,
+    pub capabilities: TokenCapabilities,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ClientToken {
+    /// Hex SHA-256 of the secret, the secret itself is never stored
+    #[serde(default)]
+    token_hash: String,
+    /// Secret of tokens issued before only hashes were stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    capabilities: TokenCapabilities,
+    created_at: u64,
+}
+
+impl ClientToken {
+    fn matches(&self, presented: &str) -> bool {
+        match &self.token {
+            Some(token) => super::gate::tokens_match(presented, token),
+            None => super::gate::tokens_match(&hash_secret(presented), &self.token_hash),
+        }
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    Sha256Hash::hash(secret.as_bytes()).to_string()
+}
+
+fn load_tokens() -> Result<Vec<(String, ClientToken)>, String> {
+    storage::open_tree(CLIENT_TOKENS_TREE)?
+        .iter()
+        .map(|entry| {
+            let (id, value) = entry.map_err(|e| format!("Failed to read tokens: {}", e))?;
+            let token = serde_json::from_slice(&value)
+                .map_err(|e| format!("Invalid stored token: {}", e))?;
+            Ok((String::from_utf8_lossy(&id).to_string(), token))
+        })
+        .collect()
+}
+
+/// Id of the issued token matching a secret presented by a client
+pub(crate) fn find_token(presented: &str) -> Option<String> {
+    load_tokens()
+        .ok()?
+        .into_iter()
+        .find(|(_, token)| token.matches(presented))
+        .map(|(id, _)| id)
+}
+
+/// Whether any token was issued (and not revoked)
+pub(crate) fn any_issued() -> bool {
+    storage::open_tree(CLIENT_TOKENS_TREE).map_or(false, |tree| !tree.is_empty())
+}
+
+/// Capabilities of a token (None if it was revoked)
+pub(crate) fn capabilities(id: &str) -> Option<TokenCapabilities> {
+    let value = storage::open_tree(CLIENT_TOKENS_TREE).ok()?.get(id.as_bytes()).ok()??;
+    serde_json::from_slice::<ClientToken>(&value)
+        .ok()
+        .map(|token| token.capabilities)
+}
+
+/// Issue a token restricting what a client of the relay may do
+///
+/// Lets several local apps share the relay: each gets its own token, e.g. one that may only
+/// write certain kinds and one that may only read. Connections without a token keep full
+/// access from loopback; once a token exists, other connections need one (or the remote
+/// access token).
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_issue_token(capabilities: TokenCapabilities) -> Result<IssuedToken, String> {
+    // Secret keys are 32 random bytes; the id is drawn separately, it is shown and logged
+    let token = SecretKey::generate().to_secret_hex();
+    let id = SecretKey::generate().to_secret_hex()[..16].to_string();
+    
+    let stored = ClientToken {
+        token_hash: hash_secret(&token),
+        token: None,
+        capabilities,
+        created_at: Timestamp::now().as_u64(),
+    };
+    let value = serde_json::to_vec(&stored)
+        .map_err(|e| format!("Failed to serialize token: {}", e))?;
+    storage::open_tree(CLIENT_TOKENS_TREE)?
+        .insert(id.as_bytes(), value)
+        .map_err(|e| format!("Failed to store token: {}", e))?;
+    
+    Ok(IssuedToken { id, token })
+}
+
+/// Revoke a token, returns false if it didn't exist
+///
+/// Clients using the token can't read or write anymore, even on open connections.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_revoke_token(id: String) -> Result<bool, String> {
+    let previous = storage::open_tree(CLIENT_TOKENS_TREE)?
+        .remove(id.as_bytes())
+        .map_err(|e| format!("Failed to revoke token: {}", e))?;
+    Ok(previous.is_some())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_list_tokens() -> Result<Vec<ClientTokenInfo>, String> {
+    Ok(load_tokens()?
+        .into_iter()
+        .map(|(id, token)| ClientTokenInfo {
+            id,
+            capabilities: token.capabilities,
+            created_at: token.created_at,
+        })
+        .collect())
+}
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static TRACE_ENABLED: AtomicBool = AtomicBool::new(false);
+// Latest client frames, oldest first
+static FRAMES: Mutex<VecDeque<ProtocolFrame>> = Mutex::new(VecDeque::new());
+
+/// Frames kept at most, older ones are dropped
+const MAX_FRAMES: usize = 500;
+/// Text of a frame kept at most (bytes)
+const MAX_FRAME_TEXT: usize = 16 * 1024;
+/// Larger messages aren't reassembled (nor recorded)
+const MAX_MESSAGE: usize = 256 * 1024;
+/// Client messages worth recording
+const TRACED_MESSAGES: [&str; 3] = ["REQ", "EVENT", "CLOSE"];
+
+/// REQ, EVENT or CLOSE message sent by a client to the relay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProtocolFrame {
+    /// Address of the client
+    pub peer: String,
+    /// Unix timestamp in milliseconds
+    pub received_at_ms: u64,
+    /// "REQ", "EVENT" or "CLOSE"
+    pub message_type: String,
+    /// Subscription id of REQ and CLOSE messages
+    pub subscription_id: Option<String>,
+    /// Raw JSON message, cut at 16 KiB
+    pub text: String,
+    pub truncated: bool,
+}
+
+fn record(peer: &str, message: &[u8]) {
+    let Ok(text) = std::str::from_utf8(message) else {
+        return;
+    };
+    let Ok(serde_json::Value::Array(parts)) = serde_json::from_str::<serde_json::Value>(text) else {
+        return;
+    };
+    let message_type = match parts.first().and_then(|part| part.as_str()) {
+        Some(message_type) if TRACED_MESSAGES.contains(&message_type) => message_type.to_string(),
+        _ => return,
+    };
+    let subscription_id = match message_type.as_str() {
+        "EVENT" => None,
+        _ => parts.get(1).and_then(|part| part.as_str()).map(|id| id.to_string()),
+    };
+    
+    let mut end = text.len().min(MAX_FRAME_TEXT);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let frame = ProtocolFrame {
+        peer: peer.to_string(),
+        received_at_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+        message_type,
+        subscription_id,
+        text: text[..end].to_string(),
+        truncated: end < text.len(),
+    };
+    if let Ok(mut frames) = FRAMES.lock() {
+        if frames.len() >= MAX_FRAMES {
+            frames.pop_front();
+        }
+        frames.push_back(frame);
+    }
+}
+
+/// Header of a WebSocket frame
+struct FrameHeader {
+    fin: bool,
+    /// RSV1, set on the first frame of messages compressed with permessage-deflate
+    compressed: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: u64,
+}
+
+fn parse_header(buf: &[u8]) -> Option<FrameHeader> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let (payload_len, mut header_len) = match buf[1] & 0x7f {
+        126 => (u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as u64, 4),
+        127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?), 10),
+        len => (len as u64, 2),
+    };
+    let mask = if buf[1] & 0x80 != 0 {
+        let mask: [u8; 4] = buf.get(header_len..header_len + 4)?.try_into().ok()?;
+        header_len += 4;
+        Some(mask)
+    } else {
+        None
+    };
+    
+    Some(FrameHeader {
+        fin: buf[0] & 0x80 != 0,
+        compressed: buf[0] & 0x40 != 0,
+        opcode: buf[0] & 0x0f,
+        mask,
+        header_len,
+        payload_len,
+    })
+}
+
+/// Follows the WebSocket frames a client sends to the relay and records its messages
+/// while the trace is on
+///
+/// Frame boundaries are tracked on every connection so the trace can be turned on at any
+/// time; payloads are only buffered and unmasked while it is on.
+pub(crate) struct FrameReader {
+    peer: String,
+    /// Start of a frame whose header (or captured payload) isn't complete yet
+    pending: Vec<u8>,
+    /// Payload bytes left to skip of a frame that isn't captured
+    skip: u64,
+    /// Text message reassembled from its fragments
+    message: Vec<u8>,
+}
+
+impl FrameReader {
+    pub(crate) fn new(peer: SocketAddr) -> Self {
+        Self { peer: peer.to_string(), pending: Vec::new(), skip: 0, message: Vec::new() }
+    }
+    
+    /// Feed bytes sent by the client, in order
+    pub(crate) fn feed(&mut self, data: &[u8]) {
+        let skipped = self.skip.min(data.len() as u64) as usize;
+        self.skip -= skipped as u64;
+        self.pending.extend_from_slice(&data[skipped..]);
+        while self.skip == 0 && self.next_frame() {}
+    }
+    
+    /// Consume the next frame of `pending`, returns false when more bytes are needed
+    fn next_frame(&mut self) -> bool {
+        let Some(header) = parse_header(&self.pending) else {
+            return false;
+        };
+        let data_frame = header.opcode <= 0x2;
+        let text = header.opcode == 0x1 || (header.opcode == 0x0 && !self.message.is_empty());
+        let capture = text
+            && !header.compressed
+            && TRACE_ENABLED.load(Ordering::Relaxed)
+            && (self.message.len() as u64).saturating_add(header.payload_len) <= MAX_MESSAGE as u64;
+        
+        if !capture {
+            // Control frames (ping, close) may come between the fragments of a message
+            if data_frame {
+                self.message.clear();
+            }
+            let available = (self.pending.len() - header.header_len) as u64;
+            let dropped = available.min(header.payload_len);
+            self.pending.drain(..header.header_len + dropped as usize);
+            self.skip = header.payload_len - dropped;
+            return true;
+        }
+        
+        let end = header.header_len + header.payload_len as usize;
+        if self.pending.len() < end {
+            return false;
+        }
+        let mut payload: Vec<u8> = self.pending.drain(..end).skip(header.header_len).collect();
+        if let Some(mask) = header.mask {
+            payload.iter_mut().enumerate().for_each(|(i, byte)| *byte ^= mask[i % 4]);
+        }
+        self.message.extend_from_slice(&payload);
+        if header.fin {
+            record(&self.peer, &std::mem::take(&mut self.message));
+        }
+        true
+    }
+}
+
+/// Record the REQ, EVENT and CLOSE messages clients send to the relay (off by default)
+///
+/// Connections already open are traced too. Turning the trace off keeps the recorded frames.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_set_protocol_trace(enabled: bool) {
+    TRACE_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_is_protocol_trace_enabled() -> bool {
+    TRACE_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Latest client messages recorded by the protocol trace, newest first
+///
+/// Keeps the latest 500 messages. Messages compressed with permessage-deflate aren't recorded.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_get_protocol_trace(limit: u32) -> Vec<ProtocolFrame> {
+    FRAMES.lock()
+        .map(|frames| frames.iter().rev().take(limit as usize).cloned().collect())
+        .unwrap_or_default()
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_clear_protocol_trace() {
+    if let Ok(mut frames) = FRAMES.lock() {
+        frames.clear();
+    }
+}
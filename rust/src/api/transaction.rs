@@ -0,0 +1,168 @@
+use nostr_database::prelude::{Event, EventId, Filter, JsonUtil, Kind};
+use nostr_database::{DatabaseEventStatus, NostrDatabase};
+use nostr_ndb::NdbDatabase;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use super::ingest::{current_version, IngestDatabase};
+use super::relay::{get_database, run_blocking};
+use super::storage;
+
+/// Journal of the transaction being applied (at most one at a time)
+const JOURNAL_TREE: &str = "store_journal";
+const JOURNAL_KEY: &[u8] = b"pending";
+
+// Transactions are applied one at a time
+static TRANSACTION_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// What is needed to undo a partially applied transaction
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Journal {
+    /// Events of the transaction that were not stored before
+    new_ids: Vec<String>,
+    /// Stored events targeted by deletions of the transaction, or replaced by its
+    /// replaceable and addressable events
+    backups: Vec<String>,
+}
+
+fn write_journal(journal: &Journal) -> Result<(), String> {
+    let value = serde_json::to_vec(journal)
+        .map_err(|e| format!("Failed to serialize journal: {}", e))?;
+    let tree = storage::open_tree(JOURNAL_TREE)?;
+    tree.insert(JOURNAL_KEY, value)
+        .map_err(|e| format!("Failed to write journal: {}", e))?;
+    tree.flush()
+        .map_err(|e| format!("Failed to write journal: {}", e))?;
+    Ok(())
+}
+
+fn clear_journal() -> Result<(), String> {
+    storage::open_tree(JOURNAL_TREE)?
+        .remove(JOURNAL_KEY)
+        .map_err(|e| format!("Failed to clear journal: {}", e))?;
+    Ok(())
+}
+
+/// Undo the changes recorded in a journal
+async fn roll_back(database: &Arc<NdbDatabase>, journal: &Journal) -> Result<(), String> {
+    let ids: Vec<EventId> = journal.new_ids.iter()
+        .filter_map(|id| EventId::from_hex(id).ok())
+        .collect();
+    if !ids.is_empty() {
+        database.delete(Filter::new().ids(ids))
+            .await
+            .map_err(|e| format!("Failed to roll back events: {}", e))?;
+    }
+    
+    for json in journal.backups.iter() {
+        if let Ok(event) = Event::from_json(json) {
+            database.save_event(&event)
+                .await
+                .map_err(|e| format!("Failed to restore event: {}", e))?;
+        }
+    }
+    
+    clear_journal()
+}
+
+/// Roll back a transaction interrupted by the app being killed (called when the database opens)
+pub(crate) async fn recover(database: &Arc<NdbDatabase>) -> Result<(), String> {
+    let value = storage::open_tree(JOURNAL_TREE)?
+        .get(JOURNAL_KEY)
+        .map_err(|e| format!("Failed to read journal: {}", e))?;
+    let journal: Journal = match value {
+        Some(value) => serde_json::from_slice(&value)
+            .map_err(|e| format!("Invalid journal: {}", e))?,
+        None => return Ok(()),
+    };
+    
+    tracing::warn!("Rolling back interrupted store transaction ({} events)", journal.new_ids.len());
+    roll_back(database, &journal).await
+}
+
+async fn apply(database: Arc<NdbDatabase>, events: Vec<Event>) -> Result<u32, String> {
+    let _guard = TRANSACTION_LOCK.lock().await;
+    
+    let mut journal = Journal::default();
+    for event in events.iter() {
+        let status = database.check_id(&event.id)
+            .await
+            .map_err(|e| format!("Failed to check event: {}", e))?;
+        match status {
+            DatabaseEventStatus::NotExistent => journal.new_ids.push(event.id.to_hex()),
+            DatabaseEventStatus::Deleted => return Err(format!("Event {} was deleted", event.id)),
+            DatabaseEventStatus::Saved => {}
+        }
+        
+        let mut replaced = Vec::new();
+        if event.kind == Kind::EventDeletion {
+            let targets: Vec<EventId> = event.tags.event_ids().copied().collect();
+            if !targets.is_empty() {
+                let stored = database.query(Filter::new().ids(targets))
+                    .await
+                    .map_err(|e| format!("Failed to query events: {}", e))?;
+                replaced.extend(stored);
+            }
+        }
+        // NDB drops the version a replaceable or addressable event replaces
+        if event.kind.is_replaceable() || event.kind.is_addressable() {
+            replaced.extend(current_version(&database, event)
+                .await
+                .filter(|current| current.id != event.id && current.created_at <= event.created_at));
+        }
+        for backup in replaced.into_iter().map(|event| event.as_json()) {
+            if !journal.backups.contains(&backup) {
+                journal.backups.push(backup);
+            }
+        }
+    }
+    write_journal(&journal)?;
+    
+    // Hooks and the rest of the ingest side effects only see the events once committed
+    let ingest = IngestDatabase::deferred(database.clone());
+    let mut stored = 0;
+    for event in events.iter() {
+        let result = ingest.save_event(event).await;
+        let new = journal.new_ids.contains(&event.id.to_hex());
+        match result {
+            Ok(status) if status.is_success() => stored += 1,
+            // Already stored before the transaction
+            Ok(_) if !new => {}
+            Ok(status) => {
+                roll_back(&database, &journal).await?;
+                return Err(format!("Event {} was rejected: {:?}", event.id, status));
+            }
+            Err(e) => {
+                roll_back(&database, &journal).await?;
+                return Err(format!("Failed to save event {}: {}", event.id, e));
+            }
+        }
+    }
+    
+    clear_journal()?;
+    ingest.release_deferred();
+    Ok(stored)
+}
+
+/// Store several events in the relay database, all or none
+///
+/// Events are validated first; if any can't be stored, the ones already stored are removed
+/// again (and events deleted by kind 5 events or replaced by replaceable events of the
+/// transaction restored). Hooks only see the events once all are stored. A transaction
+/// interrupted by the app being killed is rolled back the next time the database opens.
+///
+/// Returns the number of newly stored events.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_store_transaction(events: Vec<String>) -> Result<u32, String> {
+    let events = events.iter()
+        .map(|json| {
+            let event = Event::from_json(json)
+                .map_err(|e| format!("Invalid event JSON: {}", e))?;
+            event.verify()
+                .map_err(|e| format!("Invalid event {}: {}", event.id, e))?;
+            Ok(event)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    
+    let database = get_database()?;
+    run_blocking(apply(database, events))?
+}
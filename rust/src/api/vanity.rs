@@ -0,0 +1,140 @@
+use nostr::key::Keys;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::frb_generated::StreamSink;
+use super::nostr::{import_keys, NostrKeys};
+
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// Cancel token -> cancelled flag of the running search
+static SEARCHES: Mutex<Option<HashMap<String, Arc<AtomicBool>>>> = Mutex::new(None);
+
+/// Progress of a vanity npub search
+#[derive(Debug, Serialize, Deserialize)]
+pub enum VanityProgress {
+    /// Sent about once a second while searching
+    Searching {
+        attempts: u64,
+        attempts_per_sec: u64,
+        /// Average number of attempts needed for the prefix
+        expected_attempts: u64,
+    },
+    Found { keys: NostrKeys, attempts: u64 },
+    Cancelled { attempts: u64 },
+}
+
+/// Whether the npub data (the x-only key in 5-bit groups) starts with `prefix`
+fn matches_prefix(pubkey: &[u8; 32], prefix: &[u8]) -> bool {
+    prefix.iter().enumerate().all(|(index, value)| {
+        let bit = index * 5;
+        let byte = bit / 8;
+        let window = ((pubkey[byte] as u16) << 8) | pubkey.get(byte + 1).copied().unwrap_or(0) as u16;
+        ((window >> (11 - bit % 8)) & 0x1f) as u8 == *value
+    })
+}
+
+/// Search for a key whose npub starts with `npub1<prefix>`
+///
+/// Runs on all cores in the background and reports progress on the stream, ending with
+/// `Found` or `Cancelled`. Every prefix character makes the search 32 times longer.
+///
+/// # Arguments
+/// * `prefix` - Wanted bech32 characters after "npub1" (no "1", "b", "i" or "o")
+/// * `cancel_token` - Caller-chosen id, pass it to `cancel_vanity_mining` to stop the search
+pub fn mine_vanity_keys(prefix: String, cancel_token: String, sink: StreamSink<VanityProgress>) -> Result<(), String> {
+    let prefix = prefix.trim().to_lowercase();
+    let prefix = prefix.strip_prefix("npub1").unwrap_or(&prefix);
+    if prefix.is_empty() {
+        return Err("Prefix must not be empty".to_string());
+    }
+    let target: Vec<u8> = prefix.chars()
+        .map(|c| BECH32_CHARSET.find(c).map(|value| value as u8)
+            .ok_or_else(|| format!("'{}' can't appear in an npub", c)))
+        .collect::<Result<_, String>>()?;
+    if target.len() > 12 {
+        return Err("Prefix is too long to ever be found".to_string());
+    }
+    
+    let cancelled = Arc::new(AtomicBool::new(false));
+    {
+        let mut searches = SEARCHES.lock()
+            .map_err(|e| format!("Failed to lock vanity searches: {}", e))?;
+        let searches = searches.get_or_insert_with(HashMap::new);
+        if searches.contains_key(&cancel_token) {
+            return Err(format!("A search with token '{}' is already running", cancel_token));
+        }
+        searches.insert(cancel_token.clone(), cancelled.clone());
+    }
+    
+    let attempts = Arc::new(AtomicU64::new(0));
+    let found: Arc<Mutex<Option<Keys>>> = Arc::new(Mutex::new(None));
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    for _ in 0..threads {
+        let (target, cancelled, attempts, found) = (target.clone(), cancelled.clone(), attempts.clone(), found.clone());
+        std::thread::spawn(move || {
+            while !cancelled.load(Ordering::Relaxed) {
+                let keys = Keys::generate();
+                attempts.fetch_add(1, Ordering::Relaxed);
+                if matches_prefix(&keys.public_key().to_bytes(), &target) {
+                    if let Ok(mut found) = found.lock() {
+                        found.get_or_insert(keys);
+                    }
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            }
+        });
+    }
+    
+    // Reporter: progress once a second, then the result
+    let expected_attempts = 32u64.saturating_pow(target.len() as u32);
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        let mut last_report = started;
+        loop {
+            std::thread::sleep(Duration::from_millis(200));
+            let total = attempts.load(Ordering::Relaxed);
+            if cancelled.load(Ordering::Relaxed) {
+                let keys = found.lock().ok().and_then(|mut found| found.take());
+                let progress = match keys.map(|keys| import_keys(keys.secret_key().to_secret_hex())) {
+                    Some(Ok(keys)) => VanityProgress::Found { keys, attempts: total },
+                    _ => VanityProgress::Cancelled { attempts: total },
+                };
+                let _ = sink.add(progress);
+                break;
+            }
+            if last_report.elapsed() >= Duration::from_secs(1) {
+                last_report = Instant::now();
+                let elapsed = started.elapsed().as_secs_f64().max(0.001);
+                let progress = VanityProgress::Searching {
+                    attempts: total,
+                    attempts_per_sec: (total as f64 / elapsed) as u64,
+                    expected_attempts,
+                };
+                // Dart side went away
+                if sink.add(progress).is_err() {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        if let Ok(mut searches) = SEARCHES.lock() {
+            if let Some(searches) = searches.as_mut() {
+                searches.remove(&cancel_token);
+            }
+        }
+    });
+    
+    Ok(())
+}
+
+/// Stop a vanity search, returns false if no search runs with this token
+#[flutter_rust_bridge::frb(sync)]
+pub fn cancel_vanity_mining(cancel_token: String) -> bool {
+    SEARCHES.lock()
+        .ok()
+        .and_then(|searches| searches.as_ref()?.get(&cancel_token).cloned())
+        .map(|cancelled| cancelled.store(true, Ordering::Relaxed))
+        .is_some()
+}
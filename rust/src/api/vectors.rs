@@ -0,0 +1,153 @@
+use base64::Engine;
+use nostr::hashes::sha256::Hash as Sha256Hash;
+use nostr::hashes::Hash;
+use nostr::key::{Keys, PublicKey, SecretKey};
+use nostr::nips::nip44;
+use nostr::nips::nip44::v2::{self, ConversationKey};
+use nostr::secp256k1::rand::{self, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// Seed of the generated keys and nonces, changing it changes every vector
+const VECTOR_SEED: &str = "nostr-rust-flutter-plugin test vectors";
+
+/// Plaintexts covering the padding boundaries and multi-byte UTF-8
+const PLAINTEXTS: [&str; 6] = [
+    "a",
+    "hello nostr",
+    "0123456789abcdef0123456789abcdef",
+    "0123456789abcdef0123456789abcdef!",
+    "ünïcödé 🦩 ✅",
+    "",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestKeypair {
+    pub private_key: String,
+    pub public_key: String,
+}
+
+/// NIP-44 v2 encryption from the first key pair to the second one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Nip44Vector {
+    pub sender_private_key: String,
+    pub receiver_public_key: String,
+    /// Hex conversation key (same in both directions)
+    pub conversation_key: String,
+    /// Hex nonce used for the payload
+    pub nonce: String,
+    pub plaintext: String,
+    /// Base64 payload
+    pub payload: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CryptoTestVectors {
+    /// Version of the plugin crate that produced the vectors
+    pub version: String,
+    pub keypairs: Vec<TestKeypair>,
+    pub nip44: Vec<Nip44Vector>,
+}
+
+fn seeded(label: &str, index: usize) -> [u8; 32] {
+    Sha256Hash::hash(format!("{} {} {}", VECTOR_SEED, label, index).as_bytes()).to_byte_array()
+}
+
+/// Random source handing the library a fixed nonce, so the payloads are reproducible
+struct FixedNonce([u8; 32]);
+
+impl RngCore for FixedNonce {
+    fn next_u32(&mut self) -> u32 {
+        u32::from_be_bytes([self.0[0], self.0[1], self.0[2], self.0[3]])
+    }
+    
+    fn next_u64(&mut self) -> u64 {
+        u64::from(self.next_u32()) << 32 | u64::from(self.next_u32())
+    }
+    
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for (byte, nonce) in dest.iter_mut().zip(self.0.iter().cycle()) {
+            *byte = *nonce;
+        }
+    }
+    
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+fn conversation_key(secret_key: &SecretKey, public_key: &PublicKey) -> Result<ConversationKey, String> {
+    ConversationKey::derive(secret_key, public_key)
+        .map_err(|e| format!("Failed to derive conversation key: {}", e))
+}
+
+/// NIP-44 v2 payload with a given nonce, through the library's own encryption
+fn encrypt_with_nonce(conversation_key: &ConversationKey, nonce: &[u8; 32], plaintext: &str) -> Result<String, String> {
+    let payload = v2::encrypt_to_bytes_with_rng(&mut FixedNonce(*nonce), conversation_key, plaintext)
+        .map_err(|e| format!("Failed to encrypt: {}", e))?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(payload))
+}
+
+/// Generate NIP-44 test vectors with this build, to cross-check other implementations
+///
+/// Keys and nonces are derived from a fixed seed, so the output only changes if the
+/// crypto changes. Every payload is checked against this build's NIP-44 decryption before
+/// being returned; a mismatch is an error worth reporting. Empty plaintexts are listed
+/// with an empty payload as they must be rejected.
+///
+/// # Arguments
+/// * `count` - Number of key pairs (vectors are made between consecutive pairs), at least 2
+#[flutter_rust_bridge::frb(sync)]
+pub fn export_crypto_test_vectors(count: u32) -> Result<CryptoTestVectors, String> {
+    let count = count.max(2) as usize;
+    let keys = (0..count)
+        .map(|index| {
+            SecretKey::from_slice(&seeded("key", index))
+                .map(Keys::new)
+                .map_err(|e| format!("Invalid seeded key: {}", e))
+        })
+        .collect::<Result<Vec<Keys>, String>>()?;
+    
+    let mut vectors = Vec::new();
+    for (index, pair) in keys.windows(2).enumerate() {
+        let (sender, receiver) = (&pair[0], &pair[1]);
+        let key = conversation_key(sender.secret_key(), &receiver.public_key())?;
+        if key.as_bytes() != conversation_key(receiver.secret_key(), &sender.public_key())?.as_bytes() {
+            return Err("Conversation key differs between directions".to_string());
+        }
+        
+        for (offset, plaintext) in PLAINTEXTS.iter().enumerate() {
+            let nonce = seeded("nonce", index * PLAINTEXTS.len() + offset);
+            let payload = if plaintext.is_empty() {
+                String::new()
+            } else {
+                let payload = encrypt_with_nonce(&key, &nonce, plaintext)?;
+                let decrypted = nip44::decrypt(receiver.secret_key(), &sender.public_key(), &payload)
+                    .map_err(|e| format!("This build can't decrypt its own vector: {}", e))?;
+                if decrypted != *plaintext {
+                    return Err("This build decrypts its own vector to another plaintext".to_string());
+                }
+                payload
+            };
+            vectors.push(Nip44Vector {
+                sender_private_key: sender.secret_key().to_secret_hex(),
+                receiver_public_key: receiver.public_key().to_hex(),
+                conversation_key: hex::encode(key.as_bytes()),
+                nonce: hex::encode(nonce),
+                plaintext: plaintext.to_string(),
+                payload,
+            });
+        }
+    }
+    
+    Ok(CryptoTestVectors {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        keypairs: keys.iter()
+            .map(|keys| TestKeypair {
+                private_key: keys.secret_key().to_secret_hex(),
+                public_key: keys.public_key().to_hex(),
+            })
+            .collect(),
+        nip44: vectors,
+    })
+}
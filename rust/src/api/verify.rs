@@ -0,0 +1,99 @@
+use nostr_database::prelude::Event;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+// Sender of the running pool, replaced when the pool is resized
+static POOL: Mutex<Option<SyncSender<Job>>> = Mutex::new(None);
+static POOL_THREADS: AtomicU32 = AtomicU32::new(DEFAULT_THREADS);
+static QUEUE_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+const DEFAULT_THREADS: u32 = 2;
+/// Events waiting for verification before new ones are rejected
+const QUEUE_CAPACITY: usize = 1024;
+
+struct Job {
+    event: Event,
+    reply: oneshot::Sender<bool>,
+}
+
+fn worker(jobs: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        // The lock is only held while waiting for the next job
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let job = match job {
+            Ok(job) => job,
+            // Pool was resized or dropped
+            Err(_) => return,
+        };
+        QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+        let _ = job.reply.send(job.event.verify().is_ok());
+    }
+}
+
+fn spawn_pool(threads: u32) -> Result<SyncSender<Job>, String> {
+    let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+    let receiver = Arc::new(Mutex::new(receiver));
+    for i in 0..threads {
+        let receiver = receiver.clone();
+        std::thread::Builder::new()
+            .name(format!("nostr-verify-{}", i))
+            .spawn(move || worker(receiver))
+            .map_err(|e| format!("Failed to spawn verification thread: {}", e))?;
+    }
+    Ok(sender)
+}
+
+/// Verify the id and signature of an event on the verification pool
+///
+/// Keeps bursts of incoming events from occupying the runtime workers that serve queries.
+/// Returns an error if the queue is full.
+pub(crate) async fn verify_event(event: &Event) -> Result<bool, String> {
+    let (reply, result) = oneshot::channel();
+    {
+        let mut pool = POOL.lock()
+            .map_err(|e| format!("Failed to lock verification pool: {}", e))?;
+        if pool.is_none() {
+            *pool = Some(spawn_pool(POOL_THREADS.load(Ordering::Relaxed))?);
+        }
+        
+        QUEUE_DEPTH.fetch_add(1, Ordering::Relaxed);
+        let job = Job { event: event.clone(), reply };
+        if let Err(e) = pool.as_ref().unwrap().try_send(job) {
+            QUEUE_DEPTH.fetch_sub(1, Ordering::Relaxed);
+            return Err(match e {
+                TrySendError::Full(_) => "verification queue is full".to_string(),
+                TrySendError::Disconnected(_) => "verification pool stopped".to_string(),
+            });
+        }
+    }
+    
+    result.await.map_err(|_| "verification pool stopped".to_string())
+}
+
+/// Set the number of verification threads, restarting the pool
+pub(crate) fn set_threads(threads: u32) -> Result<(), String> {
+    if threads == 0 {
+        return Err("Thread count must be greater than zero".to_string());
+    }
+    POOL_THREADS.store(threads, Ordering::Relaxed);
+    
+    // Old workers finish the queued jobs and exit once their sender is dropped
+    let mut pool = POOL.lock()
+        .map_err(|e| format!("Failed to lock verification pool: {}", e))?;
+    *pool = None;
+    Ok(())
+}
+
+pub(crate) fn threads() -> u32 {
+    POOL_THREADS.load(Ordering::Relaxed)
+}
+
+/// Events waiting for verification
+pub(crate) fn queue_depth() -> u32 {
+    QUEUE_DEPTH.load(Ordering::Relaxed) as u32
+}
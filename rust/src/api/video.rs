@@ -0,0 +1,209 @@
+use nostr::event::{Event, EventBuilder, Kind, Tag};
+use nostr::key::{Keys, SecretKey};
+use nostr::JsonUtil;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use super::audit::{self, KeyOperation};
+use super::nostr::with_fixed_created_at;
+use super::signer::Signer;
+
+/// Normal (horizontal) video event kind
+const VIDEO_KIND: u16 = 21;
+/// Short-form (vertical) video event kind
+const SHORT_VIDEO_KIND: u16 = 22;
+
+/// One encoding of a video (an `imeta` tag)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoVariant {
+    pub url: String,
+    /// MIME type (e.g. "video/mp4")
+    pub mime_type: Option<String>,
+    /// Dimensions as "<width>x<height>"
+    pub dimensions: Option<String>,
+    /// SHA-256 (hex) of the file
+    pub sha256: Option<String>,
+    pub bitrate: Option<u64>,
+    pub duration_secs: Option<f64>,
+    /// Preview images
+    pub thumbnails: Vec<String>,
+    /// Other URLs serving the same file
+    pub fallbacks: Vec<String>,
+}
+
+/// Video event (NIP-71)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VideoEvent {
+    /// Short-form vertical video (kind 22) instead of normal video (kind 21)
+    pub short: bool,
+    pub title: String,
+    /// Event content
+    pub description: String,
+    /// Unix timestamp of the first publication
+    pub published_at: Option<u64>,
+    pub duration_secs: Option<f64>,
+    /// Encodings of the video, e.g. one per resolution
+    pub variants: Vec<VideoVariant>,
+    pub hashtags: Vec<String>,
+    pub content_warning: Option<String>,
+    /// Description for clients that don't support video events (NIP-31)
+    pub alt: Option<String>,
+}
+
+fn imeta_tag(variant: &VideoVariant) -> Result<Tag, String> {
+    if variant.url.is_empty() {
+        return Err("Video variant URL must not be empty".to_string());
+    }
+    if let Some(dim) = variant.dimensions.as_deref() {
+        let valid = dim.split_once('x')
+            .map_or(false, |(w, h)| w.parse::<u32>().is_ok() && h.parse::<u32>().is_ok());
+        if !valid {
+            return Err(format!("Invalid dimensions '{}', expected <width>x<height>", dim));
+        }
+    }
+    
+    let mut values = vec!["imeta".to_string(), format!("url {}", variant.url)];
+    if let Some(mime) = variant.mime_type.as_deref() {
+        values.push(format!("m {}", mime));
+    }
+    if let Some(dim) = variant.dimensions.as_deref() {
+        values.push(format!("dim {}", dim));
+    }
+    if let Some(hash) = variant.sha256.as_deref() {
+        values.push(format!("x {}", hash));
+    }
+    if let Some(bitrate) = variant.bitrate {
+        values.push(format!("bitrate {}", bitrate));
+    }
+    if let Some(duration) = variant.duration_secs {
+        values.push(format!("duration {}", duration));
+    }
+    values.extend(variant.thumbnails.iter().map(|url| format!("image {}", url)));
+    values.extend(variant.fallbacks.iter().map(|url| format!("fallback {}", url)));
+    
+    Tag::parse(values).map_err(|e| format!("Invalid tags: {}", e))
+}
+
+fn parse_imeta(values: &[String]) -> Option<VideoVariant> {
+    let mut variant = VideoVariant::default();
+    for value in values {
+        let (key, value) = match value.split_once(' ') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match key {
+            "url" => variant.url = value.to_string(),
+            "m" => variant.mime_type = Some(value.to_string()),
+            "dim" => variant.dimensions = Some(value.to_string()),
+            "x" => variant.sha256 = Some(value.to_string()),
+            "bitrate" => variant.bitrate = value.parse().ok(),
+            "duration" => variant.duration_secs = value.parse().ok(),
+            "image" => variant.thumbnails.push(value.to_string()),
+            "fallback" => variant.fallbacks.push(value.to_string()),
+            _ => {}
+        }
+    }
+    (!variant.url.is_empty()).then_some(variant)
+}
+
+/// Build and sign a video event (NIP-71, kind 21 or 22)
+///
+/// # Arguments
+/// * `video` - Video metadata, with at least one variant
+/// * `private_key` - Hex private key used to sign the event
+#[flutter_rust_bridge::frb(sync)]
+pub fn build_video_event(video: VideoEvent, private_key: String) -> Result<String, String> {
+    let private_key = SecretKey::from_str(&private_key)
+        .map_err(|e| format!("Invalid private key: {}", e))?;
+    let keys = Keys::new(private_key);
+    
+    let (kind, builder) = video_event_builder(video)?;
+    let event = with_fixed_created_at(builder)
+        .sign_with_keys(&keys)
+        .map_err(|e| format!("Failed to sign video event: {}", e))?;
+    audit::record(KeyOperation::SignEvent, &keys.public_key().to_hex(), Some(kind));
+    
+    Ok(event.as_json())
+}
+
+/// `build_video_event` with a platform or in-memory signer
+pub async fn build_video_event_with_signer(video: VideoEvent, signer: &Signer) -> Result<String, String> {
+    let (_, builder) = video_event_builder(video)?;
+    Ok(signer.sign_builder(builder).await?.as_json())
+}
+
+/// Event builder of a video event, and its kind
+fn video_event_builder(video: VideoEvent) -> Result<(u16, EventBuilder), String> {
+    if video.title.is_empty() {
+        return Err("Video title must not be empty".to_string());
+    }
+    if video.variants.is_empty() {
+        return Err("Video event needs at least one variant".to_string());
+    }
+    
+    let mut tags = vec![
+        Tag::parse(["title", video.title.as_str()]).map_err(|e| format!("Invalid tags: {}", e))?,
+    ];
+    if let Some(published_at) = video.published_at {
+        tags.push(Tag::parse(["published_at", &published_at.to_string()]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    if let Some(duration) = video.duration_secs {
+        tags.push(Tag::parse(["duration", &duration.to_string()]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    for variant in video.variants.iter() {
+        tags.push(imeta_tag(variant)?);
+    }
+    for hashtag in video.hashtags.iter() {
+        let hashtag = hashtag.trim_start_matches('#').to_lowercase();
+        tags.push(Tag::parse(["t", hashtag.as_str()]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    if let Some(reason) = video.content_warning.as_deref() {
+        tags.push(Tag::parse(["content-warning", reason]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    if let Some(alt) = video.alt.as_deref() {
+        tags.push(Tag::parse(["alt", alt]).map_err(|e| format!("Invalid tags: {}", e))?);
+    }
+    
+    let kind = if video.short { SHORT_VIDEO_KIND } else { VIDEO_KIND };
+    Ok((kind, EventBuilder::new(Kind::from(kind), video.description).tags(tags)))
+}
+
+/// Parse a video event (NIP-71, kind 21 or 22)
+///
+/// The top-level `duration` tag is used when present, otherwise the longest variant duration.
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_video_event(event_json: String) -> Result<VideoEvent, String> {
+    let event = Event::from_json(&event_json)
+        .map_err(|e| format!("Invalid event JSON: {}", e))?;
+    let kind = event.kind.as_u16();
+    if kind != VIDEO_KIND && kind != SHORT_VIDEO_KIND {
+        return Err(format!("Not a video event: kind {}", kind));
+    }
+    
+    let mut video = VideoEvent {
+        short: kind == SHORT_VIDEO_KIND,
+        description: event.content.clone(),
+        ..Default::default()
+    };
+    for tag in event.tags.iter() {
+        match tag.as_slice() {
+            [name, value, ..] if name == "title" => video.title = value.clone(),
+            [name, value, ..] if name == "published_at" => video.published_at = value.parse().ok(),
+            [name, value, ..] if name == "duration" => video.duration_secs = value.parse().ok(),
+            [name, value, ..] if name == "t" => video.hashtags.push(value.clone()),
+            [name, value, ..] if name == "alt" => video.alt = Some(value.clone()),
+            [name, rest @ ..] if name == "content-warning" => {
+                video.content_warning = Some(rest.first().cloned().unwrap_or_default());
+            }
+            [name, values @ ..] if name == "imeta" => video.variants.extend(parse_imeta(values)),
+            _ => {}
+        }
+    }
+    
+    if video.duration_secs.is_none() {
+        video.duration_secs = video.variants.iter()
+            .filter_map(|variant| variant.duration_secs)
+            .reduce(f64::max);
+    }
+    
+    Ok(video)
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use crate::frb_generated::StreamSink;
+use super::relay::{get_or_create_runtime, is_relay_running, relay_start_args, start_relay_again, stop_relay_for_restart};
+
+static WATCHDOG_TASK: Mutex<Option<tokio::task::JoinHandle<()>>> = Mutex::new(None);
+static STATUS_SINK: Mutex<Option<StreamSink<RelayStatusEvent>>> = Mutex::new(None);
+
+/// Settings of the relay watchdog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    /// Seconds between two probes
+    pub interval_secs: u32,
+    /// Consecutive failed probes before the relay is restarted
+    pub max_failures: u32,
+    /// How long a probe waits for the WebSocket handshake
+    pub probe_timeout_ms: u32,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 30,
+            max_failures: 3,
+            probe_timeout_ms: 5000,
+        }
+    }
+}
+
+/// Event of the relay status stream
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RelayStatusEvent {
+    /// The relay did not answer a probe
+    ProbeFailed { consecutive_failures: u32, error: String },
+    /// The relay was restarted after `max_failures` failed probes
+    Restarted { url: String, failures: Vec<String> },
+    /// Restarting failed, the watchdog tries again after the next failed probes
+    RestartFailed { error: String },
+    /// An own event was stored by the relay (see `relay_set_write_acks`)
+    EventStored { event_id: String, kind: u16 },
+    /// An own event was accepted by remote relays
+    EventMirrored { event_id: String, kind: u16, relays: Vec<String> },
+    /// Relays left out of a connection because of `max_relay_connections`
+    RelaysDropped { relays: Vec<String> },
+}
+
+pub(crate) fn emit(event: RelayStatusEvent) {
+    if let Ok(sink) = STATUS_SINK.lock() {
+        if let Some(sink) = sink.as_ref() {
+            let _ = sink.add(event);
+        }
+    }
+}
+
+/// Open a WebSocket to the relay (through the access gate) and wait for the handshake
+async fn probe(host: &str, port: u16) -> Result<(), String> {
+    // The relay is reachable on loopback whatever address it is bound to
+    let host = if host == "0.0.0.0" || host == "::" { "127.0.0.1" } else { host };
+    let mut stream = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Failed to connect: {}", e))?;
+    
+    // A relay bound to a LAN address sees the probe as remote, it needs the access token
+    let access_token = super::policy::current_config().remote_access_token;
+    let authorization = if access_token.is_empty() {
+        String::new()
+    } else {
+        format!("Authorization: Bearer {}\r\n", access_token)
+    };
+    let request = format!(
+        "GET / HTTP/1.1\r\nHost: {}:{}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n{}\r\n",
+        host, port, authorization
+    );
+    stream.write_all(request.as_bytes())
+        .await
+        .map_err(|e| format!("Failed to send handshake: {}", e))?;
+    
+    let mut response = [0u8; 64];
+    let n = stream.read(&mut response)
+        .await
+        .map_err(|e| format!("Failed to read handshake: {}", e))?;
+    let status_line = String::from_utf8_lossy(&response[..n]);
+    if !status_line.starts_with("HTTP/1.1 101") {
+        return Err(format!("Unexpected handshake response: {}", status_line.lines().next().unwrap_or("")));
+    }
+    Ok(())
+}
+
+/// Stop and start the relay with the arguments of its last start
+async fn restart() -> Result<String, String> {
+    let (host, port, db_path) = relay_start_args()
+        .ok_or_else(|| "Relay start arguments are unknown".to_string())?;
+    
+    // Starting and stopping block on the runtime, run them off the workers
+    let task = tokio::task::spawn_blocking(move || {
+        let _ = stop_relay_for_restart();
+        start_relay_again(host, port, db_path).map_err(|e| e.to_string())
+    });
+    task.await.map_err(|e| format!("Restart task failed: {}", e))?
+}
+
+async fn run_watchdog(config: WatchdogConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(1) as u64));
+    let timeout = Duration::from_millis(config.probe_timeout_ms.max(1) as u64);
+    let mut failures: Vec<String> = Vec::new();
+    
+    loop {
+        interval.tick().await;
+        
+        // Stopped on purpose, nothing to watch
+        let args = match relay_start_args() {
+            Some(args) if is_relay_running() => args,
+            _ => {
+                failures.clear();
+                continue;
+            }
+        };
+        
+        let error = match tokio::time::timeout(timeout, probe(&args.0, args.1)).await {
+            Ok(Ok(())) => {
+                failures.clear();
+                continue;
+            }
+            Ok(Err(e)) => e,
+            Err(_) => format!("No handshake within {} ms", timeout.as_millis()),
+        };
+        
+        tracing::warn!("Relay watchdog probe failed: {}", error);
+        failures.push(error.clone());
+        emit(RelayStatusEvent::ProbeFailed {
+            consecutive_failures: failures.len() as u32,
+            error,
+        });
+        if (failures.len() as u32) < config.max_failures.max(1) {
+            continue;
+        }
+        
+        match restart().await {
+            Ok(url) => {
+                tracing::warn!("Relay watchdog restarted the relay after {} failed probes", failures.len());
+                emit(RelayStatusEvent::Restarted {
+                    url,
+                    failures: std::mem::take(&mut failures),
+                });
+            }
+            Err(e) => {
+                tracing::error!("Relay watchdog failed to restart the relay: {}", e);
+                failures.clear();
+                emit(RelayStatusEvent::RestartFailed { error: e });
+            }
+        }
+    }
+}
+
+/// Start the watchdog, replacing a running one
+///
+/// It periodically opens a WebSocket to the relay; after `max_failures` consecutive
+/// failures the relay is restarted with the arguments of its last start. Probes are skipped
+/// while the relay is stopped.
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_start_watchdog(config: WatchdogConfig) -> Result<(), String> {
+    let runtime = get_or_create_runtime()?;
+    let mut task_guard = WATCHDOG_TASK.lock()
+        .map_err(|e| format!("Failed to lock watchdog task: {}", e))?;
+    if let Some(task) = task_guard.take() {
+        task.abort();
+    }
+    *task_guard = Some(runtime.spawn(run_watchdog(config)));
+    Ok(())
+}
+
+#[flutter_rust_bridge::frb(sync)]
+pub fn relay_stop_watchdog() -> Result<(), String> {
+    let mut task_guard = WATCHDOG_TASK.lock()
+        .map_err(|e| format!("Failed to lock watchdog task: {}", e))?;
+    if let Some(task) = task_guard.take() {
+        task.abort();
+    }
+    Ok(())
+}
+
+/// Stream relay status events (failed probes, restarts, write acknowledgments) to Dart
+pub fn relay_status_events(sink: StreamSink<RelayStatusEvent>) -> Result<(), String> {
+    let mut sink_guard = STATUS_SINK.lock()
+        .map_err(|e| format!("Failed to lock status sink: {}", e))?;
+    *sink_guard = Some(sink);
+    Ok(())
+}
@@ -0,0 +1,118 @@
+use nostr_database::prelude::{Filter, Kind, PublicKey};
+use serde::{Deserialize, Serialize};
+use super::client::{OUTBOX_NAMESPACE, SYNC_CURSOR_NAMESPACE};
+use super::identities::{relay_list_identities, relay_unregister_identity, PRIVATE_KINDS};
+use super::kv;
+use super::relay::{get_database, run_blocking};
+use super::schedule::SCHEDULED_NAMESPACE;
+
+/// What `wipe_account_data` removed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountWipeReport {
+    /// NIP-44 conversation keys zeroized in memory
+    pub conversation_keys: u32,
+    /// Whether the NIP-46 signer service was running with the account's key (it was stopped)
+    pub signer_service_stopped: bool,
+    /// Remote signer sessions of the account that were closed, destroying their session keys
+    pub remote_signer_sessions: u32,
+    /// Account ids of the identities registered for the pubkey
+    pub identities: Vec<String>,
+    /// DMs, seals and gift wraps sent by or addressed to the account
+    pub private_events: u64,
+    /// Sync cursors whose name contains the pubkey (e.g. "dms:<pubkey>")
+    pub sync_cursors: u32,
+    /// Events of the account waiting in the outbox or scheduled
+    pub queued_events: u32,
+    /// Truncated copies (content size limit) of events sent by or addressed to the account
+    pub truncated_events: u64,
+    /// Audit log entries whose public key was redacted
+    pub audit_entries: u32,
+}
+
+/// Whether a queued event (JSON) is authored by `pubkey`
+fn authored_by(event_json: &str, pubkey: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(event_json)
+        .is_ok_and(|event| event["pubkey"].as_str() == Some(pubkey))
+}
+
+/// Whether an event (JSON) is authored by `pubkey` or tags it
+fn involves(event: &serde_json::Value, pubkey: &str) -> bool {
+    event["pubkey"].as_str() == Some(pubkey)
+        || event["tags"].as_array().is_some_and(|tags| {
+            tags.iter().any(|tag| tag[0].as_str() == Some("p") && tag[1].as_str() == Some(pubkey))
+        })
+}
+
+/// Delete the KV entries of a namespace matching `predicate`, returns how many were deleted
+fn delete_entries(namespace: &str, predicate: impl Fn(&kv::KvEntry) -> bool) -> Result<u32, String> {
+    let mut deleted = 0;
+    for entry in kv::kv_list(namespace.to_string(), None)? {
+        if predicate(&entry) && kv::kv_delete(namespace.to_string(), entry.key)? {
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// Remove what this device keeps about an account ("delete account from this device")
+///
+/// Zeroizes the account's cached conversation keys, stops the signer service running with
+/// its key, closes its remote signer sessions (destroying their session keys), unregisters
+/// its identities, deletes its DMs (kinds 4, 13, 14, 15 and 1059, sent or received) from the
+/// relay database, the truncated copies of its events, its sync cursors and queued events
+/// from the KV store, and redacts its public key in the audit log (last, it needs the device
+/// key of a keyed log and fails on a broken one). Keys held by `SecretKeyHandle`s are
+/// zeroized when Dart disposes them.
+///
+/// The databases don't overwrite freed pages right away, deleted events may stay on disk
+/// until the space is reused. Needs the relay (or the headless database) to be open. Safe
+/// to call again if it fails half-way.
+///
+/// # Arguments
+/// * `pubkey` - Hex public key of the account
+#[flutter_rust_bridge::frb(sync)]
+pub fn wipe_account_data(pubkey: String) -> Result<AccountWipeReport, String> {
+    let author = PublicKey::from_hex(&pubkey)
+        .map_err(|e| format!("Invalid public key: {}", e))?;
+    let pubkey = author.to_hex();
+    let mut report = AccountWipeReport::default();
+    
+    // Secrets in memory first
+    report.conversation_keys = super::nostr::forget_conversation_keys(&pubkey)?;
+    report.signer_service_stopped = super::signer_service::stop_for_key(&pubkey)?;
+    report.remote_signer_sessions = super::bunker::disconnect_user(&pubkey)?;
+    
+    for identity in relay_list_identities()?.into_iter().filter(|identity| identity.pubkey == pubkey) {
+        relay_unregister_identity(identity.account_id.clone())?;
+        report.identities.push(identity.account_id);
+    }
+    
+    let database = get_database()?;
+    let kinds = PRIVATE_KINDS.iter().map(|kind| Kind::from(*kind));
+    let filters = [
+        Filter::new().kinds(kinds.clone()).author(author),
+        Filter::new().kinds(kinds).pubkey(author),
+    ];
+    report.private_events = run_blocking(async move {
+        let mut deleted = 0;
+        for filter in filters {
+            // Not tombstoned: nothing should hint at what the account received
+            deleted += database.negentropy_items(filter.clone()).await?.len() as u64;
+            database.delete(filter).await?;
+        }
+        Ok::<u64, nostr_database::prelude::DatabaseError>(deleted)
+    })?
+    .map_err(|e| format!("Failed to delete private events: {}", e))?;
+    
+    report.truncated_events = super::storage::remove_truncated(|event| involves(event, &pubkey))?;
+    report.sync_cursors = delete_entries(SYNC_CURSOR_NAMESPACE, |entry| entry.key.contains(&pubkey))?;
+    report.queued_events = delete_entries(OUTBOX_NAMESPACE, |entry| authored_by(&entry.value, &pubkey))?;
+    report.queued_events += delete_entries(SCHEDULED_NAMESPACE, |entry| {
+        serde_json::from_str::<serde_json::Value>(&entry.value)
+            .is_ok_and(|scheduled| scheduled["event_json"].as_str().is_some_and(|json| authored_by(json, &pubkey)))
+    })?;
+    report.audit_entries = super::audit::redact_pubkey(&pubkey)?;
+    
+    tracing::info!("Wiped account data: {:?}", report);
+    Ok(report)
+}
@@ -3,7 +3,31 @@ mod frb_generated;
 
 #[cfg(test)]
 mod tests {
+    use super::api::chat::*;
+    use super::api::content::*;
+    use super::api::datetime::*;
+    use super::api::delegation::*;
+    use super::api::digest::*;
+    use super::api::display::*;
+    use super::api::inbox::*;
+    use super::api::media::*;
+    use super::api::mnemonic::*;
+    use super::api::nip05::*;
     use super::api::nostr::*;
+    use super::api::pairing::*;
+    use super::api::relay_scores::*;
+    use super::api::session_keys::*;
+    use super::api::signer::*;
+    use super::api::spam::*;
+    use super::api::structured::*;
+    use super::api::tags::*;
+    use super::api::threshold::*;
+    use super::api::trace::*;
+    use super::api::vectors::*;
+    use super::api::video::*;
+    
+    // The relay is global, tests starting it take turns
+    static RELAY_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
     
     #[test]
     fn test_nostr_functions() {
@@ -32,4 +56,1250 @@ mod tests {
         
         println!("All tests passed!");
     }
+    
+    #[test]
+    fn test_chunked_content_round_trip() {
+        let keys = generate_keys().unwrap();
+        let content = "ü".repeat(100);
+        
+        let mut chunks = split_content_into_chunks(content.clone(), 30078, keys.private_key.clone(), Some(15)).unwrap();
+        assert!(chunks.len() > 1);
+        
+        // Order must not matter
+        chunks.reverse();
+        let reassembled = reassemble_chunks(chunks.clone()).unwrap();
+        assert_eq!(reassembled, content);
+        
+        // Chunks of another author are refused, even with the same content
+        let other = generate_keys().unwrap();
+        let foreign = split_content_into_chunks(content.clone(), 30078, other.private_key, Some(15)).unwrap();
+        let mut mixed = chunks.clone();
+        mixed[0] = foreign[foreign.len() - 1].clone();
+        assert!(reassemble_chunks(mixed).is_err());
+        
+        // Missing chunks must be reported
+        chunks.pop();
+        assert!(reassemble_chunks(chunks).is_err());
+        println!("✅ Chunked content round-trip test passed!");
+    }
+    
+    #[test]
+    fn test_segment_content() {
+        let content = "Read https://example.com/a_(b). #nostr 👍🏽 :wave: issue #1".to_string();
+        let segments = segment_content(content.clone());
+        
+        let kinds: Vec<ContentSegmentKind> = segments.iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, vec![
+            ContentSegmentKind::Text,
+            ContentSegmentKind::Url,
+            ContentSegmentKind::Text,
+            ContentSegmentKind::Hashtag,
+            ContentSegmentKind::Text,
+            ContentSegmentKind::Emoji,
+            ContentSegmentKind::Text,
+            ContentSegmentKind::Emoji,
+            ContentSegmentKind::Text,
+        ]);
+        assert_eq!(segments[1].text, "https://example.com/a_(b)");
+        assert_eq!(segments.iter().map(|s| s.text.as_str()).collect::<String>(), content);
+        println!("✅ Content segmentation test passed!");
+    }
+    
+    #[test]
+    fn test_id_digest() {
+        let present: Vec<[u8; 32]> = (0..200u8).map(|i| [i; 32]).collect();
+        let ids_hex = |ids: &[[u8; 32]]| ids.iter().map(|id| id.iter().map(|b| format!("{:02x}", b)).collect::<String>()).collect::<Vec<_>>();
+        
+        for format in [IdDigestFormat::Bloom, IdDigestFormat::PrefixSet] {
+            let digest = EventIdDigest::build(&present, format, None).unwrap();
+            assert!(id_digest_missing(digest.clone(), ids_hex(&present)).unwrap().is_empty());
+            
+            let missing = id_digest_missing(digest, ids_hex(&[[0xff; 32], [0xfe; 32]])).unwrap();
+            assert!(!missing.is_empty());
+        }
+        println!("✅ Event id digest test passed!");
+    }
+    
+    #[test]
+    fn test_video_event_round_trip() {
+        let keys = generate_keys().unwrap();
+        let video = VideoEvent {
+            short: true,
+            title: "Sunset".to_string(),
+            description: "Timelapse".to_string(),
+            variants: vec![
+                VideoVariant {
+                    url: "https://example.com/1080.mp4".to_string(),
+                    mime_type: Some("video/mp4".to_string()),
+                    dimensions: Some("1080x1920".to_string()),
+                    duration_secs: Some(12.5),
+                    thumbnails: vec!["https://example.com/thumb.jpg".to_string()],
+                    ..Default::default()
+                },
+                VideoVariant {
+                    url: "https://example.com/720.mp4".to_string(),
+                    dimensions: Some("720x1280".to_string()),
+                    ..Default::default()
+                },
+            ],
+            hashtags: vec!["#Timelapse".to_string()],
+            ..Default::default()
+        };
+        
+        let event_json = build_video_event(video, keys.private_key).unwrap();
+        let parsed = parse_video_event(event_json).unwrap();
+        assert!(parsed.short);
+        assert_eq!(parsed.title, "Sunset");
+        assert_eq!(parsed.variants.len(), 2);
+        assert_eq!(parsed.variants[0].thumbnails, vec!["https://example.com/thumb.jpg".to_string()]);
+        assert_eq!(parsed.duration_secs, Some(12.5));
+        assert_eq!(parsed.hashtags, vec!["timelapse".to_string()]);
+        println!("✅ Video event test passed!");
+    }
+    
+    #[test]
+    fn test_validate_mnemonic() {
+        let valid = validate_mnemonic(format!("{} about", "abandon ".repeat(11)));
+        assert!(valid.is_valid);
+        
+        let bad_checksum = validate_mnemonic("abandon ".repeat(12));
+        assert_eq!(bad_checksum.checksum_valid, Some(false));
+        
+        let typo = validate_mnemonic("abandn ability".to_string());
+        assert!(!typo.word_count_valid);
+        assert_eq!(typo.checksum_valid, None);
+        assert!(!typo.words[0].valid);
+        assert!(typo.words[0].suggestions.contains(&"abandon".to_string()));
+        assert!(typo.words[1].valid);
+        println!("✅ Mnemonic validation test passed!");
+    }
+    
+    #[test]
+    fn test_derive_keys_from_mnemonic() {
+        // NIP-06 test vector
+        let mnemonic = "leader monkey parrot ring guide accident before fence cannon height naive bean".to_string();
+        let keys = derive_keys_from_mnemonic(mnemonic.clone(), None, 0).unwrap();
+        assert_eq!(keys.private_key, "7f7ff03d123792d6ac594bfa67bf6d0c0ab55b6b1fdb6249303fe861f1ccba9a");
+        assert_eq!(keys.public_key, "17162c921dc4d2518f9a101db33695df1afb56ab82f5ff3e5da6eec3ca5cd917");
+        
+        let accounts = derive_accounts_from_mnemonic(mnemonic.clone(), None, 0, 3).unwrap();
+        assert_eq!(accounts.len(), 3);
+        assert_eq!(accounts[0].private_key, keys.private_key);
+        assert_ne!(accounts[1].private_key, accounts[2].private_key);
+        assert_eq!(derive_keys_from_mnemonic(mnemonic.clone(), None, 2).unwrap().private_key, accounts[2].private_key);
+        assert_ne!(derive_keys_from_mnemonic(mnemonic, Some("extra".to_string()), 0).unwrap().private_key, keys.private_key);
+        println!("✅ Mnemonic derivation test passed!");
+    }
+    
+    #[test]
+    fn test_tag_accessors() {
+        let keys = generate_keys().unwrap();
+        let root = "a".repeat(64);
+        let parent = "b".repeat(64);
+        let unsigned = format!(
+            r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"content":"reply","tags":[["e","{}","","root"],["e","{}","wss://relay.example","reply"],["t","nostr"],["t","rust"],["p"]]}}"#,
+            keys.public_key, root, parent
+        );
+        let event_json = sign_event(unsigned, keys.private_key).unwrap();
+        
+        assert_eq!(get_tag_values(event_json.clone(), "t".to_string()).unwrap(), vec!["nostr", "rust"]);
+        assert_eq!(get_first_tag(event_json.clone(), "p".to_string()).unwrap(), Some(vec!["p".to_string()]));
+        assert_eq!(get_first_tag(event_json.clone(), "q".to_string()).unwrap(), None);
+        
+        let thread = get_thread_refs(event_json).unwrap();
+        assert_eq!(thread.root.unwrap().event_id, root);
+        let reply = thread.reply.unwrap();
+        assert_eq!(reply.event_id, parent);
+        assert_eq!(reply.relay_url.as_deref(), Some("wss://relay.example"));
+        assert!(thread.mentions.is_empty());
+        println!("✅ Tag accessor test passed!");
+    }
+    
+    #[test]
+    fn test_score_event_spam() {
+        let keys = generate_keys().unwrap();
+        let note = |content: &str| {
+            let unsigned = serde_json::json!({
+                "pubkey": keys.public_key,
+                "created_at": 1700000000,
+                "kind": 1,
+                "content": content,
+                "tags": [],
+            });
+            sign_event(unsigned.to_string(), keys.private_key.clone()).unwrap()
+        };
+        
+        let clean = score_event_spam(note("Walked to the lake this morning, the fog was unreal")).unwrap();
+        assert_eq!(clean.score, 0);
+        
+        let link_only = score_event_spam(note("https://example.com/promo")).unwrap();
+        assert!(link_only.score >= 25);
+        
+        // Scoring doesn't record the event
+        let spam = "Claim your FREE BTC airdrop now, join t.me/example";
+        let first = note(spam);
+        let scored = score_event_spam(first.clone()).unwrap();
+        assert_eq!(score_event_spam(first.clone()).unwrap().score, scored.score);
+        
+        // The same event received twice isn't a repeat, another event with its content is
+        let id = |json: &str| {
+            let id: String = serde_json::from_str::<serde_json::Value>(json).unwrap()["id"].as_str().unwrap().to_string();
+            <[u8; 32]>::try_from(hex::decode(id).unwrap()).unwrap()
+        };
+        score(&id(&first), spam, &[]);
+        score(&id(&first), spam, &[]);
+        assert!(!score_event_spam(first).unwrap().reasons.iter().any(|r| r.starts_with("duplicate content")));
+        let repeated = score_event_spam(note(&format!("{} ", spam))).unwrap();
+        assert!(repeated.score >= 50);
+        assert!(repeated.reasons.iter().any(|r| r.starts_with("duplicate content (seen 1 times)")));
+        println!("✅ Spam scoring test passed!");
+    }
+    
+    #[test]
+    fn test_latest_replaceable_events() {
+        use super::api::client::latest_replaceable_events;
+        
+        let keys = generate_keys().unwrap();
+        let event = |kind: u16, created_at: u64, content: &str, tags: serde_json::Value| {
+            let unsigned = serde_json::json!({
+                "pubkey": keys.public_key,
+                "created_at": created_at,
+                "kind": kind,
+                "content": content,
+                "tags": tags,
+            });
+            sign_event(unsigned.to_string(), keys.private_key.clone()).unwrap()
+        };
+        let id = |json: &String| serde_json::from_str::<serde_json::Value>(json).unwrap()["id"].as_str().unwrap().to_string();
+        
+        // Same created_at: the lowest id wins, whatever the order
+        let tied = vec![event(0, 1700000000, "alice", serde_json::json!([])), event(0, 1700000000, "bob", serde_json::json!([]))];
+        let lowest = tied.iter().map(id).min().unwrap();
+        for events in [tied.clone(), tied.iter().rev().cloned().collect()] {
+            let latest = latest_replaceable_events(events).unwrap();
+            assert_eq!(latest.len(), 1);
+            assert_eq!(latest[0].id.to_hex(), lowest);
+        }
+        
+        // Newer wins, addressable events are kept per d tag, regular events are skipped
+        let mut events = tied.clone();
+        events.push(event(0, 1700000001, "carol", serde_json::json!([])));
+        events.push(event(30000, 1700000000, "", serde_json::json!([["d", "a"]])));
+        events.push(event(30000, 1700000000, "", serde_json::json!([["d", "b"]])));
+        events.push(event(1, 1700000002, "note", serde_json::json!([])));
+        let latest = latest_replaceable_events(events).unwrap();
+        assert_eq!(latest.len(), 3);
+        assert_eq!(latest[0].content, "carol");
+        println!("✅ Latest replaceable events test passed!");
+    }
+    
+    #[test]
+    fn test_social_graph_newest_versions() {
+        use super::api::export::newest_versions;
+        use nostr_database::prelude::{Event as DbEvent, JsonUtil as DbJsonUtil};
+        
+        let keys = generate_keys().unwrap();
+        let other = generate_keys().unwrap();
+        let event = |keys: &NostrKeys, kind: u16, created_at: u64, tags: serde_json::Value| {
+            let unsigned = serde_json::json!({
+                "pubkey": keys.public_key,
+                "created_at": created_at,
+                "kind": kind,
+                "content": "",
+                "tags": tags,
+            });
+            DbEvent::from_json(sign_event(unsigned.to_string(), keys.private_key.clone()).unwrap()).unwrap()
+        };
+        let follows = |pubkey: &str| serde_json::json!([["p", pubkey]]);
+        
+        let events = vec![
+            event(&keys, 3, 1700000000, follows(&other.public_key)),
+            event(&keys, 3, 1700000100, follows(&keys.public_key)),
+            event(&keys, 10000, 1700000000, serde_json::json!([["t", "a"]])),
+            event(&keys, 10000, 1700000000, serde_json::json!([["t", "b"]])),
+            event(&keys, 30000, 1700000000, serde_json::json!([["d", "friends"]])),
+            event(&keys, 30000, 1700000200, serde_json::json!([["d", "friends"], ["p", other.public_key]])),
+            event(&keys, 30000, 1700000000, serde_json::json!([["d", "family"]])),
+            event(&other, 3, 1700000000, follows(&keys.public_key)),
+        ];
+        let lowest_mute = events[2..4].iter().map(|event| event.id).min().unwrap();
+        let newest = newest_versions(events);
+        assert_eq!(newest.len(), 5);
+        
+        let find = |kind: u16, pubkey: &str, identifier: &str| {
+            newest.iter()
+                .filter(|event| event.kind.as_u16() == kind && event.pubkey.to_hex() == pubkey)
+                .filter(|event| event.tags.identifier().unwrap_or_default() == identifier)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(find(3, &keys.public_key, "")[0].created_at.as_u64(), 1700000100);
+        assert_eq!(find(3, &other.public_key, "").len(), 1);
+        assert_eq!(find(10000, &keys.public_key, "")[0].id, lowest_mute);
+        assert_eq!(find(30000, &keys.public_key, "friends")[0].created_at.as_u64(), 1700000200);
+        assert_eq!(find(30000, &keys.public_key, "family").len(), 1);
+        println!("✅ Social graph newest versions test passed!");
+    }
+    
+    #[test]
+    fn test_nip44_length_helpers() {
+        let keys = generate_keys().unwrap();
+        for len in [1usize, 32, 33, 250, 300, 1000, 5000] {
+            let plaintext = "a".repeat(len);
+            let ciphertext = nip44_encrypt(plaintext, keys.public_key.clone(), keys.private_key.clone()).unwrap();
+            assert_eq!(nip44_ciphertext_len(len as u32).unwrap() as usize, ciphertext.len());
+        }
+        
+        assert!(nip44_ciphertext_len(0).is_err());
+        assert_eq!(nip44_max_plaintext_for(100), 0);
+        let max = nip44_max_plaintext_for(1000);
+        assert!(nip44_ciphertext_len(max).unwrap() <= 1000);
+        assert!(nip44_ciphertext_len(max + 1).unwrap() > 1000);
+        println!("✅ NIP-44 length helpers test passed!");
+    }
+    
+    #[test]
+    fn test_dedupe_events() {
+        let keys = generate_keys().unwrap();
+        let note = |created_at: u64| {
+            let unsigned = serde_json::json!({
+                "pubkey": keys.public_key,
+                "created_at": created_at,
+                "kind": 1,
+                "content": format!("note {}", created_at),
+                "tags": [["t", "nostr"]],
+            });
+            sign_event(unsigned.to_string(), keys.private_key.clone()).unwrap()
+        };
+        let older = note(1700000000);
+        let newer = note(1700000100);
+        
+        // A copy with the tags stripped no longer matches its signature
+        let mut stripped: serde_json::Value = serde_json::from_str(&older).unwrap();
+        stripped["tags"] = serde_json::json!([]);
+        
+        let merged = dedupe_events(vec![
+            stripped.to_string(),
+            older.clone(),
+            newer.clone(),
+            newer.clone(),
+            "not json".to_string(),
+        ]);
+        assert_eq!(merged.len(), 2);
+        assert!(merged[0].contains("note 1700000100"));
+        assert!(merged[1].contains("\"nostr\""));
+        println!("✅ Event dedupe test passed!");
+    }
+    
+    #[test]
+    fn test_nip19_keys() {
+        // Vectors from NIP-19
+        let npub = "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg";
+        let pubkey = "7e7e9c42a91bfef19fa929e5fda1b72e0ebc1a4c1141673e2794234d86addf4e";
+        assert_eq!(npub_to_hex(npub.to_string()).unwrap(), pubkey);
+        assert_eq!(hex_to_npub(pubkey.to_string()).unwrap(), npub);
+        
+        let nsec = "nsec1vl029mgpspedva04g90vltkh6fvh240zqtv9k0t9af8935ke9laqsnlfe5";
+        let secret = "67dea2ed018072d675f5415ecfaed7d2597555e202d85b3d65ea4e58d2d92ffa";
+        assert_eq!(nsec_to_hex(nsec.to_string()).unwrap(), secret);
+        assert_eq!(hex_to_nsec(secret.to_string()).unwrap(), nsec);
+        
+        // Keys of the wrong type are rejected
+        assert!(npub_to_hex(nsec.to_string()).is_err());
+        assert!(nsec_to_hex(npub.to_string()).is_err());
+        println!("✅ NIP-19 key conversion test passed!");
+    }
+    
+    #[test]
+    fn test_decrypt_error_reasons() {
+        let alice = generate_keys().unwrap();
+        let bob = generate_keys().unwrap();
+        let eve = generate_keys().unwrap();
+        
+        let encrypted44 = nip44_encrypt("hi".to_string(), bob.public_key.clone(), alice.private_key.clone()).unwrap();
+        assert_eq!(nip44_payload_version(encrypted44.clone()).unwrap(), 2);
+        let error = nip44_decrypt(encrypted44, alice.public_key.clone(), eve.private_key.clone()).unwrap_err();
+        assert_eq!(error.reason, DecryptErrorReason::WrongKey);
+        assert_eq!(error.version, Some(2));
+        
+        let error = nip44_decrypt("#unknown".to_string(), alice.public_key.clone(), bob.private_key.clone()).unwrap_err();
+        assert_eq!(error.reason, DecryptErrorReason::VersionUnsupported);
+        let error = nip44_decrypt("not base64!".to_string(), alice.public_key.clone(), bob.private_key.clone()).unwrap_err();
+        assert_eq!(error.reason, DecryptErrorReason::InvalidBase64);
+        let v1 = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, [1u8; 99]);
+        let error = nip44_payload_version(v1).unwrap_err();
+        assert_eq!((error.reason, error.version), (DecryptErrorReason::VersionUnsupported, Some(1)));
+        
+        let error = nip04_decrypt("abc".to_string(), alice.public_key.clone(), bob.private_key.clone()).unwrap_err();
+        assert_eq!(error.reason, DecryptErrorReason::MalformedPayload);
+        let error = nip04_decrypt("abc".to_string(), "xyz".to_string(), bob.private_key.clone()).unwrap_err();
+        assert_eq!(error.reason, DecryptErrorReason::InvalidKey);
+        println!("✅ Decrypt error reason test passed!");
+    }
+    
+    #[test]
+    fn test_nip49_round_trip() {
+        // Vector from NIP-49
+        let ncryptsec = "ncryptsec1qgg9947rlpvqu76pj5ecreduf9jxhselq2nae2kghhvd5g7dgjtcxfqtd67p9m0w57lspw8gsq6yphnm8623nsl8xn9j4jdzz84zm3frztj3z7s35vpzmqf6ksu8r89qk5z2zxfmu5gv8th8wclt0h4p";
+        let secret = nip49_decrypt_key(ncryptsec.to_string(), "nostr".to_string()).unwrap();
+        assert_eq!(secret, "3501454135014541350145413501453fefb02227e449e57cf4d3a3ce05378683");
+        
+        let keys = generate_keys().unwrap();
+        let encrypted = nip49_encrypt_key(keys.private_key.clone(), "hunter2".to_string(), Some(8)).unwrap();
+        assert!(encrypted.starts_with("ncryptsec1"));
+        assert_eq!(nip49_decrypt_key(encrypted.clone(), "hunter2".to_string()).unwrap(), keys.private_key);
+        assert!(nip49_decrypt_key(encrypted, "wrong".to_string()).is_err());
+        println!("✅ NIP-49 round-trip test passed!");
+    }
+    
+    #[test]
+    fn test_import_keys() {
+        let keys = generate_keys().unwrap();
+        let from_hex = import_keys(keys.private_key.clone()).unwrap();
+        let from_nsec = import_keys(format!(" {}\n", keys.nsec)).unwrap();
+        assert_eq!(from_hex.public_key, keys.public_key);
+        assert_eq!(from_nsec.private_key, keys.private_key);
+        assert_eq!(from_nsec.npub, keys.npub);
+        
+        assert_eq!(import_keys(String::new()).unwrap_err().reason, KeyImportErrorReason::Empty);
+        assert_eq!(import_keys(keys.npub.clone()).unwrap_err().reason, KeyImportErrorReason::PublicKeyGiven);
+        assert_eq!(import_keys("z".repeat(64)).unwrap_err().reason, KeyImportErrorReason::InvalidHex);
+        assert_eq!(import_keys("0".repeat(64)).unwrap_err().reason, KeyImportErrorReason::InvalidKey);
+        assert_eq!(import_keys("abc".to_string()).unwrap_err().reason, KeyImportErrorReason::UnknownFormat);
+        println!("✅ Key import test passed!");
+    }
+    
+    #[test]
+    fn test_note_preview() {
+        let keys = generate_keys().unwrap();
+        let npub = "npub10elfcs4fr0l0r8af98jlmgdh9c8tcxjvz9qkw038js35mp4dma8qzvjptg";
+        let unsigned = format!(
+            r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"content":"Hello\n\nnostr:{} look https://example.com/cat.JPG?w=2 at this cat picture","tags":[]}}"#,
+            keys.public_key, npub
+        );
+        let event_json = sign_event(unsigned, keys.private_key.clone()).unwrap();
+        
+        let preview = generate_note_preview(event_json.clone(), 100).unwrap();
+        assert_eq!(preview, "Hello @npub10elfcs4f…jptg look at this cat picture");
+        assert_eq!(generate_note_preview(event_json, 30).unwrap(), "Hello @npub10elfcs4f…jptg…");
+        
+        let unsigned = format!(
+            r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"content":"spoiler","tags":[["content-warning","plot"]]}}"#,
+            keys.public_key
+        );
+        let event_json = sign_event(unsigned, keys.private_key.clone()).unwrap();
+        assert_eq!(generate_note_preview(event_json.clone(), 100).unwrap(), "Content warning: plot");
+        
+        // The private key has to be the one of my_pubkey
+        let other = generate_keys().unwrap();
+        let note = prepare_note_for_display(event_json.clone(), keys.public_key.clone(), Some(keys.private_key)).unwrap();
+        assert!(note.is_mine);
+        assert!(prepare_note_for_display(event_json, keys.public_key, Some(other.private_key)).is_err());
+        println!("✅ Note preview test passed!");
+    }
+    
+    #[test]
+    fn test_parse_structured_content() {
+        let keys = generate_keys().unwrap();
+        let wallet = generate_keys().unwrap();
+        let event = |kind: u16, content: &str, tags: Vec<Vec<String>>, private_key: &str| {
+            build_event(kind, content.to_string(), tags, private_key.to_string(), None).unwrap().event_json
+        };
+        let tag = |parts: &[&str]| parts.iter().map(|part| part.to_string()).collect::<Vec<String>>();
+        
+        let profile = event(0, r#"{"name":"alice","nip05":"alice@example.com","pronouns":"they"}"#, Vec::new(), &keys.private_key);
+        let StructuredContent::Profile(profile) = parse_structured_content(profile, None).unwrap() else {
+            panic!("Kind 0 should be a profile");
+        };
+        assert_eq!(profile.name.as_deref(), Some("alice"));
+        assert_eq!(profile.nip05.as_deref(), Some("alice@example.com"));
+        assert_eq!(profile.custom_json.as_deref(), Some(r#"{"pronouns":"they"}"#));
+        
+        let relays = vec![
+            tag(&["r", "wss://both.example.com"]),
+            tag(&["r", "wss://read.example.com", "read"]),
+            tag(&["r", "wss://write.example.com", "write"]),
+        ];
+        let relays = event(10002, "", relays, &keys.private_key);
+        let StructuredContent::RelayList(relays) = parse_structured_content(relays, None).unwrap() else {
+            panic!("Kind 10002 should be a relay list");
+        };
+        let modes: Vec<(bool, bool)> = relays.iter().map(|relay| (relay.read, relay.write)).collect();
+        assert_eq!(modes, vec![(true, true), (true, false), (false, true)]);
+        
+        // NWC responses need the key of the connection
+        let payload = nip44_encrypt(
+            r#"{"result_type":"pay_invoice","result":{"preimage":"abcd"}}"#.to_string(),
+            keys.public_key.clone(),
+            wallet.private_key.clone(),
+        ).unwrap();
+        let nwc = event(23195, &payload, vec![tag(&["p", &keys.public_key])], &wallet.private_key);
+        assert!(parse_structured_content(nwc.clone(), None).is_err());
+        let StructuredContent::NwcResponse(response) = parse_structured_content(nwc, Some(keys.private_key.clone())).unwrap() else {
+            panic!("Kind 23195 should be an NWC response");
+        };
+        assert_eq!(response.result_type, "pay_invoice");
+        assert_eq!(response.preimage.as_deref(), Some("abcd"));
+        assert!(response.error_code.is_none());
+        
+        let request = event(9734, "great post", vec![tag(&["p", &wallet.public_key]), tag(&["amount", "21000"])], &keys.private_key);
+        let receipt = event(
+            9735,
+            "",
+            vec![tag(&["p", &wallet.public_key]), tag(&["bolt11", "lnbc210n1"]), tag(&["description", &request])],
+            &wallet.private_key,
+        );
+        let StructuredContent::ZapReceipt(receipt) = parse_structured_content(receipt, None).unwrap() else {
+            panic!("Kind 9735 should be a zap receipt");
+        };
+        assert_eq!(receipt.sender.as_deref(), Some(keys.public_key.as_str()));
+        assert_eq!(receipt.recipient.as_deref(), Some(wallet.public_key.as_str()));
+        assert_eq!(receipt.amount_msats, Some(21000));
+        assert_eq!(receipt.comment, "great post");
+        assert!(receipt.request_valid);
+        
+        let note = event(1, "hello", Vec::new(), &keys.private_key);
+        assert!(matches!(parse_structured_content(note, None).unwrap(), StructuredContent::None));
+        println!("✅ Structured content test passed!");
+    }
+    
+    #[test]
+    fn test_delegation() {
+        let cold = generate_keys().unwrap();
+        let hot = generate_keys().unwrap();
+        let conditions = DelegationConditions {
+            kinds: vec![1],
+            created_after: Some(1600000000),
+            created_before: Some(1800000000),
+        };
+        let tag = create_delegation_tag(hot.public_key.clone(), conditions, cold.private_key.clone()).unwrap();
+        assert_eq!(tag[2], "kind=1&created_at>1600000000&created_at<1800000000");
+        
+        let delegated = |kind: u16| {
+            let unsigned = format!(
+                r#"{{"pubkey":"{}","created_at":1700000000,"kind":{},"content":"hi","tags":[{}]}}"#,
+                hot.public_key, kind, serde_json::to_string(&tag).unwrap()
+            );
+            sign_event(unsigned, hot.private_key.clone()).unwrap()
+        };
+        assert_eq!(verify_delegated_event(delegated(1)).unwrap(), Some(cold.public_key.clone()));
+        assert!(verify_delegated_event(delegated(7)).is_err());
+        
+        let two_kinds = DelegationConditions { kinds: vec![1, 7], ..Default::default() };
+        assert!(create_delegation_tag(hot.public_key.clone(), two_kinds, cold.private_key.clone()).is_err());
+        println!("✅ Delegation test passed!");
+    }
+    
+    #[test]
+    fn test_threshold_signing() {
+        let keys = generate_keys().unwrap();
+        let shares = threshold_split_key(keys.private_key.clone()).unwrap();
+        assert_eq!(shares.len(), 2);
+        assert_eq!(shares[0].group_pubkey, keys.public_key);
+        
+        let unsigned = format!(
+            r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"content":"approved twice","tags":[]}}"#,
+            keys.public_key
+        );
+        let commitments: Vec<String> = shares.iter()
+            .map(|share| threshold_commit(share.clone(), "s1".to_string()).unwrap())
+            .collect();
+        let partials: Vec<String> = shares.iter()
+            .map(|share| threshold_sign_share(share.clone(), "s1".to_string(), unsigned.clone(), commitments.clone()).unwrap())
+            .collect();
+        let signed = threshold_combine(unsigned.clone(), commitments.clone(), partials).unwrap();
+        assert!(signed.contains(&keys.public_key));
+        
+        // Nonces are single use
+        assert!(threshold_sign_share(shares[0].clone(), "s1".to_string(), unsigned.clone(), commitments).is_err());
+        
+        // A share refuses commitments that don't contain its own
+        let own = threshold_commit(shares[0].clone(), "s2".to_string()).unwrap();
+        let other = threshold_commit(shares[1].clone(), "s2".to_string()).unwrap();
+        assert!(threshold_sign_share(shares[0].clone(), "s2".to_string(), unsigned, vec![other, own]).is_err());
+        println!("✅ Threshold signing test passed!");
+    }
+    
+    #[test]
+    fn test_crypto_test_vectors() {
+        let vectors = export_crypto_test_vectors(3).unwrap();
+        assert_eq!(vectors.keypairs.len(), 3);
+        assert_eq!(vectors.nip44.len(), 12);
+        
+        // Same seed, same vectors, and the public decryption agrees with them
+        assert_eq!(vectors.nip44[0].payload, export_crypto_test_vectors(3).unwrap().nip44[0].payload);
+        for vector in vectors.nip44.iter().filter(|vector| !vector.plaintext.is_empty()) {
+            let receiver = vectors.keypairs.iter()
+                .find(|pair| pair.public_key == vector.receiver_public_key)
+                .unwrap();
+            let sender = vectors.keypairs.iter()
+                .find(|pair| pair.private_key == vector.sender_private_key)
+                .unwrap();
+            let decrypted = nip44_decrypt(vector.payload.clone(), sender.public_key.clone(), receiver.private_key.clone()).unwrap();
+            assert_eq!(decrypted, vector.plaintext);
+        }
+        println!("✅ Crypto test vectors test passed!");
+    }
+    
+    #[test]
+    fn test_sign_message() {
+        use nostr::hashes::Hash;
+        
+        let keys = generate_keys().unwrap();
+        let other = generate_keys().unwrap();
+        let signature = sign_message("login challenge 42".to_string(), keys.private_key.clone()).unwrap();
+        assert_eq!(signature.len(), 128);
+        
+        assert!(verify_message(signature.clone(), "login challenge 42".to_string(), keys.public_key.clone()).unwrap());
+        assert!(!verify_message(signature.clone(), "login challenge 43".to_string(), keys.public_key.clone()).unwrap());
+        assert!(!verify_message(signature.clone(), "login challenge 42".to_string(), other.public_key).unwrap());
+        assert!(verify_message("zz".to_string(), "login challenge 42".to_string(), keys.public_key.clone()).is_err());
+        
+        // A challenge shaped like an event serialization doesn't yield an event signature
+        let serialized = format!(r#"[0,"{}",1700000000,1,[],"forged"]"#, keys.public_key);
+        let signature = sign_message(serialized.clone(), keys.private_key.clone()).unwrap();
+        let forged = NostrEvent {
+            id: nostr::hashes::sha256::Hash::hash(serialized.as_bytes()).to_string(),
+            pubkey: keys.public_key.clone(),
+            created_at: 1700000000,
+            kind: 1,
+            tags: vec![],
+            content: "forged".to_string(),
+            sig: signature,
+        };
+        assert!(!verify_event(forged).unwrap_or(false));
+        println!("✅ Message signing test passed!");
+    }
+    
+    #[test]
+    fn test_nip44_conversation_key_cache() {
+        let alice = generate_keys().unwrap();
+        let bob = generate_keys().unwrap();
+        let payload = nip44_encrypt("cached".to_string(), bob.public_key.clone(), alice.private_key.clone()).unwrap();
+        
+        // Same key in both directions
+        let key = nip44_conversation_key(alice.public_key.clone(), bob.private_key.clone()).unwrap();
+        assert_eq!(key, nip44_conversation_key(bob.public_key.clone(), alice.private_key.clone()).unwrap());
+        assert_eq!(nip44_decrypt_with_conversation_key(payload.clone(), key).unwrap(), "cached");
+        
+        nip44_clear_conversation_keys().unwrap();
+        assert_eq!(nip44_decrypt(payload.clone(), alice.public_key, bob.private_key).unwrap(), "cached");
+        let error = nip44_decrypt_with_conversation_key(payload, "00".repeat(32)).unwrap_err();
+        assert_eq!(error.reason, DecryptErrorReason::WrongKey);
+        println!("✅ NIP-44 conversation key cache test passed!");
+    }
+    
+    #[test]
+    fn test_decrypt_batch() {
+        let alice = generate_keys().unwrap();
+        let bob = generate_keys().unwrap();
+        let mut requests: Vec<DecryptRequest> = (0..20)
+            .map(|i| DecryptRequest {
+                ciphertext: nip44_encrypt(format!("message {}", i), bob.public_key.clone(), alice.private_key.clone()).unwrap(),
+                public_key: alice.public_key.clone(),
+            })
+            .collect();
+        requests.push(DecryptRequest { ciphertext: "not a payload".to_string(), public_key: alice.public_key.clone() });
+        
+        let outcomes = nip44_decrypt_batch(requests, bob.private_key.clone());
+        assert_eq!(outcomes.len(), 21);
+        assert_eq!(outcomes[7].plaintext.as_deref(), Some("message 7"));
+        assert!(outcomes[20].plaintext.is_none());
+        assert_eq!(outcomes[20].error.as_ref().unwrap().reason, DecryptErrorReason::InvalidBase64);
+        
+        let nip04 = DecryptRequest {
+            ciphertext: nip04_encrypt("old style".to_string(), bob.public_key.clone(), alice.private_key).unwrap(),
+            public_key: alice.public_key,
+        };
+        let outcomes = nip04_decrypt_batch(vec![nip04], bob.private_key);
+        assert_eq!(outcomes[0].plaintext.as_deref(), Some("old style"));
+        println!("✅ Batch decrypt test passed!");
+    }
+    
+    #[test]
+    fn test_analyze_image() {
+        let image = image::RgbaImage::from_fn(40, 20, |x, _| image::Rgba([(x * 6) as u8, 80, 160, 255]));
+        let mut png = std::io::Cursor::new(Vec::new());
+        image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let info = runtime.block_on(analyze_image(png.clone())).unwrap();
+        assert_eq!(info.mime_type, "image/png");
+        assert_eq!((info.width, info.height), (40, 20));
+        assert_eq!(info.size, png.len() as u64);
+        assert_eq!(info.sha256.len(), 64);
+        assert!(info.blurhash.len() >= 6);
+        
+        let tag = build_image_imeta_tag("https://example.com/a.png".to_string(), info).unwrap();
+        assert!(tag.contains(&"dim 40x20".to_string()));
+        assert!(runtime.block_on(analyze_image(b"not an image".to_vec())).is_err());
+        println!("✅ Image analysis test passed!");
+    }
+    
+    #[test]
+    fn test_gift_wrap() {
+        let alice = generate_keys().unwrap();
+        let bob = generate_keys().unwrap();
+        let rumor = format!(
+            r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"content":"sealed","tags":[]}}"#,
+            alice.public_key
+        );
+        
+        let wrap = gift_wrap(rumor.clone(), bob.public_key.clone(), alice.private_key.clone()).unwrap();
+        assert!(wrap.contains("\"kind\":1059"));
+        let gift = unwrap_gift(wrap.clone(), bob.private_key.clone()).unwrap();
+        assert_eq!(gift.sender, alice.public_key);
+        assert!(gift.rumor_json.contains("sealed"));
+        
+        // Only the receiver can open it, and only the author can wrap its rumor
+        assert!(unwrap_gift(wrap, alice.private_key.clone()).is_err());
+        assert!(gift_wrap(rumor, bob.public_key, bob.private_key).is_err());
+        println!("✅ Gift wrap test passed!");
+    }
+    
+    #[test]
+    fn test_private_dm() {
+        let alice = generate_keys().unwrap();
+        let bob = generate_keys().unwrap();
+        let wraps = send_private_dm("hi bob".to_string(), bob.public_key.clone(), alice.private_key.clone(), None).unwrap();
+        assert_eq!(wraps.len(), 2);
+        
+        let received = decrypt_private_dm(wraps[0].clone(), bob.private_key.clone()).unwrap();
+        assert_eq!(received.sender, alice.public_key);
+        assert_eq!(received.receivers, vec![bob.public_key.clone()]);
+        assert_eq!(received.content, "hi bob");
+        
+        // The sender's copy is the same rumor
+        let copy = decrypt_private_dm(wraps[1].clone(), alice.private_key.clone()).unwrap();
+        assert_eq!(copy.id, received.id);
+        
+        let reply = send_private_dm("hi alice".to_string(), alice.public_key.clone(), bob.private_key, Some(received.id.clone())).unwrap();
+        let reply = decrypt_private_dm(reply[0].clone(), alice.private_key).unwrap();
+        assert_eq!(reply.reply_to, Some(received.id));
+        println!("✅ Private DM test passed!");
+    }
+    
+    #[test]
+    fn test_parse_pairing_payload() {
+        let fingerprint = "AB:".repeat(31) + "AB";
+        let payload = format!("nostrpair:wss://192.168.1.20:4869?t=secret&f={}", fingerprint);
+        let info = parse_pairing_payload(payload).unwrap();
+        assert_eq!(info.url, "wss://192.168.1.20:4869");
+        assert_eq!(info.token.as_deref(), Some("secret"));
+        assert_eq!(info.tls_fingerprint, Some("ab".repeat(32)));
+        assert_eq!(info.connect_url, "wss://192.168.1.20:4869?token=secret");
+        
+        let open = parse_pairing_payload("nostrpair:ws://192.168.1.20:4869".to_string()).unwrap();
+        assert_eq!(open.token, None);
+        assert_eq!(open.connect_url, "ws://192.168.1.20:4869");
+        assert!(parse_pairing_payload("ws://192.168.1.20:4869".to_string()).is_err());
+        println!("✅ Pairing payload test passed!");
+    }
+    
+    #[test]
+    fn test_protocol_trace() {
+        // Masked client text frame, as a browser or nostr client sends it
+        fn client_frame(text: &str, fin: bool, opcode: u8) -> Vec<u8> {
+            let mask = [0x12, 0x34, 0x56, 0x78];
+            let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+            match text.len() {
+                len if len < 126 => frame.push(0x80 | len as u8),
+                len => {
+                    frame.push(0x80 | 126);
+                    frame.extend_from_slice(&(len as u16).to_be_bytes());
+                }
+            }
+            frame.extend_from_slice(&mask);
+            frame.extend(text.bytes().enumerate().map(|(i, byte)| byte ^ mask[i % 4]));
+            frame
+        }
+        
+        relay_clear_protocol_trace();
+        relay_set_protocol_trace(true);
+        let mut reader = FrameReader::new("192.168.1.20:50000".parse().unwrap());
+        
+        // Split across reads, and fragmented with a ping in between
+        let req = client_frame(r#"["REQ","sub1",{"kinds":[1],"limit":10}]"#, true, 0x1);
+        reader.feed(&req[..5]);
+        reader.feed(&req[5..]);
+        let mut bytes = client_frame(r#"["CLOSE","#, false, 0x1);
+        bytes.extend(client_frame("", true, 0x9));
+        bytes.extend(client_frame(r#""sub1"]"#, true, 0x0));
+        bytes.extend(client_frame(&format!(r#"["NOTICE","{}"]"#, "x".repeat(200)), true, 0x1));
+        reader.feed(&bytes);
+        
+        let trace = relay_get_protocol_trace(10);
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].message_type, "CLOSE");
+        assert_eq!(trace[0].text, r#"["CLOSE","sub1"]"#);
+        assert_eq!(trace[1].message_type, "REQ");
+        assert_eq!(trace[1].subscription_id.as_deref(), Some("sub1"));
+        assert_eq!(trace[1].peer, "192.168.1.20:50000");
+        
+        // Frames sent while the trace is off are skipped without losing track
+        relay_set_protocol_trace(false);
+        let event = client_frame(&format!(r#"["EVENT",{{"content":"{}"}}]"#, "y".repeat(300)), true, 0x1);
+        reader.feed(&event[..50]);
+        relay_set_protocol_trace(true);
+        reader.feed(&event[50..]);
+        reader.feed(&client_frame(r#"["CLOSE","sub2"]"#, true, 0x1));
+        let trace = relay_get_protocol_trace(10);
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[0].subscription_id.as_deref(), Some("sub2"));
+        relay_set_protocol_trace(false);
+        println!("✅ Protocol trace test passed!");
+    }
+    
+    #[test]
+    fn test_sign_event_with_signer() {
+        let keys = generate_keys().unwrap();
+        let signer = Signer::from_private_key(keys.private_key.clone()).unwrap();
+        assert_eq!(signer.public_key(), keys.public_key);
+        
+        // No pubkey field needed, the signer's key is used
+        let unsigned = r#"{"created_at":1700000000,"kind":1,"tags":[["t","nostr"]],"content":"signed elsewhere"}"#;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let signed = runtime.block_on(sign_event_with_signer(unsigned.to_string(), &signer)).unwrap();
+        let event: serde_json::Value = serde_json::from_str(&signed).unwrap();
+        assert_eq!(event["pubkey"], keys.public_key.as_str());
+        assert_eq!(event["content"], "signed elsewhere");
+        
+        let relays = runtime.block_on(build_dm_relay_list_with_signer(vec!["wss://relay.damus.io".to_string()], &signer)).unwrap();
+        assert_eq!(parse_dm_relay_list(relays).unwrap().len(), 1);
+        
+        // Encrypting APIs
+        let bob = generate_keys().unwrap();
+        let wraps = runtime.block_on(send_private_dm_with_signer("hi bob".to_string(), bob.public_key.clone(), &signer, None)).unwrap();
+        let received = decrypt_private_dm(wraps[0].clone(), bob.private_key.clone()).unwrap();
+        assert_eq!(received.sender, keys.public_key);
+        assert_eq!(received.content, "hi bob");
+        let dm = runtime.block_on(build_encrypted_dm_with_signer("hi again".to_string(), bob.public_key.clone(), &signer, false)).unwrap();
+        let dm: serde_json::Value = serde_json::from_str(&dm.event_json).unwrap();
+        let content = dm["content"].as_str().unwrap().to_string();
+        assert_eq!(nip04_decrypt(content, keys.public_key.clone(), bob.private_key.clone()).unwrap(), "hi again");
+        
+        let built = runtime.block_on(build_event_with_signer(1, "built".to_string(), vec![], &signer, Some(1700000000))).unwrap();
+        assert!(built.event_json.contains(&keys.public_key));
+        println!("✅ Signer test passed!");
+    }
+    
+    #[test]
+    fn test_pow_difficulty() {
+        assert_eq!(event_pow_difficulty("000006d8c378af1779d2feebc7603a125d99eca0ccf1085959b307f64e5dd358".to_string()).unwrap(), 21);
+        assert_eq!(event_pow_difficulty("f".repeat(64)).unwrap(), 0);
+        assert!(event_pow_difficulty("zz".to_string()).is_err());
+        
+        // Mine 8 bits by hand, committing to that target
+        let keys = generate_keys().unwrap();
+        let mined = (0u64..)
+            .map(|nonce| {
+                let unsigned = format!(
+                    r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"tags":[["nonce","{}","8"]],"content":"pow"}}"#,
+                    keys.public_key, nonce
+                );
+                sign_event(unsigned, keys.private_key.clone()).unwrap()
+            })
+            .find(|signed| {
+                let id = serde_json::from_str::<serde_json::Value>(signed).unwrap()["id"].as_str().unwrap().to_string();
+                event_pow_difficulty(id).unwrap() >= 8
+            })
+            .unwrap();
+        assert!(verify_pow(mined.clone(), 8).unwrap());
+        // The commitment caps the credited work
+        assert!(!verify_pow(mined, 9).unwrap());
+        println!("✅ PoW difficulty test passed!");
+    }
+    
+    #[test]
+    fn test_derive_shared_secret() {
+        use nostr::hashes::Hash;
+        
+        // Random keys, so both parities of the shared point come up
+        for _ in 0..16 {
+            let alice = generate_keys().unwrap();
+            let bob = generate_keys().unwrap();
+            let ours = derive_shared_secret(alice.private_key.clone(), bob.public_key.clone()).unwrap();
+            let theirs = derive_shared_secret(bob.private_key.clone(), alice.public_key.clone()).unwrap();
+            assert_eq!(ours.x, theirs.x);
+            assert_eq!(ours.hashed, theirs.hashed);
+            assert_eq!(ours.x.len(), 64);
+            let x = hex::decode(&ours.x).unwrap();
+            assert_eq!(ours.hashed, nostr::hashes::sha256::Hash::hash(&x).to_string());
+        }
+        let alice = generate_keys().unwrap();
+        assert!(derive_shared_secret(alice.private_key, "zz".to_string()).is_err());
+        println!("✅ Shared secret test passed!");
+    }
+    
+    #[test]
+    fn test_normalize_pubkey() {
+        let keys = generate_keys().unwrap();
+        let npub = hex_to_npub(keys.public_key.clone()).unwrap();
+        assert_eq!(normalize_pubkey(keys.public_key.to_uppercase()).unwrap(), keys.public_key);
+        assert_eq!(normalize_pubkey(format!("  {}\n", npub)).unwrap(), keys.public_key);
+        assert_eq!(normalize_pubkey(format!("nostr:{}", npub)).unwrap(), keys.public_key);
+        assert!(is_valid_public_key(npub));
+        assert!(!is_valid_public_key("abc".to_string()));
+        assert!(!is_valid_public_key(keys.private_key.clone() + "00"));
+        assert!(is_valid_secret_key(keys.private_key.clone()));
+        assert!(!is_valid_secret_key(keys.public_key.clone() + "x"));
+        // Encryption takes pasted keys as they are instead of failing on them
+        let encrypted = nip44_encrypt("hi".to_string(), keys.public_key.to_uppercase(), keys.private_key.clone()).unwrap();
+        assert_eq!(nip44_decrypt(encrypted, keys.public_key.clone(), keys.private_key).unwrap(), "hi");
+        println!("✅ Pubkey normalization test passed!");
+    }
+    
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(1700000000, "en_US".to_string(), TimestampStyle::Iso8601).unwrap(), "2023-11-14T22:13:20Z");
+        // Mid-month, so the month is the same in every time zone
+        let full = format_timestamp(1700000000, "de-DE".to_string(), TimestampStyle::Full).unwrap();
+        assert!(full.contains("November") && full.contains("2023"), "{}", full);
+        let full = format_timestamp(1700000000, "fr_FR.UTF-8".to_string(), TimestampStyle::Full).unwrap();
+        assert!(full.contains("novembre"), "{}", full);
+        // Unknown locales fall back instead of failing
+        assert!(format_timestamp(1700000000, "xx".to_string(), TimestampStyle::DateTime).is_ok());
+        assert!(format_timestamp(i64::MAX, "en_US".to_string(), TimestampStyle::Date).is_err());
+        println!("✅ Timestamp formatting test passed!");
+    }
+    
+    #[test]
+    fn test_truncate_content() {
+        assert_eq!(truncate_content("hello world", 5), "hello\n[truncated, 11 bytes]");
+        // Never cuts inside a character
+        assert_eq!(truncate_content("héllo", 2), "h\n[truncated, 6 bytes]");
+        println!("✅ Content truncation test passed!");
+    }
+    
+    #[test]
+    fn test_nip05_identifier() {
+        assert_eq!(parse_identifier(" Bob@Example.com ").unwrap(), ("bob".to_string(), "example.com".to_string()));
+        assert_eq!(parse_identifier("example.com").unwrap(), ("_".to_string(), "example.com".to_string()));
+        assert!(parse_identifier("bob@example.com/path").is_err());
+        assert!(parse_identifier("b ob@example.com").is_err());
+        // Host names only
+        assert!(parse_identifier("bob@example.com:8080").is_err());
+        assert!(parse_identifier("bob@192.168.1.1").is_err());
+        assert!(parse_identifier("bob@127.1").is_err());
+        assert!(parse_identifier("bob@[::1]").is_err());
+        assert!(parse_identifier("bob@1.example.com").is_ok());
+        // Checked before anything is fetched
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        assert!(runtime.block_on(verify_nip05("bob@example.com".to_string(), "not a key".to_string())).is_err());
+        println!("✅ NIP-05 identifier test passed!");
+    }
+    
+    #[test]
+    fn test_relay_score() {
+        let fast = RemoteRelayStats { writes_ok: 20, latency_ms: Some(100.0), ..Default::default() };
+        let slow = RemoteRelayStats { writes_ok: 20, latency_ms: Some(3000.0), ..Default::default() };
+        let flaky = RemoteRelayStats { writes_ok: 5, writes_failed: 15, latency_ms: Some(100.0), ..Default::default() };
+        let fast_score = relay_score(&fast, RelayPurpose::Write).unwrap();
+        assert!(fast_score > relay_score(&slow, RelayPurpose::Write).unwrap());
+        assert!(fast_score > relay_score(&flaky, RelayPurpose::Write).unwrap());
+        // Never used for reading or DMs
+        assert!(relay_score(&fast, RelayPurpose::Read).is_none());
+        assert!(relay_score(&fast, RelayPurpose::Dm).is_none());
+        
+        let clean = RemoteRelayStats { reads_ok: 10, events_received: 500, latency_ms: Some(200.0), ..Default::default() };
+        let spammy = RemoteRelayStats { spam_events: 250, ..clean.clone() };
+        assert!(relay_score(&clean, RelayPurpose::Read).unwrap() > relay_score(&spammy, RelayPurpose::Read).unwrap());
+        println!("✅ Relay score test passed!");
+    }
+    
+    #[test]
+    fn test_generate_keys_from_seed() {
+        let keys = generate_keys_from_seed(b"integration test".to_vec()).unwrap();
+        let again = generate_keys_from_seed(b"integration test".to_vec()).unwrap();
+        assert_eq!(keys.private_key, again.private_key);
+        assert_eq!(keys.public_key, again.public_key);
+        assert_ne!(generate_keys_from_seed(b"other".to_vec()).unwrap().private_key, keys.private_key);
+        assert!(generate_keys_from_seed(Vec::new()).is_err());
+        println!("✅ Seeded key generation test passed!");
+    }
+    
+    #[test]
+    fn test_verify_events_batch() {
+        let keys = generate_keys().unwrap();
+        let signed: Vec<String> = (0..20)
+            .map(|i| {
+                let unsigned = format!(
+                    r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"tags":[],"content":"note {}"}}"#,
+                    keys.public_key, i
+                );
+                sign_event(unsigned, keys.private_key.clone()).unwrap()
+            })
+            .collect();
+        let mut tampered: serde_json::Value = serde_json::from_str(&signed[0]).unwrap();
+        tampered["content"] = serde_json::Value::String("changed".to_string());
+        
+        let mut batch = signed.clone();
+        batch.push(tampered.to_string());
+        batch.push("not json".to_string());
+        let results = verify_events_batch(batch);
+        assert_eq!(results.len(), 22);
+        assert!(results[..20].iter().all(|result| result.valid && result.error.is_none()));
+        // Results keep the order of the events
+        let first_id = serde_json::from_str::<serde_json::Value>(&signed[0]).unwrap()["id"].as_str().unwrap().to_string();
+        assert_eq!(results[0].event_id.as_deref(), Some(first_id.as_str()));
+        assert!(!results[20].valid && results[20].error.is_some());
+        assert!(!results[21].valid && results[21].event_id.is_none());
+        assert!(verify_events_batch(Vec::new()).is_empty());
+        println!("✅ Batch verification test passed!");
+    }
+    
+    #[test]
+    fn test_build_dm_notification() {
+        let alice = generate_keys().unwrap();
+        let bob = generate_keys().unwrap();
+        
+        let wraps = send_private_dm("hello\n  bob".to_string(), bob.public_key.clone(), alice.private_key.clone(), None).unwrap();
+        let notification = build_dm_notification(wraps[0].clone(), bob.private_key.clone()).unwrap();
+        assert_eq!(notification.kind, 14);
+        assert_eq!(notification.sender, alice.public_key);
+        assert_eq!(notification.snippet, "hello bob");
+        assert!(!notification.from_self);
+        // No cached profile, the abbreviated npub is shown
+        assert!(notification.sender_name.starts_with("npub1"));
+        // The sender's own copy belongs to the same conversation
+        let own_copy = build_dm_notification(wraps[1].clone(), alice.private_key.clone()).unwrap();
+        assert!(own_copy.from_self);
+        assert_eq!(own_copy.conversation_id, notification.conversation_id);
+        
+        let encrypted = nip04_encrypt("x".repeat(300), bob.public_key.clone(), alice.private_key.clone()).unwrap();
+        let dm = sign_event(
+            format!(
+                r#"{{"pubkey":"{}","created_at":1700000000,"kind":4,"tags":[["p","{}"]],"content":"{}"}}"#,
+                alice.public_key, bob.public_key, encrypted
+            ),
+            alice.private_key.clone(),
+        ).unwrap();
+        let notification = build_dm_notification(dm, bob.private_key.clone()).unwrap();
+        assert_eq!(notification.snippet.chars().count(), 121);
+        assert_eq!(notification.conversation_id, own_copy.conversation_id);
+        assert!(build_dm_notification(wraps[0].clone(), generate_keys().unwrap().private_key).is_err());
+        println!("✅ DM notification test passed!");
+    }
+    
+    #[test]
+    fn test_create_http_auth_event() {
+        let keys = generate_keys().unwrap();
+        let hash = "a".repeat(64);
+        let header = create_http_auth_event(
+            "https://files.example.com/upload?x=1".to_string(),
+            "post".to_string(),
+            Some(hash.clone()),
+            keys.private_key.clone(),
+        ).unwrap();
+        let encoded = header.strip_prefix("Nostr ").unwrap();
+        let json = String::from_utf8(base64::Engine::decode(&base64::engine::general_purpose::STANDARD, encoded).unwrap()).unwrap();
+        let event: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(event["kind"], 27235);
+        assert_eq!(event["content"], "");
+        assert_eq!(event["pubkey"], keys.public_key.as_str());
+        assert_eq!(event["tags"], serde_json::json!([
+            ["u", "https://files.example.com/upload?x=1"],
+            ["method", "POST"],
+            ["payload", hash],
+        ]));
+        assert!(verify_events_batch(vec![json])[0].valid);
+        
+        assert!(create_http_auth_event("ftp://x".to_string(), "GET".to_string(), None, keys.private_key.clone()).is_err());
+        assert!(create_http_auth_event("https://x".to_string(), "GET".to_string(), Some("abc".to_string()), keys.private_key).is_err());
+        println!("✅ HTTP auth event test passed!");
+    }
+    
+    #[test]
+    fn test_build_event() {
+        let keys = generate_keys().unwrap();
+        let built = build_event(
+            30023,
+            "Hello".to_string(),
+            vec![vec!["d".to_string(), "post".to_string()], vec!["t".to_string(), "nostr".to_string()]],
+            keys.nsec.clone(),
+            Some(1_700_000_000),
+        ).unwrap();
+        let event: serde_json::Value = serde_json::from_str(&built.event_json).unwrap();
+        assert_eq!(event["id"], built.id.as_str());
+        assert_eq!(event["kind"], 30023);
+        assert_eq!(event["created_at"], 1_700_000_000);
+        assert_eq!(event["pubkey"], keys.public_key.as_str());
+        assert_eq!(event["tags"], serde_json::json!([["d", "post"], ["t", "nostr"]]));
+        assert!(verify_events_batch(vec![built.event_json])[0].valid);
+        
+        assert!(build_event(1, String::new(), vec![vec![]], keys.private_key, None).is_err());
+        println!("✅ Build event test passed!");
+    }
+    
+    #[test]
+    fn test_session_keys() {
+        let session = session_key_generate(Some(60)).unwrap();
+        assert_eq!(session.public_key.len(), 64);
+        assert!(list_session_keys().unwrap().iter().any(|key| key.id == session.id));
+        
+        let unsigned = format!(
+            r#"{{"pubkey":"{}","created_at":1700000000,"kind":1,"tags":[],"content":"hi"}}"#,
+            session.public_key
+        );
+        let signed = session_key_sign_event(session.id.clone(), unsigned.clone()).unwrap();
+        assert!(verify_events_batch(vec![signed])[0].valid);
+        
+        let peer = generate_keys().unwrap();
+        let ciphertext = session_key_nip44_encrypt(session.id.clone(), "secret".to_string(), peer.public_key.clone()).unwrap();
+        assert_eq!(nip44_decrypt(ciphertext, session.public_key.clone(), peer.private_key).unwrap(), "secret");
+        
+        assert!(session_key_destroy(session.id.clone()).unwrap());
+        assert!(!session_key_destroy(session.id.clone()).unwrap());
+        assert!(session_key_sign_event(session.id, unsigned).is_err());
+        assert!(session_key_generate(Some(0)).is_err());
+        println!("✅ Session keys test passed!");
+    }
+    
+    #[test]
+    fn test_parse_and_verify_event() {
+        let keys = generate_keys().unwrap();
+        let built = build_event(
+            1,
+            "Hello".to_string(),
+            vec![vec!["t".to_string(), "nostr".to_string()]],
+            keys.private_key,
+            Some(1_700_000_000),
+        ).unwrap();
+        let event = parse_and_verify_event(built.event_json.clone()).unwrap();
+        assert_eq!(event.id, built.id);
+        assert_eq!(event.pubkey, keys.public_key);
+        assert_eq!(event.kind, 1);
+        assert_eq!(event.created_at, 1_700_000_000);
+        assert_eq!(event.tags, vec![vec!["t".to_string(), "nostr".to_string()]]);
+        assert_eq!(event.content, "Hello");
+        assert!(verify_event(event).unwrap());
+        
+        let tampered = built.event_json.replace("Hello", "Hullo");
+        assert!(parse_and_verify_event(tampered).is_err());
+        assert!(parse_and_verify_event("{}".to_string()).is_err());
+        println!("✅ Parse and verify event test passed!");
+    }
+    
+    #[test]
+    fn test_publish_to_dm_relays() {
+        use super::api::client::{publish_to_dm_relays, send_private_dm_to_relays};
+        use super::api::import::relay_import_events_from_bytes;
+        use super::api::relay::{relay_query_multi, start_relay, stop_relay};
+        
+        let _relay = RELAY_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let db_path = std::env::temp_dir().join(format!("dm_relays_test_{}", std::process::id()));
+        let url = start_relay("127.0.0.1".to_string(), 0, db_path.to_string_lossy().to_string()).unwrap();
+        
+        // The receiver lists the local relay as its DM relay, known from the local database only
+        let sender = generate_keys().unwrap();
+        let receiver = generate_keys().unwrap();
+        let dm_relays = build_event(
+            10050,
+            String::new(),
+            vec![vec!["relay".to_string(), url.clone()]],
+            receiver.private_key.clone(),
+            None,
+        ).unwrap();
+        relay_import_events_from_bytes(format!("{}\n", dm_relays.event_json).into_bytes()).unwrap();
+        let filter = format!(r#"{{"kinds":[10050],"authors":["{}"]}}"#, receiver.public_key);
+        for _ in 0..50 {
+            if !relay_query_multi(vec![filter.clone()], None).unwrap().is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        
+        let wraps = send_private_dm("Hi".to_string(), receiver.public_key.clone(), sender.private_key.clone(), None).unwrap();
+        let outcome = publish_to_dm_relays(wraps[0].clone(), receiver.public_key.clone(), Vec::new()).unwrap();
+        assert_eq!(outcome.accepted_relays.len(), 1, "{:?}", outcome.failed_relays);
+        
+        // The sender has no DM relay list, only the receiver's copy is published
+        let outcomes = send_private_dm_to_relays("Hi again".to_string(), receiver.public_key, sender.private_key, None, Vec::new()).unwrap();
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].accepted_relays.len(), 1);
+        
+        stop_relay().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+        println!("✅ Publish to DM relays test passed!");
+    }
+    
+    #[test]
+    fn test_query_events_multi() {
+        use super::api::import::relay_import_events_from_bytes;
+        use super::api::relay::{relay_query_multi, start_relay, stop_relay};
+        
+        let _relay = RELAY_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let db_path = std::env::temp_dir().join(format!("query_multi_test_{}", std::process::id()));
+        start_relay("127.0.0.1".to_string(), 0, db_path.to_string_lossy().to_string()).unwrap();
+        
+        let keys = generate_keys().unwrap();
+        let notes: Vec<BuiltEvent> = (0..4u64)
+            .map(|i| build_event(1, format!("note {}", i), Vec::new(), keys.private_key.clone(), Some(1_700_000_000 + i)).unwrap())
+            .collect();
+        let lines: String = notes.iter().map(|note| format!("{}\n", note.event_json)).collect();
+        relay_import_events_from_bytes(lines.into_bytes()).unwrap();
+        let all = format!(r#"{{"kinds":[1],"authors":["{}"]}}"#, keys.public_key);
+        for _ in 0..50 {
+            if relay_query_multi(vec![all.clone()], None).unwrap().len() == notes.len() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        let ids = |events: Vec<String>| -> Vec<String> {
+            events.iter()
+                .map(|json| serde_json::from_str::<serde_json::Value>(json).unwrap()["id"].as_str().unwrap().to_string())
+                .collect()
+        };
+        
+        // Overlapping filters: every event once, newest first
+        let oldest_two = format!(r#"{{"kinds":[1],"authors":["{}"],"until":1700000001}}"#, keys.public_key);
+        let newest = format!(r#"{{"kinds":[1],"authors":["{}"],"limit":1}}"#, keys.public_key);
+        let events = ids(relay_query_multi(vec![all.clone(), oldest_two.clone(), newest.clone()], None).unwrap());
+        let expected: Vec<String> = notes.iter().rev().map(|note| note.id.clone()).collect();
+        assert_eq!(events, expected);
+        
+        // Limit of each filter, then the total limit
+        let events = ids(relay_query_multi(vec![oldest_two.clone(), newest], None).unwrap());
+        assert_eq!(events, vec![expected[0].clone(), expected[2].clone(), expected[3].clone()]);
+        let events = ids(relay_query_multi(vec![oldest_two, all], Some(2)).unwrap());
+        assert_eq!(events, expected[..2].to_vec());
+        
+        stop_relay().unwrap();
+        let _ = std::fs::remove_dir_all(db_path);
+        println!("✅ Query events multi test passed!");
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_start_relay_with_fd() {
+        use super::api::relay::{start_relay_with_fd, stop_relay};
+        use std::os::fd::AsRawFd;
+        
+        let _relay = RELAY_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let db_path = std::env::temp_dir().join(format!("fd_relay_test_{}", std::process::id()));
+        let db_path = db_path.to_string_lossy().to_string();
+        
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let url = start_relay_with_fd(listener.as_raw_fd(), db_path.clone()).unwrap();
+        assert_eq!(url, format!("ws://127.0.0.1:{}", port));
+        // The relay listens on a duplicate, the caller may close its descriptor
+        drop(listener);
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_ok());
+        stop_relay().unwrap();
+        
+        // Bound but not listening yet
+        let socket = tokio::net::TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        start_relay_with_fd(socket.as_raw_fd(), db_path.clone()).unwrap();
+        assert!(std::net::TcpStream::connect(("127.0.0.1", port)).is_ok());
+        stop_relay().unwrap();
+        
+        let unbound = tokio::net::TcpSocket::new_v4().unwrap();
+        assert!(start_relay_with_fd(unbound.as_raw_fd(), db_path.clone()).is_err());
+        assert!(start_relay_with_fd(-1, db_path.clone()).is_err());
+        let _ = std::fs::remove_dir_all(db_path);
+        println!("✅ Start relay with fd test passed!");
+    }
 }